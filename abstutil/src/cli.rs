@@ -20,6 +20,11 @@ impl CmdArgs {
         .unwrap();
         log::set_max_level(log::LevelFilter::Debug);
 
+        CmdArgs::from_args(std::env::args().skip(1).collect())
+    }
+
+    // Parses flags from somewhere besides the command line, like a config file.
+    pub fn from_args(raw: Vec<String>) -> CmdArgs {
         let mut args = CmdArgs {
             kv: HashMap::new(),
             bits: HashSet::new(),
@@ -27,7 +32,7 @@ impl CmdArgs {
             used: HashSet::new(),
         };
 
-        for arg in std::env::args().skip(1) {
+        for arg in raw {
             let parts: Vec<&str> = arg.split('=').collect();
             if parts.len() == 1 {
                 if arg.starts_with("--") {
@@ -107,6 +107,11 @@ pub fn serialized_size_bytes<T: Serialize>(obj: &T) -> usize {
     bincode::serialized_size(obj).unwrap() as usize
 }
 
+// The same encoding write_binary uses, which doesn't depend on the build
+pub fn serialize_binary_into<W: Write, T: Serialize>(writer: W, obj: &T) {
+    bincode::serialize_into(writer, obj).unwrap();
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 pub fn write_binary<T: Serialize>(path: String, obj: &T) {
     if let Err(err) = maybe_write_binary(&path, obj) {
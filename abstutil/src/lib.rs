@@ -13,8 +13,8 @@ pub use crate::io::{
     basename, delete_file, deserialize_btreemap, deserialize_multimap, deserialize_usize,
     file_exists, find_next_file, find_prev_file, from_json, list_all_objects, list_dir,
     load_all_objects, maybe_read_binary, maybe_read_json, read_binary, read_json, read_object,
-    serialize_btreemap, serialize_multimap, serialize_usize, serialized_size_bytes, slurp_file,
-    to_json, write_binary, write_json, FileWithProgress,
+    serialize_binary_into, serialize_btreemap, serialize_multimap, serialize_usize,
+    serialized_size_bytes, slurp_file, to_json, write_binary, write_json, FileWithProgress,
 };
 pub use crate::random::{fork_rng, WeightedUsizeChoice};
 pub use crate::time::{
@@ -157,6 +157,9 @@ pub fn clip_map(map: &mut RawMap, timer: &mut Timer) {
     }
     map.areas = result_areas;
 
+    map.docking_stations
+        .retain(|ds| boundary_polygon.contains_pt(ds.point));
+
     // TODO Don't touch parking lots. It'll be visually obvious if a clip intersects one of these.
     // The boundary should be manually adjusted.
 
@@ -4,7 +4,9 @@ use crate::Options;
 use abstutil::{retain_btreemap, Tags, Timer};
 use geom::{HashablePt2D, PolyLine, Polygon, Pt2D, Ring};
 use kml::{ExtraShape, ExtraShapes};
-use map_model::raw::{RawArea, RawBuilding, RawMap, RawParkingLot, RawRoad, RestrictionType};
+use map_model::raw::{
+    RawArea, RawBuilding, RawDockingStation, RawMap, RawParkingLot, RawRoad, RestrictionType,
+};
 use map_model::{osm, AreaType, NamePerLanguage};
 use osm::{NodeID, OsmID, RelationID, WayID};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
@@ -50,6 +52,16 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
             out.traffic_signals
                 .insert(node.pt.to_hashable(), !backwards);
         }
+        if node
+            .tags
+            .is_any("amenity", vec!["bicycle_rental", "kick-scooter_rental"])
+        {
+            map.docking_stations.push(RawDockingStation {
+                osm_id: *id,
+                point: node.pt,
+                osm_tags: node.tags.clone(),
+            });
+        }
         if let Some(amenity) = node.tags.get("amenity") {
             out.amenities.push((
                 node.pt,
@@ -94,6 +106,7 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
                     osm_tags: way.tags.clone(),
                     turn_restrictions: Vec::new(),
                     complicated_turn_restrictions: Vec::new(),
+                    elevation_profile: Vec::new(),
                 },
             ));
             continue;
@@ -0,0 +1,433 @@
+use crate::srtm::Elevation;
+use geom::LonLat;
+use std::collections::BTreeMap;
+use std::io::{self, Error, ErrorKind};
+
+// Just enough of TIFF (https://www.adobe.io/open/standards/TIFF.html) and GeoTIFF
+// (http://docs.opengeospatial.org/is/19-008r4/19-008r4.html) to read elevation rasters:
+// uncompressed, single-band, stored in strips or tiles, and georeferenced in longitude and latitude.
+// Other rasters can be converted with something like
+// `gdalwarp -t_srs EPSG:4326 -co COMPRESS=NONE in.tif out.tif`.
+
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const TILE_WIDTH: u16 = 322;
+const TILE_LENGTH: u16 = 323;
+const TILE_OFFSETS: u16 = 324;
+const SAMPLE_FORMAT: u16 = 339;
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const GEO_KEY_DIRECTORY: u16 = 34735;
+const GDAL_NODATA: u16 = 42113;
+
+// GeoKeys
+const GT_MODEL_TYPE: f64 = 1024.0;
+const GT_RASTER_TYPE: f64 = 1025.0;
+const MODEL_TYPE_GEOGRAPHIC: f64 = 2.0;
+const RASTER_PIXEL_IS_POINT: f64 = 2.0;
+
+pub fn load(path: &str) -> Result<Elevation, io::Error> {
+    from_bytes(std::fs::read(path)?)
+}
+
+fn from_bytes(bytes: Vec<u8>) -> Result<Elevation, io::Error> {
+    let tiff = Tiff::new(bytes)?;
+
+    let width = tiff.get_usize(IMAGE_WIDTH)?;
+    let height = tiff.get_usize(IMAGE_LENGTH)?;
+    if tiff.get_usize(COMPRESSION)? != 1 {
+        return Err(invalid("compressed GeoTIFFs aren't supported"));
+    }
+    if tiff.maybe_get_usize(SAMPLES_PER_PIXEL).unwrap_or(1) != 1 {
+        return Err(invalid("only single-band GeoTIFFs are supported"));
+    }
+    let bits = tiff.get_usize(BITS_PER_SAMPLE)?;
+    // Unsigned by default
+    let format = tiff.maybe_get_usize(SAMPLE_FORMAT).unwrap_or(1);
+    if !matches!(
+        (format, bits),
+        (1, 8) | (1, 16) | (2, 16) | (2, 32) | (3, 32) | (3, 64)
+    ) {
+        return Err(invalid(format!(
+            "{}-bit samples of format {} aren't supported",
+            bits, format
+        )));
+    }
+    let bytes_per_sample = bits / 8;
+    let nodata = tiff
+        .get(GDAL_NODATA)
+        .and_then(|s| s.ascii.trim_end_matches('\0').trim().parse::<f64>().ok());
+
+    // Blocks are strips or tiles, each stored row-major
+    let (block_width, block_height, offsets) = if let Some(offsets) = tiff.get(TILE_OFFSETS) {
+        (
+            tiff.get_usize(TILE_WIDTH)?,
+            tiff.get_usize(TILE_LENGTH)?,
+            offsets.numbers.clone(),
+        )
+    } else {
+        (
+            width,
+            tiff.maybe_get_usize(ROWS_PER_STRIP).unwrap_or(height),
+            tiff.get(STRIP_OFFSETS)
+                .ok_or_else(|| invalid("no strips or tiles"))?
+                .numbers
+                .clone(),
+        )
+    };
+    let blocks_across = (width + block_width - 1) / block_width;
+
+    let mut data = vec![std::f32::NAN; width * height];
+    for (block, offset) in offsets.into_iter().enumerate() {
+        let (block_x, block_y) = (block % blocks_across, block / blocks_across);
+        for row in 0..block_height {
+            let y = block_y * block_height + row;
+            if y >= height {
+                break;
+            }
+            for col in 0..block_width {
+                let x = block_x * block_width + col;
+                if x >= width {
+                    continue;
+                }
+                let pos = (offset as usize) + (row * block_width + col) * bytes_per_sample;
+                let value = tiff.read_sample(pos, format, bits)?;
+                if Some(value) != nodata {
+                    data[y * width + x] = value as f32;
+                }
+            }
+        }
+    }
+
+    // Georeferencing
+    if let Some(keys) = tiff.get(GEO_KEY_DIRECTORY) {
+        if geo_key(&keys.numbers, GT_MODEL_TYPE)
+            .map(|t| t != MODEL_TYPE_GEOGRAPHIC)
+            .unwrap_or(false)
+        {
+            return Err(invalid(
+                "only GeoTIFFs in longitude and latitude are supported; reproject with gdalwarp",
+            ));
+        }
+    }
+    let scale = &tiff
+        .get(MODEL_PIXEL_SCALE)
+        .ok_or_else(|| invalid("no ModelPixelScaleTag"))?
+        .numbers;
+    let tiepoint = &tiff
+        .get(MODEL_TIEPOINT)
+        .ok_or_else(|| invalid("no ModelTiepointTag"))?
+        .numbers;
+    if scale.len() < 2 || tiepoint.len() < 6 {
+        return Err(invalid("malformed georeferencing tags"));
+    }
+    let (lon_step, lat_step) = (scale[0], scale[1]);
+    // The tiepoint maps raster (i, j) to (lon, lat)
+    let mut lon = tiepoint[3] - tiepoint[0] * lon_step;
+    let mut lat = tiepoint[4] + tiepoint[1] * lat_step;
+    // By default, the tiepoint is the corner of the pixel, not its center
+    let pixel_is_point = tiff
+        .get(GEO_KEY_DIRECTORY)
+        .and_then(|keys| geo_key(&keys.numbers, GT_RASTER_TYPE))
+        == Some(RASTER_PIXEL_IS_POINT);
+    if !pixel_is_point {
+        lon += lon_step / 2.0;
+        lat -= lat_step / 2.0;
+    }
+
+    Elevation::new(
+        LonLat::new(lon, lat),
+        lon_step,
+        lat_step,
+        width,
+        height,
+        data,
+    )
+}
+
+fn invalid<S: Into<String>>(msg: S) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+// The directory is a header of 4 shorts, then 4 shorts per key: ID, location, count, value.
+// Location 0 means the value is inline, which covers the keys needed here.
+fn geo_key(directory: &[f64], key: f64) -> Option<f64> {
+    directory
+        .get(4..)?
+        .chunks(4)
+        .find(|entry| entry.len() == 4 && entry[0] == key && entry[1] == 0.0)
+        .map(|entry| entry[3])
+}
+
+struct Field {
+    // Everything numeric is widened to f64. Long arrays like strip offsets fit exactly.
+    numbers: Vec<f64>,
+    ascii: String,
+}
+
+struct Tiff {
+    bytes: Vec<u8>,
+    little_endian: bool,
+    fields: BTreeMap<u16, Field>,
+}
+
+impl Tiff {
+    // Only reads the first image
+    fn new(bytes: Vec<u8>) -> Result<Tiff, io::Error> {
+        let mut tiff = Tiff {
+            little_endian: match bytes.get(0..2) {
+                Some(x) if x == b"II" => true,
+                Some(x) if x == b"MM" => false,
+                _ => {
+                    return Err(invalid("not a TIFF file"));
+                }
+            },
+            bytes,
+            fields: BTreeMap::new(),
+        };
+        if tiff.read_u16(2)? != 42 {
+            return Err(invalid("not a classic TIFF file; BigTIFF isn't supported"));
+        }
+        let ifd = tiff.read_u32(4)? as usize;
+        let num_entries = tiff.read_u16(ifd)? as usize;
+        for idx in 0..num_entries {
+            let entry = ifd + 2 + idx * 12;
+            let tag = tiff.read_u16(entry)?;
+            let field_type = tiff.read_u16(entry + 2)?;
+            let count = tiff.read_u32(entry + 4)? as usize;
+            let size = match field_type {
+                // BYTE, ASCII, SBYTE, UNDEFINED
+                1 | 2 | 6 | 7 => 1,
+                // SHORT, SSHORT
+                3 | 8 => 2,
+                // LONG, SLONG, FLOAT
+                4 | 9 | 11 => 4,
+                // DOUBLE
+                12 => 8,
+                // Rationals and anything else aren't needed
+                _ => {
+                    continue;
+                }
+            };
+            // Values that fit in 4 bytes are stored inline
+            let start = if size * count <= 4 {
+                entry + 8
+            } else {
+                tiff.read_u32(entry + 8)? as usize
+            };
+
+            let mut field = Field {
+                numbers: Vec::new(),
+                ascii: String::new(),
+            };
+            if field_type == 2 {
+                let raw = tiff
+                    .bytes
+                    .get(start..start + count)
+                    .ok_or_else(|| invalid("truncated ASCII field"))?;
+                field.ascii = String::from_utf8_lossy(raw).to_string();
+            } else {
+                for i in 0..count {
+                    let pos = start + i * size;
+                    field.numbers.push(match field_type {
+                        1 | 7 => f64::from(tiff.read_u8(pos)?),
+                        6 => f64::from(tiff.read_u8(pos)? as i8),
+                        3 => f64::from(tiff.read_u16(pos)?),
+                        8 => f64::from(tiff.read_u16(pos)? as i16),
+                        4 => f64::from(tiff.read_u32(pos)?),
+                        9 => f64::from(tiff.read_u32(pos)? as i32),
+                        11 => f64::from(f32::from_bits(tiff.read_u32(pos)?)),
+                        12 => f64::from_bits(tiff.read_u64(pos)?),
+                        _ => unreachable!(),
+                    });
+                }
+            }
+            tiff.fields.insert(tag, field);
+        }
+        Ok(tiff)
+    }
+
+    fn get(&self, tag: u16) -> Option<&Field> {
+        self.fields.get(&tag)
+    }
+
+    fn maybe_get_usize(&self, tag: u16) -> Option<usize> {
+        self.get(tag)?.numbers.get(0).map(|x| *x as usize)
+    }
+
+    fn get_usize(&self, tag: u16) -> Result<usize, io::Error> {
+        self.maybe_get_usize(tag)
+            .ok_or_else(|| invalid(format!("missing TIFF tag {}", tag)))
+    }
+
+    fn read_sample(&self, pos: usize, format: usize, bits: usize) -> Result<f64, io::Error> {
+        Ok(match (format, bits) {
+            (1, 8) => f64::from(self.read_u8(pos)?),
+            (1, 16) => f64::from(self.read_u16(pos)?),
+            (2, 16) => f64::from(self.read_u16(pos)? as i16),
+            (2, 32) => f64::from(self.read_u32(pos)? as i32),
+            (3, 32) => f64::from(f32::from_bits(self.read_u32(pos)?)),
+            (3, 64) => f64::from_bits(self.read_u64(pos)?),
+            _ => unreachable!(),
+        })
+    }
+
+    fn read_bytes(&self, pos: usize, len: usize) -> Result<u64, io::Error> {
+        let raw = self
+            .bytes
+            .get(pos..pos + len)
+            .ok_or_else(|| invalid(format!("TIFF ends before byte {}", pos + len)))?;
+        let append = |value: u64, byte: &u8| (value << 8) | u64::from(*byte);
+        Ok(if self.little_endian {
+            raw.iter().rev().fold(0, append)
+        } else {
+            raw.iter().fold(0, append)
+        })
+    }
+
+    fn read_u8(&self, pos: usize) -> Result<u8, io::Error> {
+        Ok(self.read_bytes(pos, 1)? as u8)
+    }
+
+    fn read_u16(&self, pos: usize) -> Result<u16, io::Error> {
+        Ok(self.read_bytes(pos, 2)? as u16)
+    }
+
+    fn read_u32(&self, pos: usize) -> Result<u32, io::Error> {
+        Ok(self.read_bytes(pos, 4)? as u32)
+    }
+
+    fn read_u64(&self, pos: usize) -> Result<u64, io::Error> {
+        self.read_bytes(pos, 8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::Distance;
+
+    // A little-endian TIFF with the pixels right after the header, then one IFD. Each field is
+    // (tag, (type, count, values already encoded)).
+    fn make_tiff(mut fields: Vec<(u16, (u16, u32, Vec<u8>))>, pixels: Vec<u8>) -> Vec<u8> {
+        fields.sort_by_key(|f| f.0);
+        let ifd_offset = 8 + pixels.len();
+        let mut extra_offset = ifd_offset + 2 + 12 * fields.len() + 4;
+
+        let mut bytes = b"II".to_vec();
+        bytes.extend_from_slice(&42u16.to_le_bytes());
+        bytes.extend_from_slice(&(ifd_offset as u32).to_le_bytes());
+        bytes.extend(pixels);
+        bytes.extend_from_slice(&(fields.len() as u16).to_le_bytes());
+        let mut extra = Vec::new();
+        for (tag, (field_type, count, mut values)) in fields {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&field_type.to_le_bytes());
+            bytes.extend_from_slice(&count.to_le_bytes());
+            if values.len() <= 4 {
+                values.resize(4, 0);
+                bytes.extend(values);
+            } else {
+                bytes.extend_from_slice(&(extra_offset as u32).to_le_bytes());
+                extra_offset += values.len();
+                extra.extend(values);
+            }
+        }
+        // No more IFDs
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend(extra);
+        bytes
+    }
+
+    fn short(value: u16) -> (u16, u32, Vec<u8>) {
+        (3, 1, value.to_le_bytes().to_vec())
+    }
+
+    fn shorts(values: Vec<u16>) -> (u16, u32, Vec<u8>) {
+        let count = values.len() as u32;
+        (
+            3,
+            count,
+            values
+                .into_iter()
+                .flat_map(|x| x.to_le_bytes().to_vec())
+                .collect(),
+        )
+    }
+
+    fn doubles(values: Vec<f64>) -> (u16, u32, Vec<u8>) {
+        let count = values.len() as u32;
+        (
+            12,
+            count,
+            values
+                .into_iter()
+                .flat_map(|x| x.to_le_bytes().to_vec())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_read_geotiff() {
+        // 3 columns and 2 rows of signed 16-bit samples, one of them missing
+        let pixels: Vec<u8> = vec![100i16, 200, -9999, 300, 400, 500]
+            .into_iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect();
+        let fields = vec![
+            (IMAGE_WIDTH, short(3)),
+            (IMAGE_LENGTH, short(2)),
+            (BITS_PER_SAMPLE, short(16)),
+            (COMPRESSION, short(1)),
+            (SAMPLE_FORMAT, short(2)),
+            (STRIP_OFFSETS, (4, 1, 8u32.to_le_bytes().to_vec())),
+            (MODEL_PIXEL_SCALE, doubles(vec![0.5, 0.25, 0.0])),
+            (
+                MODEL_TIEPOINT,
+                doubles(vec![0.0, 0.0, 0.0, -122.0, 47.5, 0.0]),
+            ),
+            // Geographic, and the tiepoint is the center of the pixel
+            (
+                GEO_KEY_DIRECTORY,
+                shorts(vec![1, 1, 0, 2, 1024, 0, 1, 2, 1025, 0, 1, 2]),
+            ),
+            (GDAL_NODATA, (2, 6, b"-9999\0".to_vec())),
+        ];
+        let elevation = from_bytes(make_tiff(fields, pixels)).unwrap();
+
+        assert_eq!(
+            elevation.get(LonLat::new(-122.0, 47.5)),
+            Some(Distance::meters(100.0))
+        );
+        assert_eq!(
+            elevation.get(LonLat::new(-122.0, 47.25)),
+            Some(Distance::meters(300.0))
+        );
+        // The middle of the first 4 samples
+        assert_eq!(
+            elevation.get(LonLat::new(-121.75, 47.375)),
+            Some(Distance::meters(250.0))
+        );
+        // Next to the missing sample
+        assert_eq!(elevation.get(LonLat::new(-121.25, 47.375)), None);
+        // Off the grid
+        assert_eq!(elevation.get(LonLat::new(-122.5, 47.375)), None);
+    }
+
+    #[test]
+    fn test_reject_bad_tiffs() {
+        assert!(from_bytes(b"PK\x03\x04".to_vec()).is_err());
+        // Compressed
+        let fields = vec![
+            (IMAGE_WIDTH, short(2)),
+            (IMAGE_LENGTH, short(2)),
+            (COMPRESSION, short(5)),
+        ];
+        assert!(from_bytes(make_tiff(fields, vec![0; 8])).is_err());
+    }
+}
@@ -0,0 +1,739 @@
+use abstutil::Timer;
+use chrono::{Datelike, NaiveDate, Weekday};
+use geom::{Angle, Duration, GPSBounds, LonLat, Polygon, Pt2D, Time};
+use map_model::raw::{RawGtfsRoute, RawGtfsStop, RawMap};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+
+// Imports transit routes and timetables from a GTFS feed
+// (https://developers.google.com/transit/gtfs/reference). This is a zip file with stops.txt,
+// routes.txt, trips.txt, stop_times.txt, and optionally shapes.txt, calendar.txt, and
+// calendar_dates.txt.
+//
+// We simulate one day, so only trips running on the busiest weekday in the feed are used. Each
+// distinct sequence of stops that trips of a GTFS route serve inside the map becomes a separate
+// route, with one departure per trip.
+pub fn import(
+    path: &str,
+    map: &RawMap,
+    timer: &mut Timer,
+) -> Result<Vec<RawGtfsRoute>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    timer.start("import GTFS");
+    let result = import_archive(&mut archive, map, timer);
+    timer.stop("import GTFS");
+    result
+}
+
+fn import_archive<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    map: &RawMap,
+    timer: &mut Timer,
+) -> Result<Vec<RawGtfsRoute>, Box<dyn Error>> {
+    let stops: HashMap<String, StopRecord> =
+        read::<_, StopRecord, _>(archive, "stops.txt", |_| true)?
+            .ok_or("missing stops.txt")?
+            .into_iter()
+            .map(|s| (s.stop_id.clone(), s))
+            .collect();
+    let routes: HashMap<String, RouteRecord> =
+        read::<_, RouteRecord, _>(archive, "routes.txt", |_| true)?
+            .ok_or("missing routes.txt")?
+            .into_iter()
+            .map(|r| (r.route_id.clone(), r))
+            .collect();
+    let mut trips: Vec<TripRecord> =
+        read::<_, TripRecord, _>(archive, "trips.txt", |_| true)?.ok_or("missing trips.txt")?;
+    let mut trips_per_service: HashMap<String, usize> = HashMap::new();
+    for trip in &trips {
+        *trips_per_service
+            .entry(trip.service_id.clone())
+            .or_insert(0) += 1;
+    }
+    let services = active_services(archive, &trips_per_service, timer)?;
+    trips.retain(|t| services.contains(&t.service_id));
+    let trips: HashMap<String, TripRecord> =
+        trips.into_iter().map(|t| (t.trip_id.clone(), t)).collect();
+
+    // This is usually the biggest file by far, so filter while reading.
+    let mut stop_times: BTreeMap<String, Vec<StopTimeRecord>> = BTreeMap::new();
+    for rec in read(archive, "stop_times.txt", |rec: &StopTimeRecord| {
+        trips.contains_key(&rec.trip_id)
+    })?
+    .ok_or("missing stop_times.txt")?
+    {
+        stop_times
+            .entry(rec.trip_id.clone())
+            .or_insert_with(Vec::new)
+            .push(rec);
+    }
+
+    let mut shapes: HashMap<String, Vec<(usize, Pt2D)>> = HashMap::new();
+    for rec in read::<_, ShapeRecord, _>(archive, "shapes.txt", |_| true)?.unwrap_or_else(Vec::new)
+    {
+        shapes.entry(rec.shape_id).or_insert_with(Vec::new).push((
+            rec.shape_pt_sequence,
+            Pt2D::from_gps(
+                LonLat::new(rec.shape_pt_lon, rec.shape_pt_lat),
+                &map.gps_bounds,
+            ),
+        ));
+    }
+    let shapes: HashMap<String, Vec<Pt2D>> = shapes
+        .into_iter()
+        .map(|(id, mut pts)| {
+            pts.sort_by_key(|(seq, _)| *seq);
+            (id, pts.into_iter().map(|(_, pt)| pt).collect())
+        })
+        .collect();
+
+    // Group trips by route and the stops they serve in the map
+    let mut variants: BTreeMap<(String, Vec<String>), Variant> = BTreeMap::new();
+    timer.start_iter("group GTFS trips", stop_times.len());
+    for (trip_id, mut times) in stop_times {
+        timer.next();
+        times.sort_by_key(|st| st.stop_sequence);
+        let trip = &trips[&trip_id];
+
+        // Like routes from OSM, only keep the first contiguous stretch of stops inside the map
+        let mut first = None;
+        let mut last = None;
+        for (idx, st) in times.iter().enumerate() {
+            let in_map = stops
+                .get(&st.stop_id)
+                .and_then(|s| s.pt(&map.gps_bounds))
+                .map(|pt| map.boundary_polygon.contains_pt(pt))
+                .unwrap_or(false);
+            if in_map {
+                if first.is_none() {
+                    first = Some(idx);
+                }
+                last = Some(idx);
+            } else if first.is_some() {
+                break;
+            }
+        }
+        let (first, last) = match (first, last) {
+            (Some(first), Some(last)) if last > first => (first, last),
+            // Routes with only 1 stop are useless
+            _ => continue,
+        };
+        let mut departure = match departure_time(&times, first) {
+            Some(t) => t,
+            None => {
+                timer.warn(format!("GTFS trip {} is missing times", trip_id));
+                continue;
+            }
+        };
+        // Trips coming from outside the map start at the border, not the first stop, so start
+        // them when their own schedule says they'd cross into the map.
+        if first > 0 {
+            let stop_pt = |idx: usize| {
+                stops
+                    .get(&times[idx].stop_id)
+                    .and_then(|s| s.pt(&map.gps_bounds))
+            };
+            if let (Some(prev_departure), Some(pt1), Some(pt2)) = (
+                departure_time(&times, first - 1),
+                stop_pt(first - 1),
+                stop_pt(first),
+            ) {
+                if prev_departure <= departure {
+                    let pct = pct_outside(&map.boundary_polygon, pt1, pt2);
+                    departure = prev_departure + pct * (departure - prev_departure);
+                }
+            }
+        }
+
+        let key = (
+            trip.route_id.clone(),
+            times[first..=last]
+                .iter()
+                .map(|st| st.stop_id.clone())
+                .collect::<Vec<_>>(),
+        );
+        let variant = variants.entry(key).or_insert_with(|| Variant {
+            shape_id: trip.shape_id.clone(),
+            enters_from: if first > 0 {
+                stops
+                    .get(&times[first - 1].stop_id)
+                    .and_then(|s| s.pt(&map.gps_bounds))
+            } else {
+                None
+            },
+            exits_to: times
+                .get(last + 1)
+                .and_then(|st| stops.get(&st.stop_id))
+                .and_then(|s| s.pt(&map.gps_bounds)),
+            departures: Vec::new(),
+        });
+        let timetable = timetable(&times, first, last, departure);
+        variant.departures.push((departure, timetable));
+    }
+
+    let mut results = Vec::new();
+    // For every route_id and first and last stop, the variants and how many trips each has
+    let mut ids_used: BTreeMap<String, Vec<(usize, usize, Vec<String>)>> = BTreeMap::new();
+    for ((route_id, stop_ids), mut variant) in variants {
+        let route = if let Some(r) = routes.get(&route_id) {
+            r
+        } else {
+            timer.warn(format!("GTFS trips refer to unknown route {}", route_id));
+            continue;
+        };
+        let is_bus = if let Some(x) = route_type_is_bus(route.route_type) {
+            x
+        } else {
+            continue;
+        };
+        let pts: Vec<Pt2D> = stop_ids
+            .iter()
+            .map(|id| stops[id].pt(&map.gps_bounds).unwrap())
+            .collect();
+        let shape = variant.shape_id.as_ref().and_then(|id| shapes.get(id));
+        let raw_stops = stop_ids
+            .iter()
+            .enumerate()
+            .map(|(idx, id)| RawGtfsStop {
+                stop_id: id.clone(),
+                name: stops[id]
+                    .stop_name
+                    .clone()
+                    .unwrap_or_else(|| format!("stop {}", id)),
+                pos: pts[idx],
+                heading: heading(&pts, idx, shape),
+            })
+            .collect::<Vec<_>>();
+
+        variant.departures.sort_by_key(|(t, _)| *t);
+        variant.departures.dedup_by_key(|(t, _)| *t);
+        let (spawn_times, timetables) = variant.departures.into_iter().unzip();
+
+        let short_name = route
+            .route_short_name
+            .clone()
+            .or_else(|| route.route_long_name.clone())
+            .unwrap_or_else(|| route_id.clone());
+        let long_name = route
+            .route_long_name
+            .clone()
+            .unwrap_or_else(|| short_name.clone());
+        let gtfs_id = format!("{}:{}:{}", route_id, stop_ids[0], stop_ids.last().unwrap());
+        ids_used
+            .entry(gtfs_id.clone())
+            .or_insert_with(Vec::new)
+            .push((results.len(), spawn_times.len(), stop_ids.clone()));
+
+        results.push(RawGtfsRoute {
+            gtfs_id,
+            full_name: format!("{} to {}", long_name, raw_stops.last().unwrap().name),
+            short_name,
+            is_bus,
+            stops: raw_stops,
+            enters_from: variant.enters_from,
+            exits_to: variant.exits_to,
+            spawn_times,
+            timetables,
+        });
+    }
+    // Variants skipping different stops in the middle share a gtfs_id. The one with the most trips
+    // keeps it, and the others are told apart by their stops, so that the IDs don't depend on what
+    // other variants the feed has or what order it lists trips.
+    for (_, mut variants) in ids_used {
+        variants.sort_by_key(|(_, num_trips, stop_ids)| (Reverse(*num_trips), stop_ids.clone()));
+        for (idx, _, stop_ids) in variants.into_iter().skip(1) {
+            results[idx].gtfs_id =
+                format!("{}:{:08x}", results[idx].gtfs_id, hash_stops(&stop_ids));
+        }
+    }
+
+    timer.note(format!("Imported {} routes from GTFS", results.len()));
+    Ok(results)
+}
+
+struct Variant {
+    shape_id: Option<String>,
+    enters_from: Option<Pt2D>,
+    exits_to: Option<Pt2D>,
+    departures: Vec<(Time, Vec<(Duration, Duration)>)>,
+}
+
+// Returns None if the file is optional and missing.
+fn read<R: Read + Seek, T: DeserializeOwned, F: Fn(&T) -> bool>(
+    archive: &mut zip::ZipArchive<R>,
+    name: &str,
+    keep: F,
+) -> Result<Option<Vec<T>>, Box<dyn Error>> {
+    let file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => {
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(err.into());
+        }
+    };
+    // Lots of feeds are exported from Excel, which starts files with a byte order mark. Otherwise
+    // it winds up in the first column's name.
+    let mut file = BufReader::new(file);
+    if file.fill_buf()?.starts_with(b"\xEF\xBB\xBF") {
+        file.consume(3);
+    }
+    let mut results = Vec::new();
+    for rec in csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file)
+        .deserialize()
+    {
+        let rec: T = rec.map_err(|err| format!("{}: {}", name, err))?;
+        if keep(&rec) {
+            results.push(rec);
+        }
+    }
+    Ok(Some(results))
+}
+
+// Which service_ids run on the weekday with the most trips
+fn active_services<R: Read + Seek>(
+    archive: &mut zip::ZipArchive<R>,
+    trips_per_service: &HashMap<String, usize>,
+    timer: &mut Timer,
+) -> Result<BTreeSet<String>, Box<dyn Error>> {
+    let mut services_per_day: BTreeMap<NaiveDate, BTreeSet<String>> = BTreeMap::new();
+    for rec in
+        read::<_, CalendarRecord, _>(archive, "calendar.txt", |_| true)?.unwrap_or_else(Vec::new)
+    {
+        let mut date = parse_date(&rec.start_date)?;
+        let end = parse_date(&rec.end_date)?;
+        while date <= end {
+            if rec.runs_on(date.weekday()) {
+                services_per_day
+                    .entry(date)
+                    .or_insert_with(BTreeSet::new)
+                    .insert(rec.service_id.clone());
+            }
+            date = date.succ();
+        }
+    }
+    // Some feeds only use this file
+    for rec in read::<_, CalendarDateRecord, _>(archive, "calendar_dates.txt", |_| true)?
+        .unwrap_or_else(Vec::new)
+    {
+        let services = services_per_day
+            .entry(parse_date(&rec.date)?)
+            .or_insert_with(BTreeSet::new);
+        match rec.exception_type {
+            1 => {
+                services.insert(rec.service_id);
+            }
+            2 => {
+                services.remove(&rec.service_id);
+            }
+            x => {
+                return Err(format!("calendar_dates.txt has exception_type {}", x).into());
+            }
+        }
+    }
+
+    // Break ties with the earliest date
+    let (date, services) = services_per_day
+        .into_iter()
+        .filter(|(date, _)| !matches!(date.weekday(), Weekday::Sat | Weekday::Sun))
+        .max_by_key(|(date, services)| {
+            let num_trips: usize = services
+                .iter()
+                .map(|s| trips_per_service.get(s).cloned().unwrap_or(0))
+                .sum();
+            (num_trips, Reverse(*date))
+        })
+        .ok_or("no service runs on a weekday")?;
+    timer.note(format!("Using GTFS service from {}", date));
+    Ok(services)
+}
+
+fn parse_date(x: &str) -> Result<NaiveDate, Box<dyn Error>> {
+    NaiveDate::parse_from_str(x, "%Y%m%d").map_err(|err| format!("bad date {}: {}", x, err).into())
+}
+
+// Only buses and light rail are simulated.
+fn route_type_is_bus(route_type: usize) -> Option<bool> {
+    match route_type {
+        // Bus and trolleybus, plus the extended types for coaches and buses
+        3 | 11 | 200..=299 | 700..=800 => Some(true),
+        // Tram, subway, and rail
+        0 | 1 | 2 | 100..=199 | 400..=499 | 900..=999 => Some(false),
+        _ => None,
+    }
+}
+
+// Stops that aren't timepoints might not have times. Interpolate by the number of stops in
+// between, since shape_dist_traveled is optional.
+fn departure_time(times: &[StopTimeRecord], idx: usize) -> Option<Time> {
+    if let Some(t) = times[idx].time() {
+        return Some(t);
+    }
+    let (before_idx, before) = times[..idx]
+        .iter()
+        .enumerate()
+        .rev()
+        .find_map(|(i, st)| st.time().map(|t| (i, t)))?;
+    let (after_idx, after) = times[idx + 1..]
+        .iter()
+        .enumerate()
+        .find_map(|(i, st)| st.time().map(|t| (idx + 1 + i, t)))?;
+    let pct = ((idx - before_idx) as f64) / ((after_idx - before_idx) as f64);
+    Some(before + pct * (after - before))
+}
+
+// How far along the straight line from a point outside the map to one inside it crosses the
+// boundary, as a fraction
+fn pct_outside(boundary: &Polygon, outside: Pt2D, inside: Pt2D) -> f64 {
+    let (mut lo, mut hi) = (0.0, 1.0);
+    for _ in 0..20 {
+        let mid = (lo + hi) / 2.0;
+        let pt = Pt2D::new(
+            outside.x() + mid * (inside.x() - outside.x()),
+            outside.y() + mid * (inside.y() - outside.y()),
+        );
+        if boundary.contains_pt(pt) {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    hi
+}
+
+// FNV-1a, so the result is the same on every platform and Rust version
+fn hash_stops(stop_ids: &[String]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for id in stop_ids {
+        for byte in id.bytes().chain(std::iter::once(b',')) {
+            hash ^= u32::from(byte);
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+    }
+    hash
+}
+
+// When a trip is scheduled to arrive at and depart from each stop in [first, last], relative to
+// when it starts. Empty if any times are missing or out of order, like for trips running past
+// midnight.
+fn timetable(
+    times: &[StopTimeRecord],
+    first: usize,
+    last: usize,
+    start: Time,
+) -> Vec<(Duration, Duration)> {
+    let mut result = Vec::new();
+    let mut prev_departure = start;
+    for idx in first..=last {
+        let departure = match departure_time(times, idx) {
+            Some(t) => t,
+            None => {
+                return Vec::new();
+            }
+        };
+        // Trips starting at the first stop might show up a bit before they're supposed to leave
+        let arrival = times[idx]
+            .arrival()
+            .unwrap_or(departure)
+            .max(prev_departure);
+        if departure < arrival {
+            return Vec::new();
+        }
+        result.push((arrival - start, departure - start));
+        prev_departure = departure;
+    }
+    result
+}
+
+// The direction of the shape closest to the stop. Without a shape, just point at the next stop.
+fn heading(pts: &[Pt2D], idx: usize, shape: Option<&Vec<Pt2D>>) -> Angle {
+    if let Some(shape) = shape {
+        if let Some((pt1, pt2)) = shape
+            .windows(2)
+            .filter(|pair| pair[0] != pair[1])
+            .map(|pair| (pair[0], pair[1]))
+            .min_by_key(|(pt1, pt2)| dist_to_segment(pts[idx], *pt1, *pt2))
+        {
+            return pt1.angle_to(pt2);
+        }
+    }
+    if idx == pts.len() - 1 {
+        pts[idx - 1].angle_to(pts[idx])
+    } else {
+        pts[idx].angle_to(pts[idx + 1])
+    }
+}
+
+fn dist_to_segment(pt: Pt2D, pt1: Pt2D, pt2: Pt2D) -> geom::Distance {
+    let (dx, dy) = (pt2.x() - pt1.x(), pt2.y() - pt1.y());
+    let t = (((pt.x() - pt1.x()) * dx + (pt.y() - pt1.y()) * dy) / (dx * dx + dy * dy))
+        .max(0.0)
+        .min(1.0);
+    pt.dist_to(Pt2D::new(pt1.x() + t * dx, pt1.y() + t * dy))
+}
+
+#[derive(Deserialize)]
+struct StopRecord {
+    stop_id: String,
+    stop_name: Option<String>,
+    // Only optional for generic nodes and boarding areas, which trips don't refer to
+    stop_lat: Option<f64>,
+    stop_lon: Option<f64>,
+}
+
+impl StopRecord {
+    fn pt(&self, gps_bounds: &GPSBounds) -> Option<Pt2D> {
+        Some(Pt2D::from_gps(
+            LonLat::new(self.stop_lon?, self.stop_lat?),
+            gps_bounds,
+        ))
+    }
+}
+
+#[derive(Deserialize)]
+struct RouteRecord {
+    route_id: String,
+    route_short_name: Option<String>,
+    route_long_name: Option<String>,
+    route_type: usize,
+}
+
+#[derive(Deserialize)]
+struct TripRecord {
+    route_id: String,
+    service_id: String,
+    trip_id: String,
+    shape_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StopTimeRecord {
+    trip_id: String,
+    arrival_time: Option<String>,
+    departure_time: Option<String>,
+    stop_id: String,
+    stop_sequence: usize,
+}
+
+impl StopTimeRecord {
+    fn time(&self) -> Option<Time> {
+        parse_time(
+            self.departure_time
+                .as_ref()
+                .or_else(|| self.arrival_time.as_ref())?,
+        )
+    }
+
+    fn arrival(&self) -> Option<Time> {
+        parse_time(self.arrival_time.as_ref()?)
+    }
+}
+
+fn parse_time(x: &str) -> Option<Time> {
+    let mut time = Time::parse(x).ok()?;
+    // Times past midnight belong to the previous service day. Wrap them around, like
+    // importer/src/seattle.rs does.
+    if time >= Time::START_OF_DAY + Duration::hours(24) {
+        time = time - Duration::hours(24);
+    }
+    Some(time)
+}
+
+#[derive(Deserialize)]
+struct ShapeRecord {
+    shape_id: String,
+    shape_pt_lat: f64,
+    shape_pt_lon: f64,
+    shape_pt_sequence: usize,
+}
+
+#[derive(Deserialize)]
+struct CalendarRecord {
+    service_id: String,
+    monday: u8,
+    tuesday: u8,
+    wednesday: u8,
+    thursday: u8,
+    friday: u8,
+    saturday: u8,
+    sunday: u8,
+    start_date: String,
+    end_date: String,
+}
+
+impl CalendarRecord {
+    fn runs_on(&self, day: Weekday) -> bool {
+        let x = match day {
+            Weekday::Mon => self.monday,
+            Weekday::Tue => self.tuesday,
+            Weekday::Wed => self.wednesday,
+            Weekday::Thu => self.thursday,
+            Weekday::Fri => self.friday,
+            Weekday::Sat => self.saturday,
+            Weekday::Sun => self.sunday,
+        };
+        x == 1
+    }
+}
+
+#[derive(Deserialize)]
+struct CalendarDateRecord {
+    service_id: String,
+    date: String,
+    exception_type: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    // Stop A is west of the map, and the others are inside it. Trips T1 and T2 serve B, C, and D
+    // in the map, and T3 skips C. If extra_variant, T4 serves another stop between B and D.
+    fn import_feed(extra_variant: bool) -> Vec<RawGtfsRoute> {
+        let mut trips = vec!["route_id,service_id,trip_id"];
+        let mut stop_times = vec!["trip_id,arrival_time,departure_time,stop_id,stop_sequence"];
+        if extra_variant {
+            trips.push("R1,weekday,T4");
+            stop_times.extend(vec![
+                "T4,12:00:00,12:00:00,B,1",
+                "T4,12:02:00,12:02:00,B2,2",
+                "T4,12:05:00,12:05:00,D,3",
+            ]);
+        }
+        trips.extend(vec!["R1,weekday,T1", "R1,weekday,T2", "R1,weekday,T3"]);
+        stop_times.extend(vec![
+            "T1,08:00:00,08:00:00,A,1",
+            "T1,08:07:00,08:07:00,B,2",
+            "T1,08:10:00,08:10:00,C,3",
+            "T1,08:13:00,08:13:00,D,4",
+            "T2,09:00:00,09:00:00,B,1",
+            "T2,09:03:00,09:03:00,C,2",
+            "T2,09:06:00,09:06:00,D,3",
+            "T3,10:00:00,10:00:00,B,1",
+            "T3,10:05:00,10:05:00,D,2",
+        ]);
+        let files = vec![
+            (
+                "stops.txt",
+                vec![
+                    // Some feeds start with a byte order mark
+                    "\u{feff}stop_id,stop_name,stop_lat,stop_lon",
+                    "A,West,47.005,-122.005",
+                    "B,First,47.005,-121.998",
+                    "B2,Detour,47.004,-121.996",
+                    "C,Middle,47.005,-121.995",
+                    "D,Last,47.005,-121.992",
+                ],
+            ),
+            (
+                "routes.txt",
+                vec![
+                    "route_id,route_short_name,route_long_name,route_type",
+                    "R1,1,Crosstown,3",
+                ],
+            ),
+            ("trips.txt", trips),
+            ("stop_times.txt", stop_times),
+            (
+                "calendar.txt",
+                vec![
+                    "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,\
+                     start_date,end_date",
+                    "weekday,1,1,1,1,1,0,0,20200106,20200110",
+                ],
+            ),
+        ];
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, lines) in files {
+            zip.start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(lines.join("\n").as_bytes()).unwrap();
+        }
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let mut map = RawMap::blank("test", "test");
+        map.gps_bounds =
+            GPSBounds::from(vec![LonLat::new(-122.0, 47.0), LonLat::new(-121.99, 47.01)]);
+        let max_pt = map.gps_bounds.get_max_world_pt();
+        map.boundary_polygon = Polygon::rectangle(max_pt.x(), max_pt.y());
+
+        import_archive(
+            &mut zip::ZipArchive::new(Cursor::new(bytes)).unwrap(),
+            &map,
+            &mut Timer::throwaway(),
+        )
+        .unwrap()
+    }
+
+    fn stop_ids(route: &RawGtfsRoute) -> Vec<&str> {
+        route.stops.iter().map(|s| s.stop_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_gtfs_import() {
+        let routes = import_feed(false);
+        assert_eq!(routes.len(), 2);
+
+        let main = routes
+            .iter()
+            .find(|r| stop_ids(r) == vec!["B", "C", "D"])
+            .unwrap();
+        assert_eq!(main.gtfs_id, "R1:B:D");
+        assert_eq!(main.short_name, "1");
+        assert!(main.enters_from.is_some());
+        // T1 leaves A at 8:00 and reaches B at 8:07, and the boundary is 5/7 of the way there
+        assert_eq!(main.spawn_times.len(), 2);
+        let crossing = Time::START_OF_DAY + Duration::hours(8) + Duration::minutes(5);
+        assert!((main.spawn_times[0] - crossing).inner_seconds().abs() < 1.0);
+        assert_eq!(main.spawn_times[1], Time::START_OF_DAY + Duration::hours(9));
+        assert_eq!(
+            main.timetables[1],
+            vec![
+                (Duration::ZERO, Duration::ZERO),
+                (Duration::minutes(3), Duration::minutes(3)),
+                (Duration::minutes(6), Duration::minutes(6)),
+            ]
+        );
+
+        let express = routes
+            .iter()
+            .find(|r| stop_ids(r) == vec!["B", "D"])
+            .unwrap();
+        assert!(express.gtfs_id.starts_with("R1:B:D:"));
+        assert_eq!(
+            express.spawn_times,
+            vec![Time::START_OF_DAY + Duration::hours(10)]
+        );
+    }
+
+    #[test]
+    fn test_gtfs_ids_are_stable() {
+        let ids = |routes: Vec<RawGtfsRoute>| -> BTreeMap<Vec<String>, String> {
+            routes
+                .into_iter()
+                .map(|r| {
+                    (
+                        r.stops.iter().map(|s| s.stop_id.clone()).collect(),
+                        r.gtfs_id,
+                    )
+                })
+                .collect()
+        };
+        let before = ids(import_feed(false));
+        let after = ids(import_feed(true));
+        assert_eq!(after.len(), 3);
+        for (stops, id) in before {
+            assert_eq!(after[&stops], id);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 mod clip;
 mod extract;
+mod geotiff;
+mod gtfs;
 mod parking;
 mod reader;
 mod split_ways;
@@ -7,10 +9,13 @@ mod srtm;
 mod transit;
 
 use abstutil::Timer;
-use geom::{Distance, FindClosest, GPSBounds, LonLat, Pt2D, Ring};
+use geom::{Distance, FindClosest, GPSBounds, LonLat, PolyLine, Pt2D, Ring};
 use map_model::raw::RawMap;
 use map_model::{osm, MapConfig, NamePerLanguage};
 
+// How often to sample elevation along roads
+const ELEVATION_SAMPLE_SPACING: Distance = Distance::const_meters(10.0);
+
 pub struct Options {
     pub osm_input: String,
     pub city_name: String,
@@ -23,11 +28,14 @@ pub struct Options {
     pub onstreet_parking: OnstreetParking,
     pub public_offstreet_parking: PublicOffstreetParking,
     pub private_offstreet_parking: PrivateOffstreetParking,
-    // If provided, pull elevation data from this SRTM file. The SRTM parser is incorrect, so the
-    // results will be nonsense.
+    // If provided, pull elevation data from this SRTM .hgt tile or GeoTIFF. Sets the elevation of
+    // intersections and the profile along roads.
     pub elevation: Option<String>,
     // OSM railway=rail will be included as light rail if so. Cosmetic only.
     pub include_railroads: bool,
+    // If provided, import transit routes and timetables from this GTFS zip file, instead of using
+    // OSM route relations.
+    pub gtfs: Option<String>,
 }
 
 // What roads will have on-street parking lanes? Data from
@@ -94,6 +102,26 @@ pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
     }
     map.bus_routes = routes;
 
+    if let Some(ref path) = opts.gtfs {
+        match gtfs::import(path, &map, timer) {
+            Ok(routes) => {
+                timer.note(format!(
+                    "Replacing {} routes from OSM with {} from GTFS",
+                    map.bus_routes.len(),
+                    routes.len()
+                ));
+                map.bus_routes.clear();
+                map.gtfs_routes = routes;
+            }
+            Err(err) => {
+                timer.error(format!(
+                    "Couldn't import GTFS from {}, keeping routes from OSM: {}",
+                    path, err
+                ));
+            }
+        }
+    }
+
     use_amenities(&mut map, amenities, timer);
 
     parking::apply_parking(&mut map, &opts, timer);
@@ -129,15 +157,54 @@ fn use_amenities(
 }
 
 fn use_elevation(map: &mut RawMap, path: &str, timer: &mut Timer) {
-    timer.start("apply elevation data to intersections");
-    let elevation = srtm::Elevation::load(path).unwrap();
+    timer.start("apply elevation data");
+    let elevation = match srtm::Elevation::load(path) {
+        Ok(e) => e,
+        Err(err) => panic!("Couldn't load elevation from {}: {}", path, err),
+    };
+    let mut missing = 0;
     for i in map.intersections.values_mut() {
         // TODO Not sure why, but I've seen nodes from South Carolina wind up in the updated
         // Seattle extract. And I think there's a bug with clipping, because they survive to this
         // point. O_O
         if map.boundary_polygon.contains_pt(i.point) {
-            i.elevation = elevation.get(i.point.to_gps(&map.gps_bounds));
+            if let Some(e) = elevation.get(i.point.to_gps(&map.gps_bounds)) {
+                i.elevation = e;
+            } else {
+                missing += 1;
+            }
+        }
+    }
+    if missing > 0 {
+        timer.warn(format!(
+            "{} intersections aren't covered by {}; they'll be at sea level",
+            missing, path
+        ));
+    }
+
+    for r in map.roads.values_mut() {
+        let pl = PolyLine::unchecked_new(r.center_points.clone());
+        let mut dist = Distance::ZERO;
+        let mut profile = Vec::new();
+        loop {
+            let dist_along = dist.min(pl.length());
+            if let Some(e) = pl
+                .dist_along(dist_along)
+                .ok()
+                .and_then(|(pt, _)| elevation.get(pt.to_gps(&map.gps_bounds)))
+            {
+                profile.push((dist_along, e));
+            } else {
+                // Fall back to interpolating between the intersections
+                profile.clear();
+                break;
+            }
+            if dist_along == pl.length() {
+                break;
+            }
+            dist += ELEVATION_SAMPLE_SPACING;
         }
+        r.elevation_profile = profile;
     }
-    timer.stop("apply elevation data to intersections");
+    timer.stop("apply elevation data");
 }
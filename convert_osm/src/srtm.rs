@@ -1,50 +1,218 @@
 use byteorder::{BigEndian, ReadBytesExt};
 use geom::{Distance, LonLat};
 use std::fs::File;
-use std::io;
-
-// TODO Make sure this actually works, and link to references describing the format.
-// - It's VERY wrong! Look at 19th and Boyer, and Delmar.
-// TODO Use http://gis.ess.washington.edu/data/raster/tenmeter/byquad/seattle/index.html or
-// something else instead?
-
-// Assuming the 3-second arcs
-const GRID_DIM: usize = 1201;
+use std::io::{self, BufReader, Error, ErrorKind};
 
+// A regular grid of elevation samples (a digital elevation model) in WGS84 degrees. Reads SRTM
+// .hgt tiles (https://dds.cr.usgs.gov/srtm/version2_1/Documentation/SRTM_Topo.pdf) and simple
+// GeoTIFFs.
 pub struct Elevation {
-    lon_offset: f64,
-    lat_offset: f64,
-    data: Vec<i16>,
+    // The center of the northwest sample
+    top_left: LonLat,
+    // Degrees between samples
+    lon_step: f64,
+    lat_step: f64,
+    width: usize,
+    height: usize,
+    // Row-major, starting from the north. In meters, NaN where the data is missing.
+    data: Vec<f32>,
 }
 
 impl Elevation {
+    pub fn new(
+        top_left: LonLat,
+        lon_step: f64,
+        lat_step: f64,
+        width: usize,
+        height: usize,
+        data: Vec<f32>,
+    ) -> Result<Elevation, io::Error> {
+        if width < 2 || height < 2 || data.len() != width * height {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} samples don't make a {}x{} elevation grid",
+                    data.len(),
+                    width,
+                    height
+                ),
+            ));
+        }
+        Ok(Elevation {
+            top_left,
+            lon_step,
+            lat_step,
+            width,
+            height,
+            data,
+        })
+    }
+
     pub fn load(path: &str) -> Result<Elevation, io::Error> {
         println!("Reading elevation data from {}", path);
-        let mut f = File::open(path).unwrap();
-
-        let mut e = Elevation {
-            // TODO dont hardcode
-            lon_offset: -122.0,
-            lat_offset: 47.0,
-            data: Vec::with_capacity(GRID_DIM.pow(2)),
-        };
-        // TODO off by one?
-        for _ in 0..GRID_DIM.pow(2) {
-            e.data.push(f.read_i16::<BigEndian>().unwrap());
+        let lower = path.to_lowercase();
+        if lower.ends_with(".hgt") {
+            load_hgt(path)
+        } else if lower.ends_with(".tif") || lower.ends_with(".tiff") {
+            crate::geotiff::load(path)
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} isn't a .hgt or GeoTIFF file", path),
+            ))
         }
-        Ok(e)
     }
 
-    pub fn get(&self, pt: LonLat) -> Distance {
-        // TODO assert the (lon, lat) match the offsets
-        // TODO not tons of confidence in any of this.
-        // TODO interpolate from the 4 matching tiles?
-        let x = ((pt.x() - self.lon_offset).abs() * (GRID_DIM as f64)) as usize;
-        let y = ((pt.y() - self.lat_offset).abs() * (GRID_DIM as f64)) as usize;
-        let i = x + (y * GRID_DIM);
-        if i >= self.data.len() {
-            panic!("srtm lookup for out-of-bounds {}", pt);
+    // Bilinearly interpolates between the 4 surrounding samples. None if the point is outside the
+    // grid or any of those samples are missing.
+    pub fn get(&self, pt: LonLat) -> Option<Distance> {
+        let x = (pt.x() - self.top_left.x()) / self.lon_step;
+        let y = (self.top_left.y() - pt.y()) / self.lat_step;
+        if x < 0.0 || y < 0.0 || x > (self.width - 1) as f64 || y > (self.height - 1) as f64 {
+            return None;
+        }
+        let x0 = (x.floor() as usize).min(self.width - 2);
+        let y0 = (y.floor() as usize).min(self.height - 2);
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+
+        let sample = |col: usize, row: usize| f64::from(self.data[row * self.width + col]);
+        let top = sample(x0, y0) * (1.0 - fx) + sample(x0 + 1, y0) * fx;
+        let bottom = sample(x0, y0 + 1) * (1.0 - fx) + sample(x0 + 1, y0 + 1) * fx;
+        let value = top * (1.0 - fy) + bottom * fy;
+        if value.is_nan() {
+            None
+        } else {
+            Some(Distance::meters(value))
         }
-        Distance::meters(f64::from(self.data[i]))
+    }
+}
+
+// SRTM tiles are named after their southwest corner, like N47W122.hgt, and cover one degree. They
+// have 1201x1201 (3 arc-second) or 3601x3601 (1 arc-second) samples, overlapping the neighboring
+// tiles by one row and column.
+fn load_hgt(path: &str) -> Result<Elevation, io::Error> {
+    let (lon, lat) = parse_hgt_name(path).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} isn't named like N47W122.hgt", path),
+        )
+    })?;
+
+    let f = File::open(path)?;
+    let num_samples = (f.metadata()?.len() / 2) as usize;
+    let dim = (num_samples as f64).sqrt() as usize;
+    if dim * dim != num_samples {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} isn't a square grid of samples", path),
+        ));
+    }
+
+    let mut reader = BufReader::new(f);
+    let mut data = Vec::with_capacity(num_samples);
+    for _ in 0..num_samples {
+        let value = reader.read_i16::<BigEndian>()?;
+        // Voids
+        data.push(if value == -32768 {
+            std::f32::NAN
+        } else {
+            f32::from(value)
+        });
+    }
+    let step = 1.0 / ((dim - 1) as f64);
+    Elevation::new(LonLat::new(lon, lat + 1.0), step, step, dim, dim, data)
+}
+
+// Returns the (longitude, latitude) of the southwest corner
+fn parse_hgt_name(path: &str) -> Option<(f64, f64)> {
+    let name = std::path::Path::new(path)
+        .file_name()?
+        .to_str()?
+        .to_uppercase();
+    let name = name.get(0..7)?;
+    let lat = name.get(1..3)?.parse::<f64>().ok()?;
+    let lon = name.get(4..7)?.parse::<f64>().ok()?;
+    let lat = match &name[0..1] {
+        "N" => lat,
+        "S" => -lat,
+        _ => {
+            return None;
+        }
+    };
+    let lon = match &name[3..4] {
+        "E" => lon,
+        "W" => -lon,
+        _ => {
+            return None;
+        }
+    };
+    Some((lon, lat))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hgt_name() {
+        assert_eq!(
+            parse_hgt_name("data/input/N47W122.hgt"),
+            Some((-122.0, 47.0))
+        );
+        assert_eq!(parse_hgt_name("s33e151.HGT"), Some((151.0, -33.0)));
+        assert_eq!(parse_hgt_name("X47W122.hgt"), None);
+        assert_eq!(parse_hgt_name("N47.hgt"), None);
+        assert_eq!(parse_hgt_name("N47W12"), None);
+    }
+
+    #[test]
+    fn test_bilinear() {
+        let elevation = Elevation::new(
+            LonLat::new(10.0, 50.0),
+            1.0,
+            1.0,
+            2,
+            2,
+            vec![0.0, 10.0, 20.0, 30.0],
+        )
+        .unwrap();
+        // Exactly on the samples
+        assert_eq!(
+            elevation.get(LonLat::new(10.0, 50.0)),
+            Some(Distance::meters(0.0))
+        );
+        assert_eq!(
+            elevation.get(LonLat::new(11.0, 50.0)),
+            Some(Distance::meters(10.0))
+        );
+        assert_eq!(
+            elevation.get(LonLat::new(11.0, 49.0)),
+            Some(Distance::meters(30.0))
+        );
+        // In between
+        assert_eq!(
+            elevation.get(LonLat::new(10.5, 49.5)),
+            Some(Distance::meters(15.0))
+        );
+        assert_eq!(
+            elevation.get(LonLat::new(10.25, 49.75)),
+            Some(Distance::meters(7.5))
+        );
+        // Off the grid
+        assert_eq!(elevation.get(LonLat::new(9.5, 49.5)), None);
+        assert_eq!(elevation.get(LonLat::new(10.5, 50.5)), None);
+
+        let with_void = Elevation::new(
+            LonLat::new(10.0, 50.0),
+            1.0,
+            1.0,
+            2,
+            2,
+            vec![0.0, std::f32::NAN, 20.0, 30.0],
+        )
+        .unwrap();
+        assert_eq!(with_void.get(LonLat::new(10.5, 49.5)), None);
+
+        assert!(Elevation::new(LonLat::new(10.0, 50.0), 1.0, 1.0, 2, 2, vec![0.0; 3]).is_err());
     }
 }
@@ -1,41 +1,80 @@
 use crate::app::App;
 use crate::common::heatmap::Grid;
+use crate::common::ColorLegend;
 use crate::game::{State, Transition};
-use geom::{Distance, Polygon};
-use map_model::{connectivity, BuildingID};
+use geom::{Duration, Polygon};
+use map_model::connectivity::{IsochroneGraph, TravelMode};
+use map_model::BuildingID;
 use widgetry::{
-    Btn, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line, Outcome,
-    Panel, VerticalAlignment, Widget,
+    Btn, Choice, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line,
+    Outcome, Panel, VerticalAlignment, Widget,
 };
 
+// Contours at each of these travel times
+const THRESHOLDS: [(f64, &str); 3] = [
+    (5.0, "within 5 minutes"),
+    (15.0, "within 15 minutes"),
+    (30.0, "within 30 minutes"),
+];
+
 // TODO Move cursor live
 pub struct IsochroneViewer {
+    start: BuildingID,
+    mode: TravelMode,
     panel: Panel,
     draw: Drawable,
 }
 
 impl IsochroneViewer {
     pub fn new(ctx: &mut EventCtx, app: &App, start: BuildingID) -> Box<dyn State> {
-        let draw = make_isochrone(ctx, app, start);
-        Box::new(IsochroneViewer {
-            panel: Panel::new(Widget::col(vec![
-                Widget::row(vec![
-                    Line("Isochrone").small_heading().draw(ctx),
-                    Btn::text_fg("X")
-                        .build(ctx, "close", Key::Escape)
-                        .align_right(),
-                ]),
-                // TODO legend, mode picker
-            ]))
-            .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
-            .build(ctx),
+        Box::new(IsochroneViewer::with_mode(
+            ctx,
+            app,
+            start,
+            TravelMode::Walk,
+        ))
+    }
+
+    fn with_mode(
+        ctx: &mut EventCtx,
+        app: &App,
+        start: BuildingID,
+        mode: TravelMode,
+    ) -> IsochroneViewer {
+        let draw = make_isochrone(ctx, app, start, mode);
+        let mut col = vec![
+            Widget::row(vec![
+                Line("Isochrone").small_heading().draw(ctx),
+                Btn::text_fg("X")
+                    .build(ctx, "close", Key::Escape)
+                    .align_right(),
+            ]),
+            Widget::dropdown(
+                ctx,
+                "mode",
+                mode,
+                TravelMode::all()
+                    .into_iter()
+                    .map(|m| Choice::new(m.noun(), m))
+                    .collect(),
+            ),
+        ];
+        for (color, (_, label)) in colors().into_iter().zip(THRESHOLDS.iter()) {
+            col.push(ColorLegend::row(ctx, color, *label));
+        }
+        IsochroneViewer {
+            start,
+            mode,
+            panel: Panel::new(Widget::col(col))
+                .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
+                .build(ctx),
             draw,
-        })
+        }
     }
 }
 
 impl State for IsochroneViewer {
-    fn event(&mut self, ctx: &mut EventCtx, _: &mut App) -> Transition {
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
         ctx.canvas_movement();
 
         match self.panel.event(ctx) {
@@ -45,6 +84,12 @@ impl State for IsochroneViewer {
                 }
                 _ => unreachable!(),
             },
+            Outcome::Changed => {
+                let mode = self.panel.dropdown_value("mode");
+                if mode != self.mode {
+                    *self = IsochroneViewer::with_mode(ctx, app, self.start, mode);
+                }
+            }
             _ => {}
         }
 
@@ -57,38 +102,54 @@ impl State for IsochroneViewer {
     }
 }
 
-fn make_isochrone(ctx: &mut EventCtx, app: &App, start: BuildingID) -> Drawable {
-    let bounds = app.primary.map.get_bounds();
+fn colors() -> Vec<Color> {
+    vec![
+        Color::GREEN.alpha(0.5),
+        Color::ORANGE.alpha(0.5),
+        Color::RED.alpha(0.5),
+    ]
+}
+
+fn make_isochrone(ctx: &mut EventCtx, app: &App, start: BuildingID, mode: TravelMode) -> Drawable {
+    let map = &app.primary.map;
+    let bounds = map.get_bounds();
     let resolution_m = 100.0;
-    // Distance in meters
+    // Minutes, with anything unreachable past the last threshold
+    let unreachable = THRESHOLDS[THRESHOLDS.len() - 1].0 + 1.0;
     let mut grid: Grid<f64> = Grid::new(
         (bounds.width() / resolution_m).ceil() as usize,
         (bounds.height() / resolution_m).ceil() as usize,
-        0.0,
+        unreachable,
     );
 
-    for (b, cost) in connectivity::all_costs_from(&app.primary.map, start) {
-        let pt = app.primary.map.get_b(b).polygon.center();
+    let time_limit = Duration::minutes(THRESHOLDS[THRESHOLDS.len() - 1].0 as usize);
+    for (b, cost) in IsochroneGraph::new(map, mode).all_costs_from(
+        map,
+        start,
+        app.primary.sim.time(),
+        time_limit,
+    ) {
+        let pt = map.get_b(b).polygon.center();
         let idx = grid.idx(
             ((pt.x() - bounds.min_x) / resolution_m) as usize,
             ((pt.y() - bounds.min_y) / resolution_m) as usize,
         );
         // Don't add! If two buildings map to the same cell, should pick a finer resolution.
-        grid.data[idx] = cost.inner_meters();
+        grid.data[idx] = cost.inner_seconds() / 60.0;
     }
 
-    let thresholds = vec![
-        0.1,
-        Distance::miles(0.5).inner_meters(),
-        Distance::miles(3.0).inner_meters(),
-        Distance::miles(6.0).inner_meters(),
-    ];
-    let colors = vec![
-        Color::BLACK.alpha(0.5),
-        Color::GREEN.alpha(0.5),
-        Color::BLUE.alpha(0.5),
-        Color::RED.alpha(0.5),
-    ];
+    // Contours cover everything at or above each threshold, so flip the times to get the areas
+    // reachable within them, drawing the farthest first.
+    for x in grid.data.iter_mut() {
+        *x = unreachable - *x;
+    }
+    let thresholds: Vec<f64> = THRESHOLDS
+        .iter()
+        .rev()
+        .map(|(minutes, _)| unreachable - minutes)
+        .collect();
+    let mut colors = colors();
+    colors.reverse();
     let c = contour::ContourBuilder::new(grid.width as u32, grid.height as u32, false);
     let mut batch = GeomBatch::new();
     for (feature, color) in c
@@ -1,6 +1,6 @@
 use crate::app::App;
 use crate::helpers::ID;
-use map_model::{Map, PathConstraints};
+use map_model::{Map, PathConstraints, RoutingProfile};
 use sim::{AgentID, Sim};
 use widgetry::{EventCtx, GfxCtx, Key, Line, Text};
 
@@ -59,7 +59,11 @@ impl ObjectDebugger {
                             let mut costs = Vec::new();
                             for turn in map.get_turns_to_lane(l.id) {
                                 costs.push(map_model::connectivity::driving_cost(
-                                    l, turn, constraint, map,
+                                    l,
+                                    turn,
+                                    constraint,
+                                    RoutingProfile::Fastest,
+                                    map,
                                 ));
                             }
                             println!("Costs for {:?}: {:?}", constraint, costs);
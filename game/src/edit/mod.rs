@@ -24,7 +24,7 @@ use crate::render::DrawMap;
 use crate::sandbox::{GameplayMode, SandboxMode, TimeWarpScreen};
 use abstutil::Timer;
 use geom::Speed;
-use map_model::{EditCmd, IntersectionID, LaneID, LaneType, MapEdits};
+use map_model::{EditCmd, IntersectionID, LaneID, LaneType, MapEdits, ParkingArea};
 use maplit::btreeset;
 use sim::DontDrawAgents;
 use std::collections::BTreeSet;
@@ -146,6 +146,7 @@ impl State for EditMode {
                     app.primary.current_selection = None;
                 }
             } else if let Some(ID::Road(_)) = app.primary.current_selection {
+            } else if let Some(ID::BusStop(_)) = app.primary.current_selection {
             } else {
                 app.primary.current_selection = None;
             }
@@ -326,6 +327,14 @@ impl State for EditMode {
                     return Transition::Push(LaneEditor::new(ctx, app, l, self.mode.clone()));
                 }
             }
+            if let Some(ID::BusStop(bs)) = app.primary.current_selection {
+                if app
+                    .per_obj
+                    .left_click(ctx, "create a new route starting here")
+                {
+                    return Transition::Push(RouteEditor::new_route(ctx, app, bs));
+                }
+            }
         }
 
         match self.tool_panel.event(ctx) {
@@ -804,7 +813,15 @@ fn cmd_to_id(cmd: &EditCmd) -> Option<ID> {
     match cmd {
         EditCmd::ChangeRoad { r, .. } => Some(ID::Road(*r)),
         EditCmd::ChangeIntersection { i, .. } => Some(ID::Intersection(*i)),
-        EditCmd::ChangeRouteSchedule { .. } => None,
+        EditCmd::ChangeRouteSchedule { .. }
+        | EditCmd::ChangeBusRoute { .. }
+        | EditCmd::CreateBusRoute { .. }
+        | EditCmd::DeleteBusRoute { .. } => None,
+        EditCmd::ChangeParkingPolicy { area, .. } => Some(match area {
+            ParkingArea::Lane(l) => ID::Lane(*l),
+            ParkingArea::Lot(pl) => ID::ParkingLot(*pl),
+            ParkingArea::Garage(b) => ID::Building(*b),
+        }),
     }
 }
 
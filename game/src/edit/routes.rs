@@ -1,16 +1,30 @@
-use crate::app::App;
+use crate::app::{App, ShowEverything};
+use crate::common::CommonState;
 use crate::edit::apply_map_edits;
-use crate::game::{State, Transition};
-use geom::{Duration, Time};
-use map_model::{BusRouteID, EditCmd};
+use crate::game::{PopupMsg, State, Transition};
+use crate::helpers::ID;
+use geom::{Distance, Duration, Time};
+use map_model::{
+    BusRouteID, BusStopID, EditBusRoute, EditCmd, IntersectionID, NewBusRoute, PathConstraints,
+};
+use sim::DontDrawAgents;
 use widgetry::{
-    Btn, EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel, Spinner, TextExt,
-    VerticalAlignment, Widget,
+    Btn, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line, Outcome,
+    Panel, RewriteColor, Spinner, Text, TextExt, VerticalAlignment, Widget,
 };
 
+// Change the stops and schedule of an existing route, or create a new one. Routes can only use
+// existing stops; rerouting onto a different road means picking stops there.
 pub struct RouteEditor {
     panel: Panel,
-    route: BusRouteID,
+    // None when creating a new route
+    route: Option<BusRouteID>,
+    route_type: PathConstraints,
+    path: EditBusRoute,
+    labels: Drawable,
+    // What the frequency spinner started at. Only replace the schedule if the player changes it,
+    // since real timetables usually aren't evenly spaced.
+    orig_freq_mins: isize,
 }
 
 impl RouteEditor {
@@ -18,32 +32,292 @@ impl RouteEditor {
         app.primary.current_selection = None;
 
         let route = app.primary.map.get_br(id);
-        Box::new(RouteEditor {
-            panel: Panel::new(Widget::col(vec![
-                Widget::row(vec![
-                    Line("Route editor").small_heading().draw(ctx),
-                    Btn::plaintext("X")
-                        .build(ctx, "close", Key::Escape)
-                        .align_right(),
-                ]),
-                Line(&route.full_name).draw(ctx),
-                // TODO This UI needs design, just something to start plumbing the edits
+        // Guess the current frequency from the first two departures
+        let freq_mins = if route.spawn_times.len() >= 2 {
+            ((route.spawn_times[1] - route.spawn_times[0]).inner_seconds() / 60.0).round() as isize
+        } else {
+            60
+        }
+        .max(1)
+        .min(120);
+        let mut editor = RouteEditor {
+            panel: RouteEditor::make_panel(ctx, Line(&route.full_name).draw(ctx), freq_mins),
+            route: Some(id),
+            route_type: route.route_type,
+            path: app.primary.map.get_br_edit(id),
+            labels: ctx.upload(GeomBatch::new()),
+            orig_freq_mins: freq_mins,
+        };
+        editor.update(ctx, app);
+        Box::new(editor)
+    }
+
+    pub fn new_route(ctx: &mut EventCtx, app: &mut App, first_stop: BusStopID) -> Box<dyn State> {
+        app.primary.current_selection = None;
+
+        let route_type = if app.primary.map.get_bs(first_stop).is_train_stop {
+            PathConstraints::Train
+        } else {
+            PathConstraints::Bus
+        };
+        let mut editor = RouteEditor {
+            panel: RouteEditor::make_panel(
+                ctx,
                 Widget::row(vec![
-                    "Frequency in minutes".draw_text(ctx),
-                    Spinner::new(ctx, (1, 120), 60).named("freq_mins"),
+                    "Name:".draw_text(ctx).centered_vert(),
+                    Widget::text_entry(ctx, String::new(), true).named("name"),
                 ]),
-                Btn::text_bg2("Apply").build_def(ctx, Key::Enter),
-            ]))
-            .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
-            .build(ctx),
-            route: id,
-        })
+                30,
+            ),
+            route: None,
+            route_type,
+            path: EditBusRoute {
+                stops: vec![first_stop],
+                start_border: None,
+                end_border: None,
+            },
+            labels: ctx.upload(GeomBatch::new()),
+            orig_freq_mins: 30,
+        };
+        editor.update(ctx, app);
+        Box::new(editor)
+    }
+
+    fn make_panel(ctx: &mut EventCtx, name: Widget, freq_mins: isize) -> Panel {
+        Panel::new(Widget::col(vec![
+            Widget::row(vec![
+                Line("Route editor").small_heading().draw(ctx),
+                Btn::plaintext("X")
+                    .build(ctx, "close", Key::Escape)
+                    .align_right(),
+            ]),
+            name,
+            Widget::row(vec![
+                "Frequency in minutes".draw_text(ctx),
+                Spinner::new(ctx, (1, 120), freq_mins).named("freq_mins"),
+            ]),
+            Text::from_multiline(vec![
+                Line("Click a stop to add or remove it."),
+                Line("Click a border to start or end the route there."),
+                Line("Vehicles take the fastest way between stops."),
+            ])
+            .draw(ctx),
+            Widget::nothing().named("status"),
+        ]))
+        .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
+        .build(ctx)
+    }
+
+    fn update(&mut self, ctx: &mut EventCtx, app: &App) {
+        let map = &app.primary.map;
+        let mut batch = GeomBatch::new();
+        for (idx, bs) in self.path.stops.iter().enumerate() {
+            batch.append(
+                Text::from(Line((idx + 1).to_string()))
+                    .bg(Color::PURPLE)
+                    .render_to_batch(ctx.prerender)
+                    .color(RewriteColor::ChangeAlpha(0.8))
+                    .scale(0.3)
+                    .centered_on(map.get_bs(*bs).driving_pos.pt(map)),
+            );
+        }
+        for (label, i) in vec![
+            ("start", self.path.start_border),
+            ("end", self.path.end_border),
+        ] {
+            if let Some(i) = i {
+                batch.append(
+                    Text::from(Line(label))
+                        .bg(Color::PURPLE)
+                        .render_to_batch(ctx.prerender)
+                        .color(RewriteColor::ChangeAlpha(0.8))
+                        .scale(0.3)
+                        .centered_on(map.get_i(i).polygon.center()),
+                );
+            }
+        }
+        self.labels = ctx.upload(batch);
+
+        let mut txt = Text::from(Line(format!("{} stops", self.path.stops.len())));
+        let btn = match map.check_bus_route(self.route_type, &self.path) {
+            Ok(()) => Btn::text_bg2("Apply").build_def(ctx, Key::Enter),
+            Err(err) => {
+                txt.add(Line(err).fg(Color::hex("#FF5E5E")));
+                Btn::text_bg2("Apply").inactive(ctx)
+            }
+        };
+        self.panel.replace(
+            ctx,
+            "status",
+            Widget::col(vec![txt.draw(ctx), btn]).named("status"),
+        );
+    }
+
+    fn click_stop(&mut self, app: &App, bs: BusStopID) {
+        if let Some(idx) = self.path.stops.iter().position(|x| *x == bs) {
+            self.path.stops.remove(idx);
+            return;
+        }
+
+        // Insert the stop wherever it makes the smallest (straight-line) detour
+        let map = &app.primary.map;
+        let pt = map.get_bs(bs).driving_pos.pt(map);
+        let pts: Vec<_> = self
+            .path
+            .stops
+            .iter()
+            .map(|x| map.get_bs(*x).driving_pos.pt(map))
+            .collect();
+        let mut best: Option<(Distance, usize)> = None;
+        for idx in 0..=pts.len() {
+            let detour = match (idx.checked_sub(1).map(|i| pts[i]), pts.get(idx)) {
+                (Some(prev), Some(next)) => {
+                    prev.dist_to(pt) + pt.dist_to(*next) - prev.dist_to(*next)
+                }
+                (Some(prev), None) => prev.dist_to(pt),
+                (None, Some(next)) => pt.dist_to(*next),
+                (None, None) => Distance::ZERO,
+            };
+            if best.map(|(d, _)| detour < d).unwrap_or(true) {
+                best = Some((detour, idx));
+            }
+        }
+        self.path.stops.insert(best.unwrap().1, bs);
+    }
+
+    // What clicking on a border would do, if anything
+    fn border_action(&self, app: &App, i: IntersectionID) -> Option<&'static str> {
+        let i = app.primary.map.get_i(i);
+        if self.path.start_border == Some(i.id) {
+            Some("don't start the route here")
+        } else if self.path.end_border == Some(i.id) {
+            Some("don't end the route here")
+        } else if i.is_incoming_border()
+            && (self.path.start_border.is_none() || !i.is_outgoing_border())
+        {
+            Some("start the route here")
+        } else if i.is_outgoing_border() {
+            Some("end the route here")
+        } else {
+            None
+        }
+    }
+
+    fn apply(&self, ctx: &mut EventCtx, app: &mut App) {
+        let freq_mins = self.panel.spinner("freq_mins");
+        let freq = Duration::minutes(freq_mins as usize);
+        let mut now = Time::START_OF_DAY;
+        let mut spawn_times = Vec::new();
+        while now <= Time::START_OF_DAY + Duration::hours(24) {
+            spawn_times.push(now);
+            now += freq;
+        }
+
+        let map = &app.primary.map;
+        let mut edits = map.get_edits().clone();
+        if let Some(id) = self.route {
+            let old = map.get_br_edit(id);
+            if old != self.path {
+                edits.commands.push(EditCmd::ChangeBusRoute {
+                    id,
+                    old,
+                    new: self.path.clone(),
+                });
+            }
+            if freq_mins != self.orig_freq_mins && map.get_br(id).spawn_times != spawn_times {
+                edits.commands.push(EditCmd::ChangeRouteSchedule {
+                    id,
+                    old: map.get_br(id).spawn_times.clone(),
+                    new: spawn_times,
+                });
+            }
+        } else {
+            let mut name = self.panel.text_box("name");
+            if name.is_empty() {
+                name = format!("New route {}", map.all_bus_routes().len() + 1);
+            }
+            edits.commands.push(EditCmd::CreateBusRoute {
+                id: BusRouteID(map.all_bus_routes().len()),
+                route: NewBusRoute {
+                    full_name: name.clone(),
+                    short_name: name,
+                    route_type: self.route_type,
+                    path: self.path.clone(),
+                    spawn_times,
+                },
+            });
+        }
+        apply_map_edits(ctx, app, edits);
     }
 }
 
 impl State for RouteEditor {
     fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
         ctx.canvas_movement();
+        if ctx.redo_mouseover() {
+            app.primary.current_selection = app.calculate_current_selection(
+                ctx,
+                &DontDrawAgents {},
+                &ShowEverything::new(),
+                false,
+                true,
+                false,
+            );
+            match app.primary.current_selection {
+                Some(ID::BusStop(bs)) => {
+                    let map = &app.primary.map;
+                    let lane = map.get_l(map.get_bs(bs).driving_pos.lane());
+                    if !self.route_type.can_use(lane, map) {
+                        app.primary.current_selection = None;
+                    }
+                }
+                Some(ID::Intersection(i)) => {
+                    if !app.primary.map.get_i(i).is_border() {
+                        app.primary.current_selection = None;
+                    }
+                }
+                _ => {
+                    app.primary.current_selection = None;
+                }
+            }
+        }
+
+        match app.primary.current_selection {
+            Some(ID::BusStop(bs)) => {
+                let label = if self.path.stops.contains(&bs) {
+                    "remove this stop"
+                } else {
+                    "add this stop"
+                };
+                if app.per_obj.left_click(ctx, label) {
+                    self.click_stop(app, bs);
+                    self.update(ctx, app);
+                }
+            }
+            Some(ID::Intersection(i)) => {
+                if let Some(label) = self.border_action(app, i) {
+                    if app.per_obj.left_click(ctx, label) {
+                        match label {
+                            "don't start the route here" => {
+                                self.path.start_border = None;
+                            }
+                            "don't end the route here" => {
+                                self.path.end_border = None;
+                            }
+                            "start the route here" => {
+                                self.path.start_border = Some(i);
+                            }
+                            "end the route here" => {
+                                self.path.end_border = Some(i);
+                            }
+                            _ => unreachable!(),
+                        }
+                        self.update(ctx, app);
+                    }
+                }
+            }
+            _ => {}
+        }
 
         match self.panel.event(ctx) {
             Outcome::Clicked(x) => match x.as_ref() {
@@ -51,22 +325,24 @@ impl State for RouteEditor {
                     return Transition::Pop;
                 }
                 "Apply" => {
-                    let freq = Duration::minutes(self.panel.spinner("freq_mins") as usize);
-                    let mut now = Time::START_OF_DAY;
-                    let mut hourly_times = Vec::new();
-                    while now <= Time::START_OF_DAY + Duration::hours(24) {
-                        hourly_times.push(now);
-                        now += freq;
+                    // TransitSimState only learns about a route's stops when the first bus starts,
+                    // and buses are only scheduled when the sim starts, so live edits can't handle
+                    // new stops or routes yet.
+                    let changes_stops = match self.route {
+                        Some(id) => app.primary.map.get_br_edit(id) != self.path,
+                        None => true,
+                    };
+                    if changes_stops && app.primary.current_flags.sim_flags.opts.live_map_edits {
+                        return Transition::Push(PopupMsg::new(
+                            ctx,
+                            "Error",
+                            vec![
+                                "Changing stops or creating routes isn't supported with live map \
+                                 edits",
+                            ],
+                        ));
                     }
-
-                    let mut edits = app.primary.map.get_edits().clone();
-                    edits.commands.push(EditCmd::ChangeRouteSchedule {
-                        id: self.route,
-                        old: app.primary.map.get_br(self.route).spawn_times.clone(),
-                        new: hourly_times,
-                    });
-                    apply_map_edits(ctx, app, edits);
-
+                    self.apply(ctx, app);
                     return Transition::Pop;
                 }
                 _ => unreachable!(),
@@ -77,7 +353,10 @@ impl State for RouteEditor {
         Transition::Keep
     }
 
-    fn draw(&self, g: &mut GfxCtx, _: &App) {
+    fn draw(&self, g: &mut GfxCtx, app: &App) {
         self.panel.draw(g);
+        CommonState::draw_osd(g, app);
+
+        g.redraw(&self.labels);
     }
 }
@@ -166,7 +166,7 @@ impl State for StopSignEditor {
                                 self.id,
                                 &mut Timer::throwaway(),
                             )
-                            .export(&app.primary.map),
+                            .export_edit(&app.primary.map),
                         ),
                     });
                     apply_map_edits(ctx, app, edits);
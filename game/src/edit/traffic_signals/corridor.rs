@@ -0,0 +1,161 @@
+use crate::app::{App, ShowEverything};
+use crate::common::CommonState;
+use crate::edit::traffic_signals::fade_irrelevant;
+use crate::edit::TrafficSignalEditor;
+use crate::game::{PopupMsg, State, Transition};
+use crate::helpers::ID;
+use geom::Speed;
+use map_model::{ControlTrafficSignal, IntersectionID};
+use sim::DontDrawAgents;
+use std::collections::BTreeSet;
+use widgetry::{
+    Btn, Checkbox, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line,
+    Outcome, Panel, RewriteColor, Spinner, Text, TextExt, VerticalAlignment, Widget,
+};
+
+// Pick an ordered corridor out of the signals being edited, then calculate offsets for a green
+// wave along it.
+pub struct GreenWave {
+    members: BTreeSet<IntersectionID>,
+    corridor: Vec<IntersectionID>,
+    panel: Panel,
+    labels: Drawable,
+}
+
+impl GreenWave {
+    pub fn new(ctx: &mut EventCtx, app: &App, members: BTreeSet<IntersectionID>) -> Box<dyn State> {
+        let mut state = GreenWave {
+            panel: Panel::new(Widget::col(vec![
+                Widget::row(vec![
+                    Line("Create a green wave").small_heading().draw(ctx),
+                    Btn::plaintext("X")
+                        .build(ctx, "close", Key::Escape)
+                        .align_right(),
+                ]),
+                "Select signals in the order of travel".draw_text(ctx),
+                Widget::row(vec![
+                    "Progression speed (mph):".draw_text(ctx),
+                    Spinner::new(ctx, (5, 60), 25).named("speed"),
+                ]),
+                Checkbox::switch(ctx, "use a common cycle length", None, false),
+                Widget::nothing().named("apply"),
+            ]))
+            .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
+            .build(ctx),
+            members,
+            corridor: Vec::new(),
+            labels: ctx.upload(GeomBatch::new()),
+        };
+        state.update(ctx, app);
+        Box::new(state)
+    }
+
+    fn update(&mut self, ctx: &mut EventCtx, app: &App) {
+        let mut batch = fade_irrelevant(app, &self.members);
+        for (idx, i) in self.corridor.iter().enumerate() {
+            batch.append(
+                Text::from(Line((idx + 1).to_string()))
+                    .bg(Color::PURPLE)
+                    .render_to_batch(ctx.prerender)
+                    .color(RewriteColor::ChangeAlpha(0.8))
+                    .scale(0.3)
+                    .centered_on(app.primary.map.get_i(*i).polygon.center()),
+            );
+        }
+        self.labels = ctx.upload(batch);
+
+        let btn = if self.corridor.len() < 2 {
+            Btn::text_bg2("Apply offsets").inactive(ctx)
+        } else {
+            Btn::text_bg2(format!("Apply offsets to {} signals", self.corridor.len())).build(
+                ctx,
+                "Apply offsets",
+                Key::Enter,
+            )
+        };
+        self.panel.replace(
+            ctx,
+            "apply",
+            Widget::row(vec![btn, Btn::text_bg2("Start over").build_def(ctx, None)]).named("apply"),
+        );
+    }
+}
+
+impl State for GreenWave {
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
+        ctx.canvas_movement();
+        if ctx.redo_mouseover() {
+            app.primary.current_selection = app.calculate_current_selection(
+                ctx,
+                &DontDrawAgents {},
+                &ShowEverything::new(),
+                false,
+                true,
+                false,
+            );
+        }
+        if let Some(ID::Intersection(i)) = app.primary.current_selection {
+            if self.members.contains(&i) && !self.corridor.contains(&i) {
+                if app.per_obj.left_click(ctx, "add to the corridor") {
+                    self.corridor.push(i);
+                    self.update(ctx, app);
+                }
+            } else {
+                app.primary.current_selection = None;
+            }
+        } else {
+            app.primary.current_selection = None;
+        }
+
+        match self.panel.event(ctx) {
+            Outcome::Clicked(x) => match x.as_ref() {
+                "close" => {
+                    return Transition::Pop;
+                }
+                "Start over" => {
+                    self.corridor.clear();
+                    self.update(ctx, app);
+                }
+                "Apply offsets" => {
+                    match ControlTrafficSignal::green_wave(
+                        &app.primary.map,
+                        &self.corridor,
+                        Speed::miles_per_hour(self.panel.spinner("speed") as f64),
+                        self.panel.is_checked("use a common cycle length"),
+                    ) {
+                        Ok(signals) => {
+                            return Transition::Multi(vec![
+                                Transition::Pop,
+                                Transition::ModifyState(Box::new(move |state, ctx, app| {
+                                    let editor =
+                                        state.downcast_mut::<TrafficSignalEditor>().unwrap();
+                                    let idx = editor.current_stage;
+                                    editor.add_new_edit(ctx, app, idx, |ts| {
+                                        if let Some(signal) = signals.iter().find(|s| s.id == ts.id)
+                                        {
+                                            *ts = signal.clone();
+                                        }
+                                    });
+                                })),
+                            ]);
+                        }
+                        Err(err) => {
+                            return Transition::Push(PopupMsg::new(ctx, "Error", vec![err]));
+                        }
+                    }
+                }
+                _ => unreachable!(),
+            },
+            _ => {}
+        }
+
+        Transition::Keep
+    }
+
+    fn draw(&self, g: &mut GfxCtx, app: &App) {
+        self.panel.draw(g);
+        CommonState::draw_osd(g, app);
+
+        g.redraw(&self.labels);
+    }
+}
@@ -9,7 +9,7 @@ use map_model::{
     ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, PhaseType,
 };
 use widgetry::{
-    Btn, Checkbox, Choice, EventCtx, GfxCtx, Key, Line, Outcome, Panel, Spinner, TextExt, Widget,
+    Btn, Choice, EventCtx, GfxCtx, Key, Line, Outcome, Panel, Spinner, TextExt, Widget,
 };
 
 pub struct ChangeDuration {
@@ -19,6 +19,18 @@ pub struct ChangeDuration {
 
 impl ChangeDuration {
     pub fn new(ctx: &mut EventCtx, current: PhaseType, idx: usize) -> Box<dyn State> {
+        let (policy, max_green, extension) = match current {
+            PhaseType::Fixed(d) => ("fixed", 2.0 * d, Duration::seconds(3.0)),
+            PhaseType::Adaptive(d) => ("adaptive", 2.0 * d, Duration::seconds(3.0)),
+            PhaseType::Actuated {
+                max_green,
+                extension,
+                ..
+            } => ("actuated", max_green, extension),
+            PhaseType::MaxPressure { max_green, .. } => {
+                ("max pressure", max_green, Duration::seconds(3.0))
+            }
+        };
         Box::new(ChangeDuration {
             panel: Panel::new(Widget::col(vec![
                 Widget::row(vec![
@@ -30,7 +42,21 @@ impl ChangeDuration {
                         .align_right(),
                 ]),
                 Widget::row(vec![
-                    "Seconds:".draw_text(ctx),
+                    "Type:".draw_text(ctx),
+                    Widget::dropdown(
+                        ctx,
+                        "phase type",
+                        policy,
+                        vec![
+                            Choice::new("fixed", "fixed"),
+                            Choice::new("adaptive", "adaptive"),
+                            Choice::new("actuated", "actuated"),
+                            Choice::new("max pressure", "max pressure"),
+                        ],
+                    ),
+                ]),
+                Widget::row(vec![
+                    "Seconds (minimum for actuated and max pressure):".draw_text(ctx),
                     Spinner::new(
                         ctx,
                         (5, 300),
@@ -39,18 +65,14 @@ impl ChangeDuration {
                     .named("duration"),
                 ]),
                 Widget::row(vec![
-                    "Type:".draw_text(ctx),
-                    Checkbox::toggle(
-                        ctx,
-                        "phase type",
-                        "fixed",
-                        "adaptive",
-                        None,
-                        match current {
-                            PhaseType::Fixed(_) => true,
-                            PhaseType::Adaptive(_) => false,
-                        },
-                    ),
+                    "Maximum seconds (actuated and max pressure):".draw_text(ctx),
+                    Spinner::new(ctx, (5, 600), max_green.inner_seconds() as isize)
+                        .named("max green"),
+                ]),
+                Widget::row(vec![
+                    "Seconds to extend per vehicle (actuated):".draw_text(ctx),
+                    Spinner::new(ctx, (1, 30), extension.inner_seconds() as isize)
+                        .named("extension"),
                 ]),
                 Btn::text_bg2("Apply").build_def(ctx, Key::Enter),
             ]))
@@ -67,10 +89,22 @@ impl State for ChangeDuration {
                 "close" => Transition::Pop,
                 "Apply" => {
                     let dt = Duration::seconds(self.panel.spinner("duration") as f64);
-                    let new_type = if self.panel.is_checked("phase type") {
-                        PhaseType::Fixed(dt)
-                    } else {
-                        PhaseType::Adaptive(dt)
+                    // Don't let the maximum be less than the minimum
+                    let max_green =
+                        Duration::seconds(self.panel.spinner("max green") as f64).max(dt);
+                    let new_type = match self.panel.dropdown_value::<&str, _>("phase type") {
+                        "fixed" => PhaseType::Fixed(dt),
+                        "adaptive" => PhaseType::Adaptive(dt),
+                        "actuated" => PhaseType::Actuated {
+                            min_green: dt,
+                            max_green,
+                            extension: Duration::seconds(self.panel.spinner("extension") as f64),
+                        },
+                        "max pressure" => PhaseType::MaxPressure {
+                            min_green: dt,
+                            max_green,
+                        },
+                        _ => unreachable!(),
                     };
                     let idx = self.idx;
                     return Transition::Multi(vec![
@@ -1,3 +1,4 @@
+mod corridor;
 mod edits;
 mod offsets;
 mod picker;
@@ -13,10 +14,10 @@ use crate::sandbox::GameplayMode;
 use abstutil::Timer;
 use geom::{Distance, Duration, Line, Polygon, Pt2D};
 use map_model::{
-    ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, MovementID, PhaseType, Stage,
-    TurnPriority,
+    ControlTrafficSignal, EditCmd, EditIntersection, EditTrafficSignal, IntersectionID, Map,
+    MovementID, PhaseType, Stage, TurnPriority,
 };
-use std::collections::{BTreeSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use widgetry::{
     lctrl, Btn, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line,
     MultiButton, Outcome, Panel, RewriteColor, Text, TextExt, VerticalAlignment, Widget,
@@ -211,6 +212,13 @@ impl State for TrafficSignalEditor {
                         self.members.clone(),
                     ));
                 }
+                if x == "Create a green wave" {
+                    return Transition::Push(corridor::GreenWave::new(
+                        ctx,
+                        app,
+                        self.members.clone(),
+                    ));
+                }
                 if x == "Add a new stage" {
                     self.add_new_edit(ctx, app, num_stages, |ts| {
                         ts.stages.push(Stage::new());
@@ -327,11 +335,45 @@ impl State for TrafficSignalEditor {
                 }
                 "Export" => {
                     for signal in BundleEdits::get_current(app, &self.members).signals {
-                        let ts = signal.export(&app.primary.map);
+                        let edit = signal.export_edit(&app.primary.map);
+                        let osm_id = edit.raw.intersection_osm_node_id;
                         abstutil::write_json(
-                            format!("traffic_signal_data/{}.json", ts.intersection_osm_node_id),
-                            &ts,
+                            format!("traffic_signal_data/{}.json", osm_id),
+                            &edit.raw,
                         );
+                        // The format above can't describe these stages, so save everything
+                        let full_path = format!("traffic_signal_data/{}_full.json", osm_id);
+                        if !edit.variable_stages.is_empty() {
+                            abstutil::write_json(full_path, &edit);
+                        } else if abstutil::file_exists(full_path.clone()) {
+                            // Otherwise Import would prefer a stale copy
+                            abstutil::delete_file(full_path);
+                        }
+                    }
+                }
+                "Import" => {
+                    let mut bundle = BundleEdits::get_current(app, &self.members);
+                    let mut errors = Vec::new();
+                    for ts in &mut bundle.signals {
+                        match import_signal(&app.primary.map, ts.id) {
+                            Ok(new) => {
+                                *ts = new;
+                            }
+                            Err(err) => {
+                                errors.push(err);
+                            }
+                        }
+                    }
+                    if bundle != BundleEdits::get_current(app, &self.members) {
+                        self.command_stack
+                            .push(BundleEdits::get_current(app, &self.members));
+                        self.redo_stack.clear();
+                        bundle.apply(app);
+                        self.top_panel = make_top_panel(ctx, app, true, false);
+                        self.change_stage(ctx, app, 0);
+                    }
+                    if !errors.is_empty() {
+                        return Transition::Push(PopupMsg::new(ctx, "Import failed", errors));
                     }
                 }
                 "Preview" => {
@@ -536,16 +578,29 @@ fn make_top_panel(ctx: &mut EventCtx, app: &App, can_undo: bool, can_redo: bool)
         ]),
         Widget::row(row),
         if app.opts.dev {
-            Btn::text_fg("Export")
-                .tooltip(Text::from_multiline(vec![
-                    Line("This will create a JSON file in traffic_signal_data/.").small(),
-                    Line(
-                        "Contribute this to map how this traffic signal is currently timed in \
-                         real life.",
-                    )
-                    .small(),
-                ]))
-                .build_def(ctx, None)
+            Widget::row(vec![
+                Btn::text_fg("Export")
+                    .tooltip(Text::from_multiline(vec![
+                        Line("This will create a JSON file in traffic_signal_data/.").small(),
+                        Line(
+                            "Actuated and max-pressure stages are also saved in full, in a second \
+                             file ending with _full.json.",
+                        )
+                        .small(),
+                        Line(
+                            "Contribute this to map how this traffic signal is currently timed \
+                             in real life.",
+                        )
+                        .small(),
+                    ]))
+                    .build_def(ctx, None),
+                Btn::text_fg("Import")
+                    .tooltip(Text::from_multiline(vec![
+                        Line("This will read the JSON files that Export writes.").small(),
+                        Line("The _full.json file is used when it exists.").small(),
+                    ]))
+                    .build_def(ctx, None),
+            ])
         } else {
             Widget::nothing()
         },
@@ -610,6 +665,7 @@ fn make_side_panel(
         col.push(Btn::text_bg2("Edit entire signal").build_def(ctx, Key::E));
     } else {
         col.push(Btn::text_bg2("Tune offsets between signals").build_def(ctx, Key::O));
+        col.push(Btn::text_bg2("Create a green wave").build_def(ctx, Key::G));
     }
 
     let translations = squish_polygons_together(
@@ -641,6 +697,26 @@ fn make_side_panel(
                     match canonical_stage.phase_type {
                         PhaseType::Fixed(d) => format!("Stage {}: {}", idx + 1, d),
                         PhaseType::Adaptive(d) => format!("Stage {}: {} (adaptive)", idx + 1, d),
+                        PhaseType::Actuated {
+                            min_green,
+                            max_green,
+                            extension,
+                        } => format!(
+                            "Stage {}: {} to {}, extending {} per vehicle (actuated)",
+                            idx + 1,
+                            min_green,
+                            max_green,
+                            extension
+                        ),
+                        PhaseType::MaxPressure {
+                            min_green,
+                            max_green,
+                        } => format!(
+                            "Stage {}: {} to {} (max pressure)",
+                            idx + 1,
+                            min_green,
+                            max_green
+                        ),
                     }
                     .draw_text(ctx),
                     Btn::svg_def("system/assets/tools/edit.svg").build(
@@ -699,7 +775,7 @@ impl BundleEdits {
             edits.commands.push(EditCmd::ChangeIntersection {
                 i: signal.id,
                 old: app.primary.map.get_i_edit(signal.id),
-                new: EditIntersection::TrafficSignal(signal.export(&app.primary.map)),
+                new: EditIntersection::TrafficSignal(signal.export_edit(&app.primary.map)),
             });
         }
         apply_map_edits(ctx, app, edits);
@@ -742,6 +818,27 @@ impl BundleEdits {
 }
 
 // If None, nothing missing.
+// Reads what "Export" writes, preferring the full format when it's there.
+fn import_signal(map: &Map, id: IntersectionID) -> Result<ControlTrafficSignal, String> {
+    let osm_id = map.get_i(id).orig_id.0;
+    let mut timer = Timer::throwaway();
+    let edit = match abstutil::maybe_read_json::<EditTrafficSignal>(
+        format!("traffic_signal_data/{}_full.json", osm_id),
+        &mut timer,
+    ) {
+        Ok(edit) => edit,
+        Err(_) => EditTrafficSignal {
+            raw: abstutil::maybe_read_json(
+                format!("traffic_signal_data/{}.json", osm_id),
+                &mut timer,
+            )
+            .map_err(|err| format!("Couldn't read traffic_signal_data for {}: {}", id, err))?,
+            variable_stages: BTreeMap::new(),
+        },
+    };
+    ControlTrafficSignal::import_edit(edit, id, map)
+}
+
 fn check_for_missing_turns(app: &App, members: &BTreeSet<IntersectionID>) -> Option<BundleEdits> {
     let mut all_missing = BTreeSet::new();
     for i in members {
@@ -34,12 +34,20 @@ impl ZoneEditor {
             // Starting a new zone
             btreeset! { start.id }
         };
-        let allow_through_traffic = start
+        let mut allow_through_traffic: BTreeSet<TripMode> = start
             .access_restrictions
             .allow_through_traffic
             .into_iter()
             .map(|c| TripMode::from_constraints(c))
             .collect();
+        // Shared bikes follow the same restrictions as personal ones
+        if allow_through_traffic.contains(&TripMode::Bike) {
+            allow_through_traffic.insert(TripMode::BikeShare);
+        }
+        // And ride-hail vehicles like private cars
+        if allow_through_traffic.contains(&TripMode::Drive) {
+            allow_through_traffic.insert(TripMode::RideHail);
+        }
         let cap_vehicles_per_hour = start.access_restrictions.cap_vehicles_per_hour;
 
         let (unzoomed, zoomed, legend) = draw_zone(ctx, app, &members);
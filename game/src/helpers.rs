@@ -124,9 +124,9 @@ pub fn cmp_duration_shorter(after: Duration, before: Duration) -> Vec<TextSpan>
 pub fn color_for_mode(app: &App, m: TripMode) -> Color {
     match m {
         TripMode::Walk => app.cs.unzoomed_pedestrian,
-        TripMode::Bike => app.cs.unzoomed_bike,
+        TripMode::Bike | TripMode::BikeShare => app.cs.unzoomed_bike,
         TripMode::Transit => app.cs.unzoomed_bus,
-        TripMode::Drive => app.cs.unzoomed_car,
+        TripMode::Drive | TripMode::RideHail => app.cs.unzoomed_car,
     }
 }
 
@@ -148,6 +148,8 @@ pub fn color_for_trip_phase(app: &App, tpt: TripPhaseType) -> Color {
         TripPhaseType::Parking => app.cs.parking_trip,
         TripPhaseType::WaitingForBus(_, _) => app.cs.bus_layer,
         TripPhaseType::RidingBus(_, _, _) => app.cs.bus_lane,
+        TripPhaseType::WaitingForRideHail => app.cs.bus_layer,
+        TripPhaseType::RidingRideHail(_) => app.cs.unzoomed_car,
         TripPhaseType::Aborted | TripPhaseType::Finished => unreachable!(),
         TripPhaseType::DelayedStart => Color::YELLOW,
         TripPhaseType::Remote => Color::PINK,
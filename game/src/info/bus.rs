@@ -3,7 +3,7 @@ use crate::common::ColorNetwork;
 use crate::helpers::ID;
 use crate::info::{header_btns, make_tabs, Details, Tab};
 use abstutil::{prettyprint_usize, Counter};
-use geom::{Circle, Distance, Time};
+use geom::{Circle, Distance, Duration, Time};
 use map_model::{BusRoute, BusRouteID, BusStopID, PathStep};
 use sim::{AgentID, CarID};
 use widgetry::{Btn, Color, EventCtx, Key, Line, RewriteColor, Text, TextExt, Widget};
@@ -27,15 +27,27 @@ pub fn stop(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID)
         rows.push(Btn::text_fg(format!("Route {}", r.short_name)).build(ctx, &label, None));
         details.hyperlinks.insert(label, Tab::BusRoute(r.id));
 
-        let arrivals: Vec<(Time, CarID)> = all_arrivals
+        let arrivals: Vec<(Time, CarID, Time)> = all_arrivals
             .iter()
-            .filter(|(_, _, route, stop)| r.id == *route && id == *stop)
-            .map(|(t, car, _, _)| (*t, *car))
+            .filter(|(_, _, route, stop, _)| r.id == *route && id == *stop)
+            .map(|(t, car, _, _, scheduled)| (*t, *car, *scheduled))
             .collect();
         let mut txt = Text::new();
-        if let Some((t, _)) = arrivals.last() {
+        if let Some((t, _, scheduled)) = arrivals.last() {
             // TODO Button to jump to the bus
-            txt.add(Line(format!("  Last bus arrived {} ago", sim.time() - *t)).secondary());
+            let schedule = if *t > *scheduled {
+                format!("{} late", *t - *scheduled)
+            } else {
+                format!("{} early", *scheduled - *t)
+            };
+            txt.add(
+                Line(format!(
+                    "  Last bus arrived {} ago ({})",
+                    sim.time() - *t,
+                    schedule
+                ))
+                .secondary(),
+            );
         } else {
             txt.add(Line("  No arrivals yet").secondary());
         }
@@ -44,6 +56,7 @@ pub fn stop(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID)
 
     let mut boardings: Counter<BusRouteID> = Counter::new();
     let mut alightings: Counter<BusRouteID> = Counter::new();
+    let mut left_behind: Counter<BusRouteID> = Counter::new();
     if let Some(list) = app.primary.sim.get_analytics().passengers_boarding.get(&id) {
         for (_, r, _) in list {
             boardings.inc(*r);
@@ -60,6 +73,17 @@ pub fn stop(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID)
             alightings.inc(*r);
         }
     }
+    if let Some(list) = app
+        .primary
+        .sim
+        .get_analytics()
+        .passengers_left_behind
+        .get(&id)
+    {
+        for (_, r, cnt) in list {
+            left_behind.add(*r, *cnt);
+        }
+    }
     let mut txt = Text::new();
     txt.add(Line("Total"));
     txt.append(
@@ -80,6 +104,15 @@ pub fn stop(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusStopID)
             ))
             .secondary(),
         );
+        if left_behind.get(r.id) > 0 {
+            txt.append(
+                Line(format!(
+                    ", {} left behind by a full vehicle",
+                    prettyprint_usize(left_behind.get(r.id))
+                ))
+                .secondary(),
+            );
+        }
     }
     rows.push(txt.draw(ctx));
 
@@ -173,11 +206,9 @@ pub fn route(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRouteI
     );
 
     if app.opts.dev {
-        rows.push(Btn::text_bg1("Open OSM relation").build(
-            ctx,
-            format!("open {}", route.osm_rel_id),
-            None,
-        ));
+        if let Some(id) = route.osm_rel_id {
+            rows.push(Btn::text_bg1("Open OSM relation").build(ctx, format!("open {}", id), None));
+        }
     }
 
     let buses = app.primary.sim.status_of_buses(id, map);
@@ -234,6 +265,32 @@ pub fn route(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRouteI
         .draw(ctx),
     );
 
+    {
+        let analytics = app.primary.sim.get_analytics();
+        let now = app.primary.sim.time();
+        let deviations = analytics.schedule_deviation(now, id);
+        if !deviations.is_empty() {
+            let late = deviations
+                .iter()
+                .filter(|(_, _, _, dt)| *dt > Duration::minutes(5))
+                .count();
+            let bunched = analytics.bunching(now, id, 0.5).len();
+            rows.push(
+                Text::from_all(vec![
+                    Line("Schedule"),
+                    Line(format!(
+                        ": {} of {} arrivals more than 5 minutes late, {} bunched",
+                        prettyprint_usize(late),
+                        prettyprint_usize(deviations.len()),
+                        prettyprint_usize(bunched)
+                    ))
+                    .secondary(),
+                ])
+                .draw(ctx),
+            );
+        }
+    }
+
     rows.push(format!("{} stops", route.stops.len()).draw_text(ctx));
     {
         let i = map.get_i(map.get_l(route.start).src_i);
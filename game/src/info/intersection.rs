@@ -275,6 +275,26 @@ pub fn traffic_signal(
             match stage.phase_type {
                 PhaseType::Fixed(d) => Line(format!("Stage {}: {}", idx + 1, d)),
                 PhaseType::Adaptive(d) => Line(format!("Stage {}: {} (adaptive)", idx + 1, d)),
+                PhaseType::Actuated {
+                    min_green,
+                    max_green,
+                    extension,
+                } => Line(format!(
+                    "Stage {}: {} to {}, extending {} per vehicle (actuated)",
+                    idx + 1,
+                    min_green,
+                    max_green,
+                    extension
+                )),
+                PhaseType::MaxPressure {
+                    min_green,
+                    max_green,
+                } => Line(format!(
+                    "Stage {}: {} to {} (max pressure)",
+                    idx + 1,
+                    min_green,
+                    max_green
+                )),
             }
             .draw(ctx),
         );
@@ -1,7 +1,7 @@
 use crate::app::App;
 use crate::info::{header_btns, make_table, make_tabs, throughput, DataOptions, Details, Tab};
 use abstutil::prettyprint_usize;
-use map_model::{LaneID, PathConstraints};
+use map_model::{LaneID, ParkingArea, PathConstraints};
 use std::collections::HashSet;
 use widgetry::{Btn, EventCtx, Line, LinePlot, PlotOptions, Series, Text, TextExt, Widget};
 
@@ -47,6 +47,21 @@ pub fn info(ctx: &EventCtx, app: &App, details: &mut Details, id: LaneID) -> Vec
                 l.number_parking_spots()
             ),
         ));
+        let policy = app.primary.map.get_parking_policy(ParkingArea::Lane(l.id));
+        if !policy.is_free() {
+            kv.push(("Price", policy.to_string()));
+            let (cents, overstays) = app
+                .primary
+                .sim
+                .get_analytics()
+                .parking_revenue(app.primary.sim.time())
+                .remove(&ParkingArea::Lane(l.id))
+                .unwrap_or((0, 0));
+            kv.push((
+                "Revenue",
+                format!("${:.2}, {} overstays", (cents as f64) / 100.0, overstays),
+            ));
+        }
     } else {
         kv.push(("Speed limit", r.speed_limit.to_string()));
     }
@@ -148,6 +163,11 @@ pub fn debug(ctx: &EventCtx, app: &App, details: &mut Details, id: LaneID) -> Ve
             l.length()
         ),
     ));
+    let (climb, descent) = l.elevation_change(map);
+    kv.push((
+        "Climb / descent".to_string(),
+        format!("{} / {}", climb, descent),
+    ));
     kv.push((
         "Dir and offset".to_string(),
         format!("{}, {}", r.dir(l.id), r.offset(l.id)),
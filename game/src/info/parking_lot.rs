@@ -1,7 +1,7 @@
 use crate::app::App;
 use crate::info::{header_btns, make_tabs, Details, Tab};
 use abstutil::prettyprint_usize;
-use map_model::ParkingLotID;
+use map_model::{ParkingArea, ParkingLotID};
 use std::collections::HashSet;
 use widgetry::{Btn, EventCtx, Line, LinePlot, PlotOptions, Series, TextExt, Widget};
 
@@ -18,6 +18,25 @@ pub fn info(ctx: &mut EventCtx, app: &App, details: &mut Details, id: ParkingLot
         )
         .draw_text(ctx),
     );
+    let policy = app.primary.map.get_parking_policy(ParkingArea::Lot(id));
+    if !policy.is_free() {
+        let (cents, overstays) = app
+            .primary
+            .sim
+            .get_analytics()
+            .parking_revenue(app.primary.sim.time())
+            .remove(&ParkingArea::Lot(id))
+            .unwrap_or((0, 0));
+        rows.push(
+            format!(
+                "{}. Collected ${:.2}, {} overstays",
+                policy,
+                (cents as f64) / 100.0,
+                overstays
+            )
+            .draw_text(ctx),
+        );
+    }
 
     let mut series = vec![Series {
         label: format!("After \"{}\"", app.primary.map.get_edits().edits_name),
@@ -135,8 +135,8 @@ pub fn trips(
                     ctx.prerender,
                     match trip.mode {
                         TripMode::Walk => "system/assets/meters/pedestrian.svg",
-                        TripMode::Bike => "system/assets/meters/bike.svg",
-                        TripMode::Drive => "system/assets/meters/car.svg",
+                        TripMode::Bike | TripMode::BikeShare => "system/assets/meters/bike.svg",
+                        TripMode::Drive | TripMode::RideHail => "system/assets/meters/car.svg",
                         TripMode::Transit => "system/assets/meters/bus.svg",
                     },
                 )
@@ -482,12 +482,16 @@ pub fn parked_car(
 
     // TODO prev trips, next trips, etc
 
-    let p = app.primary.sim.get_owner_of_car(id).unwrap();
-    rows.push(Btn::text_bg2(format!("Owned by {}", p)).build_def(ctx, None));
-    details.hyperlinks.insert(
-        format!("Owned by {}", p),
-        Tab::PersonTrips(p, BTreeMap::new()),
-    );
+    // Ride-hail vehicles don't belong to anybody
+    if let Some(p) = app.primary.sim.get_owner_of_car(id) {
+        rows.push(Btn::text_bg2(format!("Owned by {}", p)).build_def(ctx, None));
+        details.hyperlinks.insert(
+            format!("Owned by {}", p),
+            Tab::PersonTrips(p, BTreeMap::new()),
+        );
+    } else {
+        rows.push("Ride-hail vehicle".draw_text(ctx));
+    }
 
     if let Some(p) = app.primary.sim.lookup_parked_car(id) {
         match p.spot {
@@ -550,10 +554,16 @@ fn header(
                         ("walking", Some("system/assets/meters/pedestrian.svg"))
                     }
                     AgentID::Car(c) => match c.1 {
-                        VehicleType::Car => ("driving", Some("system/assets/meters/car.svg")),
+                        VehicleType::Car | VehicleType::Truck => {
+                            ("driving", Some("system/assets/meters/car.svg"))
+                        }
                         VehicleType::Bike => ("biking", Some("system/assets/meters/bike.svg")),
                         VehicleType::Bus | VehicleType::Train => unreachable!(),
                     },
+                    AgentID::BusPassenger(_, c) if c.1 == VehicleType::Car => (
+                        "riding in a ride-hail car",
+                        Some("system/assets/meters/car.svg"),
+                    ),
                     AgentID::BusPassenger(_, _) => {
                         ("riding a bus", Some("system/assets/meters/bus.svg"))
                     }
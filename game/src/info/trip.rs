@@ -4,7 +4,7 @@ use crate::info::{make_table, Details, Tab};
 use geom::{ArrowCap, Distance, Duration, Percent, PolyLine, Polygon, Pt2D, Time};
 use map_model::{Map, Path, PathStep};
 use maplit::btreemap;
-use sim::{AgentID, PersonID, TripEndpoint, TripID, TripPhase, TripPhaseType};
+use sim::{AgentID, PersonID, TripEndpoint, TripID, TripInfo, TripPhase, TripPhaseType};
 use std::collections::BTreeMap;
 use widgetry::{
     Btn, Color, DrawWithTooltips, EventCtx, GeomBatch, Line, LinePlot, PlotOptions, RewriteColor,
@@ -118,6 +118,11 @@ pub fn ongoing(
                 .force_width_pct(ctx, col_width),
             Line(trip.purpose.to_string()).secondary().draw(ctx),
         ]));
+        col.push(Widget::custom_row(vec![
+            Widget::custom_row(vec![Line("Routing").secondary().draw(ctx)])
+                .force_width_pct(ctx, col_width),
+            Line(trip.routing_profile.to_string()).secondary().draw(ctx),
+        ]));
     }
 
     col.push(make_timeline(
@@ -156,13 +161,13 @@ pub fn future(
         .has_prebaked()
         .and_then(|_| app.prebaked().finished_trip_time(id))
     {
-        col.extend(make_table(
-            ctx,
-            vec![
-                ("Estimated trip time", estimated_trip_time.to_string()),
-                ("Purpose", trip.purpose.to_string()),
-            ],
-        ));
+        let mut rows = vec![
+            ("Estimated trip time", estimated_trip_time.to_string()),
+            ("Purpose", trip.purpose.to_string()),
+            ("Routing", trip.routing_profile.to_string()),
+        ];
+        rows.extend(describe_itinerary(&trip, &app.primary.map));
+        col.extend(make_table(ctx, rows));
 
         let phases = app.prebaked().get_trip_phases(id, &app.primary.map);
         col.push(make_timeline(
@@ -212,18 +217,37 @@ pub fn future(
             .evenly_spaced(),
         );
 
-        col.extend(make_table(
-            ctx,
-            vec![
-                ("Departure", trip.departure.ampm_tostring()),
-                ("Purpose", trip.purpose.to_string()),
-            ],
-        ));
+        let mut rows = vec![
+            ("Departure", trip.departure.ampm_tostring()),
+            ("Purpose", trip.purpose.to_string()),
+            ("Routing", trip.routing_profile.to_string()),
+        ];
+        rows.extend(describe_itinerary(&trip, &app.primary.map));
+        col.extend(make_table(ctx, rows));
     }
 
     Widget::col(col)
 }
 
+fn describe_itinerary(trip: &TripInfo, map: &Map) -> Vec<(&'static str, String)> {
+    trip.itinerary
+        .iter()
+        .map(|ride| {
+            (
+                "Ride",
+                format!(
+                    "{} from {} to {}",
+                    map.get_br(ride.route).short_name,
+                    map.get_bs(ride.board).name,
+                    ride.alight
+                        .map(|bs| map.get_bs(bs).name.clone())
+                        .unwrap_or_else(|| "off the map".to_string())
+                ),
+            )
+        })
+        .collect()
+}
+
 pub fn finished(
     ctx: &mut EventCtx,
     app: &App,
@@ -317,6 +341,11 @@ pub fn finished(
                 .force_width_pct(ctx, col_width),
             Line(trip.purpose.to_string()).secondary().draw(ctx),
         ]));
+        col.push(Widget::custom_row(vec![
+            Widget::custom_row(vec![Line("Routing").secondary().draw(ctx)])
+                .force_width_pct(ctx, col_width),
+            Line(trip.routing_profile.to_string()).secondary().draw(ctx),
+        ]));
     }
 
     col.push(make_timeline(
@@ -544,6 +573,10 @@ fn make_timeline(
                         "system/assets/timeline/waiting_for_bus.svg"
                     }
                     TripPhaseType::RidingBus(_, _, _) => "system/assets/timeline/riding_bus.svg",
+                    TripPhaseType::WaitingForRideHail => {
+                        "system/assets/timeline/waiting_for_bus.svg"
+                    }
+                    TripPhaseType::RidingRideHail(_) => "system/assets/timeline/driving.svg",
                     TripPhaseType::Aborted | TripPhaseType::Finished => unreachable!(),
                     TripPhaseType::DelayedStart => "system/assets/timeline/delayed_start.svg",
                     // TODO What icon should represent this?
@@ -0,0 +1,157 @@
+use crate::app::App;
+use crate::common::{make_heatmap, ColorLegend, ColorScale, HeatmapOptions};
+use crate::layer::{Layer, LayerOutcome};
+use abstutil::prettyprint_usize;
+use geom::Time;
+use map_model::{Traversable, SIDEWALK_THICKNESS};
+use sim::GetDrawAgents;
+use widgetry::{
+    Btn, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line, Outcome,
+    Panel, Text, TextExt, VerticalAlignment, Widget,
+};
+
+// Fruin's pedestrian level of service for walkways, by the upper bound on density (people per
+// square meter)
+const LEVELS_OF_SERVICE: [(&str, f64); 5] = [
+    ("A: free flow", 0.31),
+    ("B: minor conflicts", 0.43),
+    ("C: some crossing conflicts", 0.71),
+    ("D: restricted speed", 1.08),
+    ("E: shuffling", 2.17),
+];
+const WORST_LEVEL_OF_SERVICE: &str = "F: jammed";
+
+pub struct PedestrianCrowding {
+    time: Time,
+    opts: HeatmapOptions,
+    unzoomed: Drawable,
+    zoomed: Drawable,
+    panel: Panel,
+}
+
+impl Layer for PedestrianCrowding {
+    fn name(&self) -> Option<&'static str> {
+        Some("pedestrian crowding")
+    }
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        app: &mut App,
+        minimap: &Panel,
+    ) -> Option<LayerOutcome> {
+        if app.primary.sim.time() != self.time {
+            let mut new = PedestrianCrowding::new(ctx, app, self.opts.clone());
+            new.panel.align_above(ctx, minimap);
+            new.panel.restore(ctx, &self.panel);
+            *self = new;
+        }
+
+        self.panel.align_above(ctx, minimap);
+        match self.panel.event(ctx) {
+            Outcome::Clicked(x) => match x.as_ref() {
+                "close" => {
+                    return Some(LayerOutcome::Close);
+                }
+                _ => unreachable!(),
+            },
+            _ => {
+                let new_opts = HeatmapOptions::from_controls(&self.panel);
+                if self.opts != new_opts {
+                    *self = PedestrianCrowding::new(ctx, app, new_opts);
+                    self.panel.align_above(ctx, minimap);
+                }
+            }
+        }
+        None
+    }
+    fn draw(&self, g: &mut GfxCtx, app: &App) {
+        self.panel.draw(g);
+        if g.canvas.cam_zoom < app.opts.min_zoom_for_detail {
+            g.redraw(&self.unzoomed);
+        } else {
+            g.redraw(&self.zoomed);
+        }
+    }
+    fn draw_minimap(&self, g: &mut GfxCtx) {
+        g.redraw(&self.unzoomed);
+    }
+}
+
+impl PedestrianCrowding {
+    pub fn new(ctx: &mut EventCtx, app: &App, opts: HeatmapOptions) -> PedestrianCrowding {
+        let map = &app.primary.map;
+
+        let mut pts = Vec::new();
+        for a in app.primary.sim.get_unzoomed_agents(map) {
+            if a.vehicle_type.is_none() {
+                pts.push(a.pos);
+            }
+        }
+        let num_peds = pts.len();
+        let mut unzoomed = GeomBatch::new();
+        let heatmap_legend = make_heatmap(ctx, &mut unzoomed, map.get_bounds(), pts, &opts);
+
+        let scale = ColorScale(vec![Color::GREEN, Color::YELLOW, Color::RED]);
+        let color = |idx: usize| scale.eval((idx as f64) / (LEVELS_OF_SERVICE.len() as f64));
+        let mut zoomed = GeomBatch::new();
+        let mut num_crowded = 0;
+        for (on, density) in app.primary.sim.get_pedestrian_density(map) {
+            let idx = LEVELS_OF_SERVICE
+                .iter()
+                .position(|(_, max)| density < *max)
+                .unwrap_or(LEVELS_OF_SERVICE.len());
+            // Level of service E or worse
+            if idx >= LEVELS_OF_SERVICE.len() - 1 {
+                num_crowded += 1;
+            }
+            let polygon = match on {
+                Traversable::Lane(l) => {
+                    let lane = map.get_l(l);
+                    lane.lane_center_pts.make_polygons(lane.width)
+                }
+                Traversable::Turn(t) => map.get_t(t).geom.make_polygons(SIDEWALK_THICKNESS),
+            };
+            zoomed.push(color(idx).alpha(0.6), polygon);
+        }
+
+        let mut col = vec![
+            Widget::row(vec![
+                Widget::draw_svg(ctx, "system/assets/tools/layers.svg"),
+                "Pedestrian crowding".draw_text(ctx),
+                Btn::plaintext("X")
+                    .build(ctx, "close", Key::Escape)
+                    .align_right(),
+            ]),
+            Text::from_multiline(vec![
+                Line(format!("{} people walking", prettyprint_usize(num_peds))),
+                Line(format!(
+                    "{} sidewalks and crosswalks at level of service E or F",
+                    prettyprint_usize(num_crowded)
+                )),
+            ])
+            .draw(ctx),
+            Text::from(Line("Zoom in to see the level of service").secondary())
+                .wrap_to_pct(ctx, 15)
+                .draw(ctx),
+        ];
+        for (idx, (label, _)) in LEVELS_OF_SERVICE.iter().enumerate() {
+            col.push(ColorLegend::row(ctx, color(idx), *label));
+        }
+        col.push(ColorLegend::row(
+            ctx,
+            color(LEVELS_OF_SERVICE.len()),
+            WORST_LEVEL_OF_SERVICE,
+        ));
+        col.extend(opts.to_controls(ctx, heatmap_legend));
+
+        PedestrianCrowding {
+            time: app.primary.sim.time(),
+            opts,
+            unzoomed: ctx.upload(unzoomed),
+            zoomed: ctx.upload(zoomed),
+            panel: Panel::new(Widget::col(col))
+                .aligned(HorizontalAlignment::Right, VerticalAlignment::Center)
+                .build(ctx),
+        }
+    }
+}
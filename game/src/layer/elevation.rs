@@ -3,7 +3,7 @@ use crate::common::{ColorLegend, ColorNetwork};
 use crate::layer::{Layer, LayerOutcome};
 use geom::{ArrowCap, Distance, PolyLine};
 use widgetry::{
-    Btn, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line, Panel, Text,
+    Btn, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line, Panel,
     TextExt, VerticalAlignment, Widget,
 };
 
@@ -94,11 +94,7 @@ impl Elevation {
                     .build(ctx, "close", Key::Escape)
                     .align_right(),
             ]),
-            Text::from_multiline(vec![
-                Line(format!("Steepest road: {:.0}% grade", max * 100.0)),
-                Line("Note: elevation data is currently wrong!").secondary(),
-            ])
-            .draw(ctx),
+            Line(format!("Steepest road: {:.0}% grade", max * 100.0)).draw(ctx),
             ColorLegend::gradient(ctx, &app.cs.good_to_bad_red, vec!["flat", "steep"]),
         ]))
         .aligned(HorizontalAlignment::Right, VerticalAlignment::Center)
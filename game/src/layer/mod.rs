@@ -1,3 +1,4 @@
+mod crowding;
 mod elevation;
 pub mod map;
 mod pandemic;
@@ -102,6 +103,7 @@ impl PickLayer {
                     btn("delay", Key::D),
                     btn("throughput", Key::T),
                     btn("traffic jams", Key::J),
+                    btn("pedestrian crowding", Key::W),
                 ]),
                 Widget::col(vec![
                     "Map".draw_text(ctx),
@@ -181,6 +183,13 @@ impl State for PickLayer {
                 "no sidewalks" => {
                     app.layer = Some(Box::new(map::Static::no_sidewalks(ctx, app)));
                 }
+                "pedestrian crowding" => {
+                    app.layer = Some(Box::new(crowding::PedestrianCrowding::new(
+                        ctx,
+                        app,
+                        HeatmapOptions::new(),
+                    )));
+                }
                 "pandemic model" => {
                     app.layer = Some(Box::new(pandemic::Pandemic::new(
                         ctx,
@@ -5,7 +5,7 @@ use abstutil::{prettyprint_usize, Counter, Parallelism};
 use geom::{Circle, Distance, Duration, Pt2D, Time};
 use map_model::{
     BuildingID, Map, OffstreetParking, ParkingLotID, PathConstraints, PathRequest, RoadID,
-    NORMAL_LANE_THICKNESS,
+    RoutingProfile, NORMAL_LANE_THICKNESS,
 };
 use sim::{GetDrawAgents, ParkingSpot, Scenario, VehicleType};
 use std::collections::BTreeSet;
@@ -404,6 +404,7 @@ impl Efficiency {
                     start,
                     end,
                     constraints: PathConstraints::Pedestrian,
+                    profile: RoutingProfile::Fastest,
                 })
                 .collect();
             timer.stop("gather requests");
@@ -165,7 +165,12 @@ fn dump_route_goldenfile(map: &map_model::Map) -> Result<(), std::io::Error> {
         writeln!(
             f,
             "{} from {} to {:?}",
-            br.osm_rel_id, br.start, br.end_border
+            br.osm_rel_id
+                .map(|id| id.to_string())
+                .or_else(|| br.gtfs_id.clone())
+                .unwrap_or_else(|| br.full_name.clone()),
+            br.start,
+            br.end_border
         )?;
         for bs in &br.stops {
             let bs = map.get_bs(*bs);
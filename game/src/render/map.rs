@@ -491,7 +491,7 @@ impl UnzoomedAgents {
 
     fn color(&self, agent: &UnzoomedAgent) -> Option<Color> {
         match agent.vehicle_type {
-            Some(VehicleType::Car) => {
+            Some(VehicleType::Car) | Some(VehicleType::Truck) => {
                 if self.cars {
                     Some(self.car_color)
                 } else {
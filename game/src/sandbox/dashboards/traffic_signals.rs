@@ -173,9 +173,15 @@ impl Demand {
                 app.primary.sim.all_trip_info(),
                 |(_, trip)| {
                     let departure = trip.departure;
-                    TripEndpoint::path_req(trip.start, trip.end, trip.mode, map)
-                        .and_then(|req| map.pathfind(req))
-                        .map(|path| (departure, path))
+                    TripEndpoint::path_req(
+                        trip.start,
+                        trip.end,
+                        trip.mode,
+                        trip.routing_profile,
+                        map,
+                    )
+                    .and_then(|req| map.pathfind(req))
+                    .map(|path| (departure, path))
                 },
             )
             .into_iter()
@@ -7,7 +7,7 @@ use crate::sandbox::gameplay::{GameplayMode, GameplayState};
 use crate::sandbox::{Actions, SandboxControls, SandboxMode};
 use abstutil::Timer;
 use geom::{Distance, Polygon};
-use map_model::{BuildingID, IntersectionID, Position, NORMAL_LANE_THICKNESS};
+use map_model::{BuildingID, IntersectionID, Position, RoutingProfile, NORMAL_LANE_THICKNESS};
 use rand::seq::SliceRandom;
 use rand::Rng;
 use sim::{
@@ -245,6 +245,18 @@ impl AgentSpawner {
                             .collect(),
                     ),
                 ]),
+                Widget::row(vec![
+                    "Routing:".draw_text(ctx),
+                    Widget::dropdown(
+                        ctx,
+                        "routing profile",
+                        RoutingProfile::Fastest,
+                        RoutingProfile::all()
+                            .into_iter()
+                            .map(|p| Choice::new(p.to_string(), p))
+                            .collect(),
+                    ),
+                ]),
                 Widget::row(vec![
                     "Number of trips:".draw_text(ctx),
                     Spinner::new(ctx, (1, 1000), 1).named("number"),
@@ -285,16 +297,19 @@ impl State for AgentSpawner {
                             from.clone(),
                             to.clone(),
                             self.panel.dropdown_value("mode"),
+                            app.primary.sim.time(),
                             map,
                         ) {
+                            let mut individ = IndividTrip::new(
+                                app.primary.sim.time(),
+                                TripPurpose::Shopping,
+                                trip,
+                            );
+                            individ.routing_profile = self.panel.dropdown_value("routing profile");
                             scenario.people.push(PersonSpec {
                                 id: PersonID(app.primary.sim.get_all_people().len() + i),
                                 orig_id: None,
-                                trips: vec![IndividTrip::new(
-                                    app.primary.sim.time(),
-                                    TripPurpose::Shopping,
-                                    trip,
-                                )],
+                                trips: vec![individ],
                             });
                         }
                     }
@@ -320,6 +335,7 @@ impl State for AgentSpawner {
                         self.source.clone().unwrap(),
                         to.clone(),
                         self.panel.dropdown_value("mode"),
+                        self.panel.dropdown_value("routing profile"),
                         &app.primary.map,
                     )
                     .and_then(|req| app.primary.map.pathfind(req))
@@ -400,6 +416,7 @@ impl State for AgentSpawner {
                         self.source.clone().unwrap(),
                         hovering.clone(),
                         self.panel.dropdown_value("mode"),
+                        self.panel.dropdown_value("routing profile"),
                         &app.primary.map,
                     )
                     .and_then(|req| app.primary.map.pathfind(req))
@@ -516,6 +533,8 @@ pub fn spawn_agents_around(i: IntersectionID, app: &mut App) {
                     },
                     TripEndpoint::Border(lane.src_i, None),
                     TripPurpose::Shopping,
+                    RoutingProfile::Fastest,
+                    None,
                     false,
                     false,
                     map,
@@ -539,6 +558,8 @@ pub fn spawn_agents_around(i: IntersectionID, app: &mut App) {
                     },
                     TripEndpoint::Border(lane.src_i, None),
                     TripPurpose::Shopping,
+                    RoutingProfile::Fastest,
+                    None,
                     false,
                     false,
                     map,
@@ -170,7 +170,11 @@ impl GameplayMode {
                     }
                     _ => {}
                 },
-                EditCmd::ChangeRouteSchedule { .. } => {}
+                EditCmd::ChangeRouteSchedule { .. }
+                | EditCmd::ChangeBusRoute { .. }
+                | EditCmd::CreateBusRoute { .. }
+                | EditCmd::DeleteBusRoute { .. }
+                | EditCmd::ChangeParkingPolicy { .. } => {}
             }
         }
         true
@@ -5,7 +5,7 @@ use crate::game::{DrawBaselayer, PopupMsg, State, Transition};
 use crate::helpers::ID;
 use crate::render::{DrawOptions, BIG_ARROW_THICKNESS};
 use geom::ArrowCap;
-use map_model::{IntersectionCluster, IntersectionID, PathConstraints};
+use map_model::{IntersectionCluster, IntersectionID, PathConstraints, RoutingProfile};
 use sim::DontDrawAgents;
 use std::collections::BTreeSet;
 use widgetry::{
@@ -169,6 +169,7 @@ impl UberTurnViewer {
                     map.get_l(t.src),
                     map.get_t(*t),
                     PathConstraints::Car,
+                    RoutingProfile::Fastest,
                     map,
                 );
             }
@@ -21,7 +21,7 @@ import time
 
 def main():
     parser = argparse.ArgumentParser()
-    parser.add_argument('--api', default='http://localhost:1234')
+    parser.add_argument('--api', default='http://localhost:1234/v1')
     parser.add_argument('--map_name', default='montlake')
     parser.add_argument('--hours', type=int, default=24)
     parser.add_argument('--cmp1', type=int, default=None)
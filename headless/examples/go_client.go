@@ -22,7 +22,7 @@ import (
 )
 
 const (
-	api = "http://localhost:1234/"
+	api = "http://localhost:1234/v1/"
 )
 
 var (
@@ -9,14 +9,14 @@ import json
 import requests
 
 
-api = 'http://localhost:1234'
+api = 'http://localhost:1234/v1'
 hours_to_sim = '12:00:00'
 
 
 def main():
     # Make sure to start the simulation from the beginning
-    print('Did you just start the simulation? Time is currently', requests.get(api + '/sim/get-time').text)
-    print('Reset the simulation:', requests.get(api + '/sim/reset').text)
+    print('Did you just start the simulation? Time is currently', requests.get(api + '/sim/get-time').json()['pretty'])
+    print('Reset the simulation:', requests.get(api + '/sim/reset').json()['message'])
     print()
 
     # Run a few hours to get a baseline
@@ -62,10 +62,10 @@ def main():
                 }
             ]
         }
-        print('Create a new person:', requests.post(api + '/sim/new-person', json=person).text)
+        print('Create a new person:', requests.post(api + '/sim/new-person', json=person).json())
     # Reset the simulation before applying the edit, since reset also clears edits.
-    print('Reset the simulation:', requests.get(api + '/sim/reset').text)
-    print('Update a traffic signal:', requests.post(api + '/traffic-signals/set', json=ts).text)
+    print('Reset the simulation:', requests.get(api + '/sim/reset').json()['message'])
+    print('Update a traffic signal:', requests.post(api + '/traffic-signals/set', json=ts).json()['message'])
     # Sanity check that the edits were applied
     if False:
         print('Current map edits:\n', requests.get(api + '/map/get-edits').json())
@@ -92,7 +92,7 @@ def main():
 
 # Returns (trips, delay, throughput)
 def run_experiment():
-    print(requests.get(api + '/sim/goto-time', params={'t': hours_to_sim}).text)
+    print(requests.get(api + '/sim/goto-time', params={'t': hours_to_sim}).json()['message'])
     raw_trips = requests.get(api + '/data/get-finished-trips').json()['trips']
     raw_delays = requests.get(api + '/traffic-signals/get-delays', params={'id': 67, 't1': '00:00:00', 't2': hours_to_sim}).json()
     raw_thruput = requests.get(api + '/traffic-signals/get-cumulative-thruput', params={'id': 67}).json()
@@ -0,0 +1,508 @@
+// The plumbing for the versioned HTTP API: parsing routes and parameters, reporting errors with
+// proper status codes, and the types sent back to clients. The schema for everything here lives
+// in openapi.json; the tests at the bottom check that every route is documented there.
+
+use crate::session::SessionInfo;
+use abstutil::serialize_btreemap;
+use geom::{Duration, LonLat, Time};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Response, StatusCode};
+use map_model::{
+    BuildingID, ControlTrafficSignal, LaneID, Map, MovementID, ParkingArea, ParkingLotID, RoadID,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sim::{GetDrawAgents, PersonID, Sim, TripID, TripMode, VehicleType};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+// Every route lives under this prefix. If the API ever changes incompatibly, bump this and keep
+// serving the old version for a while.
+pub const API_PREFIX: &str = "/v1";
+
+pub const OPENAPI_SCHEMA: &str = include_str!("openapi.json");
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Route {
+    SimReset,
+    SimLoad,
+    SimGetTime,
+    SimGotoTime,
+    SimNewPerson,
+    SimSubscribe,
+    SimSaveState,
+    SimLoadState,
+    SimListStates,
+    SimDeleteState,
+    TrafficSignalsGet,
+    TrafficSignalsSet,
+    TrafficSignalsGetDelays,
+    TrafficSignalsGetCumulativeThruput,
+    DataGetFinishedTrips,
+    DataGetAgentPositions,
+    DataGetParkingRevenue,
+    MapGetEdits,
+    MapSetEdits,
+    MapGetRoad,
+    MapEditRoad,
+    MapEditIntersection,
+    MapGetRouteSchedule,
+    MapEditRouteSchedule,
+    MapGetParkingPolicy,
+    MapEditParkingPolicy,
+    SessionsList,
+    SessionsCreate,
+    SessionsClone,
+    SessionsDelete,
+    Schema,
+}
+
+impl Route {
+    fn all() -> Vec<Route> {
+        vec![
+            Route::SimReset,
+            Route::SimLoad,
+            Route::SimGetTime,
+            Route::SimGotoTime,
+            Route::SimNewPerson,
+            Route::SimSubscribe,
+            Route::SimSaveState,
+            Route::SimLoadState,
+            Route::SimListStates,
+            Route::SimDeleteState,
+            Route::TrafficSignalsGet,
+            Route::TrafficSignalsSet,
+            Route::TrafficSignalsGetDelays,
+            Route::TrafficSignalsGetCumulativeThruput,
+            Route::DataGetFinishedTrips,
+            Route::DataGetAgentPositions,
+            Route::DataGetParkingRevenue,
+            Route::MapGetEdits,
+            Route::MapSetEdits,
+            Route::MapGetRoad,
+            Route::MapEditRoad,
+            Route::MapEditIntersection,
+            Route::MapGetRouteSchedule,
+            Route::MapEditRouteSchedule,
+            Route::MapGetParkingPolicy,
+            Route::MapEditParkingPolicy,
+            Route::SessionsList,
+            Route::SessionsCreate,
+            Route::SessionsClone,
+            Route::SessionsDelete,
+            Route::Schema,
+        ]
+    }
+
+    // Relative to API_PREFIX
+    fn path(self) -> &'static str {
+        match self {
+            Route::SimReset => "/sim/reset",
+            Route::SimLoad => "/sim/load",
+            Route::SimGetTime => "/sim/get-time",
+            Route::SimGotoTime => "/sim/goto-time",
+            Route::SimNewPerson => "/sim/new-person",
+            Route::SimSubscribe => "/sim/subscribe",
+            Route::SimSaveState => "/sim/save-state",
+            Route::SimLoadState => "/sim/load-state",
+            Route::SimListStates => "/sim/list-states",
+            Route::SimDeleteState => "/sim/delete-state",
+            Route::TrafficSignalsGet => "/traffic-signals/get",
+            Route::TrafficSignalsSet => "/traffic-signals/set",
+            Route::TrafficSignalsGetDelays => "/traffic-signals/get-delays",
+            Route::TrafficSignalsGetCumulativeThruput => "/traffic-signals/get-cumulative-thruput",
+            Route::DataGetFinishedTrips => "/data/get-finished-trips",
+            Route::DataGetAgentPositions => "/data/get-agent-positions",
+            Route::DataGetParkingRevenue => "/data/get-parking-revenue",
+            Route::MapGetEdits => "/map/get-edits",
+            Route::MapSetEdits => "/map/set-edits",
+            Route::MapGetRoad => "/map/get-road",
+            Route::MapEditRoad => "/map/edit-road",
+            Route::MapEditIntersection => "/map/edit-intersection",
+            Route::MapGetRouteSchedule => "/map/get-route-schedule",
+            Route::MapEditRouteSchedule => "/map/edit-route-schedule",
+            Route::MapGetParkingPolicy => "/map/get-parking-policy",
+            Route::MapEditParkingPolicy => "/map/edit-parking-policy",
+            Route::SessionsList => "/sessions/list",
+            Route::SessionsCreate => "/sessions/create",
+            Route::SessionsClone => "/sessions/clone",
+            Route::SessionsDelete => "/sessions/delete",
+            Route::Schema => "/schema",
+        }
+    }
+
+    fn method(self) -> Method {
+        match self {
+            Route::SimLoad
+            | Route::SimNewPerson
+            | Route::SimSaveState
+            | Route::SimLoadState
+            | Route::SimDeleteState
+            | Route::TrafficSignalsSet
+            | Route::MapSetEdits
+            | Route::MapEditRoad
+            | Route::MapEditIntersection
+            | Route::MapEditRouteSchedule
+            | Route::MapEditParkingPolicy
+            | Route::SessionsCreate
+            | Route::SessionsClone
+            | Route::SessionsDelete => Method::POST,
+            _ => Method::GET,
+        }
+    }
+
+    // Does this route operate on one session, chosen by the optional ?session= parameter?
+    pub fn uses_session(self) -> bool {
+        match self {
+            Route::SessionsList
+            | Route::SessionsCreate
+            | Route::SessionsClone
+            | Route::SessionsDelete
+            | Route::Schema => false,
+            _ => true,
+        }
+    }
+
+    pub fn parse(method: &Method, full_path: &str) -> Result<Route, ApiError> {
+        let path = if let Some(path) = full_path.strip_prefix(API_PREFIX) {
+            path
+        } else {
+            return Err(ApiError::not_found(format!(
+                "{} doesn't exist. All routes start with {}",
+                full_path, API_PREFIX
+            )));
+        };
+        let route = Route::all()
+            .into_iter()
+            .find(|r| r.path() == path)
+            .ok_or_else(|| ApiError::not_found(format!("{} doesn't exist", full_path)))?;
+        if &route.method() != method {
+            return Err(ApiError::method_not_allowed(format!(
+                "{} must be called with {}, not {}",
+                full_path,
+                route.method(),
+                method
+            )));
+        }
+        Ok(route)
+    }
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    status: u16,
+    error: String,
+}
+
+impl ApiError {
+    pub fn new<I: Into<String>>(status: StatusCode, message: I) -> ApiError {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request<I: Into<String>>(message: I) -> ApiError {
+        ApiError::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn not_found<I: Into<String>>(message: I) -> ApiError {
+        ApiError::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn method_not_allowed<I: Into<String>>(message: I) -> ApiError {
+        ApiError::new(StatusCode::METHOD_NOT_ALLOWED, message)
+    }
+
+    pub fn conflict<I: Into<String>>(message: I) -> ApiError {
+        ApiError::new(StatusCode::CONFLICT, message)
+    }
+
+    pub fn internal<I: Into<String>>(message: I) -> ApiError {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+
+    pub fn into_response(self) -> Response<Body> {
+        json_response(
+            self.status,
+            abstutil::to_json(&ErrorBody {
+                status: self.status.as_u16(),
+                error: self.message,
+            }),
+        )
+    }
+}
+
+pub fn json_response(status: StatusCode, json: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json))
+        .unwrap()
+}
+
+pub struct Params(pub HashMap<String, String>);
+
+impl Params {
+    pub fn required(&self, key: &str) -> Result<&str, ApiError> {
+        self.0
+            .get(key)
+            .map(|x| x.as_str())
+            .ok_or_else(|| ApiError::bad_request(format!("missing required parameter {}", key)))
+    }
+
+    pub fn optional(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(|x| x.as_str())
+    }
+
+    pub fn parse<T: FromStr>(&self, key: &str) -> Result<T, ApiError> {
+        let value = self.required(key)?;
+        value
+            .parse::<T>()
+            .map_err(|_| ApiError::bad_request(format!("parameter {}={} is malformed", key, value)))
+    }
+
+    pub fn optional_parse<T: FromStr>(&self, key: &str) -> Result<Option<T>, ApiError> {
+        if self.0.contains_key(key) {
+            self.parse(key).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Exactly one of lane, lot, or garage
+    pub fn parking_area(&self) -> Result<ParkingArea, ApiError> {
+        match (
+            self.optional_parse::<usize>("lane")?,
+            self.optional_parse::<usize>("lot")?,
+            self.optional_parse::<usize>("garage")?,
+        ) {
+            (Some(l), None, None) => Ok(ParkingArea::Lane(LaneID(l))),
+            (None, Some(pl), None) => Ok(ParkingArea::Lot(ParkingLotID(pl))),
+            (None, None, Some(b)) => Ok(ParkingArea::Garage(BuildingID(b))),
+            _ => Err(ApiError::bad_request(
+                "pass exactly one of the lane, lot, or garage parameters",
+            )),
+        }
+    }
+
+    pub fn time(&self, key: &str) -> Result<Time, ApiError> {
+        let value = self.required(key)?;
+        Time::parse(value)
+            .map_err(|err| ApiError::bad_request(format!("parameter {}={}: {}", key, value, err)))
+    }
+}
+
+pub fn parse_body<T: DeserializeOwned>(body: &Vec<u8>) -> Result<T, ApiError> {
+    abstutil::from_json(body)
+        .map_err(|err| ApiError::bad_request(format!("couldn't parse the JSON body: {}", err)))
+}
+
+// Response types. Every route returns one of these (or an error) as JSON.
+
+#[derive(Serialize)]
+pub struct Message {
+    pub message: String,
+}
+
+impl Message {
+    pub fn new<I: Into<String>>(message: I) -> Message {
+        Message {
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CurrentTime {
+    // Seconds since midnight
+    pub time: Time,
+    // Something like 07:30:00.0
+    pub pretty: String,
+}
+
+#[derive(Serialize)]
+pub struct NewPerson {
+    pub person: PersonID,
+}
+
+#[derive(Serialize)]
+pub struct FinishedTrips {
+    // TODO Hack: No TripMode means aborted
+    // Finish time, ID, mode (or None as aborted), trip duration
+    pub trips: Vec<(Time, TripID, Option<TripMode>, Duration)>,
+}
+
+#[derive(Serialize)]
+pub struct Delays {
+    #[serde(serialize_with = "serialize_btreemap")]
+    pub per_direction: BTreeMap<MovementID, Vec<Duration>>,
+}
+
+#[derive(Serialize)]
+pub struct Throughput {
+    #[serde(serialize_with = "serialize_btreemap")]
+    pub per_direction: BTreeMap<MovementID, usize>,
+}
+
+#[derive(Serialize)]
+pub struct AgentPositions {
+    pub agents: Vec<AgentPosition>,
+}
+
+impl AgentPositions {
+    pub fn new(sim: &Sim, map: &Map) -> AgentPositions {
+        AgentPositions {
+            agents: sim
+                .get_unzoomed_agents(map)
+                .into_iter()
+                .map(|a| AgentPosition {
+                    vehicle_type: a.vehicle_type,
+                    pos: a.pos.to_gps(map.get_gps_bounds()),
+                    person: a.person,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ParkingRevenue {
+    // Cents collected everywhere during each hour, labeled by the start of the hour
+    pub per_hour: Vec<(Time, usize)>,
+    // Every area with a price or time limit
+    pub areas: Vec<ParkingAreaRevenue>,
+}
+
+#[derive(Serialize)]
+pub struct ParkingAreaRevenue {
+    pub area: ParkingArea,
+    pub cents: usize,
+    // How many cars stayed past the time limit
+    pub overstays: usize,
+    // The average fraction of spots filled during each hour. Not tracked for garages.
+    pub occupancy_per_hour: Vec<(Time, f64)>,
+}
+
+impl ParkingRevenue {
+    pub fn new(sim: &Sim, map: &Map) -> ParkingRevenue {
+        let now = sim.time();
+        let analytics = sim.get_analytics();
+        let mut revenue = analytics.parking_revenue(now);
+        ParkingRevenue {
+            per_hour: analytics.parking_revenue_per_hour(now, None),
+            areas: map
+                .all_parking_policies()
+                .keys()
+                .map(|area| {
+                    let (cents, overstays) = revenue.remove(area).unwrap_or((0, 0));
+                    ParkingAreaRevenue {
+                        area: *area,
+                        cents,
+                        overstays,
+                        occupancy_per_hour: match area {
+                            ParkingArea::Lane(l) => analytics.parking_lane_occupancy_per_hour(
+                                now,
+                                *l,
+                                map.get_l(*l).number_parking_spots(),
+                            ),
+                            ParkingArea::Lot(pl) => analytics.parking_lot_occupancy_per_hour(
+                                now,
+                                *pl,
+                                map.get_pl(*pl).capacity(),
+                            ),
+                            ParkingArea::Garage(_) => Vec::new(),
+                        },
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AgentPosition {
+    // None for pedestrians
+    pub vehicle_type: Option<VehicleType>,
+    pub pos: LonLat,
+    // None for buses
+    pub person: Option<PersonID>,
+}
+
+#[derive(Serialize)]
+pub struct Savestates {
+    pub in_memory: Vec<SavestateInfo>,
+    // Just the labels. These're only for the session's current map.
+    pub on_disk: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct SavestateInfo {
+    pub label: String,
+    pub time: Time,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RouteSchedule {
+    // When a bus starts the route
+    pub departures: Vec<Time>,
+}
+
+// The body of /map/edit-intersection
+#[derive(Deserialize)]
+pub enum IntersectionControl {
+    // Roads left out keep the default assignment.
+    StopSign { must_stop: Vec<(RoadID, bool)> },
+    TrafficSignal(ControlTrafficSignal),
+    Closed,
+}
+
+#[derive(Serialize)]
+pub struct Sessions {
+    pub sessions: Vec<SessionInfo>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_matches_routes() {
+        let schema: serde_json::Value = serde_json::from_str(OPENAPI_SCHEMA).unwrap();
+        let paths = schema["paths"].as_object().unwrap();
+
+        let mut ok = true;
+        for route in Route::all() {
+            let method = route.method().as_str().to_lowercase();
+            if paths
+                .get(route.path())
+                .and_then(|p| p.get(&method))
+                .is_none()
+            {
+                println!("{} {} isn't in openapi.json", route.method(), route.path());
+                ok = false;
+            }
+        }
+        for (path, methods) in paths {
+            for method in methods.as_object().unwrap().keys() {
+                if !Route::all()
+                    .into_iter()
+                    .any(|r| r.path() == path && r.method().as_str().to_lowercase() == *method)
+                {
+                    println!(
+                        "openapi.json has {} {}, but there's no such route",
+                        method, path
+                    );
+                    ok = false;
+                }
+            }
+        }
+        assert!(ok);
+    }
+}
@@ -0,0 +1,297 @@
+// Granular map edits. Each one is validated the same way the UI does before it's applied, so a
+// script can't put the map into a state that the simulation can't handle. These are live edits,
+// like /traffic-signals/set; /sim/reset undoes them. The running simulation adjusts right away,
+// aborting trips that cross anything changed.
+
+use crate::api::{ApiError, IntersectionControl, RouteSchedule};
+use abstutil::Timer;
+use geom::{Duration, Speed};
+use map_model::{
+    connectivity, BusRouteID, ControlStopSign, ControlTrafficSignal, EditCmd, EditEffects,
+    EditIntersection, EditRoad, IntersectionID, IntersectionType, LaneType, Map, OffstreetParking,
+    ParkingArea, ParkingPolicy, PathConstraints, RoadID,
+};
+use sim::Sim;
+use std::collections::BTreeSet;
+
+pub fn get_road(map: &Map, r: RoadID) -> Result<EditRoad, ApiError> {
+    check_road(map, r)?;
+    Ok(map.get_r_edit(r))
+}
+
+pub fn edit_road(
+    map: &mut Map,
+    sim: &mut Sim,
+    r: RoadID,
+    new: EditRoad,
+) -> Result<EditEffects, ApiError> {
+    check_road(map, r)?;
+    let old = map.get_r_edit(r);
+    if old.lanes_ltr.len() != new.lanes_ltr.len() {
+        return Err(ApiError::bad_request(format!(
+            "{} has {} lanes, but the edit has {}. Lanes can be changed, but not added or removed.",
+            r,
+            old.lanes_ltr.len(),
+            new.lanes_ltr.len()
+        )));
+    }
+    for (idx, ((old_lt, old_dir), (new_lt, new_dir))) in
+        old.lanes_ltr.iter().zip(new.lanes_ltr.iter()).enumerate()
+    {
+        // Same as what the UI allows
+        let fixed = |lt: LaneType| {
+            lt == LaneType::Sidewalk
+                || lt == LaneType::Shoulder
+                || lt == LaneType::SharedLeftTurn
+                || lt == LaneType::LightRail
+        };
+        if (old_lt != new_lt || old_dir != new_dir) && (fixed(*old_lt) || fixed(*new_lt)) {
+            return Err(ApiError::bad_request(format!(
+                "lane {} of {} can't be changed from {:?} {:?} to {:?} {:?}",
+                idx, r, old_lt, old_dir, new_lt, new_dir
+            )));
+        }
+    }
+    if new.speed_limit <= Speed::ZERO {
+        return Err(ApiError::bad_request("the speed limit must be positive"));
+    }
+
+    let cmd = EditCmd::ChangeRoad { r, old, new };
+    let mut errors = Vec::new();
+    try_cmd(map, cmd.clone(), |map| {
+        let road = map.get_r(r);
+        let all_types: BTreeSet<LaneType> =
+            road.lanes_ltr().into_iter().map(|(_, _, lt)| lt).collect();
+        if all_types.contains(&LaneType::Parking) && !all_types.contains(&LaneType::Driving) {
+            errors.push("a parking lane needs a driving lane somewhere on the same road");
+        }
+        if !road.all_bus_stops(map).is_empty()
+            && !road
+                .lanes_ltr()
+                .into_iter()
+                .any(|(l, _, _)| PathConstraints::Bus.can_use(map.get_l(l), map))
+        {
+            errors.push("the bus stops on this road need a driving or bus lane");
+        }
+    });
+    if !errors.is_empty() {
+        return Err(ApiError::bad_request(errors.join("; ")));
+    }
+    Ok(apply(map, sim, cmd))
+}
+
+pub fn edit_intersection(
+    map: &mut Map,
+    sim: &mut Sim,
+    i: IntersectionID,
+    control: IntersectionControl,
+) -> Result<EditEffects, ApiError> {
+    if i.0 >= map.all_intersections().len() {
+        return Err(ApiError::not_found(format!("{} doesn't exist", i)));
+    }
+    if map.get_i(i).intersection_type == IntersectionType::Border {
+        return Err(ApiError::bad_request(format!(
+            "{} is a border, so it can't be edited",
+            i
+        )));
+    }
+
+    let new = match control {
+        IntersectionControl::StopSign { must_stop } => {
+            let mut ss = ControlStopSign::new(map, i);
+            for (r, stop) in must_stop {
+                if let Some(road) = ss.roads.get_mut(&r) {
+                    road.must_stop = stop;
+                } else {
+                    return Err(ApiError::bad_request(format!(
+                        "{} doesn't have a stop sign facing {}",
+                        i, r
+                    )));
+                }
+            }
+            EditIntersection::StopSign(ss)
+        }
+        IntersectionControl::TrafficSignal(ts) => {
+            if ts.id != i {
+                return Err(ApiError::bad_request(format!(
+                    "the signal is for {}, not {}",
+                    ts.id, i
+                )));
+            }
+            // The movements through a closed intersection don't exist yet, so the signal can't be
+            // checked.
+            if map.get_i(i).intersection_type == IntersectionType::Construction {
+                return Err(ApiError::bad_request(format!(
+                    "{} is closed. Reopen it with a stop sign first, then change it to a signal.",
+                    i
+                )));
+            }
+            let raw = ts
+                .validate()
+                .map_err(ApiError::bad_request)?
+                .export_edit(map);
+            ControlTrafficSignal::import_edit(raw.clone(), i, map)
+                .map_err(ApiError::bad_request)?;
+            EditIntersection::TrafficSignal(raw)
+        }
+        IntersectionControl::Closed => EditIntersection::Closed,
+    };
+    let cmd = EditCmd::ChangeIntersection {
+        i,
+        old: map.get_i_edit(i),
+        new,
+    };
+
+    // Closing intersections can strand people on sidewalks.
+    let (_, disconnected_before) = connectivity::find_scc(map, PathConstraints::Pedestrian);
+    let mut newly_disconnected = 0;
+    try_cmd(map, cmd.clone(), |map| {
+        let (_, disconnected_after) = connectivity::find_scc(map, PathConstraints::Pedestrian);
+        newly_disconnected = disconnected_after.difference(&disconnected_before).count();
+    });
+    if newly_disconnected > 0 {
+        return Err(ApiError::bad_request(format!(
+            "this edit would disconnect {} sidewalks",
+            newly_disconnected
+        )));
+    }
+    Ok(apply(map, sim, cmd))
+}
+
+pub fn get_route_schedule(map: &Map, id: BusRouteID) -> Result<RouteSchedule, ApiError> {
+    check_route(map, id)?;
+    Ok(RouteSchedule {
+        departures: map.get_br(id).spawn_times.clone(),
+    })
+}
+
+pub fn edit_route_schedule(
+    map: &mut Map,
+    sim: &mut Sim,
+    id: BusRouteID,
+    schedule: RouteSchedule,
+) -> Result<EditEffects, ApiError> {
+    check_route(map, id)?;
+    let new = schedule.departures;
+    if new.is_empty() {
+        return Err(ApiError::bad_request(
+            "a route needs at least one departure",
+        ));
+    }
+    if new.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(ApiError::bad_request(
+            "departure times must be in increasing order",
+        ));
+    }
+    let cmd = EditCmd::ChangeRouteSchedule {
+        id,
+        old: map.get_br(id).spawn_times.clone(),
+        new,
+    };
+    Ok(apply(map, sim, cmd))
+}
+
+pub fn get_parking_policy(map: &Map, area: ParkingArea) -> Result<ParkingPolicy, ApiError> {
+    check_parking_area(map, area)?;
+    Ok(map.get_parking_policy(area))
+}
+
+pub fn edit_parking_policy(
+    map: &mut Map,
+    sim: &mut Sim,
+    area: ParkingArea,
+    new: ParkingPolicy,
+) -> Result<EditEffects, ApiError> {
+    check_parking_area(map, area)?;
+    if new
+        .max_stay
+        .map(|max| max <= Duration::ZERO)
+        .unwrap_or(false)
+    {
+        return Err(ApiError::bad_request("the time limit must be positive"));
+    }
+    if new.loading_zone {
+        if let ParkingArea::Lot(_) | ParkingArea::Garage(_) = area {
+            return Err(ApiError::bad_request(
+                "only on-street parking can be a loading zone",
+            ));
+        }
+    }
+    let cmd = EditCmd::ChangeParkingPolicy {
+        area,
+        old: map.get_parking_policy(area),
+        new,
+    };
+    Ok(apply(map, sim, cmd))
+}
+
+fn check_parking_area(map: &Map, area: ParkingArea) -> Result<(), ApiError> {
+    match area {
+        ParkingArea::Lane(l) => {
+            if l.0 >= map.all_lanes().len() {
+                return Err(ApiError::not_found(format!("{} doesn't exist", l)));
+            }
+            if !map.get_l(l).is_parking() {
+                return Err(ApiError::bad_request(format!("{} isn't a parking lane", l)));
+            }
+        }
+        ParkingArea::Lot(pl) => {
+            if pl.0 >= map.all_parking_lots().len() {
+                return Err(ApiError::not_found(format!("{} doesn't exist", pl)));
+            }
+        }
+        ParkingArea::Garage(b) => {
+            if b.0 >= map.all_buildings().len() {
+                return Err(ApiError::not_found(format!("{} doesn't exist", b)));
+            }
+            if let OffstreetParking::Private(_, _) = map.get_b(b).parking {
+                return Err(ApiError::bad_request(format!(
+                    "{} doesn't have a public garage",
+                    b
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_road(map: &Map, r: RoadID) -> Result<(), ApiError> {
+    if r.0 >= map.all_roads().len() {
+        return Err(ApiError::not_found(format!("{} doesn't exist", r)));
+    }
+    Ok(())
+}
+
+fn check_route(map: &Map, id: BusRouteID) -> Result<(), ApiError> {
+    if id.0 >= map.all_bus_routes().len() {
+        return Err(ApiError::not_found(format!("{} doesn't exist", id)));
+    }
+    Ok(())
+}
+
+// Speculatively apply the command, run some checks, then undo it.
+fn try_cmd<F: FnMut(&Map)>(map: &mut Map, cmd: EditCmd, mut check: F) {
+    let orig_edits = map.get_edits().clone();
+    let mut edits = orig_edits.clone();
+    edits.commands.push(cmd);
+    map.try_apply_edits(edits, &mut Timer::throwaway());
+    check(map);
+    map.must_apply_edits(orig_edits, &mut Timer::throwaway());
+}
+
+// Same as what the UI does with live edits
+fn apply(map: &mut Map, sim: &mut Sim, cmd: EditCmd) -> EditEffects {
+    let mut edits = map.get_edits().clone();
+    edits.commands.push(cmd);
+    let (changed_roads, deleted_turns, added_turns, changed_intersections) =
+        map.must_apply_edits(edits, &mut Timer::throwaway());
+    map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+    sim.handle_live_edited_traffic_signals(map);
+    sim.handle_live_edits(map);
+    EditEffects {
+        changed_roads,
+        changed_intersections,
+        added_turns,
+        deleted_turns,
+    }
+}
@@ -2,35 +2,43 @@
 // https://dabreegster.github.io/abstreet/dev/api.html for documentation. To run this:
 //
 // > cd headless; cargo run -- --port=1234 ../data/system/scenarios/montlake/weekday.bin
-// > curl http://localhost:1234/get-time
-// 00:00:00.0
-// > curl http://localhost:1234/goto-time?t=01:01:00
-// it's now 01:01:00.0
-// > curl http://localhost:1234/get-delays
+// > curl http://localhost:1234/v1/sim/get-time
+// {"time": 0.0, "pretty": "00:00:00.0"}
+// > curl http://localhost:1234/v1/sim/goto-time?t=01:01:00
+// {"message": "it's now 01:01:00.0"}
+// > curl http://localhost:1234/v1/traffic-signals/get-delays?id=42&t1=00:00:00&t2=01:00:00
 // ... huge JSON blob
+// > curl http://localhost:1234/v1/schema
+// ... OpenAPI description of every route
+// > curl -N http://localhost:1234/v1/sim/subscribe?events=TripFinished&snapshots=60
+// ... events and agent positions as they happen, while somebody else calls goto-time
 
-use abstutil::{serialize_btreemap, CmdArgs, Timer};
-use geom::{Duration, LonLat, Time};
-use hyper::{Body, Request, Response, Server};
-use map_model::{
-    CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map,
-    MapEdits, MovementID, PermanentMapEdits,
+mod api;
+mod edits;
+mod savestates;
+mod session;
+mod stream;
+
+use crate::api::{
+    parse_body, AgentPositions, ApiError, CurrentTime, Delays, FinishedTrips, IntersectionControl,
+    Message, NewPerson, Params, ParkingRevenue, Route, RouteSchedule, Sessions, Throughput,
+    OPENAPI_SCHEMA,
 };
-use serde::Serialize;
-use sim::{
-    ExternalPerson, GetDrawAgents, PersonID, Scenario, Sim, SimFlags, SimOptions, TripID, TripMode,
-    VehicleType,
+use crate::session::{Session, DEFAULT_SESSION};
+use abstutil::{CmdArgs, Timer};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use map_model::{
+    BusRouteID, CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection, EditRoad,
+    IntersectionID, Map, MapEdits, MovementID, ParkingPolicy, PermanentMapEdits, RoadID,
 };
+use sim::{ExternalPerson, PersonID, Scenario, SimFlags};
 use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
-use std::error::Error;
 use std::sync::RwLock;
 
 lazy_static::lazy_static! {
-    static ref MAP: RwLock<Map> = RwLock::new(Map::blank());
-    static ref SIM: RwLock<Sim> = RwLock::new(Sim::new(&Map::blank(), SimOptions::new("tmp"), &mut Timer::throwaway()));
-    static ref FLAGS: RwLock<SimFlags> = RwLock::new(SimFlags::for_test("tmp"));
-    static ref EDITS: RwLock<Option<MapEdits>> = RwLock::new(None);
+    // New sessions take everything besides the scenario and modifiers from the command-line flags.
+    static ref TEMPLATE_FLAGS: RwLock<SimFlags> = RwLock::new(SimFlags::for_test("tmp"));
 }
 
 #[tokio::main]
@@ -42,16 +50,15 @@ async fn main() {
     let load_edits = args.optional("--edits");
     args.done();
 
-    let (mut map, sim, _) = sim_flags.load(&mut timer);
+    let mut session = Session::new(sim_flags.clone(), None, &mut timer);
     if let Some(path) = load_edits {
-        let edits = MapEdits::load(&map, path, &mut timer).unwrap();
-        *EDITS.write().unwrap() = Some(edits);
+        session.edits = Some(MapEdits::load(&session.map, path, &mut timer).unwrap());
+        session.apply_edits();
     }
+    session::insert(DEFAULT_SESSION.to_string(), session).unwrap();
+    *TEMPLATE_FLAGS.write().unwrap() = sim_flags;
 
-    apply_edits(&mut map);
-    *MAP.write().unwrap() = map;
-    *SIM.write().unwrap() = sim;
-    *FLAGS.write().unwrap() = sim_flags;
+    tokio::spawn(stream::keep_alive());
 
     let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
     println!("Listening on http://{}", addr);
@@ -64,131 +71,271 @@ async fn main() {
 }
 
 async fn serve_req(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+    let method = req.method().clone();
     let path = req.uri().path().to_string();
     // Url::parse needs an absolute URL
-    let params: HashMap<String, String> =
+    let params = Params(
         url::Url::parse(&format!("http://localhost{}", req.uri()))
             .unwrap()
             .query_pairs()
             .map(|(k, v)| (k.to_string(), v.to_string()))
-            .collect();
+            .collect::<HashMap<String, String>>(),
+    );
     let body = hyper::body::to_bytes(req).await?.to_vec();
-    let resp = match handle_command(
-        &path,
-        &params,
-        &body,
-        &mut SIM.write().unwrap(),
-        &mut MAP.write().unwrap(),
-    ) {
-        Ok(resp) => resp,
+    let result = match Route::parse(&method, &path) {
+        // This response never ends, so it can't be produced all at once like the others.
+        Ok(Route::SimSubscribe) => {
+            let name = params.optional("session").unwrap_or(DEFAULT_SESSION);
+            match stream::subscribe(name, &params) {
+                Ok(resp) => {
+                    return Ok(resp);
+                }
+                Err(err) => Err(err),
+            }
+        }
+        // Simulating can take a while, so don't tie up the threads handling other requests.
+        Ok(route) => {
+            match tokio::task::spawn_blocking(move || handle_command(route, &params, &body)).await {
+                Ok(result) => result,
+                Err(err) => Err(ApiError::internal(format!("the server crashed: {}", err))),
+            }
+        }
+        Err(err) => Err(err),
+    };
+    let resp = match result {
+        Ok(json) => api::json_response(StatusCode::OK, json),
         Err(err) => {
-            // TODO Error codes
-            format!("Bad command {} with params {:?}: {}", path, params, err)
+            println!(
+                "{} {} failed ({}): {}",
+                method, path, err.status, err.message
+            );
+            err.into_response()
         }
     };
-    Ok(Response::new(Body::from(resp)))
+    Ok(resp)
+}
+
+// Returns the JSON response
+fn handle_command(route: Route, params: &Params, body: &Vec<u8>) -> Result<String, ApiError> {
+    if route.uses_session() {
+        let name = params.optional("session").unwrap_or(DEFAULT_SESSION);
+        let session = session::get(name)?;
+        let mut session = session.lock().map_err(|_| {
+            ApiError::internal(format!(
+                "session {} crashed while handling an earlier request. Delete it and start over.",
+                name
+            ))
+        })?;
+        return handle_session_command(route, params, body, name, &mut session);
+    }
+
+    match route {
+        Route::SessionsList => Ok(abstutil::to_json(&Sessions {
+            sessions: session::list(),
+        })),
+        Route::SessionsCreate => {
+            let name = params.required("name")?;
+            session::check_name_free(name)?;
+            let input: SimFlags = parse_body(body)?;
+            let mut flags = TEMPLATE_FLAGS.read().unwrap().clone();
+            flags.load = input.load;
+            flags.modifiers = input.modifiers;
+            if let Some(seed) = params.optional_parse::<u8>("rng_seed")? {
+                flags.rng_seed = seed;
+            }
+            let session = Session::new(flags, None, &mut Timer::new(format!("create {}", name)));
+            session::insert(name.to_string(), session)?;
+            Ok(abstutil::to_json(&Message::new(format!(
+                "session {} created",
+                name
+            ))))
+        }
+        Route::SessionsClone => {
+            let from = params.required("from")?;
+            let name = params.required("name")?;
+            session::check_name_free(name)?;
+            let copy = session::get(from)?
+                .lock()
+                .map_err(|_| ApiError::internal(format!("session {} crashed earlier", from)))?
+                .duplicate();
+            session::insert(name.to_string(), copy)?;
+            Ok(abstutil::to_json(&Message::new(format!(
+                "session {} copied to {}",
+                from, name
+            ))))
+        }
+        Route::SessionsDelete => {
+            let name = params.required("name")?;
+            session::remove(name)?;
+            stream::unsubscribe_all(name);
+            Ok(abstutil::to_json(&Message::new(format!(
+                "session {} deleted",
+                name
+            ))))
+        }
+        Route::Schema => Ok(OPENAPI_SCHEMA.to_string()),
+        _ => unreachable!(),
+    }
 }
 
-fn handle_command(
-    path: &str,
-    params: &HashMap<String, String>,
+fn handle_session_command(
+    route: Route,
+    params: &Params,
     body: &Vec<u8>,
-    sim: &mut Sim,
-    map: &mut Map,
-) -> Result<String, Box<dyn Error>> {
-    match path {
+    name: &str,
+    session: &mut Session,
+) -> Result<String, ApiError> {
+    match route {
         // Controlling the simulation
-        "/sim/reset" => {
-            let (mut new_map, new_sim, _) =
-                FLAGS.read().unwrap().load(&mut Timer::new("reset sim"));
-            apply_edits(&mut new_map);
-            *map = new_map;
-            *sim = new_sim;
-            Ok(format!("sim reloaded"))
+        Route::SimReset => {
+            session.reset(&mut Timer::new("reset sim"));
+            Ok(abstutil::to_json(&Message::new("sim reloaded")))
         }
-        "/sim/load" => {
+        Route::SimLoad => {
+            let flags: SimFlags = parse_body(body)?;
+
             // Reset --edits
-            *EDITS.write().unwrap() = None;
+            session.edits = None;
+            // Savestates from a different scenario or map don't make sense anymore. The ones on
+            // disk stay, in case the controller switches back.
+            session.savestates.clear();
 
-            let flags: SimFlags = abstutil::from_json(body)?;
             // Only a few fields from SimFlags can be specified through the API. For the rest
             // (namely SimOptions), keep the ones from the command line.
-            FLAGS.write().unwrap().load = flags.load;
-            FLAGS.write().unwrap().modifiers = flags.modifiers;
+            session.flags.load = flags.load;
+            session.flags.modifiers = flags.modifiers;
 
             // Also reset
-            let (new_map, new_sim, _) = FLAGS.read().unwrap().load(&mut Timer::new("reset sim"));
-            *map = new_map;
-            *sim = new_sim;
-            Ok(format!("flags changed and sim reloaded"))
+            session.reset(&mut Timer::new("reset sim"));
+            Ok(abstutil::to_json(&Message::new(
+                "flags changed and sim reloaded",
+            )))
         }
-        "/sim/get-time" => Ok(sim.time().to_string()),
-        "/sim/goto-time" => {
-            let t = Time::parse(&params["t"])?;
-            if t <= sim.time() {
-                Err(format!("{} is in the past. call /sim/reset first?", t).into())
+        Route::SimGetTime => Ok(abstutil::to_json(&CurrentTime {
+            time: session.sim.time(),
+            pretty: session.sim.time().to_string(),
+        })),
+        Route::SimGotoTime => {
+            let t = params.time("t")?;
+            if t <= session.sim.time() {
+                Err(ApiError::conflict(format!(
+                    "{} is in the past. call /sim/reset or /sim/load-state first?",
+                    t
+                )))
             } else {
-                let dt = t - sim.time();
-                sim.timed_step(map, dt, &mut None, &mut Timer::new("goto-time"));
-                Ok(format!("it's now {}", t))
+                if let Some(interval) = stream::publish_interval(name) {
+                    // Somebody's watching, so advance in small steps and publish after each one.
+                    session.sim.record_events(true);
+                    while session.sim.time() < t {
+                        let dt = t - session.sim.time();
+                        session.sim.timed_step(
+                            &session.map,
+                            if dt < interval { dt } else { interval },
+                            &mut None,
+                            &mut Timer::throwaway(),
+                        );
+                        stream::publish(name, &mut session.sim, &session.map);
+                    }
+                    session.sim.record_events(false);
+                } else {
+                    let dt = t - session.sim.time();
+                    session.sim.timed_step(
+                        &session.map,
+                        dt,
+                        &mut None,
+                        &mut Timer::new("goto-time"),
+                    );
+                }
+                Ok(abstutil::to_json(&Message::new(format!("it's now {}", t))))
             }
         }
-        "/sim/new-person" => {
-            let input: ExternalPerson = abstutil::from_json(body)?;
+        Route::SimNewPerson => {
+            let input: ExternalPerson = parse_body(body)?;
             for trip in &input.trips {
-                if trip.departure < sim.time() {
-                    return Err(format!(
+                if trip.departure < session.sim.time() {
+                    return Err(ApiError::conflict(format!(
                         "It's {} now, so you can't start a trip at {}",
-                        sim.time(),
+                        session.sim.time(),
                         trip.departure
-                    )
-                    .into());
+                    )));
                 }
             }
 
-            let mut scenario = Scenario::empty(map, "one-shot");
-            scenario.people = ExternalPerson::import(map, vec![input])?;
-            let id = PersonID(sim.get_all_people().len());
+            let mut scenario = Scenario::empty(&session.map, "one-shot");
+            scenario.people =
+                ExternalPerson::import(&session.map, vec![input]).map_err(ApiError::bad_request)?;
+            let id = PersonID(session.sim.get_all_people().len());
             scenario.people[0].id = id;
-            let mut rng = FLAGS.read().unwrap().make_rng();
-            scenario.instantiate(sim, map, &mut rng, &mut Timer::throwaway());
-            Ok(format!("{} created", id))
+            let mut rng = session.flags.make_rng();
+            scenario.instantiate(
+                &mut session.sim,
+                &session.map,
+                &mut rng,
+                &mut Timer::throwaway(),
+            );
+            Ok(abstutil::to_json(&NewPerson { person: id }))
+        }
+        Route::SimSaveState => {
+            let label = params.required("label")?;
+            let to_disk = params.optional_parse::<bool>("to_disk")?.unwrap_or(false);
+            savestates::save(session, label, to_disk)?;
+            Ok(abstutil::to_json(&Message::new(format!(
+                "saved {} at {}",
+                label,
+                session.sim.time()
+            ))))
+        }
+        Route::SimLoadState => {
+            let label = params.required("label")?;
+            savestates::load(session, label)?;
+            Ok(abstutil::to_json(&Message::new(format!(
+                "loaded {}, it's now {}",
+                label,
+                session.sim.time()
+            ))))
+        }
+        Route::SimListStates => Ok(abstutil::to_json(&savestates::list(session))),
+        Route::SimDeleteState => {
+            let label = params.required("label")?;
+            savestates::delete(session, label)?;
+            Ok(abstutil::to_json(&Message::new(format!(
+                "deleted {}",
+                label
+            ))))
         }
         // Traffic signals
-        "/traffic-signals/get" => {
-            let i = IntersectionID(params["id"].parse::<usize>()?);
-            if let Some(ts) = map.maybe_get_traffic_signal(i) {
-                Ok(abstutil::to_json(ts))
-            } else {
-                Err(format!("{} isn't a traffic signal", i).into())
-            }
+        Route::TrafficSignalsGet => {
+            let i = IntersectionID(params.parse::<usize>("id")?);
+            Ok(abstutil::to_json(get_traffic_signal(&session.map, i)?))
         }
-        "/traffic-signals/set" => {
-            let ts: ControlTrafficSignal = abstutil::from_json(body)?;
+        Route::TrafficSignalsSet => {
+            let ts: ControlTrafficSignal = parse_body(body)?;
             let id = ts.id;
+            get_traffic_signal(&session.map, id)?;
 
             // incremental_edit_traffic_signal is the cheap option, but since we may need to call
             // get-edits later, go through the proper flow.
-            let mut edits = map.get_edits().clone();
+            let mut edits = session.map.get_edits().clone();
             edits.commands.push(EditCmd::ChangeIntersection {
                 i: id,
-                old: map.get_i_edit(id),
-                new: EditIntersection::TrafficSignal(ts.export(map)),
+                old: session.map.get_i_edit(id),
+                new: EditIntersection::TrafficSignal(ts.export_edit(&session.map)),
             });
-            map.must_apply_edits(edits, &mut Timer::throwaway());
-            map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+            session.map.must_apply_edits(edits, &mut Timer::throwaway());
+            session
+                .map
+                .recalculate_pathfinding_after_edits(&mut Timer::throwaway());
 
-            Ok(format!("{} has been updated", id))
+            Ok(abstutil::to_json(&Message::new(format!(
+                "{} has been updated",
+                id
+            ))))
         }
-        "/traffic-signals/get-delays" => {
-            let i = IntersectionID(params["id"].parse::<usize>()?);
-            let t1 = Time::parse(&params["t1"])?;
-            let t2 = Time::parse(&params["t2"])?;
-            let ts = if let Some(ts) = map.maybe_get_traffic_signal(i) {
-                ts
-            } else {
-                return Err(format!("{} isn't a traffic signal", i).into());
-            };
+        Route::TrafficSignalsGetDelays => {
+            let i = IntersectionID(params.parse::<usize>("id")?);
+            let t1 = params.time("t1")?;
+            let t2 = params.time("t2")?;
+            let ts = get_traffic_signal(&session.map, i)?;
             let movements: Vec<&MovementID> = ts.movements.keys().collect();
 
             let mut delays = Delays {
@@ -197,7 +344,7 @@ fn handle_command(
             for m in ts.movements.keys() {
                 delays.per_direction.insert(m.clone(), Vec::new());
             }
-            if let Some(list) = sim.get_analytics().intersection_delays.get(&i) {
+            if let Some(list) = session.sim.get_analytics().intersection_delays.get(&i) {
                 for (idx, t, dt, _) in list {
                     if *t >= t1 && *t <= t2 {
                         delays
@@ -210,13 +357,9 @@ fn handle_command(
             }
             Ok(abstutil::to_json(&delays))
         }
-        "/traffic-signals/get-cumulative-thruput" => {
-            let i = IntersectionID(params["id"].parse::<usize>()?);
-            let ts = if let Some(ts) = map.maybe_get_traffic_signal(i) {
-                ts
-            } else {
-                return Err(format!("{} isn't a traffic signal", i).into());
-            };
+        Route::TrafficSignalsGetCumulativeThruput => {
+            let i = IntersectionID(params.parse::<usize>("id")?);
+            let ts = get_traffic_signal(&session.map, i)?;
 
             let mut thruput = Throughput {
                 per_direction: BTreeMap::new(),
@@ -224,7 +367,9 @@ fn handle_command(
             for (idx, m) in ts.movements.keys().enumerate() {
                 thruput.per_direction.insert(
                     m.clone(),
-                    sim.get_analytics()
+                    session
+                        .sim
+                        .get_analytics()
                         .traffic_signal_thruput
                         .total_for(CompressedMovementID {
                             i,
@@ -235,79 +380,107 @@ fn handle_command(
             Ok(abstutil::to_json(&thruput))
         }
         // Querying data
-        "/data/get-finished-trips" => Ok(abstutil::to_json(&FinishedTrips {
-            trips: sim.get_analytics().finished_trips.clone(),
-        })),
-        "/data/get-agent-positions" => Ok(abstutil::to_json(&AgentPositions {
-            agents: sim
-                .get_unzoomed_agents(map)
-                .into_iter()
-                .map(|a| AgentPosition {
-                    vehicle_type: a.vehicle_type,
-                    pos: a.pos.to_gps(map.get_gps_bounds()),
-                    person: a.person,
-                })
-                .collect(),
+        Route::DataGetFinishedTrips => Ok(abstutil::to_json(&FinishedTrips {
+            trips: session.sim.get_analytics().finished_trips.clone(),
         })),
+        Route::DataGetAgentPositions => Ok(abstutil::to_json(&AgentPositions::new(
+            &session.sim,
+            &session.map,
+        ))),
+        Route::DataGetParkingRevenue => Ok(abstutil::to_json(&ParkingRevenue::new(
+            &session.sim,
+            &session.map,
+        ))),
         // Controlling the map
-        "/map/get-edits" => {
-            let mut edits = map.get_edits().clone();
+        Route::MapGetEdits => {
+            let mut edits = session.map.get_edits().clone();
             edits.commands.clear();
-            edits.compress(map);
+            edits.compress(&session.map);
             Ok(abstutil::to_json(&PermanentMapEdits::to_permanent(
-                &edits, map,
+                &edits,
+                &session.map,
             )))
         }
-        "/map/set-edits" => {
-            let perma: PermanentMapEdits = abstutil::from_json(body)?;
-            let edits = PermanentMapEdits::from_permanent(perma, map)?;
-            *EDITS.write().unwrap() = Some(edits);
-            apply_edits(map);
-            Ok(format!("loaded edits"))
+        Route::MapSetEdits => {
+            let perma: PermanentMapEdits = parse_body(body)?;
+            let edits = PermanentMapEdits::from_permanent(perma, &session.map)
+                .map_err(ApiError::bad_request)?;
+            session.edits = Some(edits);
+            session.apply_edits();
+            Ok(abstutil::to_json(&Message::new("loaded edits")))
+        }
+        Route::MapGetRoad => {
+            let r = RoadID(params.parse::<usize>("id")?);
+            Ok(abstutil::to_json(&edits::get_road(&session.map, r)?))
+        }
+        Route::MapEditRoad => {
+            let r = RoadID(params.parse::<usize>("id")?);
+            let new: EditRoad = parse_body(body)?;
+            Ok(abstutil::to_json(&edits::edit_road(
+                &mut session.map,
+                &mut session.sim,
+                r,
+                new,
+            )?))
+        }
+        Route::MapEditIntersection => {
+            let i = IntersectionID(params.parse::<usize>("id")?);
+            let control: IntersectionControl = parse_body(body)?;
+            Ok(abstutil::to_json(&edits::edit_intersection(
+                &mut session.map,
+                &mut session.sim,
+                i,
+                control,
+            )?))
+        }
+        Route::MapGetRouteSchedule => {
+            let id = BusRouteID(params.parse::<usize>("id")?);
+            Ok(abstutil::to_json(&edits::get_route_schedule(
+                &session.map,
+                id,
+            )?))
+        }
+        Route::MapEditRouteSchedule => {
+            let id = BusRouteID(params.parse::<usize>("id")?);
+            let schedule: RouteSchedule = parse_body(body)?;
+            Ok(abstutil::to_json(&edits::edit_route_schedule(
+                &mut session.map,
+                &mut session.sim,
+                id,
+                schedule,
+            )?))
         }
-        _ => Err("Unknown command".into()),
+        Route::MapGetParkingPolicy => {
+            let area = params.parking_area()?;
+            Ok(abstutil::to_json(&edits::get_parking_policy(
+                &session.map,
+                area,
+            )?))
+        }
+        Route::MapEditParkingPolicy => {
+            let area = params.parking_area()?;
+            let policy: ParkingPolicy = parse_body(body)?;
+            Ok(abstutil::to_json(&edits::edit_parking_policy(
+                &mut session.map,
+                &mut session.sim,
+                area,
+                policy,
+            )?))
+        }
+        // Handled directly by serve_req
+        Route::SimSubscribe => unreachable!(),
+        Route::SessionsList
+        | Route::SessionsCreate
+        | Route::SessionsClone
+        | Route::SessionsDelete
+        | Route::Schema => unreachable!(),
     }
 }
 
-fn apply_edits(map: &mut Map) {
-    if let Some(edits) = EDITS.read().unwrap().as_ref() {
-        let mut timer = Timer::new(format!("apply edits {}", edits.edits_name));
-        map.must_apply_edits(edits.clone(), &mut timer);
-        map.recalculate_pathfinding_after_edits(&mut timer);
+fn get_traffic_signal(map: &Map, i: IntersectionID) -> Result<&ControlTrafficSignal, ApiError> {
+    if i.0 >= map.all_intersections().len() {
+        return Err(ApiError::not_found(format!("{} doesn't exist", i)));
     }
-}
-
-// TODO I think specifying the API with protobufs or similar will be a better idea.
-
-#[derive(Serialize)]
-struct FinishedTrips {
-    // TODO Hack: No TripMode means aborted
-    // Finish time, ID, mode (or None as aborted), trip duration
-    trips: Vec<(Time, TripID, Option<TripMode>, Duration)>,
-}
-
-#[derive(Serialize)]
-struct Delays {
-    #[serde(serialize_with = "serialize_btreemap")]
-    per_direction: BTreeMap<MovementID, Vec<Duration>>,
-}
-
-#[derive(Serialize)]
-struct Throughput {
-    #[serde(serialize_with = "serialize_btreemap")]
-    per_direction: BTreeMap<MovementID, usize>,
-}
-
-#[derive(Serialize)]
-struct AgentPositions {
-    agents: Vec<AgentPosition>,
-}
-
-#[derive(Serialize)]
-struct AgentPosition {
-    // None for pedestrians
-    vehicle_type: Option<VehicleType>,
-    pos: LonLat,
-    // None for buses
-    person: Option<PersonID>,
+    map.maybe_get_traffic_signal(i)
+        .ok_or_else(|| ApiError::not_found(format!("{} isn't a traffic signal", i)))
 }
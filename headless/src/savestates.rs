@@ -0,0 +1,143 @@
+// Savestates let a controller simulate the early morning once, then branch from the same point as
+// many times as it likes. Each one remembers the live map edits too, since the agents' paths
+// depend on them. They're kept in memory, and optionally written to disk so they outlive the
+// server.
+
+use crate::api::{ApiError, SavestateInfo, Savestates};
+use crate::session::Session;
+use abstutil::Timer;
+use map_model::{MapEdits, PermanentMapEdits};
+use sim::Sim;
+
+#[derive(Clone)]
+pub struct Savestate {
+    sim: Sim,
+    edits: MapEdits,
+}
+
+pub fn save(session: &mut Session, label: &str, to_disk: bool) -> Result<(), ApiError> {
+    check_label(label)?;
+    if to_disk {
+        let path = disk_path(session.map.get_name(), label);
+        session.sim.save_as(path.clone());
+        abstutil::write_json(
+            edits_path(&path),
+            &PermanentMapEdits::to_permanent(session.map.get_edits(), &session.map),
+        );
+    }
+    session.savestates.insert(
+        label.to_string(),
+        Savestate {
+            sim: session.sim.clone(),
+            edits: session.map.get_edits().clone(),
+        },
+    );
+    Ok(())
+}
+
+// Prefers the copy in memory, if there is one.
+pub fn load(session: &mut Session, label: &str) -> Result<(), ApiError> {
+    check_label(label)?;
+    if let Some(state) = session.savestates.get(label) {
+        let state = state.clone();
+        session
+            .map
+            .must_apply_edits(state.edits, &mut Timer::throwaway());
+        session
+            .map
+            .recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+        session.sim = state.sim;
+        return Ok(());
+    }
+
+    let path = disk_path(session.map.get_name(), label);
+    if !abstutil::file_exists(path.clone()) {
+        return Err(ApiError::not_found(format!(
+            "no savestate named {} in memory or at {}",
+            label, path
+        )));
+    }
+    let mut timer = Timer::new(format!("load savestate {}", label));
+    // The paths in the savestate are recalculated, so the map has to match first.
+    let perma: PermanentMapEdits = abstutil::maybe_read_json(edits_path(&path), &mut timer)
+        .map_err(|err| ApiError::internal(format!("can't read edits for {}: {}", label, err)))?;
+    let edits = PermanentMapEdits::from_permanent(perma, &session.map).map_err(|err| {
+        ApiError::conflict(format!(
+            "the edits for {} don't match this map anymore: {}",
+            label, err
+        ))
+    })?;
+    session.map.must_apply_edits(edits.clone(), &mut timer);
+    session.map.recalculate_pathfinding_after_edits(&mut timer);
+    session.sim = Sim::load_savestate(path, &session.map, &mut timer)
+        .map_err(|err| ApiError::internal(format!("can't load {}: {}", label, err)))?;
+    session.savestates.insert(
+        label.to_string(),
+        Savestate {
+            sim: session.sim.clone(),
+            edits,
+        },
+    );
+    Ok(())
+}
+
+pub fn delete(session: &mut Session, label: &str) -> Result<(), ApiError> {
+    check_label(label)?;
+    let in_memory = session.savestates.remove(label).is_some();
+    let path = disk_path(session.map.get_name(), label);
+    let on_disk = abstutil::file_exists(path.clone());
+    if on_disk {
+        abstutil::delete_file(edits_path(&path));
+        abstutil::delete_file(path);
+    }
+    if !in_memory && !on_disk {
+        return Err(ApiError::not_found(format!("no savestate named {}", label)));
+    }
+    Ok(())
+}
+
+pub fn list(session: &Session) -> Savestates {
+    Savestates {
+        in_memory: session
+            .savestates
+            .iter()
+            .map(|(label, state)| SavestateInfo {
+                label: label.clone(),
+                time: state.sim.time(),
+            })
+            .collect(),
+        on_disk: abstutil::list_dir(std::path::Path::new(&abstutil::path(format!(
+            "player/saves/{}/headless",
+            session.map.get_name()
+        ))))
+        .into_iter()
+        .filter(|path| path.ends_with(".bin"))
+        .map(|path| abstutil::basename(&path))
+        .collect(),
+    }
+}
+
+// Labels become filenames, so keep them simple.
+fn check_label(label: &str) -> Result<(), ApiError> {
+    if label.is_empty()
+        || !label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(ApiError::bad_request(format!(
+            "savestate labels can only have letters, numbers, - and _, not {:?}",
+            label
+        )));
+    }
+    Ok(())
+}
+
+// Not under the usual savestate directory for the map, edits, and scenario, because the live
+// edits and scenario may change while the server runs.
+fn disk_path(map_name: &str, label: &str) -> String {
+    abstutil::path(format!("player/saves/{}/headless/{}.bin", map_name, label))
+}
+
+fn edits_path(sim_path: &str) -> String {
+    format!("{}_edits.json", sim_path.trim_end_matches(".bin"))
+}
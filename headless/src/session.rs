@@ -0,0 +1,178 @@
+// The server hosts any number of named sessions, each with its own map, simulation, and edits, so
+// independent experiments can run side-by-side. Sessions on the same map share the work of loading
+// it from disk.
+
+use crate::api::ApiError;
+use crate::savestates::Savestate;
+use abstutil::Timer;
+use geom::Time;
+use map_model::{Map, MapEdits};
+use serde::Serialize;
+use sim::{Sim, SimFlags};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+
+// Requests that don't specify a session use this one. It's created from the command-line flags.
+pub const DEFAULT_SESSION: &str = "default";
+
+pub struct Session {
+    pub map: Map,
+    pub sim: Sim,
+    pub flags: SimFlags,
+    // Applied on top of whatever map the flags load. Unlike live edits (like
+    // /traffic-signals/set), these survive /sim/reset.
+    pub edits: Option<MapEdits>,
+    // Keyed by label
+    pub savestates: BTreeMap<String, Savestate>,
+}
+
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub name: String,
+    // If the session is in the middle of handling another request, the other fields are missing.
+    pub busy: bool,
+    pub load: Option<String>,
+    pub map_name: Option<String>,
+    pub edits_name: Option<String>,
+    pub time: Option<Time>,
+}
+
+lazy_static::lazy_static! {
+    static ref SESSIONS: RwLock<BTreeMap<String, Arc<Mutex<Session>>>> = RwLock::new(BTreeMap::new());
+    // Keyed by path. These never have edits applied. Each map has its own lock, so loading one
+    // doesn't block sessions using another.
+    static ref BASE_MAPS: Mutex<HashMap<String, Arc<Mutex<Option<Map>>>>> = Mutex::new(HashMap::new());
+}
+
+impl Session {
+    pub fn new(flags: SimFlags, edits: Option<MapEdits>, timer: &mut Timer) -> Session {
+        let (map, sim, _) = flags.load_with_map(get_base_map, timer);
+        let mut session = Session {
+            map,
+            sim,
+            flags,
+            edits,
+            savestates: BTreeMap::new(),
+        };
+        session.apply_edits();
+        session
+    }
+
+    pub fn reset(&mut self, timer: &mut Timer) {
+        let (map, sim, _) = self.flags.load_with_map(get_base_map, timer);
+        self.map = map;
+        self.sim = sim;
+        self.apply_edits();
+    }
+
+    pub fn apply_edits(&mut self) {
+        if let Some(edits) = self.edits.as_ref() {
+            let mut timer = Timer::new(format!("apply edits {}", edits.edits_name));
+            self.map.must_apply_edits(edits.clone(), &mut timer);
+            self.map.recalculate_pathfinding_after_edits(&mut timer);
+        }
+    }
+
+    // Exactly copies the current state, including live map edits and savestates.
+    pub fn duplicate(&self) -> Session {
+        Session {
+            map: self.map.clone(),
+            sim: self.sim.clone(),
+            flags: self.flags.clone(),
+            edits: self.edits.clone(),
+            savestates: self.savestates.clone(),
+        }
+    }
+}
+
+// Maps are only loaded from disk once, then copied for every session that needs them. Sessions
+// asking for the same map while it's loading wait for it.
+fn get_base_map(path: String, timer: &mut Timer) -> Map {
+    let slot = BASE_MAPS
+        .lock()
+        .unwrap()
+        .entry(path.clone())
+        .or_insert_with(|| Arc::new(Mutex::new(None)))
+        .clone();
+    let mut maybe_map = slot.lock().unwrap();
+    if maybe_map.is_none() {
+        *maybe_map = Some(Map::new(path, timer));
+    } else {
+        timer.note(format!("Reusing {}, already loaded", path));
+    }
+    maybe_map.as_ref().unwrap().clone()
+}
+
+pub fn get(name: &str) -> Result<Arc<Mutex<Session>>, ApiError> {
+    SESSIONS
+        .read()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| ApiError::not_found(format!("no session named {}", name)))
+}
+
+pub fn insert(name: String, session: Session) -> Result<(), ApiError> {
+    let mut sessions = SESSIONS.write().unwrap();
+    if sessions.contains_key(&name) {
+        return Err(ApiError::conflict(format!(
+            "a session named {} already exists",
+            name
+        )));
+    }
+    sessions.insert(name, Arc::new(Mutex::new(session)));
+    Ok(())
+}
+
+// The name must not be taken already, but this doesn't reserve it. Use this to fail quickly before
+// doing anything expensive; insert will check again.
+pub fn check_name_free(name: &str) -> Result<(), ApiError> {
+    if name.is_empty() {
+        return Err(ApiError::bad_request("session names can't be empty"));
+    }
+    if SESSIONS.read().unwrap().contains_key(name) {
+        return Err(ApiError::conflict(format!(
+            "a session named {} already exists",
+            name
+        )));
+    }
+    Ok(())
+}
+
+pub fn remove(name: &str) -> Result<(), ApiError> {
+    // If somebody's still using the session, they'll finish their request, then it'll be dropped.
+    if SESSIONS.write().unwrap().remove(name).is_none() {
+        return Err(ApiError::not_found(format!("no session named {}", name)));
+    }
+    Ok(())
+}
+
+pub fn list() -> Vec<SessionInfo> {
+    let sessions: Vec<(String, Arc<Mutex<Session>>)> = SESSIONS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, session)| (name.clone(), session.clone()))
+        .collect();
+    sessions
+        .into_iter()
+        .map(|(name, session)| match session.try_lock() {
+            Ok(session) => SessionInfo {
+                name,
+                busy: false,
+                load: Some(session.flags.load.clone()),
+                map_name: Some(session.map.get_name().to_string()),
+                edits_name: session.edits.as_ref().map(|e| e.edits_name.clone()),
+                time: Some(session.sim.time()),
+            },
+            Err(_) => SessionInfo {
+                name,
+                busy: true,
+                load: None,
+                map_name: None,
+                edits_name: None,
+                time: None,
+            },
+        })
+        .collect()
+}
@@ -0,0 +1,211 @@
+// Clients can watch a session live instead of polling. /sim/subscribe opens a server-sent events
+// stream, and while /sim/goto-time runs, the simulation's events and periodic snapshots of agent
+// positions are pushed to everybody subscribed to that session.
+
+use crate::api::{AgentPositions, ApiError, Params};
+use crate::session;
+use geom::{Duration, Time};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Response, StatusCode};
+use map_model::Map;
+use serde::Serialize;
+use sim::Sim;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+// When nobody asks for snapshots, still publish events at least this often while simulating.
+const MAX_PUBLISH_INTERVAL: Duration = Duration::const_seconds(60.0);
+// A send only fails after the client disconnects, so ping everybody this often in real time.
+// Otherwise somebody whose filter matches nothing, or who's watching an idle session, is never
+// forgotten.
+const KEEPALIVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+lazy_static::lazy_static! {
+    // Keyed by session name
+    static ref SUBSCRIBERS: Mutex<BTreeMap<String, Vec<Subscriber>>> = Mutex::new(BTreeMap::new());
+}
+
+struct Subscriber {
+    // The names of the Event variants to send, like TripFinished. None means everything.
+    events: Option<BTreeSet<String>>,
+    snapshot_every: Option<Duration>,
+    last_snapshot: Option<Time>,
+    tx: UnboundedSender<Result<String, std::io::Error>>,
+}
+
+#[derive(Serialize)]
+struct TimedEvent<'a> {
+    time: Time,
+    event: &'a serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct Snapshot {
+    time: Time,
+    agents: AgentPositions,
+}
+
+// Parameters are events=Type1,Type2 (an empty list means no events) and snapshots=seconds.
+pub fn subscribe(name: &str, params: &Params) -> Result<Response<Body>, ApiError> {
+    // Make sure the session exists. Nothing stops it from being deleted right after, but then the
+    // stream just ends.
+    session::get(name)?;
+
+    let events = params.optional("events").map(|list| {
+        list.split(',')
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect::<BTreeSet<String>>()
+    });
+    let snapshot_every = match params.optional_parse::<f64>("snapshots")? {
+        Some(secs) if secs <= 0.0 => {
+            return Err(ApiError::bad_request(
+                "snapshots must be a positive number of seconds",
+            ));
+        }
+        Some(secs) => Some(Duration::seconds(secs)),
+        None => None,
+    };
+
+    let (tx, rx) = unbounded_channel();
+    // Let the client know the stream is live
+    tx.send(Ok(format!(": subscribed to session {}\n\n", name)))
+        .unwrap();
+    SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .entry(name.to_string())
+        .or_insert_with(Vec::new)
+        .push(Subscriber {
+            events,
+            snapshot_every,
+            last_snapshot: None,
+            tx,
+        });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(Body::wrap_stream(rx))
+        .unwrap())
+}
+
+// If anybody is watching this session, how far can the simulation advance before publishing
+// again?
+pub fn publish_interval(name: &str) -> Option<Duration> {
+    let subscribers = SUBSCRIBERS.lock().unwrap();
+    let list = subscribers.get(name)?;
+    if list.is_empty() {
+        return None;
+    }
+    Some(
+        list.iter()
+            .filter_map(|s| s.snapshot_every)
+            .fold(MAX_PUBLISH_INTERVAL, |a, b| if b < a { b } else { a }),
+    )
+}
+
+// Sends everything recorded since the last call to every subscriber that wants it. Subscribers
+// who've disconnected are forgotten.
+pub fn publish(name: &str, sim: &mut Sim, map: &Map) {
+    let events: Vec<(Time, String, serde_json::Value)> = sim
+        .drain_recorded_events()
+        .into_iter()
+        .map(|(time, ev)| {
+            let value = serde_json::to_value(&ev).unwrap();
+            (time, event_type(&value), value)
+        })
+        .collect();
+    let now = sim.time();
+
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    let list = if let Some(list) = subscribers.get_mut(name) {
+        list
+    } else {
+        return;
+    };
+    // Only calculate this if somebody wants it
+    let mut snapshot: Option<String> = None;
+    let mut still_connected = Vec::new();
+    for mut sub in list.drain(..) {
+        let mut msgs = Vec::new();
+        for (time, ev_type, ev) in &events {
+            if sub
+                .events
+                .as_ref()
+                .map(|filter| filter.contains(ev_type))
+                .unwrap_or(true)
+            {
+                msgs.push(sse(
+                    "sim_event",
+                    serde_json::to_string(&TimedEvent {
+                        time: *time,
+                        event: ev,
+                    })
+                    .unwrap(),
+                ));
+            }
+        }
+        if let Some(every) = sub.snapshot_every {
+            if sub.last_snapshot.map(|t| now - t >= every).unwrap_or(true) {
+                sub.last_snapshot = Some(now);
+                if snapshot.is_none() {
+                    snapshot = Some(sse(
+                        "snapshot",
+                        serde_json::to_string(&Snapshot {
+                            time: now,
+                            agents: AgentPositions::new(sim, map),
+                        })
+                        .unwrap(),
+                    ));
+                }
+                msgs.push(snapshot.clone().unwrap());
+            }
+        }
+        // If any send fails, the client disconnected, so forget about them.
+        if msgs.into_iter().all(|msg| sub.tx.send(Ok(msg)).is_ok()) {
+            still_connected.push(sub);
+        }
+    }
+    *list = still_connected;
+}
+
+// Runs forever, sending a comment (which clients ignore) to every subscriber and forgetting the
+// ones who've disconnected.
+pub async fn keep_alive() {
+    let mut interval = tokio::time::interval(KEEPALIVE_INTERVAL);
+    loop {
+        interval.tick().await;
+        ping_all();
+    }
+}
+
+fn ping_all() {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    for list in subscribers.values_mut() {
+        list.retain(|sub| sub.tx.send(Ok(": keepalive\n\n".to_string())).is_ok());
+    }
+    subscribers.retain(|_, list| !list.is_empty());
+}
+
+// Ends the stream for everybody watching this session.
+pub fn unsubscribe_all(name: &str) {
+    SUBSCRIBERS.lock().unwrap().remove(name);
+}
+
+// The name of the Event variant, like TripFinished
+fn event_type(ev: &serde_json::Value) -> String {
+    match ev {
+        serde_json::Value::String(name) => name.clone(),
+        serde_json::Value::Object(map) => map.keys().next().unwrap().clone(),
+        x => panic!("Event serialized strangely: {}", x),
+    }
+}
+
+// The wire format is described at
+// https://html.spec.whatwg.org/multipage/server-sent-events.html. The JSON is all on one line.
+fn sse(event: &str, data: String) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
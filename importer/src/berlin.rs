@@ -76,6 +76,7 @@ pub fn osm_to_raw(name: &str, timer: &mut Timer, config: &ImporterConfiguration)
             private_offstreet_parking: convert_osm::PrivateOffstreetParking::FixedPerBldg(3),
             elevation: None,
             include_railroads: true,
+            gtfs: crate::utils::local_gtfs("berlin"),
         },
         timer,
     );
@@ -0,0 +1,109 @@
+// For every building with residents, counts the jobs and shops reachable within some time by one
+// mode, and writes the results as GeoJSON points, for equity analyses in QGIS or similar.
+//
+// cargo run --release --bin accessibility -- data/system/maps/montlake.bin --mode=transit \
+//     --minutes=30 --time=08:00:00 --output=accessibility.json
+//
+// Modes are walk, bike, drive, and transit. Travel times ignore traffic, delays at intersections,
+// and parking. The time of day only matters for transit, since waits depend on the schedule.
+
+use abstutil::{prettyprint_usize, CmdArgs, Parallelism, Timer};
+use geojson::feature::Id;
+use geojson::{Feature, FeatureCollection, GeoJson, Geometry, Value};
+use geom::{Duration, Time};
+use map_model::connectivity::{IsochroneGraph, TravelMode};
+use map_model::{Building, BuildingID, BuildingType, Map};
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let map_path = args.required_free();
+    let mode = args
+        .optional_parse("--mode", |s| {
+            TravelMode::all()
+                .into_iter()
+                .find(|m| m.noun() == s)
+                .ok_or_else(|| format!("unknown mode {}", s))
+        })
+        .unwrap_or(TravelMode::Walk);
+    let minutes = args
+        .optional_parse("--minutes", |s| s.parse::<usize>())
+        .unwrap_or(15);
+    let depart = args
+        .optional_parse("--time", Time::parse)
+        .unwrap_or(Time::START_OF_DAY + Duration::hours(8));
+    let output = args
+        .optional("--output")
+        .unwrap_or_else(|| "accessibility.json".to_string());
+    args.done();
+
+    let mut timer = Timer::new("measure accessibility");
+    let map = Map::new(map_path, &mut timer);
+    timer.start(format!("build {} graph", mode.noun()));
+    let graph = IsochroneGraph::new(&map, mode);
+    timer.stop(format!("build {} graph", mode.noun()));
+
+    let homes: Vec<BuildingID> = map
+        .all_buildings()
+        .iter()
+        .filter(|b| b.bldg_type.has_residents())
+        .map(|b| b.id)
+        .collect();
+    let time_limit = Duration::minutes(minutes);
+    let results = timer.parallelize("flood from every home", Parallelism::Fastest, homes, |b| {
+        let mut jobs = 0;
+        let mut shops = 0;
+        for (dst, _) in graph.all_costs_from(&map, b, depart, time_limit) {
+            let dst = map.get_b(dst);
+            jobs += num_jobs(dst);
+            shops += dst.amenities.len();
+        }
+        (b, jobs, shops)
+    });
+
+    let gps_bounds = map.get_gps_bounds();
+    let mut features = Vec::new();
+    for (b, jobs, shops) in results {
+        let bldg = map.get_b(b);
+        let pt = bldg.label_center.to_gps(gps_bounds);
+        let mut properties = serde_json::Map::new();
+        properties.insert("osm_id".to_string(), bldg.orig_id.to_string().into());
+        properties.insert("residents".to_string(), num_residents(bldg).into());
+        properties.insert("mode".to_string(), mode.noun().into());
+        properties.insert("minutes".to_string(), minutes.into());
+        properties.insert("time".to_string(), depart.to_string().into());
+        properties.insert("jobs".to_string(), jobs.into());
+        properties.insert("shops".to_string(), shops.into());
+        features.push(Feature {
+            bbox: None,
+            geometry: Some(Geometry::new(Value::Point(vec![pt.x(), pt.y()]))),
+            id: Some(Id::Number(b.0.into())),
+            properties: Some(properties),
+            foreign_members: None,
+        });
+    }
+    println!(
+        "{} homes measured, writing {}",
+        prettyprint_usize(features.len()),
+        output
+    );
+    let geojson = GeoJson::from(FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    });
+    abstutil::write_json(output, &geojson);
+}
+
+fn num_residents(b: &Building) -> usize {
+    match b.bldg_type {
+        BuildingType::Residential(n) | BuildingType::ResidentialCommercial(n, _) => n,
+        BuildingType::Commercial(_) | BuildingType::Empty => 0,
+    }
+}
+
+fn num_jobs(b: &Building) -> usize {
+    match b.bldg_type {
+        BuildingType::Commercial(n) | BuildingType::ResidentialCommercial(_, n) => n,
+        BuildingType::Residential(_) | BuildingType::Empty => 0,
+    }
+}
@@ -0,0 +1,177 @@
+// Iterative dynamic traffic assignment. Scenarios normally send every driver along the fastest
+// route without any traffic, so on congested maps, everybody piles onto the same arterials. This
+// simulates the whole day, measures how long cars actually took to cross each road every hour,
+// and moves some drivers to routes that would've been faster, using the method of successive
+// averages. It repeats until the relative gap is small enough, then saves the routes in a copy of
+// the scenario named <name>_assigned, so later runs can start from equilibrium.
+//
+// cargo run --release --bin assign_traffic -- data/system/scenarios/montlake/weekday.bin
+//
+// Any of the usual simulation flags also work.
+
+use abstutil::{CmdArgs, Parallelism, Timer};
+use geom::{Duration, Time};
+use map_model::{
+    Lane, LaneID, Map, Path, PathConstraints, PathRequest, RoutingProfile, Traversable,
+};
+use rand::Rng;
+use sim::{Analytics, Scenario, Sim, SimFlags, TripID, TripPhaseType};
+use std::collections::BTreeMap;
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let flags = SimFlags::from_args(&mut args);
+    let max_iterations = args
+        .optional_parse("--iterations", |s| s.parse::<usize>())
+        .unwrap_or(10);
+    // Stop once the routes drivers used are this close to the best ones available, relatively
+    let max_gap = args
+        .optional_parse("--max_gap", |s| s.parse::<f64>())
+        .unwrap_or(0.01);
+    args.done();
+
+    let mut timer = Timer::new("assign traffic");
+    let mut scenario: Scenario = abstutil::read_binary(flags.load.clone(), &mut timer);
+    let map = Map::new(abstutil::path_map(&scenario.map_name), &mut timer);
+    let mut rng = flags.make_rng();
+
+    for iteration in 1..=max_iterations {
+        timer.start(format!("iteration {}", iteration));
+        let mut sim = Sim::new(&map, flags.opts.clone(), &mut timer);
+        scenario.instantiate(&mut sim, &map, &mut flags.make_rng(), &mut timer);
+        let end_of_day = Time::START_OF_DAY + Duration::hours(24);
+        while !sim.is_done() && sim.time() < end_of_day {
+            sim.timed_step(&map, Duration::hours(1), &mut None, &mut timer);
+        }
+
+        let legs = driving_legs(&sim, &scenario);
+        let analytics = sim.get_analytics();
+        let results = timer.parallelize(
+            "compare routes",
+            Parallelism::Fastest,
+            legs,
+            |(trip, depart, req)| compare_routes(trip, depart, req, &scenario, analytics, &map),
+        );
+
+        let mut total_current = Duration::ZERO;
+        let mut total_best = Duration::ZERO;
+        for (_, _, current, best) in &results {
+            total_current += current.1;
+            total_best += best.as_ref().unwrap_or(current).1;
+        }
+        let gap = if total_best == Duration::ZERO {
+            0.0
+        } else {
+            (total_current - total_best) / total_best
+        };
+
+        // Each iteration, a smaller share of drivers switch, so the assignment settles down
+        let step = 1.0 / ((iteration + 1) as f64);
+        let mut reassigned = 0;
+        let num_legs = results.len();
+        for ((person, idx), _, current, best) in results {
+            let trip = &mut scenario.people[person].trips[idx];
+            trip.route = Some(current.0);
+            if gap > max_gap {
+                if let Some((route, dt)) = best {
+                    if dt < current.1 && rng.gen_bool(step) {
+                        trip.route = Some(route);
+                        reassigned += 1;
+                    }
+                }
+            }
+        }
+        timer.stop(format!("iteration {}", iteration));
+        println!(
+            "Iteration {}: relative gap {:.4}, moved {} of {} drivers",
+            iteration, gap, reassigned, num_legs
+        );
+        if gap <= max_gap {
+            break;
+        }
+    }
+
+    scenario.scenario_name = format!("{}_assigned", scenario.scenario_name);
+    scenario.save();
+    println!(
+        "Wrote {}",
+        abstutil::path_scenario(&scenario.map_name, &scenario.scenario_name)
+    );
+}
+
+// For every personal car trip following the fastest route, where the scenario has it, when the
+// driving started, and where the car went
+fn driving_legs(sim: &Sim, scenario: &Scenario) -> Vec<((usize, usize), Time, PathRequest)> {
+    let mut trip_to_scenario: BTreeMap<TripID, (usize, usize)> = BTreeMap::new();
+    for (person_idx, person) in scenario.people.iter().enumerate() {
+        for (idx, trip) in sim.get_person(person.id).trips.iter().enumerate() {
+            trip_to_scenario.insert(*trip, (person_idx, idx));
+        }
+    }
+
+    let mut legs: BTreeMap<TripID, (Time, PathRequest)> = BTreeMap::new();
+    for (time, trip, maybe_req, phase) in &sim.get_analytics().trip_log {
+        if let (TripPhaseType::Driving, Some(req)) = (phase, maybe_req) {
+            if req.constraints == PathConstraints::Car && req.profile == RoutingProfile::Fastest {
+                legs.entry(*trip).or_insert_with(|| (*time, req.clone()));
+            }
+        }
+    }
+    legs.into_iter()
+        .filter_map(|(trip, (time, req))| Some((*trip_to_scenario.get(&trip)?, time, req)))
+        .collect()
+}
+
+// Returns the route the driver used and the best one now, as lanes along with how long each takes
+// with the travel times measured in the hour the driver would reach each road. The best is None if
+// there's no path.
+fn compare_routes(
+    trip: (usize, usize),
+    depart: Time,
+    req: PathRequest,
+    scenario: &Scenario,
+    analytics: &Analytics,
+    map: &Map,
+) -> (
+    (usize, usize),
+    Time,
+    (Vec<LaneID>, Duration),
+    Option<(Vec<LaneID>, Duration)>,
+) {
+    // Drivers don't expect to go faster than the speed limit, even if somebody did
+    let lane_time = |lane: &Lane, time: Time| {
+        let speed_limit_time = lane.length() / map.get_r(lane.parent).speed_limit;
+        match analytics
+            .hourly_road_travel_times
+            .get(&(lane.get_directed_parent(map), time.get_hours()))
+        {
+            Some((total, count)) => (*total / (*count as f64)).max(speed_limit_time),
+            None => speed_limit_time,
+        }
+    };
+    let describe = |path: Path| {
+        let mut lanes = Vec::new();
+        let mut total = Duration::ZERO;
+        for step in path.get_steps() {
+            let dt = match step.as_traversable() {
+                Traversable::Lane(l) => {
+                    lanes.push(l);
+                    lane_time(map.get_l(l), depart + total)
+                }
+                on => on.length(map) / on.speed_limit(map),
+            };
+            total += dt;
+        }
+        (lanes, total)
+    };
+
+    // Same as what the simulation does
+    let current = scenario.people[trip.0].trips[trip.1]
+        .route
+        .as_ref()
+        .and_then(|route| Path::from_lanes(&req, route, map))
+        .or_else(|| map.pathfind(req.clone()))
+        .unwrap();
+    let best = map.pathfind_with_travel_times(req, depart, &lane_time);
+    (trip, depart, describe(current), best.map(describe))
+}
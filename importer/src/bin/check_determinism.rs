@@ -0,0 +1,57 @@
+// Runs a scenario twice and checks that both runs do exactly the same thing. Or with --golden,
+// runs once and compares against a trace saved earlier with --save_trace. Usage:
+//
+// > cargo run --release --bin check_determinism -- data/system/scenarios/montlake/weekday.bin
+//
+// Any of the usual simulation flags, like --rng_seed, work.
+
+use abstutil::{CmdArgs, Timer};
+use geom::{Duration, Time};
+use sim::{ReplayTrace, SimFlags};
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let flags = SimFlags::from_args(&mut args);
+    let end_time = args
+        .optional_parse("--until", |t| Time::parse(t))
+        .unwrap_or(Time::START_OF_DAY + Duration::hours(24));
+    let golden = args.optional("--golden");
+    let save_trace = args.optional("--save_trace");
+    args.done();
+
+    let mut timer = Timer::new("check determinism");
+    let expected = if let Some(path) = golden {
+        abstutil::read_binary::<ReplayTrace>(path, &mut timer)
+    } else {
+        run(&flags, end_time, &mut timer)
+    };
+    let actual = run(&flags, end_time, &mut timer);
+    if let Some(path) = save_trace {
+        abstutil::write_binary(path, &actual);
+    }
+
+    match expected.first_divergence(&actual) {
+        Some(divergence) => {
+            println!("{}", divergence);
+            std::process::exit(1);
+        }
+        None => {
+            println!(
+                "Both runs match, through {} steps",
+                abstutil::prettyprint_usize(actual.num_steps())
+            );
+        }
+    }
+}
+
+fn run(flags: &SimFlags, end_time: Time, timer: &mut Timer) -> ReplayTrace {
+    let (map, mut sim, _) = flags.load(timer);
+    sim.record_trace();
+    timer.start(format!("simulate until {}", end_time));
+    while !sim.is_done() && sim.time() < end_time {
+        let dt = (end_time - sim.time()).min(Duration::hours(1));
+        sim.timed_step(&map, dt, &mut None, &mut Timer::throwaway());
+    }
+    timer.stop(format!("simulate until {}", end_time));
+    sim.take_trace().unwrap()
+}
@@ -0,0 +1,508 @@
+// Runs many simulations in parallel and compares proposed map edits against a baseline without
+// them. The input is a JSON file like:
+//
+// {
+//   "scenarios": ["data/system/scenarios/montlake/weekday.bin"],
+//   "modifier_sets": [[], [{"CancelPeople": 10}]],
+//   "seeds": [42, 7],
+//   "sim_options": [[], ["--car_acceleration", "--ride_hail_fleet=50"]],
+//   "edits": ["data/player/edits/montlake/bike lanes.json"],
+//   "end_time": "24:00:00"
+// }
+//
+// Every combination of scenario, set of modifiers, seed, and set of simulation flags runs once
+// without edits and once per edits file. Reports go in the --output directory:
+//
+// - report.json: everything below, plus a summary of every run
+// - modes.csv: trip time statistics per mode for every run, next to the baseline's
+// - trips.csv: every trip's duration with and without each set of edits, including trips that only
+//   finished in one of the runs
+// - intersections.csv: the average delay at every traffic signal, next to the baseline's
+
+use abstutil::{CmdArgs, Parallelism, Timer};
+use geom::{Duration, Histogram, Statistic, Time};
+use map_model::{IntersectionID, Map, MapEdits};
+use serde::{Deserialize, Serialize};
+use sim::{ScenarioModifier, SimFlags, TripID, TripMode};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let config_path = args.required("--config");
+    let output = args.required("--output");
+    let parallelism = if args.enabled("--polite") {
+        Parallelism::Polite
+    } else {
+        Parallelism::Fastest
+    };
+    args.done();
+
+    let mut timer = Timer::new("run experiments");
+    let config: Config = abstutil::read_json(config_path, &mut timer);
+    let end_time = config
+        .end_time
+        .as_ref()
+        .map(|t| Time::parse(t).unwrap())
+        .unwrap_or(Time::START_OF_DAY + Duration::hours(24));
+    let modifier_sets = if config.modifier_sets.is_empty() {
+        vec![Vec::new()]
+    } else {
+        config.modifier_sets.clone()
+    };
+    let option_sets = if config.sim_options.is_empty() {
+        vec![Vec::new()]
+    } else {
+        config.sim_options.clone()
+    };
+    // Catch typos before spending hours simulating
+    for options in &option_sets {
+        make_flags("scenario", options, 0);
+    }
+
+    let mut jobs = Vec::new();
+    for scenario in &config.scenarios {
+        for modifiers in &modifier_sets {
+            for seed in &config.seeds {
+                for options in &option_sets {
+                    for edits in std::iter::once(None).chain(config.edits.iter().map(Some)) {
+                        jobs.push(Job {
+                            scenario: scenario.clone(),
+                            modifiers: modifiers.clone(),
+                            seed: *seed,
+                            options: options.clone(),
+                            edits: edits.cloned(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Keyed by path. Each map has its own lock, so jobs only wait for somebody else loading the
+    // same map.
+    let base_maps: Mutex<HashMap<String, Arc<Mutex<Option<Map>>>>> = Mutex::new(HashMap::new());
+    let base_maps = &base_maps;
+    let results = timer.parallelize(
+        &format!("run {} simulations", jobs.len()),
+        parallelism,
+        jobs,
+        |job| run(job, end_time, base_maps),
+    );
+
+    write_reports(results, &output, &mut timer);
+}
+
+#[derive(Deserialize)]
+struct Config {
+    // Paths to scenario files
+    scenarios: Vec<String>,
+    // Each entry is applied to the scenario together. If this is empty, scenarios are run as-is.
+    #[serde(default)]
+    modifier_sets: Vec<Vec<ScenarioModifier>>,
+    seeds: Vec<u8>,
+    // Each entry is a list of the usual simulation flags, like --car_acceleration, used together.
+    // If this is empty, runs use the defaults.
+    #[serde(default)]
+    sim_options: Vec<Vec<String>>,
+    // Paths to edits files. Every one is compared against the run without any edits.
+    edits: Vec<String>,
+    // Like 24:00:00. Trips that haven't finished by then count as unfinished.
+    end_time: Option<String>,
+}
+
+struct Job {
+    scenario: String,
+    modifiers: Vec<ScenarioModifier>,
+    seed: u8,
+    options: Vec<String>,
+    edits: Option<String>,
+}
+
+impl Job {
+    // Identifies the baseline this job gets compared to
+    fn baseline_key(&self) -> (String, String, u8, String) {
+        (
+            self.scenario.clone(),
+            describe_modifiers(&self.modifiers),
+            self.seed,
+            describe_options(&self.options),
+        )
+    }
+}
+
+struct RunResult {
+    job: Job,
+    end_time: Time,
+    unfinished_trips: usize,
+    // None mode means aborted
+    trips: BTreeMap<TripID, (Option<TripMode>, Duration)>,
+    intersection_delays: BTreeMap<IntersectionID, Vec<Duration>>,
+}
+
+// Parses the simulation flags the same way as the command line, so they also pick the RNG seed for
+// things like the pandemic model.
+fn make_flags(scenario: &str, options: &Vec<String>, seed: u8) -> SimFlags {
+    let mut raw = vec![scenario.to_string(), "--run_name=experiment".to_string()];
+    raw.extend(options.iter().cloned());
+    raw.push(format!("--rng_seed={}", seed));
+    let mut args = CmdArgs::from_args(raw);
+    let flags = SimFlags::from_args(&mut args);
+    args.done();
+    flags
+}
+
+fn run(
+    job: Job,
+    end_time: Time,
+    base_maps: &Mutex<HashMap<String, Arc<Mutex<Option<Map>>>>>,
+) -> RunResult {
+    let mut flags = make_flags(&job.scenario, &job.options, job.seed);
+    flags.modifiers = job.modifiers.clone();
+    let mut timer = Timer::throwaway();
+    let (map, mut sim, _) = flags.load_with_map(
+        |path, timer| {
+            // Only hold the lock for the whole cache long enough to find this map's slot
+            let slot = base_maps
+                .lock()
+                .unwrap()
+                .entry(path.clone())
+                .or_insert_with(|| Arc::new(Mutex::new(None)))
+                .clone();
+            let mut map = {
+                let mut maybe_map = slot.lock().unwrap();
+                if maybe_map.is_none() {
+                    *maybe_map = Some(Map::new(path, timer));
+                }
+                maybe_map.as_ref().unwrap().clone()
+            };
+            // The edits have to be in place before the scenario is instantiated.
+            if let Some(ref edits) = job.edits {
+                let edits = MapEdits::load(&map, edits.clone(), timer).unwrap();
+                map.must_apply_edits(edits, timer);
+                map.recalculate_pathfinding_after_edits(timer);
+            }
+            map
+        },
+        &mut timer,
+    );
+
+    while !sim.is_done() && sim.time() < end_time {
+        let dt = (end_time - sim.time()).min(Duration::hours(1));
+        sim.timed_step(&map, dt, &mut None, &mut timer);
+    }
+
+    let analytics = sim.get_analytics();
+    RunResult {
+        end_time: sim.time(),
+        unfinished_trips: sim.num_trips().1,
+        trips: analytics
+            .finished_trips
+            .iter()
+            .map(|(_, id, mode, dt)| (*id, (*mode, *dt)))
+            .collect(),
+        intersection_delays: analytics
+            .intersection_delays
+            .iter()
+            .map(|(i, list)| (*i, list.iter().map(|(_, _, dt, _)| *dt).collect()))
+            .collect(),
+        job,
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    runs: Vec<RunSummary>,
+}
+
+#[derive(Serialize)]
+struct RunSummary {
+    scenario: String,
+    modifiers: String,
+    seed: u8,
+    options: String,
+    // None for the baseline
+    edits: Option<String>,
+    end_time: Time,
+    finished_trips: usize,
+    aborted_trips: usize,
+    unfinished_trips: usize,
+    modes: Vec<ModeSummary>,
+    // Only for runs with edits
+    vs_baseline: Option<Comparison>,
+}
+
+#[derive(Serialize)]
+struct ModeSummary {
+    mode: TripMode,
+    trips: usize,
+    mean: Option<Duration>,
+    p50: Option<Duration>,
+    p90: Option<Duration>,
+    p99: Option<Duration>,
+}
+
+#[derive(Serialize)]
+struct Comparison {
+    // Only counting trips that finished in both runs
+    faster_trips: usize,
+    slower_trips: usize,
+    same_trips: usize,
+    // Positive means the edits helped
+    total_time_saved: Duration,
+    newly_aborted_trips: usize,
+    no_longer_aborted_trips: usize,
+}
+
+fn write_reports(results: Vec<RunResult>, output: &str, timer: &mut Timer) {
+    std::fs::create_dir_all(output).unwrap();
+
+    let mut baselines: BTreeMap<(String, String, u8, String), &RunResult> = BTreeMap::new();
+    for result in &results {
+        if result.job.edits.is_none() {
+            baselines.insert(result.job.baseline_key(), result);
+        }
+    }
+
+    let mut modes_csv = csv::Writer::from_path(format!("{}/modes.csv", output)).unwrap();
+    let mut trips_csv = csv::Writer::from_path(format!("{}/trips.csv", output)).unwrap();
+    let mut intersections_csv =
+        csv::Writer::from_path(format!("{}/intersections.csv", output)).unwrap();
+    let mut report = Report { runs: Vec::new() };
+
+    timer.start_iter("write reports", results.len());
+    for result in &results {
+        timer.next();
+        let baseline = baselines[&result.job.baseline_key()];
+        let modes = summarize_modes(result);
+        let baseline_modes = summarize_modes(baseline);
+
+        for (m, b) in modes.iter().zip(baseline_modes.iter()) {
+            modes_csv
+                .serialize(ModeRow {
+                    scenario: result.job.scenario.clone(),
+                    modifiers: describe_modifiers(&result.job.modifiers),
+                    seed: result.job.seed,
+                    options: describe_options(&result.job.options),
+                    edits: edits_name(&result.job),
+                    mode: format!("{:?}", m.mode),
+                    trips: m.trips,
+                    mean: secs(m.mean),
+                    p50: secs(m.p50),
+                    p90: secs(m.p90),
+                    p99: secs(m.p99),
+                    baseline_trips: b.trips,
+                    baseline_mean: secs(b.mean),
+                    baseline_p50: secs(b.p50),
+                    baseline_p90: secs(b.p90),
+                    baseline_p99: secs(b.p99),
+                })
+                .unwrap();
+        }
+
+        // Include intersections that only one of the runs used
+        let intersections: BTreeSet<&IntersectionID> = result
+            .intersection_delays
+            .keys()
+            .chain(baseline.intersection_delays.keys())
+            .collect();
+        for i in intersections {
+            let after = result.intersection_delays.get(i);
+            let before = baseline.intersection_delays.get(i);
+            intersections_csv
+                .serialize(IntersectionRow {
+                    scenario: result.job.scenario.clone(),
+                    modifiers: describe_modifiers(&result.job.modifiers),
+                    seed: result.job.seed,
+                    options: describe_options(&result.job.options),
+                    edits: edits_name(&result.job),
+                    intersection: i.0,
+                    agents: after.map(|list| list.len()).unwrap_or(0),
+                    mean_delay: after.and_then(|list| mean(list)),
+                    baseline_agents: before.map(|list| list.len()).unwrap_or(0),
+                    baseline_mean_delay: before.and_then(|list| mean(list)),
+                })
+                .unwrap();
+        }
+
+        let vs_baseline = if result.job.edits.is_some() {
+            let mut cmp = Comparison {
+                faster_trips: 0,
+                slower_trips: 0,
+                same_trips: 0,
+                total_time_saved: Duration::ZERO,
+                newly_aborted_trips: 0,
+                no_longer_aborted_trips: 0,
+            };
+            // Trips that didn't finish or abort in one of the runs are still listed
+            let trips: BTreeSet<&TripID> =
+                result.trips.keys().chain(baseline.trips.keys()).collect();
+            for id in trips {
+                let before = baseline.trips.get(id);
+                let after = result.trips.get(id);
+                match (before, after) {
+                    (Some((Some(_), dt_before)), Some((Some(_), dt))) => {
+                        if dt < dt_before {
+                            cmp.faster_trips += 1;
+                        } else if dt > dt_before {
+                            cmp.slower_trips += 1;
+                        } else {
+                            cmp.same_trips += 1;
+                        }
+                        cmp.total_time_saved += *dt_before - *dt;
+                    }
+                    (Some((Some(_), _)), Some((None, _))) => {
+                        cmp.newly_aborted_trips += 1;
+                    }
+                    (Some((None, _)), Some((Some(_), _))) => {
+                        cmp.no_longer_aborted_trips += 1;
+                    }
+                    _ => {}
+                }
+                trips_csv
+                    .serialize(TripRow {
+                        scenario: result.job.scenario.clone(),
+                        modifiers: describe_modifiers(&result.job.modifiers),
+                        seed: result.job.seed,
+                        options: describe_options(&result.job.options),
+                        edits: edits_name(&result.job),
+                        trip: id.0,
+                        mode: after
+                            .and_then(|(m, _)| *m)
+                            .or_else(|| before.and_then(|(m, _)| *m))
+                            .map(|m| format!("{:?}", m)),
+                        baseline_duration: before.and_then(|(m, dt)| m.map(|_| dt.inner_seconds())),
+                        duration: after.and_then(|(m, dt)| m.map(|_| dt.inner_seconds())),
+                    })
+                    .unwrap();
+            }
+            Some(cmp)
+        } else {
+            None
+        };
+
+        report.runs.push(RunSummary {
+            scenario: result.job.scenario.clone(),
+            modifiers: describe_modifiers(&result.job.modifiers),
+            seed: result.job.seed,
+            options: describe_options(&result.job.options),
+            edits: result.job.edits.clone(),
+            end_time: result.end_time,
+            finished_trips: result.trips.values().filter(|(m, _)| m.is_some()).count(),
+            aborted_trips: result.trips.values().filter(|(m, _)| m.is_none()).count(),
+            unfinished_trips: result.unfinished_trips,
+            modes,
+            vs_baseline,
+        });
+    }
+
+    modes_csv.flush().unwrap();
+    trips_csv.flush().unwrap();
+    intersections_csv.flush().unwrap();
+    abstutil::write_json(format!("{}/report.json", output), &report);
+    println!("Wrote reports to {}", output);
+}
+
+#[derive(Serialize)]
+struct ModeRow {
+    scenario: String,
+    modifiers: String,
+    seed: u8,
+    options: String,
+    edits: String,
+    mode: String,
+    trips: usize,
+    // All durations are in seconds
+    mean: Option<f64>,
+    p50: Option<f64>,
+    p90: Option<f64>,
+    p99: Option<f64>,
+    baseline_trips: usize,
+    baseline_mean: Option<f64>,
+    baseline_p50: Option<f64>,
+    baseline_p90: Option<f64>,
+    baseline_p99: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct TripRow {
+    scenario: String,
+    modifiers: String,
+    seed: u8,
+    options: String,
+    edits: String,
+    trip: usize,
+    mode: Option<String>,
+    // Missing if the trip was aborted or didn't finish
+    baseline_duration: Option<f64>,
+    duration: Option<f64>,
+}
+
+#[derive(Serialize)]
+struct IntersectionRow {
+    scenario: String,
+    modifiers: String,
+    seed: u8,
+    options: String,
+    edits: String,
+    intersection: usize,
+    agents: usize,
+    mean_delay: Option<f64>,
+    baseline_agents: usize,
+    baseline_mean_delay: Option<f64>,
+}
+
+fn summarize_modes(result: &RunResult) -> Vec<ModeSummary> {
+    TripMode::all()
+        .into_iter()
+        .map(|mode| {
+            let mut hgram = Histogram::new();
+            for (m, dt) in result.trips.values() {
+                if *m == Some(mode) {
+                    hgram.add(*dt);
+                }
+            }
+            ModeSummary {
+                mode,
+                trips: hgram.count(),
+                mean: hgram.select(Statistic::Mean),
+                p50: hgram.select(Statistic::P50),
+                p90: hgram.select(Statistic::P90),
+                p99: hgram.select(Statistic::P99),
+            }
+        })
+        .collect()
+}
+
+fn describe_modifiers(modifiers: &Vec<ScenarioModifier>) -> String {
+    if modifiers.is_empty() {
+        return "none".to_string();
+    }
+    modifiers
+        .iter()
+        .map(|m| m.describe())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn describe_options(options: &Vec<String>) -> String {
+    if options.is_empty() {
+        return "defaults".to_string();
+    }
+    options.join(" ")
+}
+
+fn edits_name(job: &Job) -> String {
+    job.edits.clone().unwrap_or_else(|| "baseline".to_string())
+}
+
+fn secs(dt: Option<Duration>) -> Option<f64> {
+    dt.map(|dt| dt.inner_seconds())
+}
+
+fn mean(list: &Vec<Duration>) -> Option<f64> {
+    if list.is_empty() {
+        return None;
+    }
+    Some(list.iter().map(|dt| dt.inner_seconds()).sum::<f64>() / (list.len() as f64))
+}
@@ -39,6 +39,7 @@ pub fn osm_to_raw(name: &str, timer: &mut abstutil::Timer, config: &ImporterConf
             // TODO: investigate why some many buildings drop their private parkings
             elevation: None,
             include_railroads: true,
+            gtfs: crate::utils::local_gtfs("krakow"),
         },
         timer,
     );
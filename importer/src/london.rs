@@ -38,6 +38,7 @@ pub fn osm_to_raw(name: &str, timer: &mut abstutil::Timer, config: &ImporterConf
             private_offstreet_parking: convert_osm::PrivateOffstreetParking::FixedPerBldg(10),
             elevation: None,
             include_railroads: true,
+            gtfs: crate::utils::local_gtfs("london"),
         },
         timer,
     );
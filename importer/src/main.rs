@@ -29,6 +29,7 @@ struct Job {
     oneshot: Option<String>,
     oneshot_clip: Option<String>,
     oneshot_drive_on_left: bool,
+    oneshot_gtfs: Option<String>,
 }
 
 fn main() {
@@ -55,6 +56,8 @@ fn main() {
         oneshot: args.optional("--oneshot"),
         oneshot_clip: args.optional("--oneshot_clip"),
         oneshot_drive_on_left: args.enabled("--oneshot_drive_on_left"),
+        // Import transit routes and schedules from this GTFS .zip instead of OSM relations.
+        oneshot_gtfs: args.optional("--oneshot_gtfs"),
     };
     args.done();
     if !job.osm_to_raw
@@ -86,6 +89,7 @@ fn main() {
             path,
             job.oneshot_clip,
             !job.oneshot_drive_on_left,
+            job.oneshot_gtfs,
             !job.skip_ch,
         );
         return;
@@ -197,7 +201,13 @@ fn main() {
     }
 }
 
-fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool, build_ch: bool) {
+fn oneshot(
+    osm_path: String,
+    clip: Option<String>,
+    drive_on_right: bool,
+    gtfs: Option<String>,
+    build_ch: bool,
+) {
     let mut timer = abstutil::Timer::new("oneshot");
     println!("- Running convert_osm on {}", osm_path);
     let name = abstutil::basename(&osm_path);
@@ -222,6 +232,7 @@ fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool, build_c
             private_offstreet_parking: convert_osm::PrivateOffstreetParking::FixedPerBldg(1),
             elevation: None,
             include_railroads: true,
+            gtfs,
         },
         &mut timer,
     );
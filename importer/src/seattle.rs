@@ -99,6 +99,7 @@ pub fn osm_to_raw(name: &str, timer: &mut abstutil::Timer, config: &ImporterConf
             elevation: Some(abstutil::path("input/seattle/N47W122.hgt")),
             // They mess up 16th and E Marginal badly enough to cause gridlock.
             include_railroads: false,
+            gtfs: crate::utils::local_gtfs("seattle"),
         },
         timer,
     );
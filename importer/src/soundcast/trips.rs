@@ -1,7 +1,9 @@
 use crate::soundcast::popdat::{Endpoint, OrigTrip, PopDat};
 use abstutil::{prettyprint_usize, MultiMap, Parallelism, Timer};
 use geom::LonLat;
-use map_model::{osm, BuildingID, IntersectionID, Map, PathConstraints, PathRequest, PathStep};
+use map_model::{
+    osm, BuildingID, IntersectionID, Map, PathConstraints, PathRequest, PathStep, RoutingProfile,
+};
 use sim::{
     IndividTrip, OffMapLocation, OrigPersonID, PersonID, PersonSpec, Scenario, SpawnTrip,
     TripEndpoint, TripMode,
@@ -92,6 +94,7 @@ fn endpoints(
                         start,
                         end,
                         constraints,
+                        profile: RoutingProfile::Fastest,
                     })
                 })
             }) {
@@ -221,15 +224,19 @@ fn clip_trips(map: &Map, popdat: &PopDat, huge_map: &Map, timer: &mut Timer) ->
                 map,
                 &osm_id_to_bldg,
                 match orig.mode {
-                    TripMode::Walk | TripMode::Transit => {
+                    TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
                         (&incoming_borders_walking, &outgoing_borders_walking)
                     }
-                    TripMode::Drive => (&incoming_borders_driving, &outgoing_borders_driving),
+                    TripMode::Drive | TripMode::RideHail => {
+                        (&incoming_borders_driving, &outgoing_borders_driving)
+                    }
                     TripMode::Bike => (&incoming_borders_biking, &outgoing_borders_biking),
                 },
                 match orig.mode {
-                    TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-                    TripMode::Drive => PathConstraints::Car,
+                    TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
+                        PathConstraints::Pedestrian
+                    }
+                    TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
                     TripMode::Bike => PathConstraints::Bike,
                 },
                 maybe_huge_map.as_ref(),
@@ -271,7 +278,7 @@ pub fn make_weekday_scenario(
         trips,
         |trip| {
             (
-                SpawnTrip::new(trip.from, trip.to, trip.orig.mode, map),
+                SpawnTrip::new(trip.from, trip.to, trip.orig.mode, trip.orig.depart_at, map),
                 trip.orig.depart_at,
                 trip.orig.person,
                 trip.orig.seq,
@@ -320,6 +327,7 @@ pub fn make_weekday_scenario(
         map_name: map.get_name().to_string(),
         people,
         only_seed_buses: None,
+        pct_informed_drivers: 100,
     }
     .remove_weird_schedules(map)
 }
@@ -391,6 +399,7 @@ pub fn make_weekday_scenario_with_everyone(
         map_name: map.get_name().to_string(),
         people,
         only_seed_buses: None,
+        pct_informed_drivers: 100,
     }
     .remove_weird_schedules(map)
 }
@@ -129,6 +129,17 @@ fn run(cmd: &mut Command) {
     }
 }
 
+// If a GTFS feed has been placed at data/input/$city/gtfs.zip, use it for transit routes instead
+// of OSM relations.
+pub fn local_gtfs(city: &str) -> Option<String> {
+    let path = abstutil::path(format!("input/{}/gtfs.zip", city));
+    if abstutil::file_exists(path.clone()) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
 // Converts a RawMap to a Map.
 pub fn raw_to_map(name: &str, build_ch: bool, timer: &mut Timer) -> map_model::Map {
     timer.start(format!("Raw->Map for {}", name));
@@ -1,8 +1,13 @@
 pub use crate::pathfind::driving_cost;
-use crate::{BuildingID, LaneID, Map, PathConstraints, PathRequest};
-use geom::Distance;
+use crate::pathfind::WalkingNode;
+use crate::{
+    Building, BuildingID, BusRouteID, BusStopID, Lane, LaneID, Map, Path, PathConstraints,
+    PathRequest, PathStep, Position, RoutingProfile,
+};
+use geom::{Distance, Duration, Speed, Time};
 use petgraph::graphmap::DiGraphMap;
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 
 // SCC = strongly connected component
 
@@ -40,22 +45,341 @@ pub fn find_scc(map: &Map, constraints: PathConstraints) -> (HashSet<LaneID>, Ha
     (largest_group, disconnected)
 }
 
-// TODO Different cost function
-pub fn all_costs_from(map: &Map, start: BuildingID) -> HashMap<BuildingID, Distance> {
-    let mut results = HashMap::new();
-    let start = map.get_b(start).sidewalk_pos;
-    // TODO This is SO inefficient. Flood out and mark off buildings as we go. Granularity of lane
-    // makes more sense.
-    for b in map.all_buildings() {
-        if let Some(path) = map.pathfind(PathRequest {
-            start,
-            end: b.sidewalk_pos,
-            constraints: PathConstraints::Pedestrian,
-        }) {
-            // TODO Distance isn't an interesting thing to show at all, we want the path cost
-            // (probably in time)
-            results.insert(b.id, path.total_length());
-        }
-    }
-    results
+// Roughly 3mph, the same as pathfinding assumes
+const WALKING_SPEED: Speed = Speed::const_meters_per_second(1.34);
+// 10mph, the fastest simulated cyclists
+const MAX_BIKE_SPEED: Speed = Speed::const_meters_per_second(4.4704);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TravelMode {
+    Walk,
+    Bike,
+    Drive,
+    // Walking, and riding any bus or train along the way
+    Transit,
+}
+
+impl TravelMode {
+    pub fn all() -> Vec<TravelMode> {
+        vec![
+            TravelMode::Walk,
+            TravelMode::Bike,
+            TravelMode::Drive,
+            TravelMode::Transit,
+        ]
+    }
+
+    pub fn noun(self) -> &'static str {
+        match self {
+            TravelMode::Walk => "walk",
+            TravelMode::Bike => "bike",
+            TravelMode::Drive => "drive",
+            TravelMode::Transit => "transit",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+enum Node {
+    Walking(WalkingNode),
+    // At the start of a lane, in a vehicle
+    Lane(LaneID),
+    // Waiting at a stop, for any route
+    Stop(BusStopID),
+    // On board a route, at the stop with this index
+    OnRoute(BusRouteID, usize),
+}
+
+// Measures how long it takes to get from one building to every other one, flooding out from the
+// start. Traffic, delays at intersections, and finding parking are ignored. Transit riders wait
+// however long is expected around the time they reach each stop. Building the graph is the slow
+// part (for transit, it pathfinds between every pair of stops), so reuse it for many starts.
+pub struct IsochroneGraph {
+    mode: TravelMode,
+    edges: HashMap<Node, Vec<(Node, Duration)>>,
+    // Per stop, every route serving it and the index of the stop along it. How long boarding
+    // takes depends on the time.
+    boardings: HashMap<BusStopID, Vec<(BusRouteID, usize)>>,
+    // Per lane, the buildings connected to it and how far along
+    buildings: HashMap<LaneID, Vec<(BuildingID, Distance)>>,
+}
+
+impl IsochroneGraph {
+    pub fn new(map: &Map, mode: TravelMode) -> IsochroneGraph {
+        let mut graph = IsochroneGraph {
+            mode,
+            edges: HashMap::new(),
+            boardings: HashMap::new(),
+            buildings: HashMap::new(),
+        };
+        for b in map.all_buildings() {
+            if let Some(pos) = graph.connection(b, map) {
+                graph
+                    .buildings
+                    .entry(pos.lane())
+                    .or_insert_with(Vec::new)
+                    .push((b.id, pos.dist_along()));
+            }
+        }
+
+        match mode {
+            TravelMode::Walk | TravelMode::Transit => {
+                for l in map.all_lanes() {
+                    if !l.is_walkable() {
+                        continue;
+                    }
+                    let dt = l.length() / WALKING_SPEED;
+                    graph.add_edge(sidewalk(l.id, false), sidewalk(l.id, true), dt);
+                    graph.add_edge(sidewalk(l.id, true), sidewalk(l.id, false), dt);
+                    for turn in map.get_turns_for(l.id, PathConstraints::Pedestrian) {
+                        graph.add_edge(
+                            sidewalk(l.id, l.dst_i == turn.id.parent),
+                            sidewalk(turn.id.dst, map.get_l(turn.id.dst).dst_i == turn.id.parent),
+                            turn.geom.length() / WALKING_SPEED,
+                        );
+                    }
+                }
+                if mode == TravelMode::Transit {
+                    graph.add_transit(map);
+                }
+            }
+            TravelMode::Bike | TravelMode::Drive => {
+                let constraints = graph.constraints();
+                for l in map.all_lanes() {
+                    if !constraints.can_use(l, map) {
+                        continue;
+                    }
+                    for turn in map.get_turns_for(l.id, constraints) {
+                        let dst = map.get_l(turn.id.dst);
+                        let dt = l.length() / graph.speed(l, map)
+                            + turn.geom.length() / graph.speed(dst, map);
+                        graph.add_edge(Node::Lane(l.id), Node::Lane(dst.id), dt);
+                    }
+                }
+            }
+        }
+        graph
+    }
+
+    // Returns every building reachable within the time limit when leaving at some time, and how
+    // long it takes to get there.
+    pub fn all_costs_from(
+        &self,
+        map: &Map,
+        start: BuildingID,
+        depart: Time,
+        time_limit: Duration,
+    ) -> HashMap<BuildingID, Duration> {
+        let mut results = HashMap::new();
+        let start = match self.connection(map.get_b(start), map) {
+            Some(pos) => pos,
+            None => {
+                return results;
+            }
+        };
+        let start_lane = map.get_l(start.lane());
+
+        // Reach the rest of the graph from the middle of the start lane. Buildings on that lane
+        // can be reached directly.
+        let mut queue: BinaryHeap<(Reverse<Duration>, Node)> = BinaryHeap::new();
+        let mut direct = Vec::new();
+        let same_lane = self
+            .buildings
+            .get(&start_lane.id)
+            .map(|list| list.as_slice())
+            .unwrap_or(&[]);
+        if self.mode == TravelMode::Walk || self.mode == TravelMode::Transit {
+            queue.push((
+                Reverse(start.dist_along() / WALKING_SPEED),
+                sidewalk(start_lane.id, false),
+            ));
+            queue.push((
+                Reverse((start_lane.length() - start.dist_along()) / WALKING_SPEED),
+                sidewalk(start_lane.id, true),
+            ));
+            for (b, dist) in same_lane {
+                let gap = if *dist > start.dist_along() {
+                    *dist - start.dist_along()
+                } else {
+                    start.dist_along() - *dist
+                };
+                direct.push((*b, gap / WALKING_SPEED));
+            }
+        } else {
+            let speed = self.speed(start_lane, map);
+            for (next, dt) in self
+                .edges
+                .get(&Node::Lane(start_lane.id))
+                .into_iter()
+                .flatten()
+            {
+                queue.push((Reverse(*dt - start.dist_along() / speed), *next));
+            }
+            for (b, dist) in same_lane {
+                if *dist >= start.dist_along() {
+                    direct.push((*b, (*dist - start.dist_along()) / speed));
+                }
+            }
+        }
+
+        let mut costs: HashMap<Node, Duration> = HashMap::new();
+        while let Some((Reverse(cost), node)) = queue.pop() {
+            if cost > time_limit || costs.contains_key(&node) {
+                continue;
+            }
+            costs.insert(node, cost);
+            for (next, dt) in self.edges.get(&node).into_iter().flatten() {
+                if !costs.contains_key(next) {
+                    queue.push((Reverse(cost + *dt), *next));
+                }
+            }
+            if let Node::Stop(bs) = node {
+                for (route, idx) in self.boardings.get(&bs).into_iter().flatten() {
+                    let next = Node::OnRoute(*route, *idx);
+                    if !costs.contains_key(&next) {
+                        let wait = map.get_br(*route).expected_wait(depart + cost);
+                        queue.push((Reverse(cost + wait), next));
+                    }
+                }
+            }
+        }
+
+        let mut record = |b: BuildingID, cost: Duration| {
+            if cost <= time_limit {
+                let best = results.entry(b).or_insert(cost);
+                if cost < *best {
+                    *best = cost;
+                }
+            }
+        };
+        for (b, cost) in direct {
+            record(b, cost);
+        }
+        for (node, cost) in costs {
+            let (l, from_start, speed) = match node {
+                Node::Walking(WalkingNode::SidewalkEndpoint(l, dst_i)) => {
+                    (l, !dst_i, WALKING_SPEED)
+                }
+                Node::Lane(l) => (l, true, self.speed(map.get_l(l), map)),
+                _ => {
+                    continue;
+                }
+            };
+            let len = map.get_l(l).length();
+            for (b, dist) in self.buildings.get(&l).into_iter().flatten() {
+                let remaining = if from_start { *dist } else { len - *dist };
+                record(*b, cost + remaining / speed);
+            }
+        }
+        results
+    }
+
+    fn add_edge(&mut self, from: Node, to: Node, dt: Duration) {
+        self.edges
+            .entry(from)
+            .or_insert_with(Vec::new)
+            .push((to, dt));
+    }
+
+    fn add_transit(&mut self, map: &Map) {
+        for route in map.all_bus_routes() {
+            for (idx, bs) in route.stops.iter().enumerate() {
+                self.boardings
+                    .entry(*bs)
+                    .or_insert_with(Vec::new)
+                    .push((route.id, idx));
+                self.add_edge(
+                    Node::OnRoute(route.id, idx),
+                    Node::Stop(*bs),
+                    Duration::ZERO,
+                );
+            }
+            for (idx, pair) in route.stops.windows(2).enumerate() {
+                let req = PathRequest {
+                    start: map.get_bs(pair[0]).driving_pos,
+                    end: map.get_bs(pair[1]).driving_pos,
+                    constraints: route.route_type,
+                    profile: RoutingProfile::Fastest,
+                };
+                if let Some(path) = map.pathfind(req.clone()) {
+                    self.add_edge(
+                        Node::OnRoute(route.id, idx),
+                        Node::OnRoute(route.id, idx + 1),
+                        free_flow_time(&path, &req, map),
+                    );
+                }
+            }
+        }
+
+        // Walk between stops and both ends of their sidewalk. Stops no route serves anymore just
+        // never connect to anything.
+        for stop in map.all_bus_stops().values() {
+            let lane = map.get_l(stop.sidewalk_pos.lane());
+            let dist = stop.sidewalk_pos.dist_along();
+            for (endpt, walk) in vec![(false, dist), (true, lane.length() - dist)] {
+                let dt = walk / WALKING_SPEED;
+                self.add_edge(sidewalk(lane.id, endpt), Node::Stop(stop.id), dt);
+                self.add_edge(Node::Stop(stop.id), sidewalk(lane.id, endpt), dt);
+            }
+        }
+    }
+
+    fn constraints(&self) -> PathConstraints {
+        match self.mode {
+            TravelMode::Walk | TravelMode::Transit => PathConstraints::Pedestrian,
+            TravelMode::Bike => PathConstraints::Bike,
+            TravelMode::Drive => PathConstraints::Car,
+        }
+    }
+
+    // Where the building connects to the graph
+    fn connection(&self, b: &Building, map: &Map) -> Option<Position> {
+        match self.mode {
+            TravelMode::Walk | TravelMode::Transit => Some(b.sidewalk_pos),
+            TravelMode::Bike => b.biking_connection(map).map(|(pos, _)| pos),
+            TravelMode::Drive => b.driving_connection(map).map(|(pos, _)| pos),
+        }
+    }
+
+    fn speed(&self, lane: &Lane, map: &Map) -> Speed {
+        let speed_limit = map.get_r(lane.parent).speed_limit;
+        match self.mode {
+            TravelMode::Walk | TravelMode::Transit => WALKING_SPEED,
+            TravelMode::Bike => speed_limit.min(MAX_BIKE_SPEED),
+            TravelMode::Drive => speed_limit,
+        }
+    }
+}
+
+// Builds the graph from scratch, so only use this for one-off queries
+pub fn all_costs_from(
+    map: &Map,
+    start: BuildingID,
+    depart: Time,
+    time_limit: Duration,
+    mode: TravelMode,
+) -> HashMap<BuildingID, Duration> {
+    IsochroneGraph::new(map, mode).all_costs_from(map, start, depart, time_limit)
+}
+
+fn sidewalk(l: LaneID, dst_i: bool) -> Node {
+    Node::Walking(WalkingNode::SidewalkEndpoint(l, dst_i))
+}
+
+// How long a vehicle takes to follow the path at the speed limit, without stopping
+fn free_flow_time(path: &Path, req: &PathRequest, map: &Map) -> Duration {
+    let mut total = Duration::ZERO;
+    for step in path.get_steps() {
+        let on = step.as_traversable();
+        total += on.length(map) / on.speed_limit(map);
+    }
+    // The first and last lanes are only partly crossed
+    if let Some(PathStep::Lane(l)) = path.get_steps().front() {
+        total -= req.start.dist_along() / map.get_r(map.get_l(*l).parent).speed_limit;
+    }
+    if let Some(PathStep::Lane(l)) = path.get_steps().back() {
+        let lane = map.get_l(*l);
+        total -= (lane.length() - req.end.dist_along()) / map.get_r(lane.parent).speed_limit;
+    }
+    total
 }
@@ -42,6 +42,13 @@ pub fn upgrade(mut value: Value, map: &Map) -> Result<PermanentMapEdits, String>
             .unwrap()
             .insert("version".to_string(), Value::Number(2.into()));
     }
+    if value["version"] == Value::Number(2.into()) {
+        fix_variable_stages(&mut value);
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(3.into()));
+    }
 
     abstutil::from_json(&value.to_string().into_bytes()).map_err(|x| x.to_string())
 }
@@ -125,6 +132,25 @@ fn fix_road_direction(value: &mut Value) {
     });
 }
 
+// Actuated and max-pressure stages wrapped the raw signal with their extra timing
+fn fix_variable_stages(value: &mut Value) {
+    walk(value, &|map| {
+        if map.len() == 1 && map.contains_key("TrafficSignal") {
+            let raw = map.remove("TrafficSignal").unwrap();
+            let mut wrapped = serde_json::Map::new();
+            wrapped.insert("raw".to_string(), raw);
+            wrapped.insert(
+                "variable_stages".to_string(),
+                Value::Object(serde_json::Map::new()),
+            );
+            map.insert("TrafficSignal".to_string(), Value::Object(wrapped));
+            true
+        } else {
+            false
+        }
+    })
+}
+
 // b6ab06d51a3b22702b66db296ed4dfd27e8403a0 (and adjacent commits) removed some commands that
 // target a single lane in favor of a consolidated ChangeRoad.
 fn fix_old_lane_cmds(value: &mut Value, map: &Map) -> Result<(), String> {
@@ -2,10 +2,13 @@ mod compat;
 mod perma;
 
 use crate::make::initial::lane_specs::get_lane_specs_ltr;
+use crate::make::transit::{check_route, pick_start_and_end};
+use crate::pathfind::CarGraph;
 use crate::{
-    connectivity, AccessRestrictions, BusRouteID, ControlStopSign, ControlTrafficSignal, Direction,
-    DrivingSide, IntersectionID, IntersectionType, LaneID, LaneType, Map, PathConstraints,
-    Pathfinder, Road, RoadID, TurnID, Zone,
+    connectivity, AccessRestrictions, BusRoute, BusRouteID, BusStopID, ControlStopSign,
+    ControlTrafficSignal, Direction, DrivingSide, IntersectionID, IntersectionType, LaneID,
+    LaneType, Map, ParkingArea, ParkingPolicy, PathConstraints, Pathfinder, PhaseType, Road,
+    RoadID, TurnID, Zone,
 };
 use abstutil::{retain_btreemap, retain_btreeset, Timer};
 use geom::{Speed, Time};
@@ -22,6 +25,8 @@ pub struct MapEdits {
     pub changed_roads: BTreeSet<RoadID>,
     pub original_intersections: BTreeMap<IntersectionID, EditIntersection>,
     pub changed_routes: BTreeSet<BusRouteID>,
+    pub original_routes: BTreeMap<BusRouteID, EditBusRoute>,
+    pub changed_parking: BTreeSet<ParkingArea>,
 
     // Edits without these are player generated.
     pub proposal_description: Vec<String>,
@@ -34,10 +39,19 @@ pub enum EditIntersection {
     StopSign(ControlStopSign),
     // Don't keep ControlTrafficSignal here, because it contains movements that should be
     // generated after all lane edits are applied.
-    TrafficSignal(seattle_traffic_signals::TrafficSignal),
+    TrafficSignal(EditTrafficSignal),
     Closed,
 }
 
+// The seattle_traffic_signals format can only describe fixed and adaptive stages. Actuated and
+// max-pressure stages are written there as fixed stages lasting their minimum green time, and their
+// real timing is kept alongside, by stage index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EditTrafficSignal {
+    pub raw: seattle_traffic_signals::TrafficSignal,
+    pub variable_stages: BTreeMap<usize, PhaseType>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EditRoad {
     pub lanes_ltr: Vec<(LaneType, Direction)>,
@@ -45,6 +59,26 @@ pub struct EditRoad {
     pub access_restrictions: AccessRestrictions,
 }
 
+// The path of a transit route. The exact lanes where vehicles start and end are worked out from
+// the stops. Vehicles take the fastest path between stops, so there's no way to route them along
+// particular roads, except by adding stops there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditBusRoute {
+    pub stops: Vec<BusStopID>,
+    pub start_border: Option<IntersectionID>,
+    pub end_border: Option<IntersectionID>,
+}
+
+// Everything needed to create a route from scratch
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewBusRoute {
+    pub full_name: String,
+    pub short_name: String,
+    pub route_type: PathConstraints,
+    pub path: EditBusRoute,
+    pub spawn_times: Vec<Time>,
+}
+
 impl EditRoad {
     pub fn get_orig_from_osm(r: &Road, driving_side: DrivingSide) -> EditRoad {
         EditRoad {
@@ -107,8 +141,30 @@ pub enum EditCmd {
         old: Vec<Time>,
         new: Vec<Time>,
     },
+    // Add or remove stops, or change where the route enters and leaves the map
+    ChangeBusRoute {
+        id: BusRouteID,
+        old: EditBusRoute,
+        new: EditBusRoute,
+    },
+    // New routes are always added after all existing routes.
+    CreateBusRoute {
+        id: BusRouteID,
+        route: NewBusRoute,
+    },
+    // Only used to undo CreateBusRoute, so this is always the last route.
+    DeleteBusRoute {
+        id: BusRouteID,
+        route: NewBusRoute,
+    },
+    ChangeParkingPolicy {
+        area: ParkingArea,
+        old: ParkingPolicy,
+        new: ParkingPolicy,
+    },
 }
 
+#[derive(Serialize)]
 pub struct EditEffects {
     pub changed_roads: BTreeSet<RoadID>,
     pub changed_intersections: BTreeSet<IntersectionID>,
@@ -127,6 +183,8 @@ impl MapEdits {
             changed_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
             changed_routes: BTreeSet::new(),
+            original_routes: BTreeMap::new(),
+            changed_parking: BTreeSet::new(),
         }
     }
 
@@ -160,6 +218,8 @@ impl MapEdits {
         self.changed_roads.clear();
         self.original_intersections.clear();
         self.changed_routes.clear();
+        self.original_routes.clear();
+        self.changed_parking.clear();
 
         for cmd in &self.commands {
             match cmd {
@@ -174,6 +234,16 @@ impl MapEdits {
                 EditCmd::ChangeRouteSchedule { id, .. } => {
                     self.changed_routes.insert(*id);
                 }
+                EditCmd::ChangeBusRoute { id, ref old, .. } => {
+                    if !self.original_routes.contains_key(id) {
+                        self.original_routes.insert(*id, old.clone());
+                    }
+                }
+                // Created routes are found from the map directly
+                EditCmd::CreateBusRoute { .. } | EditCmd::DeleteBusRoute { .. } => {}
+                EditCmd::ChangeParkingPolicy { area, .. } => {
+                    self.changed_parking.insert(*area);
+                }
             }
         }
 
@@ -185,8 +255,17 @@ impl MapEdits {
             map.get_i_edit(*i) != orig.clone()
         });
         retain_btreeset(&mut self.changed_routes, |br| {
-            let r = map.get_br(*br);
-            r.spawn_times != r.orig_spawn_times
+            // Undoing CreateBusRoute might've removed the route
+            map.all_bus_routes()
+                .get(br.0)
+                .map(|r| r.spawn_times != r.orig_spawn_times)
+                .unwrap_or(false)
+        });
+        retain_btreemap(&mut self.original_routes, |br, orig| {
+            br.0 < map.all_bus_routes().len() && map.get_br_edit(*br) != orig.clone()
+        });
+        retain_btreeset(&mut self.changed_parking, |area| {
+            !map.get_parking_policy(*area).is_free()
         });
     }
 
@@ -206,6 +285,33 @@ impl MapEdits {
                 new: map.get_i_edit(*i),
             });
         }
+        // Recreate new routes first, since the other commands might refer to them. Their schedule
+        // and path as originally created are the baseline for later changes.
+        for r in map.all_bus_routes() {
+            if r.is_created_by_edits() {
+                self.commands.push(EditCmd::CreateBusRoute {
+                    id: r.id,
+                    route: NewBusRoute {
+                        full_name: r.full_name.clone(),
+                        short_name: r.short_name.clone(),
+                        route_type: r.route_type,
+                        path: self
+                            .original_routes
+                            .get(&r.id)
+                            .cloned()
+                            .unwrap_or_else(|| map.get_br_edit(r.id)),
+                        spawn_times: r.orig_spawn_times.clone(),
+                    },
+                });
+            }
+        }
+        for (r, old) in &self.original_routes {
+            self.commands.push(EditCmd::ChangeBusRoute {
+                id: *r,
+                old: old.clone(),
+                new: map.get_br_edit(*r),
+            });
+        }
         for r in &self.changed_routes {
             let r = map.get_br(*r);
             self.commands.push(EditCmd::ChangeRouteSchedule {
@@ -214,6 +320,13 @@ impl MapEdits {
                 old: r.orig_spawn_times.clone(),
             });
         }
+        for area in &self.changed_parking {
+            self.commands.push(EditCmd::ChangeParkingPolicy {
+                area: *area,
+                old: ParkingPolicy::free(),
+                new: map.get_parking_policy(*area),
+            });
+        }
     }
 
     // Pick apart changed_roads and figure out if an entire road was edited, or just a few lanes.
@@ -276,6 +389,34 @@ impl EditCmd {
             EditCmd::ChangeRouteSchedule { id, .. } => {
                 format!("reschedule route {}", map.get_br(*id).short_name)
             }
+            EditCmd::ChangeBusRoute { id, old, new } => {
+                let added = new.stops.iter().filter(|s| !old.stops.contains(s)).count();
+                let removed = old.stops.iter().filter(|s| !new.stops.contains(s)).count();
+                if added > 0 {
+                    details.push(format!("{} stops added", added));
+                }
+                if removed > 0 {
+                    details.push(format!("{} stops removed", removed));
+                }
+                if old.start_border != new.start_border || old.end_border != new.end_border {
+                    details.push(format!("different start or end"));
+                }
+                format!("reroute {}", map.get_br(*id).short_name)
+            }
+            EditCmd::CreateBusRoute { route, .. } => {
+                details.push(format!("{} stops", route.path.stops.len()));
+                format!("new route {}", route.short_name)
+            }
+            EditCmd::DeleteBusRoute { route, .. } => format!("remove route {}", route.short_name),
+            EditCmd::ChangeParkingPolicy { area, old, new } => {
+                details.push(format!("was {}", old));
+                details.push(format!("now {}", new));
+                match area {
+                    ParkingArea::Lane(l) => format!("parking on {}", l),
+                    ParkingArea::Lot(pl) => format!("parking in {}", pl),
+                    ParkingArea::Garage(b) => format!("garage at {}", b),
+                }
+            }
         };
         (summary, details)
     }
@@ -349,7 +490,7 @@ impl EditCmd {
                         }
                         map.traffic_signals.insert(
                             *i,
-                            ControlTrafficSignal::import(raw_ts.clone(), *i, map).unwrap(),
+                            ControlTrafficSignal::import_edit(raw_ts.clone(), *i, map).unwrap(),
                         );
                     }
                     EditIntersection::Closed => {
@@ -361,9 +502,87 @@ impl EditCmd {
                     recalculate_turns(*i, map, effects, timer);
                 }
             }
+            // PermanentMapEdits::from_permanent only checks what doesn't depend on lanes, since
+            // earlier commands can change those. Skip commands that don't work, rather than crash.
             EditCmd::ChangeRouteSchedule { id, new, .. } => {
+                if id.0 >= map.bus_routes.len() || new.is_empty() {
+                    timer.error(format!("Skipping invalid schedule change for {}", id));
+                    return;
+                }
                 map.bus_routes[id.0].spawn_times = new.clone();
             }
+            EditCmd::ChangeBusRoute { id, ref new, .. } => {
+                if id.0 >= map.bus_routes.len() {
+                    timer.error(format!("Skipping change to {}, which doesn't exist", id));
+                    return;
+                }
+                if map.get_br_edit(*id) == new.clone() {
+                    return;
+                }
+
+                match pick_start_and_end(
+                    map,
+                    &new.stops,
+                    map.get_br(*id).route_type,
+                    new.start_border,
+                    new.end_border,
+                )
+                .and_then(|(start, end_border)| {
+                    let mut route = map.get_br(*id).clone();
+                    route.stops = new.stops.clone();
+                    route.start = start;
+                    route.end_border = end_border;
+                    check_route(map, &route)?;
+                    Ok(route)
+                }) {
+                    Ok(route) => {
+                        map.bus_routes[id.0] = route;
+                    }
+                    Err(err) => {
+                        timer.error(format!("Skipping change to {}: {}", id, err));
+                    }
+                }
+            }
+            EditCmd::CreateBusRoute { id, ref route } => {
+                if map.bus_routes.len() > id.0 {
+                    return;
+                }
+                if map.bus_routes.len() < id.0 {
+                    timer.error(format!(
+                        "Skipping creating {}, since an earlier route wasn't created",
+                        id
+                    ));
+                    return;
+                }
+                match map.make_br(*id, route) {
+                    Ok(route) => {
+                        map.bus_routes.push(route);
+                    }
+                    Err(err) => {
+                        timer.error(format!("Skipping creating {}: {}", id, err));
+                    }
+                }
+            }
+            EditCmd::DeleteBusRoute { id, .. } => {
+                if map.bus_routes.len() <= id.0 {
+                    return;
+                }
+                if map.bus_routes.len() != id.0 + 1 {
+                    timer.error(format!(
+                        "Skipping deleting {}, which isn't the last route",
+                        id
+                    ));
+                    return;
+                }
+                map.bus_routes.pop();
+            }
+            EditCmd::ChangeParkingPolicy { area, new, .. } => {
+                if new.is_free() {
+                    map.parking_policies.remove(area);
+                } else {
+                    map.parking_policies.insert(*area, *new);
+                }
+            }
         }
     }
 
@@ -384,6 +603,18 @@ impl EditCmd {
                 old: new,
                 new: old,
             },
+            EditCmd::ChangeBusRoute { id, old, new } => EditCmd::ChangeBusRoute {
+                id,
+                old: new,
+                new: old,
+            },
+            EditCmd::CreateBusRoute { id, route } => EditCmd::DeleteBusRoute { id, route },
+            EditCmd::DeleteBusRoute { id, route } => EditCmd::CreateBusRoute { id, route },
+            EditCmd::ChangeParkingPolicy { area, old, new } => EditCmd::ChangeParkingPolicy {
+                area,
+                old: new,
+                new: old,
+            },
         }
     }
 }
@@ -491,13 +722,106 @@ impl Map {
         match self.get_i(i).intersection_type {
             IntersectionType::StopSign => EditIntersection::StopSign(self.get_stop_sign(i).clone()),
             IntersectionType::TrafficSignal => {
-                EditIntersection::TrafficSignal(self.get_traffic_signal(i).export(self))
+                EditIntersection::TrafficSignal(self.get_traffic_signal(i).export_edit(self))
             }
             IntersectionType::Construction => EditIntersection::Closed,
             IntersectionType::Border => unreachable!(),
         }
     }
 
+    pub fn get_br_edit(&self, id: BusRouteID) -> EditBusRoute {
+        let r = self.get_br(id);
+        let start_i = self.get_l(r.start).src_i;
+        EditBusRoute {
+            stops: r.stops.clone(),
+            start_border: if self.get_i(start_i).is_incoming_border() {
+                Some(start_i)
+            } else {
+                None
+            },
+            end_border: r.end_border.map(|l| self.get_l(l).dst_i),
+        }
+    }
+
+    // Check that vehicles can follow a route before editing it. Works in the middle of editing,
+    // before pathfinding has been updated.
+    pub fn check_bus_route(
+        &self,
+        route_type: PathConstraints,
+        path: &EditBusRoute,
+    ) -> Result<(), String> {
+        self.make_br(
+            BusRouteID(self.bus_routes.len()),
+            &NewBusRoute {
+                full_name: String::new(),
+                short_name: String::new(),
+                route_type,
+                path: path.clone(),
+                spawn_times: Vec::new(),
+            },
+        )
+        .map(|_| ())
+    }
+
+    // The checks on a route that don't depend on lanes, so other edits can't change the answer
+    pub(crate) fn check_bus_route_stops(&self, path: &EditBusRoute) -> Result<(), String> {
+        if path.stops.len() < 2 {
+            return Err(format!("A route needs at least 2 stops"));
+        }
+        for pair in path.stops.windows(2) {
+            if pair[0] == pair[1] {
+                return Err(format!("A route can't stop at {} twice in a row", pair[0]));
+            }
+        }
+        if let Some(i) = path.start_border {
+            if !self.get_i(i).is_incoming_border() {
+                return Err(format!("{} isn't a border where vehicles enter", i));
+            }
+        }
+        if let Some(i) = path.end_border {
+            if !self.get_i(i).is_outgoing_border() {
+                return Err(format!("{} isn't a border where vehicles leave", i));
+            }
+        }
+        Ok(())
+    }
+
+    fn make_br(&self, id: BusRouteID, new: &NewBusRoute) -> Result<BusRoute, String> {
+        let path = &new.path;
+        self.check_bus_route_stops(path)?;
+        for bs in &path.stops {
+            let lane = self.get_l(self.get_bs(*bs).driving_pos.lane());
+            if !new.route_type.can_use(lane, self) {
+                return Err(format!("{} can't be used by {:?}", bs, new.route_type));
+            }
+        }
+
+        let (start, end_border) = pick_start_and_end(
+            self,
+            &path.stops,
+            new.route_type,
+            path.start_border,
+            path.end_border,
+        )?;
+        let route = BusRoute {
+            id,
+            full_name: new.full_name.clone(),
+            short_name: new.short_name.clone(),
+            gtfs_trip_marker: None,
+            osm_rel_id: None,
+            gtfs_id: None,
+            stops: path.stops.clone(),
+            start,
+            end_border,
+            route_type: new.route_type,
+            spawn_times: new.spawn_times.clone(),
+            orig_spawn_times: new.spawn_times.clone(),
+            timetable: None,
+        };
+        check_route(self, &route)?;
+        Ok(route)
+    }
+
     pub fn save_edits(&self) {
         // Don't overwrite the current edits with the compressed first. Otherwise, undo/redo order
         // in the UI gets messed up.
@@ -618,6 +942,7 @@ impl Map {
         let mut pathfinder = std::mem::replace(&mut self.pathfinder, Pathfinder::Dijkstra);
         pathfinder.apply_edits(self, timer);
         self.pathfinder = pathfinder;
+        self.car_graph = CarGraph::new(self);
 
         // Also recompute blackholes. This is cheap enough to do from scratch.
         timer.start("recompute blackholes");
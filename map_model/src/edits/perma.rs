@@ -1,8 +1,13 @@
-use crate::edits::{EditCmd, EditIntersection, EditRoad, MapEdits};
+use crate::edits::{
+    EditBusRoute, EditCmd, EditIntersection, EditRoad, EditTrafficSignal, MapEdits, NewBusRoute,
+};
 use crate::raw::OriginalRoad;
-use crate::{osm, ControlStopSign, IntersectionID, Map};
+use crate::{
+    osm, BusRouteID, BusStopID, ControlStopSign, IntersectionID, Map, ParkingArea, ParkingPolicy,
+    PathConstraints,
+};
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
+use geom::{Distance, LonLat, Pt2D, Time};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -30,7 +35,7 @@ pub(crate) enum PermanentEditIntersection {
         )]
         must_stop: BTreeMap<OriginalRoad, bool>,
     },
-    TrafficSignal(seattle_traffic_signals::TrafficSignal),
+    TrafficSignal(EditTrafficSignal),
     Closed,
 }
 
@@ -47,10 +52,62 @@ pub(crate) enum PermanentEditCmd {
         old: PermanentEditIntersection,
     },
     ChangeRouteSchedule {
-        osm_rel_id: osm::RelationID,
+        #[serde(flatten)]
+        route: PermanentBusRouteID,
         old: Vec<Time>,
         new: Vec<Time>,
     },
+    ChangeBusRoute {
+        #[serde(flatten)]
+        route: PermanentBusRouteID,
+        old: PermanentEditBusRoute,
+        new: PermanentEditBusRoute,
+    },
+    CreateBusRoute {
+        route: PermanentNewBusRoute,
+    },
+    DeleteBusRoute {
+        route: PermanentNewBusRoute,
+    },
+    ChangeParkingPolicy {
+        area: PermanentParkingArea,
+        old: ParkingPolicy,
+        new: ParkingPolicy,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) enum PermanentParkingArea {
+    // The parking lane's index in lanes_ltr
+    Lane(OriginalRoad, usize),
+    Lot(osm::OsmID),
+    Garage(osm::OsmID),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct PermanentBusRouteID {
+    // Routes from OSM use osm_rel_id, and routes from GTFS use gtfs_id
+    osm_rel_id: Option<osm::RelationID>,
+    gtfs_id: Option<String>,
+    // Routes created by edits are numbered in the order they were created
+    created_route: Option<usize>,
+}
+
+// Bus stops don't have an ID from OSM, so just remember where they are.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct PermanentEditBusRoute {
+    stops: Vec<LonLat>,
+    start_border: Option<osm::NodeID>,
+    end_border: Option<osm::NodeID>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct PermanentNewBusRoute {
+    full_name: String,
+    short_name: String,
+    route_type: PathConstraints,
+    path: PermanentEditBusRoute,
+    spawn_times: Vec<Time>,
 }
 
 impl EditCmd {
@@ -68,11 +125,29 @@ impl EditCmd {
             },
             EditCmd::ChangeRouteSchedule { id, old, new } => {
                 PermanentEditCmd::ChangeRouteSchedule {
-                    osm_rel_id: map.get_br(*id).osm_rel_id,
+                    route: PermanentBusRouteID::new(*id, map),
                     old: old.clone(),
                     new: new.clone(),
                 }
             }
+            EditCmd::ChangeBusRoute { id, old, new } => PermanentEditCmd::ChangeBusRoute {
+                route: PermanentBusRouteID::new(*id, map),
+                old: old.to_permanent(map),
+                new: new.to_permanent(map),
+            },
+            EditCmd::CreateBusRoute { route, .. } => PermanentEditCmd::CreateBusRoute {
+                route: route.to_permanent(map),
+            },
+            EditCmd::DeleteBusRoute { route, .. } => PermanentEditCmd::DeleteBusRoute {
+                route: route.to_permanent(map),
+            },
+            EditCmd::ChangeParkingPolicy { area, old, new } => {
+                PermanentEditCmd::ChangeParkingPolicy {
+                    area: PermanentParkingArea::new(*area, map),
+                    old: *old,
+                    new: *new,
+                }
+            }
         }
     }
 }
@@ -83,7 +158,7 @@ impl PermanentMapEdits {
             map_name: map.get_name().to_string(),
             edits_name: edits.edits_name.clone(),
             // Increase this every time there's a schema change
-            version: 2,
+            version: 3,
             proposal_description: edits.proposal_description.clone(),
             proposal_link: edits.proposal_link.clone(),
             commands: edits.commands.iter().map(|cmd| cmd.to_perma(map)).collect(),
@@ -91,6 +166,8 @@ impl PermanentMapEdits {
     }
 
     pub fn from_permanent(perma: PermanentMapEdits, map: &Map) -> Result<MapEdits, String> {
+        // Routes created by the edits come after all of the original routes.
+        let mut num_created_routes = 0;
         let mut edits = MapEdits {
             edits_name: perma.edits_name,
             proposal_description: perma.proposal_description,
@@ -124,22 +201,66 @@ impl PermanentMapEdits {
                             })?,
                         })
                     }
-                    PermanentEditCmd::ChangeRouteSchedule {
-                        osm_rel_id,
-                        old,
-                        new,
-                    } => {
-                        let id = map
-                            .find_br(osm_rel_id)
-                            .ok_or(format!("can't find {}", osm_rel_id))?;
+                    PermanentEditCmd::ChangeRouteSchedule { route, old, new } => {
+                        let id = route.from_permanent(map, num_created_routes)?;
+                        if new.is_empty() {
+                            return Err(format!("{} would have no departures", id));
+                        }
                         Ok(EditCmd::ChangeRouteSchedule { id, old, new })
                     }
+                    PermanentEditCmd::ChangeBusRoute { route, old, new } => {
+                        let id = route.from_permanent(map, num_created_routes)?;
+                        // Whether vehicles can actually follow the route depends on the lanes,
+                        // which earlier commands might change, so that's checked when applying.
+                        let new = new.from_permanent(map)?;
+                        map.check_bus_route_stops(&new)
+                            .map_err(|err| format!("can't change {}: {}", id, err))?;
+                        Ok(EditCmd::ChangeBusRoute {
+                            id,
+                            old: old.from_permanent(map)?,
+                            new,
+                        })
+                    }
+                    PermanentEditCmd::CreateBusRoute { route } => {
+                        let id = BusRouteID(num_original_routes(map) + num_created_routes);
+                        let route = route.from_permanent(map)?;
+                        if route.spawn_times.is_empty() {
+                            return Err(format!(
+                                "new route {} has no departures",
+                                route.short_name
+                            ));
+                        }
+                        map.check_bus_route_stops(&route.path).map_err(|err| {
+                            format!("can't create route {}: {}", route.short_name, err)
+                        })?;
+                        num_created_routes += 1;
+                        Ok(EditCmd::CreateBusRoute { id, route })
+                    }
+                    PermanentEditCmd::DeleteBusRoute { route } => {
+                        if num_created_routes == 0 {
+                            return Err("DeleteBusRoute without a created route".to_string());
+                        }
+                        num_created_routes -= 1;
+                        Ok(EditCmd::DeleteBusRoute {
+                            id: BusRouteID(num_original_routes(map) + num_created_routes),
+                            route: route.from_permanent(map)?,
+                        })
+                    }
+                    PermanentEditCmd::ChangeParkingPolicy { area, old, new } => {
+                        Ok(EditCmd::ChangeParkingPolicy {
+                            area: area.from_permanent(map)?,
+                            old,
+                            new,
+                        })
+                    }
                 })
                 .collect::<Result<Vec<EditCmd>, String>>()?,
 
             changed_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
             changed_routes: BTreeSet::new(),
+            original_routes: BTreeMap::new(),
+            changed_parking: BTreeSet::new(),
         };
         edits.update_derived(map);
         Ok(edits)
@@ -197,3 +318,164 @@ impl PermanentEditIntersection {
         }
     }
 }
+
+impl PermanentBusRouteID {
+    fn new(id: BusRouteID, map: &Map) -> PermanentBusRouteID {
+        let r = map.get_br(id);
+        PermanentBusRouteID {
+            osm_rel_id: r.osm_rel_id,
+            gtfs_id: r.gtfs_id.clone(),
+            created_route: if r.is_created_by_edits() {
+                Some(id.0 - num_original_routes(map))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn from_permanent(self, map: &Map, num_created_routes: usize) -> Result<BusRouteID, String> {
+        match (self.osm_rel_id, self.gtfs_id, self.created_route) {
+            (Some(osm_rel_id), _, _) => map
+                .find_br(osm_rel_id)
+                .ok_or(format!("can't find {}", osm_rel_id)),
+            (None, Some(gtfs_id), _) => map
+                .find_br_by_gtfs_id(&gtfs_id)
+                .ok_or(format!("can't find GTFS route {}", gtfs_id)),
+            // Earlier commands create the route; it might not exist in the map yet.
+            (None, None, Some(idx)) => {
+                if idx >= num_created_routes {
+                    return Err(format!(
+                        "edit refers to created route #{}, but only {} were created before it",
+                        idx, num_created_routes
+                    ));
+                }
+                Ok(BusRouteID(num_original_routes(map) + idx))
+            }
+            (None, None, None) => Err("edit doesn't say which route".to_string()),
+        }
+    }
+}
+
+impl PermanentParkingArea {
+    fn new(area: ParkingArea, map: &Map) -> PermanentParkingArea {
+        match area {
+            ParkingArea::Lane(l) => {
+                let r = map.get_parent(l);
+                let idx = r
+                    .lanes_ltr()
+                    .into_iter()
+                    .position(|(id, _, _)| id == l)
+                    .unwrap();
+                PermanentParkingArea::Lane(r.orig_id, idx)
+            }
+            ParkingArea::Lot(pl) => PermanentParkingArea::Lot(map.get_pl(pl).osm_id),
+            ParkingArea::Garage(b) => PermanentParkingArea::Garage(map.get_b(b).orig_id),
+        }
+    }
+
+    fn from_permanent(self, map: &Map) -> Result<ParkingArea, String> {
+        match self {
+            PermanentParkingArea::Lane(r, idx) => {
+                let id = map.find_r_by_osm_id(r)?;
+                map.get_r(id)
+                    .lanes_ltr()
+                    .get(idx)
+                    .map(|(l, _, _)| ParkingArea::Lane(*l))
+                    .ok_or(format!("{} doesn't have a lane {} anymore", r, idx))
+            }
+            PermanentParkingArea::Lot(id) => map
+                .all_parking_lots()
+                .iter()
+                .find(|pl| pl.osm_id == id)
+                .map(|pl| ParkingArea::Lot(pl.id))
+                .ok_or(format!("can't find parking lot {}", id)),
+            PermanentParkingArea::Garage(id) => map
+                .find_b_by_osm_id(id)
+                .map(ParkingArea::Garage)
+                .ok_or(format!("can't find garage {}", id)),
+        }
+    }
+}
+
+// Routes created by edits always come after these.
+fn num_original_routes(map: &Map) -> usize {
+    map.all_bus_routes()
+        .iter()
+        .filter(|r| !r.is_created_by_edits())
+        .count()
+}
+
+impl EditBusRoute {
+    fn to_permanent(&self, map: &Map) -> PermanentEditBusRoute {
+        PermanentEditBusRoute {
+            stops: self
+                .stops
+                .iter()
+                .map(|bs| {
+                    map.get_bs(*bs)
+                        .sidewalk_pos
+                        .pt(map)
+                        .to_gps(map.get_gps_bounds())
+                })
+                .collect(),
+            start_border: self.start_border.map(|i| map.get_i(i).orig_id),
+            end_border: self.end_border.map(|i| map.get_i(i).orig_id),
+        }
+    }
+}
+
+impl PermanentEditBusRoute {
+    fn from_permanent(self, map: &Map) -> Result<EditBusRoute, String> {
+        let mut stops = Vec::new();
+        for gps in self.stops {
+            stops.push(find_bus_stop(gps, map)?);
+        }
+        Ok(EditBusRoute {
+            stops,
+            start_border: match self.start_border {
+                Some(i) => Some(map.find_i_by_osm_id(i)?),
+                None => None,
+            },
+            end_border: match self.end_border {
+                Some(i) => Some(map.find_i_by_osm_id(i)?),
+                None => None,
+            },
+        })
+    }
+}
+
+// The stop closest to where it used to be, allowing for some drift between map updates
+fn find_bus_stop(gps: LonLat, map: &Map) -> Result<BusStopID, String> {
+    let pt = Pt2D::from_gps(gps, map.get_gps_bounds());
+    map.all_bus_stops()
+        .values()
+        .map(|bs| (bs.id, bs.sidewalk_pos.pt(map).dist_to(pt)))
+        .filter(|(_, dist)| *dist <= Distance::meters(10.0))
+        .min_by_key(|(_, dist)| *dist)
+        .map(|(id, _)| id)
+        .ok_or(format!("no bus stop near {} anymore", gps))
+}
+
+impl NewBusRoute {
+    fn to_permanent(&self, map: &Map) -> PermanentNewBusRoute {
+        PermanentNewBusRoute {
+            full_name: self.full_name.clone(),
+            short_name: self.short_name.clone(),
+            route_type: self.route_type,
+            path: self.path.to_permanent(map),
+            spawn_times: self.spawn_times.clone(),
+        }
+    }
+}
+
+impl PermanentNewBusRoute {
+    fn from_permanent(self, map: &Map) -> Result<NewBusRoute, String> {
+        Ok(NewBusRoute {
+            full_name: self.full_name,
+            short_name: self.short_name,
+            route_type: self.route_type,
+            path: self.path.from_permanent(map)?,
+            spawn_times: self.spawn_times,
+        })
+    }
+}
@@ -11,19 +11,21 @@ mod traversable;
 
 pub use crate::city::City;
 pub use crate::edits::{
-    EditCmd, EditEffects, EditIntersection, EditRoad, MapEdits, PermanentMapEdits,
+    EditBusRoute, EditCmd, EditEffects, EditIntersection, EditRoad, EditTrafficSignal, MapEdits,
+    NewBusRoute, PermanentMapEdits,
 };
 pub use crate::map::{DrivingSide, MapConfig};
 pub use crate::objects::area::{Area, AreaID, AreaType};
 pub use crate::objects::building::{
     Building, BuildingID, BuildingType, NamePerLanguage, OffstreetParking,
 };
-pub use crate::objects::bus_stop::{BusRoute, BusRouteID, BusStop, BusStopID};
+pub use crate::objects::bus_stop::{BusRoute, BusRouteID, BusStop, BusStopID, Timetable};
+pub use crate::objects::docking_station::{DockingStation, DockingStationID};
 pub use crate::objects::intersection::{Intersection, IntersectionID, IntersectionType};
 pub use crate::objects::lane::{
     Lane, LaneID, LaneType, PARKING_LOT_SPOT_LENGTH, PARKING_SPOT_LENGTH,
 };
-pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID};
+pub use crate::objects::parking_lot::{ParkingArea, ParkingLot, ParkingLotID, ParkingPolicy};
 pub use crate::objects::road::{DirectedRoadID, Direction, Road, RoadID};
 pub use crate::objects::stop_signs::{ControlStopSign, RoadWithStopSign};
 pub use crate::objects::traffic_signals::{ControlTrafficSignal, PhaseType, Stage};
@@ -33,7 +35,9 @@ pub use crate::objects::turn::{
 pub use crate::objects::zone::{AccessRestrictions, Zone};
 pub use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn, UberTurnGroup};
 use crate::pathfind::Pathfinder;
-pub use crate::pathfind::{Path, PathConstraints, PathRequest, PathStep};
+pub use crate::pathfind::{
+    Path, PathConstraints, PathRequest, PathStep, RoutingProfile, TransitRide,
+};
 pub use crate::traversable::{Position, Traversable};
 use abstutil::{deserialize_btreemap, serialize_btreemap};
 use geom::{Bounds, Distance, GPSBounds, Polygon};
@@ -45,7 +49,7 @@ pub const NORMAL_LANE_THICKNESS: Distance = Distance::const_meters(2.5);
 pub const SIDEWALK_THICKNESS: Distance = Distance::const_meters(1.5);
 pub(crate) const SHOULDER_THICKNESS: Distance = Distance::const_meters(0.5);
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Map {
     roads: Vec<Road>,
     lanes: Vec<Lane>,
@@ -64,6 +68,13 @@ pub struct Map {
     bus_routes: Vec<BusRoute>,
     areas: Vec<Area>,
     parking_lots: Vec<ParkingLot>,
+    // Only set by edits
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    parking_policies: BTreeMap<ParkingArea, ParkingPolicy>,
+    docking_stations: Vec<DockingStation>,
     boundary_polygon: Polygon,
 
     // Note that border nodes belong in neither!
@@ -76,6 +87,9 @@ pub struct Map {
 
     pathfinder: Pathfinder,
     pathfinder_dirty: bool,
+    // Not the source of truth, just cached. Only for searches with changing costs.
+    #[serde(skip_serializing, skip_deserializing)]
+    car_graph: crate::pathfind::CarGraph,
     // Not the source of truth, just cached.
     zones: Vec<Zone>,
 
@@ -0,0 +1,74 @@
+use crate::make::match_points_to_lanes;
+use crate::raw::RawDockingStation;
+use crate::{DockingStation, DockingStationID, Map, PathConstraints};
+use abstutil::Timer;
+use geom::{Distance, HashablePt2D};
+use std::collections::HashSet;
+
+// If OSM doesn't say how many docks a station has, guess.
+const DEFAULT_CAPACITY: usize = 10;
+
+pub fn make_all_docking_stations(
+    input: &Vec<RawDockingStation>,
+    map: &Map,
+    timer: &mut Timer,
+) -> Vec<DockingStation> {
+    let query: HashSet<HashablePt2D> = input.iter().map(|ds| ds.point.to_hashable()).collect();
+    let sidewalk_pts = match_points_to_lanes(
+        map.get_bounds(),
+        query,
+        map.all_lanes(),
+        |l| l.is_walkable(),
+        Distance::ZERO,
+        // Stations are usually right on the sidewalk or in a plaza next to it
+        Distance::meters(100.0),
+        timer,
+    );
+
+    let mut results = Vec::new();
+    for orig in input {
+        let sidewalk_pos = if let Some(pos) = sidewalk_pts.get(&orig.point.to_hashable()) {
+            *pos
+        } else {
+            timer.warn(format!(
+                "Skipping docking station {} because it's not near a sidewalk",
+                orig.osm_id
+            ));
+            continue;
+        };
+        let sidewalk = sidewalk_pos.lane();
+        let driving_pos = if let Some(l) = map.get_parent(sidewalk).find_closest_lane(
+            sidewalk,
+            |l| !l.biking_blackhole && PathConstraints::Bike.can_use(l, map),
+            map,
+        ) {
+            sidewalk_pos.equiv_pos(l, map)
+        } else {
+            timer.warn(format!(
+                "Skipping docking station {} because there's nowhere to ride from it",
+                orig.osm_id
+            ));
+            continue;
+        };
+
+        results.push(DockingStation {
+            id: DockingStationID(results.len()),
+            osm_id: orig.osm_id,
+            name: orig
+                .osm_tags
+                .get("name")
+                .cloned()
+                .unwrap_or_else(|| "unnamed docking station".to_string()),
+            point: orig.point,
+            capacity: orig
+                .osm_tags
+                .get("capacity")
+                .and_then(|x| x.parse::<usize>().ok())
+                .filter(|x| *x > 0)
+                .unwrap_or(DEFAULT_CAPACITY),
+            sidewalk_pos,
+            driving_pos,
+        });
+    }
+    results
+}
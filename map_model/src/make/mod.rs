@@ -1,22 +1,23 @@
 mod bridges;
 mod buildings;
+mod docking_stations;
 pub mod initial;
 mod parking_lots;
 mod remove_disconnected;
 pub mod traffic_signals;
-mod transit;
+pub mod transit;
 pub mod turns;
 mod walking_turns;
 
-use crate::pathfind::Pathfinder;
-use crate::raw::{OriginalRoad, RawMap};
+use crate::pathfind::{CarGraph, Pathfinder};
+use crate::raw::{OriginalRoad, RawMap, RawRoad};
 use crate::{
     connectivity, osm, AccessRestrictions, Area, AreaID, ControlStopSign, ControlTrafficSignal,
     Direction, Intersection, IntersectionID, IntersectionType, Lane, LaneID, Map, MapEdits,
     Movement, PathConstraints, Position, Road, RoadID, Zone,
 };
 use abstutil::{Parallelism, Timer};
-use geom::{Bounds, Distance, FindClosest, HashablePt2D, Speed, EPSILON_DIST};
+use geom::{Bounds, Distance, FindClosest, HashablePt2D, PolyLine, Speed, EPSILON_DIST};
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 impl Map {
@@ -40,6 +41,8 @@ impl Map {
             bus_routes: Vec::new(),
             areas: Vec::new(),
             parking_lots: Vec::new(),
+            parking_policies: BTreeMap::new(),
+            docking_stations: Vec::new(),
             zones: Vec::new(),
             boundary_polygon: raw.boundary_polygon.clone(),
             stop_signs: BTreeMap::new(),
@@ -49,6 +52,7 @@ impl Map {
             config: raw.config.clone(),
             pathfinder: Pathfinder::Dijkstra,
             pathfinder_dirty: false,
+            car_graph: CarGraph::default(),
             city_name: raw.city_name.clone(),
             name: raw.name.clone(),
             edits: MapEdits::new(),
@@ -118,6 +122,12 @@ impl Map {
                 orig_id: r.id,
                 lanes_ltr: Vec::new(),
                 center_pts: r.trimmed_center_pts.clone(),
+                elevation_profile: elevation_profile(
+                    &raw.roads[&r.id],
+                    &r.trimmed_center_pts,
+                    map.intersections[i1.0].elevation,
+                    map.intersections[i2.0].elevation,
+                ),
                 src_i: i1,
                 dst_i: i2,
                 speed_limit: Speed::ZERO,
@@ -271,6 +281,9 @@ impl Map {
             timer,
         );
 
+        map.docking_stations =
+            docking_stations::make_all_docking_stations(&raw.docking_stations, &map, timer);
+
         map.zones = Zone::make_all(&map);
 
         for (idx, a) in raw.areas.iter().enumerate() {
@@ -318,7 +331,7 @@ impl Map {
         traffic_signals::synchronize(&mut map);
 
         // Note this will always use the slower Pathfinder::Dijkstra.
-        transit::make_stops_and_routes(&mut map, &raw.bus_routes, timer);
+        transit::make_stops_and_routes(&mut map, &raw.bus_routes, &raw.gtfs_routes, timer);
         for id in map.bus_stops.keys() {
             assert!(!map.get_routes_serving_stop(*id).is_empty());
         }
@@ -330,11 +343,31 @@ impl Map {
             ));
             timer.stop("setup ContractionHierarchyPathfinder");
         }
+        map.car_graph = CarGraph::new(&map);
 
         map
     }
 }
 
+// Stretch the profile sampled along the raw road over the trimmed center line. Without elevation
+// data, just interpolate between the two intersections.
+fn elevation_profile(
+    raw: &RawRoad,
+    trimmed_center_pts: &PolyLine,
+    src_elevation: Distance,
+    dst_elevation: Distance,
+) -> Vec<(Distance, Distance)> {
+    let len = trimmed_center_pts.length();
+    let raw_len = PolyLine::unchecked_new(raw.center_points.clone()).length();
+    if raw.elevation_profile.len() < 2 || raw_len == Distance::ZERO {
+        return vec![(Distance::ZERO, src_elevation), (len, dst_elevation)];
+    }
+    raw.elevation_profile
+        .iter()
+        .map(|(dist, elevation)| (len * (*dist / raw_len), *elevation))
+        .collect()
+}
+
 // If the result doesn't contain a requested point, then there was no matching lane close
 // enough.
 fn match_points_to_lanes<F: Fn(&Lane) -> bool>(
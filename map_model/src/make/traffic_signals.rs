@@ -1,9 +1,10 @@
 use crate::{
-    ControlTrafficSignal, IntersectionCluster, IntersectionID, Map, Movement, MovementID,
-    PhaseType, RoadID, Stage, TurnPriority, TurnType,
+    ControlTrafficSignal, DirectedRoadID, Direction, IntersectionCluster, IntersectionID, Map,
+    Movement, MovementID, PathConstraints, PhaseType, RoadID, Stage, TurnPriority, TurnType,
 };
 use abstutil::Timer;
-use geom::Duration;
+use geom::{Distance, Duration, Speed};
+use petgraph::graphmap::DiGraphMap;
 use std::collections::HashSet;
 
 pub fn get_possible_policies(
@@ -547,3 +548,242 @@ pub fn synchronize(map: &mut Map) {
         }
     }
 }
+
+// Pick offsets for a corridor of signals, listed in the order of travel, so that somebody moving
+// along it at a steady speed reaches each signal just as the stage serving the corridor turns
+// green. The first signal keeps its offset. Offsets only stay lined up if every signal has the
+// same cycle length; if common_cycle is true, every signal's stages are stretched to match the
+// longest cycle in the corridor.
+pub fn green_wave(
+    map: &Map,
+    corridor: &[IntersectionID],
+    speed: Speed,
+    common_cycle: bool,
+) -> Result<Vec<ControlTrafficSignal>, String> {
+    if corridor.len() < 2 {
+        return Err("A corridor needs at least two signals".to_string());
+    }
+    if speed <= Speed::ZERO {
+        return Err(format!("Can't progress at {}", speed));
+    }
+    let mut seen = HashSet::new();
+    let mut signals = Vec::new();
+    for i in corridor {
+        if !seen.insert(*i) {
+            return Err(format!("{} is in the corridor twice", i));
+        }
+        let ts = map
+            .maybe_get_traffic_signal(*i)
+            .ok_or_else(|| format!("{} isn't a traffic signal", i))?;
+        if ts.stages.iter().any(|s| s.phase_type.is_variable()) {
+            return Err(format!(
+                "{} has actuated or max-pressure stages, so its cycle length varies",
+                i
+            ));
+        }
+        if cycle_length(ts) == Duration::ZERO {
+            return Err(format!("{} has no cycle", i));
+        }
+        signals.push(ts.clone());
+    }
+
+    // Where the corridor enters and leaves each signal, and how long it takes to get there from
+    // the first
+    let mut incoming = vec![None; corridor.len()];
+    let mut outgoing = vec![None; corridor.len()];
+    let mut arrival = vec![Duration::ZERO];
+    for (idx, pair) in corridor.windows(2).enumerate() {
+        let path = driving_path_btwn(map, pair[0], pair[1])
+            .ok_or_else(|| format!("Can't drive from {} to {}", pair[0], pair[1]))?;
+        let mut dist = Distance::ZERO;
+        for dr in path {
+            if outgoing[idx].is_none() {
+                outgoing[idx] = Some(dr);
+            }
+            incoming[idx + 1] = Some(dr);
+            dist += map.get_r(dr.id).center_pts.length();
+        }
+        arrival.push(arrival[idx] + dist / speed);
+    }
+
+    if common_cycle {
+        let cycle = signals.iter().map(cycle_length).max().unwrap();
+        for ts in &mut signals {
+            stretch_cycle(ts, cycle);
+        }
+    }
+
+    let mut green_starts = Vec::new();
+    for (idx, ts) in signals.iter().enumerate() {
+        let stage = corridor_stage(ts, incoming[idx], outgoing[idx]).ok_or_else(|| {
+            format!(
+                "{} doesn't have a stage protecting movements along the corridor",
+                ts.id
+            )
+        })?;
+        green_starts.push(
+            ts.stages[0..stage]
+                .iter()
+                .map(|s| s.phase_type.simple_duration())
+                .sum::<Duration>(),
+        );
+    }
+
+    // SignalState starts a signal offset seconds into its cycle, so the corridor stage of the
+    // first signal turns green at green_starts[0] - offset (plus any number of cycles).
+    let first_green = green_starts[0] - signals[0].offset;
+    for (idx, ts) in signals.iter_mut().enumerate().skip(1) {
+        let cycle = cycle_length(ts);
+        ts.offset = wrap_offset(green_starts[idx] - first_green - arrival[idx], cycle);
+    }
+    Ok(signals)
+}
+
+fn cycle_length(ts: &ControlTrafficSignal) -> Duration {
+    ts.stages
+        .iter()
+        .map(|s| s.phase_type.simple_duration())
+        .sum()
+}
+
+// Shortest path between two intersections along roads that cars can drive in that direction, so
+// the corridor follows one-ways the same way traffic does
+fn driving_path_btwn(
+    map: &Map,
+    i1: IntersectionID,
+    i2: IntersectionID,
+) -> Option<Vec<DirectedRoadID>> {
+    let mut graph: DiGraphMap<IntersectionID, DirectedRoadID> = DiGraphMap::new();
+    for r in map.all_roads() {
+        for dir in vec![Direction::Fwd, Direction::Back] {
+            let dr = DirectedRoadID { id: r.id, dir };
+            if !dr.lanes(PathConstraints::Car, map).is_empty() {
+                graph.add_edge(dr.src_i(map), dr.dst_i(map), dr);
+            }
+        }
+    }
+    let (_, path) = petgraph::algo::astar(
+        &graph,
+        i1,
+        |i| i == i2,
+        |(_, _, dr)| map.get_r(dr.id).center_pts.length(),
+        |_| Distance::ZERO,
+    )?;
+    Some(
+        path.windows(2)
+            .map(|pair| *graph.edge_weight(pair[0], pair[1]).unwrap())
+            .collect(),
+    )
+}
+
+// Stretching or squeezing a cycle never makes a stage shorter than this, unless it already was
+const MIN_STAGE_DURATION: Duration = Duration::const_seconds(5.0);
+
+fn stretch_cycle(ts: &mut ControlTrafficSignal, cycle: Duration) {
+    let durations = stretch_durations(
+        ts.stages
+            .iter()
+            .map(|s| s.phase_type.simple_duration())
+            .collect(),
+        cycle,
+    );
+    for (stage, dt) in ts.stages.iter_mut().zip(durations) {
+        stage.phase_type = match stage.phase_type {
+            PhaseType::Adaptive(_) => PhaseType::Adaptive(dt),
+            _ => PhaseType::Fixed(dt),
+        };
+    }
+}
+
+// Scale every duration proportionally, rounding to whole seconds like the signal data format. The
+// longest stage absorbs the rounding error. If the cycle is too short to keep every stage at the
+// minimum, the result is longer than asked.
+fn stretch_durations(durations: Vec<Duration>, cycle: Duration) -> Vec<Duration> {
+    let factor = cycle / durations.iter().cloned().sum::<Duration>();
+    let mins: Vec<Duration> = durations
+        .iter()
+        .map(|dt| (*dt).min(MIN_STAGE_DURATION))
+        .collect();
+    let mut result: Vec<Duration> = durations
+        .iter()
+        .zip(mins.iter())
+        .map(|(dt, min)| Duration::seconds((*dt * factor).inner_seconds().round()).max(*min))
+        .collect();
+    if let Some(longest) = (0..result.len()).max_by_key(|idx| result[*idx]) {
+        let error = cycle - result.iter().cloned().sum::<Duration>();
+        result[longest] = (result[longest] + error).max(mins[longest]);
+    }
+    result
+}
+
+// Prefer the first stage protecting the movement straight through the corridor. Otherwise, settle
+// for any movement in from or out along the corridor -- at the ends, there's only one.
+fn corridor_stage(
+    ts: &ControlTrafficSignal,
+    from: Option<DirectedRoadID>,
+    to: Option<DirectedRoadID>,
+) -> Option<usize> {
+    ts.stages
+        .iter()
+        .position(|s| {
+            s.protected_movements.iter().any(|m| {
+                !m.crosswalk
+                    && from.map(|r| m.from == r).unwrap_or(true)
+                    && to.map(|r| m.to == r).unwrap_or(true)
+            })
+        })
+        .or_else(|| {
+            ts.stages.iter().position(|s| {
+                s.protected_movements
+                    .iter()
+                    .any(|m| !m.crosswalk && (Some(m.from) == from || Some(m.to) == to))
+            })
+        })
+}
+
+// Into [0, cycle), rounded to whole seconds
+fn wrap_offset(offset: Duration, cycle: Duration) -> Duration {
+    let secs = offset
+        .inner_seconds()
+        .rem_euclid(cycle.inner_seconds())
+        .round();
+    Duration::seconds(secs % cycle.inner_seconds())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stretch_durations() {
+        let secs =
+            |list: Vec<f64>| -> Vec<Duration> { list.into_iter().map(Duration::seconds).collect() };
+        let mut ok = true;
+        for (input, cycle, expected) in vec![
+            // Doubling is exact
+            (vec![30.0, 10.0, 20.0], 120.0, vec![60.0, 20.0, 40.0]),
+            // The rounding error goes to the longest stage, not the last
+            (
+                vec![10.0, 10.0, 10.0, 40.0],
+                100.0,
+                vec![14.0, 14.0, 14.0, 58.0],
+            ),
+            // Squeezing hard keeps short stages at the minimum
+            (vec![10.0, 100.0], 30.0, vec![5.0, 25.0]),
+            // Stages already under the minimum don't grow
+            (vec![3.0, 57.0], 60.0, vec![3.0, 57.0]),
+            // When the cycle can't fit the minimums, it runs long rather than going negative
+            (vec![10.0, 10.0, 10.0], 6.0, vec![5.0, 5.0, 5.0]),
+        ] {
+            let actual = stretch_durations(secs(input.clone()), Duration::seconds(cycle));
+            if actual != secs(expected.clone()) {
+                ok = false;
+                println!(
+                    "Stretching {:?} to {}s gave {:?}, but expected {:?}",
+                    input, cycle, actual, expected
+                );
+            }
+        }
+        assert!(ok);
+    }
+}
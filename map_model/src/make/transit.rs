@@ -1,13 +1,20 @@
 use crate::make::match_points_to_lanes;
-use crate::raw::{RawBusRoute, RawBusStop};
+use crate::pathfind::Pathfinder;
+use crate::raw::{RawBusRoute, RawBusStop, RawGtfsRoute, RawGtfsStop};
 use crate::{
-    BusRoute, BusRouteID, BusStop, BusStopID, LaneID, LaneType, Map, PathConstraints, Position,
+    BusRoute, BusRouteID, BusStop, BusStopID, Intersection, IntersectionID, LaneID, LaneType, Map,
+    PathConstraints, Position, Timetable,
 };
 use abstutil::Timer;
-use geom::{Distance, Duration, FindClosest, HashablePt2D, Time};
+use geom::{Distance, Duration, FindClosest, HashablePt2D, Pt2D, Time};
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
-pub fn make_stops_and_routes(map: &mut Map, raw_routes: &Vec<RawBusRoute>, timer: &mut Timer) {
+pub fn make_stops_and_routes(
+    map: &mut Map,
+    raw_routes: &Vec<RawBusRoute>,
+    gtfs_routes: &Vec<RawGtfsRoute>,
+    timer: &mut Timer,
+) {
     timer.start("make transit stops and routes");
     let matcher = Matcher::new(raw_routes, map, timer);
 
@@ -22,6 +29,18 @@ pub fn make_stops_and_routes(map: &mut Map, raw_routes: &Vec<RawBusRoute>, timer
         }
     }
 
+    if !gtfs_routes.is_empty() {
+        let matcher = GtfsMatcher::new(map);
+        for r in gtfs_routes {
+            if let Err(err) = make_gtfs_route(map, r, &mut pt_to_stop, &matcher, timer) {
+                timer.warn(format!(
+                    "Skipping GTFS route {} ({}): {}",
+                    r.full_name, r.gtfs_id, err
+                ));
+            }
+        }
+    }
+
     // Remove orphaned bus stops. This messes up the BusStopID indexing.
     for id in map
         .bus_stops
@@ -53,29 +72,14 @@ fn make_route(
     for stop in &r.stops {
         match matcher.lookup(route_type, stop, map) {
             Ok((sidewalk_pos, driving_pos)) => {
-                // Create a new bus stop if needed.
-                let stop_id = if let Some(id) = pt_to_stop.get(&(sidewalk_pos, driving_pos)) {
-                    *id
-                } else {
-                    let id = BusStopID {
-                        sidewalk: sidewalk_pos.lane(),
-                        idx: map.get_l(sidewalk_pos.lane()).bus_stops.len(),
-                    };
-                    pt_to_stop.insert((sidewalk_pos, driving_pos), id);
-                    map.lanes[sidewalk_pos.lane().0].bus_stops.insert(id);
-                    map.bus_stops.insert(
-                        id,
-                        BusStop {
-                            id,
-                            name: stop.name.clone(),
-                            driving_pos,
-                            sidewalk_pos,
-                            is_train_stop: !r.is_bus,
-                        },
-                    );
-                    id
-                };
-                stops.push(stop_id);
+                stops.push(get_or_create_stop(
+                    map,
+                    pt_to_stop,
+                    sidewalk_pos,
+                    driving_pos,
+                    &stop.name,
+                    !r.is_bus,
+                ));
             }
             Err(err) => {
                 return Err(format!("couldn't match stop {}: {}", stop.name, err));
@@ -84,29 +88,191 @@ fn make_route(
     }
 
     // Start or end at a border?
-    let mut end_border = None;
-    let start = if let Some(i) = r.border_start {
+    let border_start = r.border_start.map(|i| {
         let i = map.get_i(map.find_i_by_osm_id(i).unwrap());
         if !i.is_border() {
             panic!("Route starts at {}, but isn't a border?", i.orig_id);
         }
+        i.id
+    });
+    let border_end = r.border_end.map(|i| {
+        let i = map.get_i(map.find_i_by_osm_id(i).unwrap());
+        if !i.is_border() {
+            panic!("Route ends at {}, but isn't a border?", i.orig_id);
+        }
+        i.id
+    });
+    let (start, end_border) = pick_start_and_end(map, &stops, route_type, border_start, border_end)
+        .map_err(|err| format!("Route {} {}", r.osm_rel_id, err))?;
+
+    let route = BusRoute {
+        id: BusRouteID(map.bus_routes.len()),
+        full_name: r.full_name.clone(),
+        short_name: r.short_name.clone(),
+        osm_rel_id: Some(r.osm_rel_id),
+        gtfs_id: None,
+        gtfs_trip_marker: r.gtfs_trip_marker.clone(),
+        stops,
+        route_type,
+        start,
+        end_border,
+        spawn_times: default_spawn_times(),
+        orig_spawn_times: default_spawn_times(),
+        timetable: None,
+    };
+    check_and_add_route(map, route)
+}
+
+// Unlike routes from OSM, stops that can't be matched are skipped, rather than the whole route.
+fn make_gtfs_route(
+    map: &mut Map,
+    r: &RawGtfsRoute,
+    pt_to_stop: &mut BTreeMap<(Position, Position), BusStopID>,
+    matcher: &GtfsMatcher,
+    timer: &mut Timer,
+) -> Result<(), String> {
+    let route_type = if r.is_bus {
+        PathConstraints::Bus
+    } else {
+        PathConstraints::Train
+    };
+
+    let mut stops = Vec::new();
+    // Which of the GTFS stops each one came from
+    let mut stop_indices = Vec::new();
+    for (idx, stop) in r.stops.iter().enumerate() {
+        match matcher.lookup(route_type, stop, map) {
+            Ok((sidewalk_pos, driving_pos)) => {
+                let id = get_or_create_stop(
+                    map,
+                    pt_to_stop,
+                    sidewalk_pos,
+                    driving_pos,
+                    &stop.name,
+                    !r.is_bus,
+                );
+                // Two GTFS stops close together might match to the same place
+                if stops.last() != Some(&id) {
+                    stops.push(id);
+                    stop_indices.push(idx);
+                }
+            }
+            Err(err) => {
+                timer.warn(format!(
+                    "GTFS stop {} ({}) on route {} unmatched: {}",
+                    stop.stop_id, stop.name, r.gtfs_id, err
+                ));
+            }
+        }
+    }
+    if stops.len() < 2 {
+        return Err(format!(
+            "only {} of {} stops matched",
+            stops.len(),
+            r.stops.len()
+        ));
+    }
+
+    let first_pt = map.get_bs(stops[0]).driving_pos.pt(map);
+    let last_pt = map.get_bs(*stops.last().unwrap()).driving_pos.pt(map);
+    let border_start = r
+        .enters_from
+        .and_then(|pt| closest_border(map, route_type, pt, first_pt, true));
+    let border_end = r
+        .exits_to
+        .and_then(|pt| closest_border(map, route_type, last_pt, pt, false));
+    let (start, end_border) =
+        pick_start_and_end(map, &stops, route_type, border_start, border_end)?;
+
+    let trips: Vec<(Time, Vec<(Duration, Duration)>)> = r
+        .spawn_times
+        .iter()
+        .zip(r.timetables.iter())
+        .filter(|(_, times)| times.len() == r.stops.len())
+        .map(|(t, times)| (*t, stop_indices.iter().map(|idx| times[*idx]).collect()))
+        .collect();
+    let timetable = if trips.is_empty() {
+        None
+    } else {
+        Some(Timetable {
+            stops: stops.clone(),
+            trips,
+        })
+    };
+
+    let route = BusRoute {
+        id: BusRouteID(map.bus_routes.len()),
+        full_name: r.full_name.clone(),
+        short_name: r.short_name.clone(),
+        osm_rel_id: None,
+        gtfs_id: Some(r.gtfs_id.clone()),
+        gtfs_trip_marker: None,
+        stops,
+        route_type,
+        start,
+        end_border,
+        spawn_times: r.spawn_times.clone(),
+        orig_spawn_times: r.spawn_times.clone(),
+        timetable,
+    };
+    check_and_add_route(map, route)
+}
+
+fn get_or_create_stop(
+    map: &mut Map,
+    pt_to_stop: &mut BTreeMap<(Position, Position), BusStopID>,
+    sidewalk_pos: Position,
+    driving_pos: Position,
+    name: &str,
+    is_train_stop: bool,
+) -> BusStopID {
+    if let Some(id) = pt_to_stop.get(&(sidewalk_pos, driving_pos)) {
+        return *id;
+    }
+    let id = BusStopID {
+        sidewalk: sidewalk_pos.lane(),
+        idx: map.get_l(sidewalk_pos.lane()).bus_stops.len(),
+    };
+    pt_to_stop.insert((sidewalk_pos, driving_pos), id);
+    map.lanes[sidewalk_pos.lane().0].bus_stops.insert(id);
+    map.bus_stops.insert(
+        id,
+        BusStop {
+            id,
+            name: name.to_string(),
+            driving_pos,
+            sidewalk_pos,
+            is_train_stop,
+        },
+    );
+    id
+}
+
+// Returns the start lane and the optional end border lane.
+pub(crate) fn pick_start_and_end(
+    map: &Map,
+    stops: &[BusStopID],
+    route_type: PathConstraints,
+    border_start: Option<IntersectionID>,
+    border_end: Option<IntersectionID>,
+) -> Result<(LaneID, Option<LaneID>), String> {
+    let mut end_border = None;
+    let start = if let Some(i) = border_start {
+        let i = map.get_i(i);
         if let Some(l) = i.get_outgoing_lanes(map, route_type).get(0) {
             *l
         } else {
             return Err(format!(
-                "Route {} starts at {} ({}), but no starting lane for a {:?}?",
-                r.osm_rel_id, i.id, i.orig_id, route_type
+                "starts at {} ({}), but no starting lane for a {:?}?",
+                i.id, i.orig_id, route_type
             ));
         }
     } else {
         // Not starting at a border. Find a lane at or before the first stop that's at least 13m.
         pick_start_lane(map.get_bs(stops[0]).driving_pos, route_type, map)?
     };
-    if let Some(i) = r.border_end {
-        let i = map.get_i(map.find_i_by_osm_id(i).unwrap());
-        if !i.is_border() {
-            panic!("Route ends at {}, but isn't a border?", i.orig_id);
-        }
+    if let Some(i) = border_end {
+        let i = map.get_i(i);
         // If the last stop is on a lane leading to the border, don't try to lane-change last
         // minute
         let last_stop_l = map.get_bs(*stops.last().unwrap()).driving_pos.lane();
@@ -117,26 +283,50 @@ fn make_route(
         } else {
             // TODO Should panic
             println!(
-                "Route {} ends at {} ({}), but no ending lane for a {:?}?",
-                r.osm_rel_id, i.id, i.orig_id, route_type
+                "Route ends at {} ({}), but no ending lane for a {:?}?",
+                i.id, i.orig_id, route_type
             );
         }
     }
+    Ok((start, end_border))
+}
 
-    let route = BusRoute {
-        id: BusRouteID(map.bus_routes.len()),
-        full_name: r.full_name.clone(),
-        short_name: r.short_name.clone(),
-        osm_rel_id: r.osm_rel_id,
-        gtfs_trip_marker: r.gtfs_trip_marker.clone(),
-        stops,
-        route_type,
-        start,
-        end_border,
-        spawn_times: default_spawn_times(),
-        orig_spawn_times: default_spawn_times(),
-    };
+// The border that makes for the shortest detour going from one point to another. GTFS only
+// tells us the route leaves the map somewhere between two stops.
+fn closest_border(
+    map: &Map,
+    route_type: PathConstraints,
+    from: Pt2D,
+    to: Pt2D,
+    incoming: bool,
+) -> Option<IntersectionID> {
+    map.all_intersections()
+        .iter()
+        .filter(|i| {
+            if incoming {
+                i.is_incoming_border() && !i.get_outgoing_lanes(map, route_type).is_empty()
+            } else {
+                i.is_outgoing_border() && i.get_incoming_lanes(map, route_type).next().is_some()
+            }
+        })
+        .min_by_key(|i| detour(i, from, to))
+        .map(|i| i.id)
+}
+
+fn detour(i: &Intersection, from: Pt2D, to: Pt2D) -> Distance {
+    let pt = i.polygon.center();
+    from.dist_to(pt) + pt.dist_to(to)
+}
+
+fn check_and_add_route(map: &mut Map, route: BusRoute) -> Result<(), String> {
+    check_route(map, &route)?;
+    map.bus_routes.push(route);
+    Ok(())
+}
 
+// Make sure vehicles can follow the whole route. This is also used while editing, so don't depend
+// on the map's pathfinder being up-to-date.
+pub(crate) fn check_route(map: &Map, route: &BusRoute) -> Result<(), String> {
     let mut debug_route = format!("All parts of the route:");
     debug_route = format!("{}\nStart at {}", debug_route, route.start);
     for (idx, bs) in route.stops.iter().enumerate() {
@@ -162,7 +352,7 @@ fn make_route(
             ));
         }
 
-        if map.pathfind(req.clone()).is_none() {
+        if Pathfinder::Dijkstra.pathfind(req.clone(), map).is_none() {
             return Err(format!(
                 "No path between stop on {} and {}: {}. {}",
                 map.get_parent(req.start.lane()).orig_id,
@@ -172,8 +362,6 @@ fn make_route(
             ));
         }
     }
-
-    map.bus_routes.push(route);
     Ok(())
 }
 
@@ -268,7 +456,7 @@ impl Matcher {
         let (_, pt) = closest
             .closest_pt(stop.vehicle_pos.1, Distance::meters(10.0))
             .ok_or_else(|| format!("{} isn't near {}", stop.vehicle_pos.0, l.id))?;
-        let mut driving_pos = Position::new(l.id, l.dist_along_of_point(pt).unwrap());
+        let driving_pos = Position::new(l.id, l.dist_along_of_point(pt).unwrap());
 
         let sidewalk_pos = if let Some(pt) = stop.ped_pos {
             *self
@@ -287,22 +475,103 @@ impl Matcher {
             driving_pos.equiv_pos(sidewalk, map)
         };
 
-        // If we're a stop right at an incoming border, make sure to be at least past where the bus
-        // will spawn from the border. pick_start_lane() can't do anything for borders.
-        if map
-            .get_i(map.get_l(driving_pos.lane()).src_i)
-            .is_incoming_border()
-        {
-            if let Some(pos) = driving_pos.min_dist(Distance::meters(1.0), map) {
-                driving_pos = pos;
-            } else {
-                return Err(format!(
-                    "too close to start of a border {}",
-                    driving_pos.lane()
-                ));
+        Ok((sidewalk_pos, past_border_start(driving_pos, map)?))
+    }
+}
+
+// GTFS stops are just points, so look for the closest lane going the right way.
+struct GtfsMatcher {
+    bus_lanes: FindClosest<LaneID>,
+    light_rail_lanes: FindClosest<LaneID>,
+    sidewalks: FindClosest<LaneID>,
+}
+
+impl GtfsMatcher {
+    fn new(map: &Map) -> GtfsMatcher {
+        let mut bus_lanes = FindClosest::new(map.get_bounds());
+        let mut light_rail_lanes = FindClosest::new(map.get_bounds());
+        let mut sidewalks = FindClosest::new(map.get_bounds());
+        for l in map.all_lanes() {
+            if PathConstraints::Bus.can_use(l, map) {
+                bus_lanes.add(l.id, l.lane_center_pts.points());
+            } else if l.lane_type == LaneType::LightRail {
+                light_rail_lanes.add(l.id, l.lane_center_pts.points());
+            } else if l.is_walkable() {
+                sidewalks.add(l.id, l.lane_center_pts.points());
             }
         }
-        Ok((sidewalk_pos, driving_pos))
+        GtfsMatcher {
+            bus_lanes,
+            light_rail_lanes,
+            sidewalks,
+        }
+    }
+
+    // returns (sidewalk, driving)
+    fn lookup(
+        &self,
+        route_type: PathConstraints,
+        stop: &RawGtfsStop,
+        map: &Map,
+    ) -> Result<(Position, Position), String> {
+        let lanes = if route_type == PathConstraints::Train {
+            &self.light_rail_lanes
+        } else {
+            &self.bus_lanes
+        };
+        // Stops are usually at the curb, so the closest lane going the right way is the rightmost.
+        let (l, dist_along) = lanes
+            .all_close_pts(stop.pos, Distance::meters(30.0))
+            .into_iter()
+            .filter_map(|(l, pt, dist)| {
+                let (dist_along, angle) = map.get_l(l).lane_center_pts.dist_along_of_point(pt)?;
+                if angle.approx_eq(stop.heading, 90.0) {
+                    Some((l, dist_along, dist))
+                } else {
+                    None
+                }
+            })
+            .min_by_key(|(_, _, dist)| *dist)
+            .map(|(l, dist_along, _)| (l, dist_along))
+            .ok_or("no lane going the right way within 30m")?;
+        let driving_pos = Position::new(l, dist_along);
+
+        let sidewalk_pos = if route_type == PathConstraints::Train {
+            // Light rail platforms aren't necessarily next to the tracks
+            let (sidewalk, pt) = self
+                .sidewalks
+                .closest_pt(stop.pos, Distance::meters(50.0))
+                .ok_or("no sidewalk within 50m")?;
+            let (dist_along, _) = map
+                .get_l(sidewalk)
+                .lane_center_pts
+                .dist_along_of_point(pt)
+                .ok_or_else(|| format!("couldn't find {} along {}", pt, sidewalk))?;
+            Position::new(sidewalk, dist_along)
+        } else {
+            let sidewalk = map
+                .get_parent(l)
+                .find_closest_lane(l, |l| PathConstraints::Pedestrian.can_use(l, map), map)
+                .ok_or_else(|| format!("driving {} to sidewalk failed", l))?;
+            driving_pos.equiv_pos(sidewalk, map)
+        };
+
+        Ok((sidewalk_pos, past_border_start(driving_pos, map)?))
+    }
+}
+
+// If we're a stop right at an incoming border, make sure to be at least past where the bus will
+// spawn from the border. pick_start_lane() can't do anything for borders.
+fn past_border_start(driving_pos: Position, map: &Map) -> Result<Position, String> {
+    if map
+        .get_i(map.get_l(driving_pos.lane()).src_i)
+        .is_incoming_border()
+    {
+        driving_pos
+            .min_dist(Distance::meters(1.0), map)
+            .ok_or_else(|| format!("too close to start of a border {}", driving_pos.lane()))
+    } else {
+        Ok(driving_pos)
     }
 }
 
@@ -1,12 +1,14 @@
+use crate::pathfind::CarGraph;
 use crate::raw::{OriginalRoad, RawMap};
 use crate::{
     osm, Area, AreaID, Building, BuildingID, BuildingType, BusRoute, BusRouteID, BusStop,
-    BusStopID, ControlStopSign, ControlTrafficSignal, Intersection, IntersectionID, Lane, LaneID,
-    LaneType, Map, MapEdits, MovementID, OffstreetParking, ParkingLot, ParkingLotID, Path,
-    PathConstraints, PathRequest, Pathfinder, Position, Road, RoadID, Turn, TurnID, TurnType, Zone,
+    BusStopID, ControlStopSign, ControlTrafficSignal, DockingStation, DockingStationID,
+    Intersection, IntersectionID, Lane, LaneID, LaneType, Map, MapEdits, MovementID,
+    OffstreetParking, ParkingArea, ParkingLot, ParkingLotID, ParkingPolicy, Path, PathConstraints,
+    PathRequest, Pathfinder, Position, Road, RoadID, TransitRide, Turn, TurnID, TurnType, Zone,
 };
 use abstutil::Timer;
-use geom::{Bounds, Distance, GPSBounds, Polygon, Pt2D, Ring, Time};
+use geom::{Bounds, Distance, Duration, GPSBounds, Polygon, Pt2D, Ring, Time};
 use petgraph::graphmap::UnGraphMap;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
@@ -32,6 +34,7 @@ impl Map {
                 Ok(map) => {
                     let mut map: Map = map;
                     map.edits = map.new_edits();
+                    map.car_graph = CarGraph::new(&map);
 
                     if false {
                         use abstutil::{prettyprint_usize, serialized_size_bytes};
@@ -137,6 +140,8 @@ impl Map {
             bus_routes: Vec::new(),
             areas: Vec::new(),
             parking_lots: Vec::new(),
+            parking_policies: BTreeMap::new(),
+            docking_stations: Vec::new(),
             zones: Vec::new(),
             boundary_polygon: Ring::must_new(vec![
                 Pt2D::new(0.0, 0.0),
@@ -155,6 +160,7 @@ impl Map {
             },
             pathfinder: Pathfinder::Dijkstra,
             pathfinder_dirty: false,
+            car_graph: CarGraph::default(),
             city_name: "blank city".to_string(),
             name: "blank".to_string(),
             edits: MapEdits::new(),
@@ -189,6 +195,10 @@ impl Map {
         &self.parking_lots
     }
 
+    pub fn all_docking_stations(&self) -> &Vec<DockingStation> {
+        &self.docking_stations
+    }
+
     pub fn all_zones(&self) -> &Vec<Zone> {
         &self.zones
     }
@@ -270,6 +280,21 @@ impl Map {
         &self.parking_lots[id.0]
     }
 
+    pub fn get_parking_policy(&self, area: ParkingArea) -> ParkingPolicy {
+        self.parking_policies
+            .get(&area)
+            .cloned()
+            .unwrap_or_else(ParkingPolicy::free)
+    }
+
+    pub fn all_parking_policies(&self) -> &BTreeMap<ParkingArea, ParkingPolicy> {
+        &self.parking_policies
+    }
+
+    pub fn get_ds(&self, id: DockingStationID) -> &DockingStation {
+        &self.docking_stations[id.0]
+    }
+
     pub fn get_stop_sign(&self, id: IntersectionID) -> &ControlStopSign {
         &self.stop_signs[&id]
     }
@@ -540,13 +565,27 @@ impl Map {
         assert!(!self.pathfinder_dirty);
         self.pathfinder.pathfind_avoiding_zones(req, avoid, self)
     }
+    // Much slower than pathfind, since the costs change every time. Only for cars. The travel
+    // time of each lane can depend on when the car reaches it, leaving at depart.
+    pub fn pathfind_with_travel_times<F: Fn(&Lane, Time) -> Duration>(
+        &self,
+        req: PathRequest,
+        depart: Time,
+        travel_time: F,
+    ) -> Option<Path> {
+        assert!(!self.pathfinder_dirty);
+        self.car_graph.pathfind(req, depart, travel_time, self)
+    }
 
+    // Whether riding transit is faster than walking for a trip starting at some time, and if so,
+    // which routes to take.
     pub fn should_use_transit(
         &self,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, Option<BusStopID>, BusRouteID)> {
-        self.pathfinder.should_use_transit(self, start, end)
+        depart: Time,
+    ) -> Option<Vec<TransitRide>> {
+        self.pathfinder.should_use_transit(self, start, end, depart)
     }
 
     // None for SharedSidewalkCorners
@@ -594,7 +633,16 @@ impl Map {
 
     pub fn find_br(&self, id: osm::RelationID) -> Option<BusRouteID> {
         for br in self.all_bus_routes() {
-            if br.osm_rel_id == id {
+            if br.osm_rel_id == Some(id) {
+                return Some(br.id);
+            }
+        }
+        None
+    }
+
+    pub fn find_br_by_gtfs_id(&self, id: &str) -> Option<BusRouteID> {
+        for br in self.all_bus_routes() {
+            if br.gtfs_id.as_deref() == Some(id) {
                 return Some(br.id);
             }
         }
@@ -27,7 +27,7 @@ pub enum AreaType {
     Island,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Area {
     pub id: AreaID,
     pub area_type: AreaType,
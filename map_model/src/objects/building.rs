@@ -22,7 +22,7 @@ impl fmt::Display for BuildingID {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Building {
     pub id: BuildingID,
     pub polygon: Polygon,
@@ -55,7 +55,7 @@ pub enum OffstreetParking {
     Private(usize, bool),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum BuildingType {
     // An estimated number of residents
     Residential(usize),
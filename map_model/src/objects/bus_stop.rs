@@ -1,6 +1,6 @@
-use crate::{osm, LaneID, Map, PathConstraints, PathRequest, Position};
+use crate::{osm, LaneID, Map, PathConstraints, PathRequest, Position, RoutingProfile};
 use abstutil::{deserialize_usize, serialize_usize};
-use geom::Time;
+use geom::{Duration, Time};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -44,13 +44,16 @@ pub struct BusStop {
     pub is_train_stop: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct BusRoute {
     pub id: BusRouteID,
     pub full_name: String,
     pub short_name: String,
     pub gtfs_trip_marker: Option<String>,
-    pub osm_rel_id: osm::RelationID,
+    // Depending where the route came from, one of these identifies it across map updates. Routes
+    // created by map edits have neither.
+    pub osm_rel_id: Option<osm::RelationID>,
+    pub gtfs_id: Option<String>,
     pub stops: Vec<BusStopID>,
     // May be a border or not. If not, is long enough for buses to spawn fully.
     pub start: LaneID,
@@ -61,21 +64,39 @@ pub struct BusRoute {
     // Explicitly store whatever the original was, since this can't be reconstructed without side
     // input.
     pub orig_spawn_times: Vec<Time>,
+    // Only routes from GTFS have one. Otherwise, the simulation makes up a schedule.
+    pub timetable: Option<Timetable>,
+}
+
+// The real schedule of a route, from GTFS stop_times.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Timetable {
+    // The stops this was made for. Once edits change them, it doesn't apply anymore.
+    pub stops: Vec<BusStopID>,
+    // For every original trip, when it starts, and when it's scheduled to arrive at and depart from
+    // each stop, relative to that.
+    pub trips: Vec<(Time, Vec<(Duration, Duration)>)>,
 }
 
 impl BusRoute {
+    pub fn is_created_by_edits(&self) -> bool {
+        self.osm_rel_id.is_none() && self.gtfs_id.is_none()
+    }
+
     pub fn all_steps(&self, map: &Map) -> Vec<PathRequest> {
         let mut steps = Vec::new();
         steps.push(PathRequest {
             start: Position::start(self.start),
             end: map.get_bs(self.stops[0]).driving_pos,
             constraints: self.route_type,
+            profile: RoutingProfile::Fastest,
         });
         for pair in self.stops.windows(2) {
             steps.push(PathRequest {
                 start: map.get_bs(pair[0]).driving_pos,
                 end: map.get_bs(pair[1]).driving_pos,
                 constraints: self.route_type,
+                profile: RoutingProfile::Fastest,
             });
         }
         if let Some(end) = self.end_border {
@@ -83,11 +104,77 @@ impl BusRoute {
                 start: map.get_bs(*self.stops.last().unwrap()).driving_pos,
                 end: Position::end(end, map),
                 constraints: self.route_type,
+                profile: RoutingProfile::Fastest,
             });
         }
         steps
     }
 
+    // The real schedule for a vehicle starting the route at some time, using the original trip
+    // starting closest to then. None if the route didn't come with one or edits changed its stops.
+    pub fn scheduled_stops(&self, start: Time) -> Option<&Vec<(Duration, Duration)>> {
+        let timetable = self.timetable.as_ref()?;
+        if timetable.stops != self.stops {
+            return None;
+        }
+        timetable
+            .trips
+            .iter()
+            .min_by_key(|(t, _)| if *t > start { *t - start } else { start - *t })
+            .map(|(_, times)| times)
+    }
+
+    // How long somebody showing up at a stop at a random moment in the hour around some time waits
+    // for the next departure, on average. Departures are when vehicles start the route, so this
+    // assumes they take about as long to reach every stop. After the last departure, people wait
+    // for the first one the next day.
+    pub fn expected_wait(&self, time: Time) -> Duration {
+        if self.spawn_times.is_empty() {
+            return Duration::hours(24);
+        }
+        let window = Duration::minutes(30);
+        let start = if time > Time::START_OF_DAY + window {
+            time - window
+        } else {
+            Time::START_OF_DAY
+        };
+        let end = time + window;
+
+        // Everybody showing up between t and the next departure waits until then. On average,
+        // that's halfway between the waits at either end.
+        let mut total = 0.0;
+        let mut t = start;
+        for next in self
+            .spawn_times
+            .iter()
+            .cloned()
+            .chain(self.spawn_times.iter().map(|t| *t + Duration::hours(24)))
+        {
+            if next <= t {
+                continue;
+            }
+            let until = next.min(end);
+            total +=
+                (until - t).inner_seconds() * ((next - t) + (next - until)).inner_seconds() / 2.0;
+            t = until;
+            if t >= end {
+                break;
+            }
+        }
+        Duration::seconds(total / (end - start).inner_seconds())
+    }
+
+    // Half the average time between departures over the whole day. Routes running only once a day
+    // get a long wait, rather than a day-long one. Only for graphs that can't vary over the day;
+    // use expected_wait otherwise.
+    pub fn average_wait(&self) -> Duration {
+        if self.spawn_times.len() < 2 {
+            return Duration::minutes(30);
+        }
+        let span = *self.spawn_times.last().unwrap() - self.spawn_times[0];
+        0.5 * span / (self.spawn_times.len() - 1) as f64
+    }
+
     pub fn plural_noun(&self) -> &'static str {
         if self.route_type == PathConstraints::Bus {
             "buses"
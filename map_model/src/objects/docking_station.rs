@@ -0,0 +1,37 @@
+use crate::{osm, Position};
+use abstutil::{deserialize_usize, serialize_usize};
+use geom::Pt2D;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DockingStationID(
+    #[serde(
+        serialize_with = "serialize_usize",
+        deserialize_with = "deserialize_usize"
+    )]
+    pub usize,
+);
+
+impl fmt::Display for DockingStationID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Docking station #{}", self.0)
+    }
+}
+
+// Where people pick up and return shared bikes or scooters. Scooter-share stations are simulated
+// exactly like bikeshare ones.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DockingStation {
+    pub id: DockingStationID,
+    pub osm_id: osm::NodeID,
+    pub name: String,
+    pub point: Pt2D,
+    // How many vehicles can be docked here
+    pub capacity: usize,
+
+    pub sidewalk_pos: Position,
+    // Where the vehicle starts and finishes riding. Like bike racks at buildings, there's no
+    // buffer from the ends of the lane.
+    pub driving_pos: Position,
+}
@@ -28,7 +28,7 @@ pub enum IntersectionType {
     Construction,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Intersection {
     pub id: IntersectionID,
     // This needs to be in clockwise orientation, or later rendering of sidewalk corners breaks.
@@ -99,7 +99,7 @@ impl LaneType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Lane {
     pub id: LaneID,
     pub parent: RoadID,
@@ -165,6 +165,58 @@ impl Lane {
         self.lane_center_pts.length()
     }
 
+    // (distance along the lane, elevation), following the lane's direction
+    pub fn elevation_profile(&self, map: &Map) -> Vec<(Distance, Distance)> {
+        let road = map.get_r(self.parent);
+        let road_len = road.center_pts.length();
+        let len = self.length();
+        let fwd = road.dir(self.id) == Direction::Fwd;
+        let mut profile: Vec<(Distance, Distance)> = road
+            .elevation_profile
+            .iter()
+            .map(|(dist, elevation)| {
+                let pct = if road_len == Distance::ZERO {
+                    0.0
+                } else {
+                    *dist / road_len
+                };
+                (len * if fwd { pct } else { 1.0 - pct }, *elevation)
+            })
+            .collect();
+        if !fwd {
+            profile.reverse();
+        }
+        profile
+    }
+
+    // (length, grade) of each piece of the lane. The grade is in [-1.0, 1.0]; positive is uphill.
+    pub fn grade_profile(&self, map: &Map) -> Vec<(Distance, f64)> {
+        let mut grades = Vec::new();
+        for pair in self.elevation_profile(map).windows(2) {
+            let run = pair[1].0 - pair[0].0;
+            if run > Distance::ZERO {
+                let grade = (pair[1].1 - pair[0].1) / run;
+                grades.push((run, grade.max(-1.0).min(1.0)));
+            }
+        }
+        grades
+    }
+
+    // (total climb, total descent) along the lane
+    pub fn elevation_change(&self, map: &Map) -> (Distance, Distance) {
+        let mut climb = Distance::ZERO;
+        let mut descent = Distance::ZERO;
+        for pair in self.elevation_profile(map).windows(2) {
+            let rise = pair[1].1 - pair[0].1;
+            if rise > Distance::ZERO {
+                climb += rise;
+            } else {
+                descent -= rise;
+            }
+        }
+        (climb, descent)
+    }
+
     pub fn intersections(&self) -> Vec<IntersectionID> {
         // TODO I think we're assuming there are no loop lanes
         vec![self.src_i, self.dst_i]
@@ -216,7 +268,7 @@ impl Lane {
     }
 
     // TODO Store this natively if this winds up being useful.
-    pub(crate) fn get_directed_parent(&self, map: &Map) -> DirectedRoadID {
+    pub fn get_directed_parent(&self, map: &Map) -> DirectedRoadID {
         let r = map.get_r(self.parent);
         DirectedRoadID {
             id: r.id,
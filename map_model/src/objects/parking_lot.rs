@@ -1,6 +1,6 @@
-use crate::{osm, Position};
+use crate::{osm, BuildingID, LaneID, Position};
 use abstutil::{deserialize_usize, serialize_usize};
-use geom::{Angle, Line, PolyLine, Polygon, Pt2D};
+use geom::{Angle, Duration, Line, PolyLine, Polygon, Pt2D};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -28,7 +28,7 @@ impl fmt::Display for ParkingLotID {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ParkingLot {
     pub id: ParkingLotID,
     pub polygon: Polygon,
@@ -56,3 +56,94 @@ impl ParkingLot {
         self.spots.len() + self.extra_spots
     }
 }
+
+// Anywhere with a price or time limit on parking. Private garages and driveways aren't included;
+// only their owners can use them.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ParkingArea {
+    Lane(LaneID),
+    Lot(ParkingLotID),
+    // A building with OffstreetParking::PublicGarage
+    Garage(BuildingID),
+}
+
+// Parking is free and unlimited unless an edit says otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParkingPolicy {
+    pub cents_per_hour: usize,
+    pub max_stay: Option<Duration>,
+    // Only for on-street parking. Reserved for delivery trucks while they make a delivery; nobody
+    // else can park here.
+    #[serde(default)]
+    pub loading_zone: bool,
+}
+
+impl ParkingPolicy {
+    pub fn free() -> ParkingPolicy {
+        ParkingPolicy {
+            cents_per_hour: 0,
+            max_stay: None,
+            loading_zone: false,
+        }
+    }
+
+    pub fn is_free(&self) -> bool {
+        *self == ParkingPolicy::free()
+    }
+
+    // Prorated by the minute, so a short stay at a cheap spot might cost nothing.
+    pub fn cost(&self, stay: Duration) -> usize {
+        let minutes = (stay.inner_seconds() / 60.0).ceil();
+        (minutes * (self.cents_per_hour as f64) / 60.0).round() as usize
+    }
+
+    pub fn overstayed(&self, stay: Duration) -> bool {
+        self.max_stay.map(|max| stay > max).unwrap_or(false)
+    }
+}
+
+impl fmt::Display for ParkingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.cents_per_hour == 0 {
+            write!(f, "free")?;
+        } else {
+            write!(
+                f,
+                "${}.{:02}/hour",
+                self.cents_per_hour / 100,
+                self.cents_per_hour % 100
+            )?;
+        }
+        if let Some(max) = self.max_stay {
+            write!(f, ", {} limit", max)?;
+        }
+        if self.loading_zone {
+            write!(f, ", loading zone")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost() {
+        let policy = ParkingPolicy {
+            cents_per_hour: 200,
+            max_stay: Some(Duration::hours(2)),
+            loading_zone: false,
+        };
+        assert_eq!(policy.cost(Duration::ZERO), 0);
+        // Rounded up to a whole minute, then to the nearest cent
+        assert_eq!(policy.cost(Duration::seconds(10.0)), 3);
+        assert_eq!(policy.cost(Duration::minutes(30)), 100);
+        assert_eq!(policy.cost(Duration::hours(3)), 600);
+        assert_eq!(ParkingPolicy::free().cost(Duration::hours(3)), 0);
+
+        assert!(!policy.overstayed(Duration::hours(2)));
+        assert!(policy.overstayed(Duration::hours(2) + Duration::seconds(1.0)));
+        assert!(!ParkingPolicy::free().overstayed(Duration::hours(24)));
+    }
+}
@@ -87,7 +87,7 @@ impl DirectedRoadID {
 }
 
 // These're bidirectional (possibly)
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Road {
     pub id: RoadID,
     pub osm_tags: Tags,
@@ -107,6 +107,8 @@ pub struct Road {
     // orientation. No edits ever change this.
     // TODO Maybe deprecated in favor of get_left_side?
     pub center_pts: PolyLine,
+    // (distance along center_pts, elevation), covering both ends
+    pub elevation_profile: Vec<(Distance, Distance)>,
     pub src_i: IntersectionID,
     pub dst_i: IntersectionID,
 }
@@ -1,11 +1,11 @@
-use crate::make::traffic_signals::{brute_force, get_possible_policies};
+use crate::make::traffic_signals::{brute_force, get_possible_policies, green_wave};
 use crate::raw::OriginalRoad;
 use crate::{
-    osm, CompressedMovementID, DirectedRoadID, Direction, IntersectionID, Map, Movement,
-    MovementID, TurnID, TurnPriority, TurnType,
+    osm, CompressedMovementID, DirectedRoadID, Direction, EditTrafficSignal, IntersectionID, Map,
+    Movement, MovementID, TurnID, TurnPriority, TurnType,
 };
 use abstutil::{deserialize_btreemap, retain_btreeset, serialize_btreemap, Timer};
-use geom::Duration;
+use geom::{Duration, Speed};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
@@ -39,14 +39,71 @@ pub enum PhaseType {
     // repeat the stage entirely.
     // TODO This is a silly policy, but a start towards variable timers.
     Adaptive(Duration),
+    // Stay green for at least min_green. After that, every vehicle detected approaching for one of
+    // the protected movements extends the stage by another extension, up to max_green. If nobody's
+    // detected, gap out and move on to the next stage.
+    Actuated {
+        min_green: Duration,
+        max_green: Duration,
+        extension: Duration,
+    },
+    // Every min_green, compare the pressure of all stages -- how many vehicles are queued for the
+    // protected movements, minus how many are already on the lanes they lead to -- and switch to
+    // the stage with the most. The stages don't have to run in order, but this one won't stay green
+    // for longer than max_green at a time.
+    MaxPressure {
+        min_green: Duration,
+        max_green: Duration,
+    },
 }
 
 impl PhaseType {
     // TODO Maybe don't have this; force callers to acknowledge different policies
+    // For actuated and max-pressure stages, this is the minimum.
     pub fn simple_duration(&self) -> Duration {
         match self {
             PhaseType::Fixed(d) | PhaseType::Adaptive(d) => *d,
+            PhaseType::Actuated { min_green, .. } | PhaseType::MaxPressure { min_green, .. } => {
+                *min_green
+            }
+        }
+    }
+
+    // True for the policies that seattle_traffic_signals can't describe
+    pub fn is_variable(&self) -> bool {
+        match self {
+            PhaseType::Fixed(_) | PhaseType::Adaptive(_) => false,
+            PhaseType::Actuated { .. } | PhaseType::MaxPressure { .. } => true,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let (min_green, max_green) = match self {
+            PhaseType::Fixed(_) | PhaseType::Adaptive(_) => {
+                return Ok(());
+            }
+            PhaseType::Actuated {
+                min_green,
+                max_green,
+                extension,
+            } => {
+                if *extension <= Duration::ZERO {
+                    return Err(format!("{:?} needs a positive extension", self));
+                }
+                (*min_green, *max_green)
+            }
+            PhaseType::MaxPressure {
+                min_green,
+                max_green,
+            } => (*min_green, *max_green),
+        };
+        if min_green <= Duration::ZERO {
+            return Err(format!("{:?} needs a positive min_green", self));
+        }
+        if max_green < min_green {
+            return Err(format!("{:?} has max_green less than min_green", self));
         }
+        Ok(())
     }
 }
 
@@ -66,6 +123,17 @@ impl ControlTrafficSignal {
     ) -> Vec<(String, ControlTrafficSignal)> {
         get_possible_policies(map, id, timer)
     }
+    // Offsets (and optionally a common cycle length) for a green wave along an ordered corridor.
+    // Returns the modified signals, to be applied together as one edit.
+    pub fn green_wave(
+        map: &Map,
+        corridor: &[IntersectionID],
+        speed: Speed,
+        common_cycle: bool,
+    ) -> Result<Vec<ControlTrafficSignal>, String> {
+        green_wave(map, corridor, speed, common_cycle)
+    }
+
     // TODO tmp
     pub fn brute_force(map: &Map, id: IntersectionID) {
         brute_force(map, id)
@@ -112,6 +180,8 @@ impl ControlTrafficSignal {
             for m in stage.yield_movements.iter().map(|m| &self.movements[m]) {
                 assert!(m.turn_type != TurnType::Crosswalk);
             }
+
+            stage.phase_type.validate()?;
         }
 
         Ok(self)
@@ -283,6 +353,13 @@ impl ControlTrafficSignal {
                         PhaseType::Adaptive(d) => {
                             seattle_traffic_signals::PhaseType::Adaptive(d.inner_seconds() as usize)
                         }
+                        // Lossy! Use export_edit to keep these.
+                        PhaseType::Actuated { min_green, .. }
+                        | PhaseType::MaxPressure { min_green, .. } => {
+                            seattle_traffic_signals::PhaseType::Fixed(
+                                min_green.inner_seconds() as usize
+                            )
+                        }
                     },
                 })
                 .collect(),
@@ -290,6 +367,40 @@ impl ControlTrafficSignal {
         }
     }
 
+    // Unlike export, this keeps actuated and max-pressure stages.
+    pub fn export_edit(&self, map: &Map) -> EditTrafficSignal {
+        EditTrafficSignal {
+            raw: self.export(map),
+            variable_stages: self
+                .stages
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.phase_type.is_variable())
+                .map(|(idx, s)| (idx, s.phase_type.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn import_edit(
+        edit: EditTrafficSignal,
+        id: IntersectionID,
+        map: &Map,
+    ) -> Result<ControlTrafficSignal, String> {
+        let mut ts = ControlTrafficSignal::import(edit.raw, id, map)?;
+        for (idx, phase_type) in edit.variable_stages {
+            if idx >= ts.stages.len() {
+                return Err(format!(
+                    "Timing for stage {} of {}, but it only has {} stages",
+                    idx + 1,
+                    id,
+                    ts.stages.len()
+                ));
+            }
+            ts.stages[idx].phase_type = phase_type;
+        }
+        ts.validate()
+    }
+
     pub fn import(
         raw: seattle_traffic_signals::TrafficSignal,
         id: IntersectionID,
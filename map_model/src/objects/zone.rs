@@ -1,4 +1,4 @@
-use crate::pathfind::{driving_cost, walking_cost, WalkingNode};
+use crate::pathfind::{driving_cost, walking_cost, walking_lane_cost, WalkingNode};
 use crate::{
     IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, RoadID, TurnID,
 };
@@ -24,7 +24,7 @@ impl AccessRestrictions {
 
 // A contiguous set of roads with access restrictions. This is derived from all the map's roads and
 // kept cached for performance.
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Zone {
     pub members: BTreeSet<RoadID>,
     pub borders: BTreeSet<IntersectionID>,
@@ -77,7 +77,13 @@ impl Zone {
             req.start.lane(),
             |l| l == req.end.lane(),
             |(_, _, turn)| {
-                driving_cost(map.get_l(turn.src), map.get_t(*turn), req.constraints, map)
+                driving_cost(
+                    map.get_l(turn.src),
+                    map.get_t(*turn),
+                    req.constraints,
+                    req.profile,
+                    map,
+                )
             },
             |_| 0.0,
         )?;
@@ -103,7 +109,7 @@ impl Zone {
             for l in map.get_r(*r).all_lanes() {
                 let l = map.get_l(l);
                 if l.is_walkable() {
-                    let cost = walking_cost(l.length());
+                    let cost = walking_lane_cost(l, req.profile, map);
                     let n1 = WalkingNode::SidewalkEndpoint(l.id, true);
                     let n2 = WalkingNode::SidewalkEndpoint(l.id, false);
                     graph.add_edge(n1, n2, cost);
@@ -1,15 +1,17 @@
 use crate::pathfind::driving::VehiclePathfinder;
 use crate::pathfind::walking::{
-    one_step_walking_path, walking_path_to_steps, SidewalkPathfinder, WalkingNode,
+    one_step_walking_path, wait_cost, walking_path_to_steps, SidewalkPathfinder, TransitRide,
+    WalkingNode,
 };
 use crate::{
-    BusRouteID, BusStopID, Intersection, Map, Path, PathConstraints, PathRequest, Position, TurnID,
-    Zone,
+    Intersection, Map, Path, PathConstraints, PathRequest, Position, RoutingProfile, TurnID, Zone,
 };
 use abstutil::Timer;
+use geom::Time;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ContractionHierarchyPathfinder {
     car_graph: VehiclePathfinder,
     bike_graph: VehiclePathfinder,
@@ -17,37 +19,89 @@ pub struct ContractionHierarchyPathfinder {
     train_graph: VehiclePathfinder,
     walking_graph: SidewalkPathfinder,
     walking_with_transit_graph: SidewalkPathfinder,
+    // Everything above uses RoutingProfile::Fastest. Every other profile gets its own graphs.
+    vehicle_profile_graphs: BTreeMap<(PathConstraints, RoutingProfile), VehiclePathfinder>,
+    walking_profile_graphs: BTreeMap<RoutingProfile, SidewalkPathfinder>,
 }
 
 impl ContractionHierarchyPathfinder {
     pub fn new(map: &Map, timer: &mut Timer) -> ContractionHierarchyPathfinder {
         timer.start("prepare pathfinding for cars");
-        let car_graph = VehiclePathfinder::new(map, PathConstraints::Car, None);
+        let car_graph =
+            VehiclePathfinder::new(map, PathConstraints::Car, RoutingProfile::Fastest, None);
         timer.stop("prepare pathfinding for cars");
 
         // The edge weights for bikes are so different from the driving graph that reusing the node
         // ordering actually hurts!
         timer.start("prepare pathfinding for bikes");
-        let bike_graph = VehiclePathfinder::new(map, PathConstraints::Bike, None);
+        let bike_graph =
+            VehiclePathfinder::new(map, PathConstraints::Bike, RoutingProfile::Fastest, None);
         timer.stop("prepare pathfinding for bikes");
 
         timer.start("prepare pathfinding for buses");
-        let bus_graph = VehiclePathfinder::new(map, PathConstraints::Bus, Some(&car_graph));
+        let bus_graph = VehiclePathfinder::new(
+            map,
+            PathConstraints::Bus,
+            RoutingProfile::Fastest,
+            Some(&car_graph),
+        );
         timer.stop("prepare pathfinding for buses");
 
         timer.start("prepare pathfinding for trains");
-        let train_graph = VehiclePathfinder::new(map, PathConstraints::Train, None);
+        let train_graph =
+            VehiclePathfinder::new(map, PathConstraints::Train, RoutingProfile::Fastest, None);
         timer.stop("prepare pathfinding for trains");
 
         timer.start("prepare pathfinding for pedestrians");
-        let walking_graph = SidewalkPathfinder::new(map, false, &bus_graph, &train_graph);
+        let walking_graph = SidewalkPathfinder::new(
+            map,
+            false,
+            RoutingProfile::Fastest,
+            &bus_graph,
+            &train_graph,
+        );
         timer.stop("prepare pathfinding for pedestrians");
 
+        // TODO Deciding to use transit ignores the routing profile
         timer.start("prepare pathfinding for pedestrians using transit");
         let walking_with_transit_graph =
-            SidewalkPathfinder::new(map, true, &bus_graph, &train_graph);
+            SidewalkPathfinder::new(map, true, RoutingProfile::Fastest, &bus_graph, &train_graph);
         timer.stop("prepare pathfinding for pedestrians using transit");
 
+        // The weights for the other profiles are close enough to the defaults for reusing node
+        // ordering to help.
+        let mut vehicle_profile_graphs = BTreeMap::new();
+        for (constraints, seed) in &[
+            (PathConstraints::Car, &car_graph),
+            (PathConstraints::Bike, &bike_graph),
+        ] {
+            let constraints = *constraints;
+            for profile in RoutingProfile::alternatives(constraints) {
+                timer.start(format!(
+                    "prepare pathfinding for {:?} ({})",
+                    constraints, profile
+                ));
+                vehicle_profile_graphs.insert(
+                    (constraints, profile),
+                    VehiclePathfinder::new(map, constraints, profile, Some(*seed)),
+                );
+                timer.stop(format!(
+                    "prepare pathfinding for {:?} ({})",
+                    constraints, profile
+                ));
+            }
+        }
+
+        let mut walking_profile_graphs = BTreeMap::new();
+        for profile in RoutingProfile::alternatives(PathConstraints::Pedestrian) {
+            timer.start(format!("prepare pathfinding for pedestrians ({})", profile));
+            walking_profile_graphs.insert(
+                profile,
+                SidewalkPathfinder::new(map, false, profile, &bus_graph, &train_graph),
+            );
+            timer.stop(format!("prepare pathfinding for pedestrians ({})", profile));
+        }
+
         ContractionHierarchyPathfinder {
             car_graph,
             bike_graph,
@@ -55,9 +109,35 @@ impl ContractionHierarchyPathfinder {
             train_graph,
             walking_graph,
             walking_with_transit_graph,
+            vehicle_profile_graphs,
+            walking_profile_graphs,
+        }
+    }
+
+    fn get_vehicle_graph(
+        &self,
+        constraints: PathConstraints,
+        profile: RoutingProfile,
+    ) -> &VehiclePathfinder {
+        if let Some(graph) = self.vehicle_profile_graphs.get(&(constraints, profile)) {
+            return graph;
+        }
+        match constraints {
+            PathConstraints::Pedestrian => unreachable!(),
+            PathConstraints::Car => &self.car_graph,
+            PathConstraints::Bike => &self.bike_graph,
+            PathConstraints::Bus => &self.bus_graph,
+            PathConstraints::Train => &self.train_graph,
         }
     }
 
+    fn get_walking_graph(&self, profile: RoutingProfile) -> &SidewalkPathfinder {
+        // Profiles that don't apply to pedestrians fall back to the default
+        self.walking_profile_graphs
+            .get(&profile)
+            .unwrap_or(&self.walking_graph)
+    }
+
     pub fn pathfind(&self, req: PathRequest, map: &Map) -> Option<Path> {
         if req.start.lane() == req.end.lane() && req.constraints == PathConstraints::Pedestrian {
             return Some(one_step_walking_path(&req, map));
@@ -130,16 +210,16 @@ impl ContractionHierarchyPathfinder {
             }
             (None, None) => {}
         }
-        match req.constraints {
-            PathConstraints::Pedestrian => {
-                let steps = walking_path_to_steps(self.walking_graph.pathfind(&req, map)?, map);
-                Some(Path::new(map, steps, req.end.dist_along(), Vec::new()))
-            }
-            PathConstraints::Car => self.car_graph.pathfind(&req, map).map(|(p, _)| p),
-            PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
-            PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
-            PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
+        if req.constraints == PathConstraints::Pedestrian {
+            let steps = walking_path_to_steps(
+                self.get_walking_graph(req.profile).pathfind(&req, map)?,
+                map,
+            );
+            return Some(Path::new(map, steps, req.end.dist_along(), Vec::new()));
         }
+        self.get_vehicle_graph(req.constraints, req.profile)
+            .pathfind(&req, map)
+            .map(|(p, _)| p)
     }
 
     // TODO Alright, reconsider refactoring pieces of this again. :)
@@ -190,6 +270,7 @@ impl ContractionHierarchyPathfinder {
                 Position::start(src)
             },
             constraints: req.constraints,
+            profile: req.profile,
         };
         req.start = if map.get_l(dst).src_i == i.id {
             Position::start(dst)
@@ -207,7 +288,7 @@ impl ContractionHierarchyPathfinder {
                 one_step.dedup();
                 one_step
             } else {
-                self.walking_graph.pathfind(&req, map)?
+                self.get_walking_graph(req.profile).pathfind(&req, map)?
             };
             interior_path.extend(main_path);
             let steps = walking_path_to_steps(interior_path, map);
@@ -215,13 +296,9 @@ impl ContractionHierarchyPathfinder {
         }
 
         let mut interior_path = zone.pathfind(interior_req, map)?;
-        let main_path = match req.constraints {
-            PathConstraints::Pedestrian => unreachable!(),
-            PathConstraints::Car => self.car_graph.pathfind(&req, map).map(|(p, _)| p),
-            PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
-            PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
-            PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
-        }?;
+        let (main_path, _) = self
+            .get_vehicle_graph(req.constraints, req.profile)
+            .pathfind(&req, map)?;
         interior_path.append(main_path, map);
         Some(interior_path)
     }
@@ -273,6 +350,7 @@ impl ContractionHierarchyPathfinder {
             },
             end: req.end,
             constraints: req.constraints,
+            profile: req.profile,
         };
         let orig_end_dist = req.end.dist_along();
         req.end = if map.get_l(src).dst_i == i.id {
@@ -291,7 +369,7 @@ impl ContractionHierarchyPathfinder {
                 one_step.dedup();
                 one_step
             } else {
-                self.walking_graph.pathfind(&req, map)?
+                self.get_walking_graph(req.profile).pathfind(&req, map)?
             };
 
             main_path.extend(interior_path);
@@ -300,13 +378,9 @@ impl ContractionHierarchyPathfinder {
         }
 
         let interior_path = zone.pathfind(interior_req, map)?;
-        let mut main_path = match req.constraints {
-            PathConstraints::Pedestrian => unreachable!(),
-            PathConstraints::Car => self.car_graph.pathfind(&req, map).map(|(p, _)| p),
-            PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
-            PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
-            PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
-        }?;
+        let (mut main_path, _) = self
+            .get_vehicle_graph(req.constraints, req.profile)
+            .pathfind(&req, map)?;
         main_path.append(interior_path, map);
         main_path.end_dist = orig_end_dist;
         Some(main_path)
@@ -317,9 +391,21 @@ impl ContractionHierarchyPathfinder {
         map: &Map,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, Option<BusStopID>, BusRouteID)> {
-        self.walking_with_transit_graph
-            .should_use_transit(map, start, end)
+        depart: Time,
+    ) -> Option<Vec<TransitRide>> {
+        let (rides, mut cost) = self
+            .walking_with_transit_graph
+            .should_use_transit(map, start, end)?;
+        // The graph can't change over the day, so it assumes the average wait for every route.
+        // Swap in the wait around the time the trip starts, and just walk if that's faster.
+        for ride in &rides {
+            let route = map.get_br(ride.route);
+            cost = cost - wait_cost(route.average_wait()) + wait_cost(route.expected_wait(depart));
+        }
+        match self.walking_graph.cost(map, start, end) {
+            Some(walking) if walking <= cost => None,
+            _ => Some(rides),
+        }
     }
 
     pub fn apply_edits(&mut self, map: &Map, timer: &mut Timer) {
@@ -346,5 +432,28 @@ impl ContractionHierarchyPathfinder {
         self.walking_with_transit_graph
             .apply_edits(map, &self.bus_graph, &self.train_graph);
         timer.stop("apply edits to pedestrian using transit pathfinding");
+
+        for ((constraints, profile), graph) in self.vehicle_profile_graphs.iter_mut() {
+            timer.start(format!(
+                "apply edits to {:?} pathfinding ({})",
+                constraints, profile
+            ));
+            graph.apply_edits(map);
+            timer.stop(format!(
+                "apply edits to {:?} pathfinding ({})",
+                constraints, profile
+            ));
+        }
+        for (profile, graph) in self.walking_profile_graphs.iter_mut() {
+            timer.start(format!(
+                "apply edits to pedestrian pathfinding ({})",
+                profile
+            ));
+            graph.apply_edits(map, &self.bus_graph, &self.train_graph);
+            timer.stop(format!(
+                "apply edits to pedestrian pathfinding ({})",
+                profile
+            ));
+        }
     }
 }
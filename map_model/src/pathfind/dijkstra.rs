@@ -1,10 +1,12 @@
 use crate::pathfind::driving::driving_cost;
 use crate::pathfind::walking::{
-    one_step_walking_path, walking_cost, walking_path_to_steps, WalkingNode,
+    one_step_walking_path, walking_cost, walking_lane_cost, walking_path_to_steps, WalkingNode,
 };
-use crate::{LaneID, Map, Path, PathConstraints, PathRequest, PathStep, TurnID};
+use crate::{Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, TurnID};
+use geom::{Duration, Time};
 use petgraph::graphmap::DiGraphMap;
-use std::collections::BTreeSet;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
 
 // TODO These should maybe keep the DiGraphMaps as state. It's cheap to recalculate it for edits.
 
@@ -48,14 +50,96 @@ pub fn pathfind_avoiding_zones(
     calc_path(graph, req, map)
 }
 
+// The lanes and turns cars can use, without zones that don't allow through traffic. Building this
+// is most of the work of a search, so the map keeps one around for searches with costs that change
+// every time.
+#[derive(Clone, Default)]
+pub struct CarGraph {
+    graph: DiGraphMap<LaneID, TurnID>,
+}
+
+impl CarGraph {
+    pub fn new(map: &Map) -> CarGraph {
+        let constraints = PathConstraints::Car;
+        let mut graph: DiGraphMap<LaneID, TurnID> = DiGraphMap::new();
+        for l in map.all_lanes() {
+            if constraints.can_use(l, map)
+                && map
+                    .get_r(l.parent)
+                    .access_restrictions
+                    .allow_through_traffic
+                    .contains(constraints)
+            {
+                for turn in map.get_turns_for(l.id, constraints) {
+                    graph.add_edge(turn.id.src, turn.id.dst, turn.id);
+                }
+            }
+        }
+        CarGraph { graph }
+    }
+
+    // The caller estimates how long it takes to cross each lane when a car starts on it at some
+    // time, maybe from observed traffic; turns take their free-flow time. Zones are avoided
+    // entirely, so the start and end shouldn't be inside of one.
+    pub fn pathfind<F: Fn(&Lane, Time) -> Duration>(
+        &self,
+        req: PathRequest,
+        depart: Time,
+        travel_time: F,
+        map: &Map,
+    ) -> Option<Path> {
+        assert_eq!(req.constraints, PathConstraints::Car);
+        // Visit lanes in the order cars reach them, remembering the lane before each one
+        let mut prev: HashMap<LaneID, Option<LaneID>> = HashMap::new();
+        let mut queue: BinaryHeap<(Reverse<Time>, LaneID, Option<LaneID>)> = BinaryHeap::new();
+        queue.push((Reverse(depart), req.start.lane(), None));
+        while let Some((Reverse(time), lane, from)) = queue.pop() {
+            if prev.contains_key(&lane) {
+                continue;
+            }
+            prev.insert(lane, from);
+            if lane == req.end.lane() {
+                let mut path = vec![lane];
+                while let Some(Some(l)) = prev.get(path.last().unwrap()) {
+                    path.push(*l);
+                }
+                path.reverse();
+                return Some(lanes_to_path(path, req, map));
+            }
+
+            let leave = time + travel_time(map.get_l(lane), time);
+            for (_, next, turn) in self.graph.edges(lane) {
+                if !prev.contains_key(&next) {
+                    let t = map.get_t(*turn);
+                    let arrive = leave + t.geom.length() / map.get_parent(next).speed_limit;
+                    queue.push((Reverse(arrive), next, Some(lane)));
+                }
+            }
+        }
+        None
+    }
+}
+
 fn calc_path(graph: DiGraphMap<LaneID, TurnID>, req: PathRequest, map: &Map) -> Option<Path> {
     let (_, path) = petgraph::algo::astar(
         &graph,
         req.start.lane(),
         |l| l == req.end.lane(),
-        |(_, _, turn)| driving_cost(map.get_l(turn.src), map.get_t(*turn), req.constraints, map),
+        |(_, _, turn)| {
+            driving_cost(
+                map.get_l(turn.src),
+                map.get_t(*turn),
+                req.constraints,
+                req.profile,
+                map,
+            )
+        },
         |_| 0.0,
     )?;
+    Some(lanes_to_path(path, req, map))
+}
+
+fn lanes_to_path(path: Vec<LaneID>, req: PathRequest, map: &Map) -> Path {
     let mut steps = Vec::new();
     for pair in path.windows(2) {
         steps.push(PathStep::Lane(pair[0]));
@@ -68,7 +152,7 @@ fn calc_path(graph: DiGraphMap<LaneID, TurnID>, req: PathRequest, map: &Map) ->
     }
     steps.push(PathStep::Lane(req.end.lane()));
     assert_eq!(steps[0], PathStep::Lane(req.start.lane()));
-    Some(Path::new(map, steps, req.end.dist_along(), Vec::new()))
+    Path::new(map, steps, req.end.dist_along(), Vec::new())
 }
 
 // TODO Not happy this works so differently
@@ -76,7 +160,7 @@ fn pathfind_walking(req: PathRequest, map: &Map) -> Option<Vec<WalkingNode>> {
     let mut graph: DiGraphMap<WalkingNode, usize> = DiGraphMap::new();
     for l in map.all_lanes() {
         if l.is_walkable() {
-            let cost = walking_cost(l.length());
+            let cost = walking_lane_cost(l, req.profile, map);
             let n1 = WalkingNode::SidewalkEndpoint(l.id, true);
             let n2 = WalkingNode::SidewalkEndpoint(l.id, false);
             graph.add_edge(n1, n2, cost);
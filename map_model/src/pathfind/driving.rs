@@ -1,8 +1,13 @@
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
+use crate::pathfind::steep_penalty;
 use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn};
-use crate::{Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Turn, TurnID};
+use crate::{
+    osm, Lane, LaneID, LaneType, Map, Path, PathConstraints, PathRequest, PathStep, RoutingProfile,
+    Turn, TurnID,
+};
 use abstutil::MultiMap;
 use fast_paths::{deserialize_32, serialize_32, FastGraph, InputGraph, PathCalculator};
+use geom::Speed;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use thread_local::ThreadLocal;
@@ -15,11 +20,26 @@ pub struct VehiclePathfinder {
     nodes: NodeMap<Node>,
     uber_turns: Vec<UberTurn>,
     constraints: PathConstraints,
+    profile: RoutingProfile,
 
     #[serde(skip_serializing, skip_deserializing)]
     path_calc: ThreadLocal<RefCell<PathCalculator>>,
 }
 
+// The PathCalculator is just a cache per thread, so don't bother copying it.
+impl Clone for VehiclePathfinder {
+    fn clone(&self) -> VehiclePathfinder {
+        VehiclePathfinder {
+            graph: self.graph.clone(),
+            nodes: self.nodes.clone(),
+            uber_turns: self.uber_turns.clone(),
+            constraints: self.constraints,
+            profile: self.profile,
+            path_calc: ThreadLocal::new(),
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 enum Node {
     Lane(LaneID),
@@ -30,6 +50,7 @@ impl VehiclePathfinder {
     pub fn new(
         map: &Map,
         constraints: PathConstraints,
+        profile: RoutingProfile,
         seed: Option<&VehiclePathfinder>,
     ) -> VehiclePathfinder {
         // Insert every lane as a node. Even if the lane type is wrong now, it might change later,
@@ -49,7 +70,7 @@ impl VehiclePathfinder {
             }
         }
 
-        let input_graph = make_input_graph(map, &nodes, &uber_turns, constraints);
+        let input_graph = make_input_graph(map, &nodes, &uber_turns, constraints, profile);
 
         // All VehiclePathfinders have the same nodes (lanes), so if we're not the first being
         // built, seed from the node ordering.
@@ -65,6 +86,7 @@ impl VehiclePathfinder {
             nodes,
             uber_turns,
             constraints,
+            profile,
             path_calc: ThreadLocal::new(),
         }
     }
@@ -121,7 +143,13 @@ impl VehiclePathfinder {
         // the node ordering.
         // TODO Make sure the result of this is deterministic and equivalent to computing from
         // scratch.
-        let input_graph = make_input_graph(map, &self.nodes, &self.uber_turns, self.constraints);
+        let input_graph = make_input_graph(
+            map,
+            &self.nodes,
+            &self.uber_turns,
+            self.constraints,
+            self.profile,
+        );
         let node_ordering = self.graph.get_node_ordering();
         self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
     }
@@ -132,6 +160,7 @@ fn make_input_graph(
     nodes: &NodeMap<Node>,
     uber_turns: &Vec<UberTurn>,
     constraints: PathConstraints,
+    profile: RoutingProfile,
 ) -> InputGraph {
     let mut input_graph = InputGraph::new();
 
@@ -178,7 +207,7 @@ fn make_input_graph(
                     input_graph.add_edge(
                         from,
                         nodes.get(Node::Lane(turn.id.dst)),
-                        round(driving_cost(l, turn, constraints, map)),
+                        round(driving_cost(l, turn, constraints, profile, map)),
                     );
                 }
             } else {
@@ -188,7 +217,13 @@ fn make_input_graph(
 
                     let mut sum_cost = 0.0;
                     for t in &ut.path {
-                        sum_cost += driving_cost(map.get_l(t.src), map.get_t(*t), constraints, map);
+                        sum_cost += driving_cost(
+                            map.get_l(t.src),
+                            map.get_t(*t),
+                            constraints,
+                            profile,
+                            map,
+                        );
                     }
                     input_graph.add_edge(from, nodes.get(Node::UberTurn(*idx)), round(sum_cost));
                     input_graph.add_edge(
@@ -213,7 +248,18 @@ fn make_input_graph(
     input_graph
 }
 
-pub fn driving_cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map: &Map) -> f64 {
+// Each meter climbed costs as much as riding this many meters on flat ground
+const BIKE_COST_PER_METER_CLIMBED: f64 = 10.0;
+// How much worse a highway seems to somebody avoiding them
+const AVOID_HIGHWAYS_FACTOR: f64 = 5.0;
+
+pub fn driving_cost(
+    lane: &Lane,
+    turn: &Turn,
+    constraints: PathConstraints,
+    profile: RoutingProfile,
+    map: &Map,
+) -> f64 {
     // TODO Could cost turns differently.
 
     let base = match constraints {
@@ -221,18 +267,28 @@ pub fn driving_cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map:
             // Prefer slightly longer route on faster roads
             let t1 = lane.length() / map.get_r(lane.parent).speed_limit;
             let t2 = turn.geom.length() / map.get_parent(turn.id.dst).speed_limit;
-            (t1 + t2).inner_seconds()
+            let mut cost = (t1 + t2).inner_seconds();
+            if profile == RoutingProfile::AvoidHighways
+                && map.get_r(lane.parent).get_rank() == osm::RoadRank::Highway
+            {
+                cost *= AVOID_HIGHWAYS_FACTOR;
+            }
+            cost
         }
         PathConstraints::Bike => {
             // Speed limits don't matter, bikes are usually constrained by their own speed limit.
             let dist = lane.length() + turn.geom.length();
-            // TODO Elevation gain is bad, loss is good.
+            // Climbing is much harder than riding the same distance on flat ground. Going downhill
+            // isn't rewarded, so costs stay positive.
+            let (climb, _) = lane.elevation_change(map);
             // TODO If we're on a driving lane, higher speed limit is worse.
             // TODO Bike lanes next to parking is dangerous.
 
             // TODO Prefer bike lanes, then bus lanes, then driving lanes. For now, express that as
             // an extra cost.
-            let lt_penalty = if lane.is_biking() {
+            let lt_penalty = if profile == RoutingProfile::LowStress {
+                low_stress_penalty(lane, map)
+            } else if lane.is_biking() {
                 1.0
             } else if lane.is_bus() {
                 1.1
@@ -241,8 +297,12 @@ pub fn driving_cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map:
                 1.5
             };
 
+            let mut cost = lt_penalty * dist + climb * BIKE_COST_PER_METER_CLIMBED;
+            if profile == RoutingProfile::AvoidSteep {
+                cost += steep_penalty(lane, map);
+            }
             // 1m resolution is fine
-            (lt_penalty * dist).inner_meters()
+            cost.inner_meters()
         }
         PathConstraints::Bus => {
             // Like Car, but prefer bus lanes.
@@ -272,6 +332,33 @@ pub fn driving_cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map:
     base + (extra_penalty as f64)
 }
 
+// Cyclists avoiding traffic stress will go far out of their way to use a bike lane that isn't
+// right next to moving traffic, or a quiet street.
+fn low_stress_penalty(lane: &Lane, map: &Map) -> f64 {
+    let road = map.get_r(lane.parent);
+    if lane.is_biking() {
+        let lanes = road.lanes_ltr();
+        let idx = lanes.iter().position(|(l, _, _)| *l == lane.id).unwrap();
+        let next_to_traffic = lanes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i + 1 == idx || *i == idx + 1)
+            .any(|(_, (_, _, lt))| lt.is_for_moving_vehicles() && *lt != LaneType::Biking);
+        if next_to_traffic {
+            1.3
+        } else {
+            1.0
+        }
+    } else if road.get_rank() == osm::RoadRank::Local
+        && road.speed_limit <= Speed::miles_per_hour(25.0)
+    {
+        2.0
+    } else {
+        // Mixing with buses or fast traffic
+        5.0
+    }
+}
+
 // Round up! 0 cost edges are ignored
 fn round(cost: f64) -> usize {
     (cost.round() as usize).max(1)
@@ -7,15 +7,13 @@ pub mod uber_turns;
 mod walking;
 
 pub use self::ch::ContractionHierarchyPathfinder;
+pub use self::dijkstra::CarGraph;
 pub use self::driving::driving_cost;
-pub use self::walking::{walking_cost, WalkingNode};
-use crate::{
-    osm, BusRouteID, BusStopID, Lane, LaneID, LaneType, Map, Position, Traversable, TurnID,
-    UberTurn,
-};
+pub use self::walking::{walking_cost, walking_lane_cost, TransitRide, WalkingNode};
+use crate::{osm, Lane, LaneID, LaneType, Map, Position, Traversable, TurnID, UberTurn};
 use abstutil::Timer;
 use enumset::EnumSetType;
-use geom::{Distance, PolyLine, EPSILON_DIST};
+use geom::{Distance, PolyLine, Time, EPSILON_DIST};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeSet, VecDeque};
 use std::fmt;
@@ -155,6 +153,25 @@ impl Path {
         )
     }
 
+    // Follow a fixed route, given as every lane along the way. None if the route doesn't go from
+    // the request's start to end, or if the map has changed and it's no longer possible.
+    pub fn from_lanes(req: &PathRequest, lanes: &[LaneID], map: &Map) -> Option<Path> {
+        if lanes.first() != Some(&req.start.lane()) || lanes.last() != Some(&req.end.lane()) {
+            return None;
+        }
+        let mut steps = Vec::new();
+        for pair in lanes.windows(2) {
+            let turn = map
+                .get_turns_for(pair[0], req.constraints)
+                .into_iter()
+                .find(|t| t.id.dst == pair[1])?;
+            steps.push(PathStep::Lane(pair[0]));
+            steps.push(PathStep::Turn(turn.id));
+        }
+        steps.push(PathStep::Lane(req.end.lane()));
+        Some(Path::new(map, steps, req.end.dist_along(), Vec::new()))
+    }
+
     // Only used for weird serialization magic.
     pub fn dummy() -> Path {
         Path {
@@ -292,6 +309,36 @@ impl Path {
         }
     }
 
+    // Swap the current lane for a parallel one, along with the turn leaving it. Unlike
+    // modify_step, this changes where the agent currently is. Trusting the caller to do this in
+    // valid ways.
+    pub fn change_current_lane(&mut self, lane: LaneID, turn: TurnID, map: &Map) {
+        assert!(self.currently_inside_ut.is_none());
+        for (idx, step) in vec![(0, PathStep::Lane(lane)), (1, PathStep::Turn(turn))] {
+            self.total_length -= self.steps[idx].as_traversable().length(map);
+            self.steps[idx] = step;
+            self.total_length += self.steps[idx].as_traversable().length(map);
+        }
+    }
+
+    // Swap everything after the current step for a different route to the same place, keeping the
+    // progress made so far. The replacement has to start with the current step.
+    pub fn replace_remaining(&mut self, other: Path, map: &Map) {
+        assert!(self.currently_inside_ut.is_none());
+        assert_eq!(self.steps[0], other.steps[0]);
+        for step in self.steps.drain(1..) {
+            self.total_length -= step.as_traversable().length(map);
+            match step {
+                PathStep::Lane(_) | PathStep::ContraflowLane(_) => self.total_lanes -= 1,
+                _ => {}
+            };
+        }
+        for step in other.steps.into_iter().skip(1) {
+            self.add(step, map);
+        }
+        self.uber_turns = other.uber_turns;
+    }
+
     pub fn current_step(&self) -> PathStep {
         self.steps[0]
     }
@@ -500,23 +547,93 @@ impl PathConstraints {
     }
 }
 
+// Different people weigh routes differently. Each profile only changes costs for some
+// PathConstraints; for everything else, it's the same as Fastest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum RoutingProfile {
+    // The default for everybody
+    Fastest,
+    // The safest option for cyclists: stick to bike lanes and quiet streets, and avoid mixing
+    // with fast traffic.
+    LowStress,
+    // Drivers stay off freeways when there's a reasonable alternative.
+    AvoidHighways,
+    // Pedestrians and cyclists avoid steep hills, uphill or downhill.
+    AvoidSteep,
+}
+
+// Lanes steeper than this count against AvoidSteep
+const STEEP_GRADE: f64 = 0.05;
+// Each meter of a steep lane seems this much longer to somebody avoiding hills
+const AVOID_STEEP_FACTOR: f64 = 5.0;
+
+impl RoutingProfile {
+    pub fn all() -> Vec<RoutingProfile> {
+        vec![
+            RoutingProfile::Fastest,
+            RoutingProfile::LowStress,
+            RoutingProfile::AvoidHighways,
+            RoutingProfile::AvoidSteep,
+        ]
+    }
+
+    // Every profile besides Fastest that changes costs for these constraints. Each of these gets
+    // its own contraction hierarchy.
+    pub fn alternatives(constraints: PathConstraints) -> Vec<RoutingProfile> {
+        match constraints {
+            PathConstraints::Pedestrian => vec![RoutingProfile::AvoidSteep],
+            PathConstraints::Car => vec![RoutingProfile::AvoidHighways],
+            PathConstraints::Bike => vec![RoutingProfile::LowStress, RoutingProfile::AvoidSteep],
+            PathConstraints::Bus | PathConstraints::Train => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for RoutingProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                RoutingProfile::Fastest => "fastest",
+                RoutingProfile::LowStress => "low traffic stress",
+                RoutingProfile::AvoidHighways => "avoid highways",
+                RoutingProfile::AvoidSteep => "avoid steep hills",
+            }
+        )
+    }
+}
+
+// How much longer the lane seems to somebody avoiding steep hills, in either direction
+pub(crate) fn steep_penalty(lane: &Lane, map: &Map) -> Distance {
+    let steep: Distance = lane
+        .grade_profile(map)
+        .into_iter()
+        .filter(|(_, grade)| grade.abs() > STEEP_GRADE)
+        .map(|(dist, _)| dist)
+        .sum();
+    AVOID_STEEP_FACTOR * steep
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct PathRequest {
     pub start: Position,
     pub end: Position,
     pub constraints: PathConstraints,
+    pub profile: RoutingProfile,
 }
 
 impl fmt::Display for PathRequest {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "PathRequest({} along {}... to {} along {} for {:?})",
+            "PathRequest({} along {}... to {} along {} for {:?}, {})",
             self.start.dist_along(),
             self.start.lane(),
             self.end.dist_along(),
             self.end.lane(),
             self.constraints,
+            self.profile,
         )
     }
 }
@@ -588,7 +705,7 @@ fn validate_restrictions(map: &Map, steps: &Vec<PathStep>) {
 // Most of the time, prefer using the faster contraction hierarchies. But sometimes, callers can
 // explicitly opt into a slower (but preparation-free) pathfinder that just uses Dijkstra's
 // maneuever.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum Pathfinder {
     Dijkstra,
     CH(ContractionHierarchyPathfinder),
@@ -615,11 +732,12 @@ impl Pathfinder {
         map: &Map,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, Option<BusStopID>, BusRouteID)> {
+        depart: Time,
+    ) -> Option<Vec<TransitRide>> {
         match self {
             // TODO Implement this
             Pathfinder::Dijkstra => None,
-            Pathfinder::CH(ref p) => p.should_use_transit(map, start, end),
+            Pathfinder::CH(ref p) => p.should_use_transit(map, start, end, depart),
         }
     }
 
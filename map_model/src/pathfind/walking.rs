@@ -1,11 +1,12 @@
 use crate::pathfind::driving::VehiclePathfinder;
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
+use crate::pathfind::steep_penalty;
 use crate::{
-    BusRoute, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathConstraints,
-    PathRequest, PathStep, Position,
+    BusRouteID, BusStopID, IntersectionID, Lane, LaneID, Map, Path, PathConstraints, PathRequest,
+    PathStep, Position, RoutingProfile,
 };
 use fast_paths::{deserialize_32, serialize_32, FastGraph, InputGraph, PathCalculator};
-use geom::{Distance, Speed};
+use geom::{Distance, Duration, Speed};
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::HashSet;
@@ -18,21 +19,45 @@ pub struct SidewalkPathfinder {
     #[serde(deserialize_with = "deserialize_nodemap")]
     nodes: NodeMap<WalkingNode>,
     use_transit: bool,
+    profile: RoutingProfile,
 
     #[serde(skip_serializing, skip_deserializing)]
     path_calc: ThreadLocal<RefCell<PathCalculator>>,
 }
 
+// The PathCalculator is just a cache per thread, so don't bother copying it.
+impl Clone for SidewalkPathfinder {
+    fn clone(&self) -> SidewalkPathfinder {
+        SidewalkPathfinder {
+            graph: self.graph.clone(),
+            nodes: self.nodes.clone(),
+            use_transit: self.use_transit,
+            profile: self.profile,
+            path_calc: ThreadLocal::new(),
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Serialize, Deserialize)]
 pub enum WalkingNode {
     // false is src_i, true is dst_i
     SidewalkEndpoint(LaneID, bool),
-    // TODO Lots of complexity below could be avoided by explicitly sticking BusRouteID here too.
-    // Worth it?
+    // Waiting at a stop, for any route
     RideBus(BusStopID),
+    // On board a route, at the stop with this index
+    OnRoute(BusRouteID, usize),
     LeaveMap(IntersectionID),
 }
 
+// One ride on a transit route, as part of a longer journey. If there's no stop to alight at, ride
+// off the map at the route's end border.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TransitRide {
+    pub route: BusRouteID,
+    pub board: BusStopID,
+    pub alight: Option<BusStopID>,
+}
+
 impl WalkingNode {
     pub fn closest(pos: Position, map: &Map) -> WalkingNode {
         let dst_i = map.get_l(pos.lane()).length() - pos.dist_along() <= pos.dist_along();
@@ -59,32 +84,16 @@ impl SidewalkPathfinder {
     pub fn new(
         map: &Map,
         use_transit: bool,
+        profile: RoutingProfile,
         bus_graph: &VehiclePathfinder,
         train_graph: &VehiclePathfinder,
     ) -> SidewalkPathfinder {
-        let mut nodes = NodeMap::new();
-        // We're assuming that to start with, no sidewalks are closed for construction!
-        for l in map.all_lanes() {
-            if l.is_walkable() {
-                nodes.get_or_insert(WalkingNode::SidewalkEndpoint(l.id, true));
-                nodes.get_or_insert(WalkingNode::SidewalkEndpoint(l.id, false));
-            }
-        }
-        if use_transit {
-            // Add a node for each bus stop.
-            for bs in map.all_bus_stops().keys() {
-                nodes.get_or_insert(WalkingNode::RideBus(*bs));
-            }
-            for i in map.all_outgoing_borders() {
-                // We could filter for those with sidewalks, but eh
-                nodes.get_or_insert(WalkingNode::LeaveMap(i.id));
-            }
-        }
-
+        let nodes = make_nodes(map, use_transit);
         let graph = fast_paths::prepare(&make_input_graph(
             map,
             &nodes,
             use_transit,
+            profile,
             bus_graph,
             train_graph,
         ));
@@ -92,6 +101,7 @@ impl SidewalkPathfinder {
             graph,
             nodes,
             use_transit,
+            profile,
             path_calc: ThreadLocal::new(),
         }
     }
@@ -102,12 +112,26 @@ impl SidewalkPathfinder {
         bus_graph: &VehiclePathfinder,
         train_graph: &VehiclePathfinder,
     ) {
-        // The NodeMap is all sidewalks, bus stops, and borders -- it won't change. So we can also
-        // reuse the node ordering.
-        let input_graph =
-            make_input_graph(map, &self.nodes, self.use_transit, bus_graph, train_graph);
-        let node_ordering = self.graph.get_node_ordering();
-        self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
+        // The NodeMap is all sidewalks, bus stops, stops along each route, and borders. Usually
+        // it won't change, so we can also reuse the node ordering.
+        let nodes = make_nodes(map, self.use_transit);
+        let input_graph = make_input_graph(
+            map,
+            &nodes,
+            self.use_transit,
+            self.profile,
+            bus_graph,
+            train_graph,
+        );
+        if nodes == self.nodes {
+            let node_ordering = self.graph.get_node_ordering();
+            self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
+        } else {
+            // Transit routes were created or rerouted, so start over.
+            self.graph = fast_paths::prepare(&input_graph);
+            self.nodes = nodes;
+            self.path_calc = ThreadLocal::new();
+        }
     }
 
     // Returns the raw nodes
@@ -125,19 +149,31 @@ impl SidewalkPathfinder {
         Some(self.nodes.translate(&raw_path))
     }
 
-    // Attempt the pathfinding and see if we should ride a bus. If so, says (stop1, optional stop
-    // 2, route). If there's no stop 2, then ride the bus off the border.
+    // The cost of the best path between two positions, in seconds
+    pub fn cost(&self, map: &Map, start: Position, end: Position) -> Option<usize> {
+        fast_paths::calc_path(
+            &self.graph,
+            self.nodes.get(WalkingNode::closest(start, map)),
+            self.nodes.get(WalkingNode::closest(end, map)),
+        )
+        .map(|path| path.get_weight())
+    }
+
+    // Attempt the pathfinding and see if we should ride transit. If so, returns every ride in
+    // order, possibly with transfers between them, and the cost of the whole path, assuming the
+    // average wait for each route. The last ride might go off the map.
     pub fn should_use_transit(
         &self,
         map: &Map,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, Option<BusStopID>, BusRouteID)> {
+    ) -> Option<(Vec<TransitRide>, usize)> {
         let raw_path = fast_paths::calc_path(
             &self.graph,
             self.nodes.get(WalkingNode::closest(start, map)),
             self.nodes.get(WalkingNode::end_transit(end, map)),
         )?;
+        let cost = raw_path.get_weight();
 
         let nodes = self.nodes.translate(&raw_path);
         if false {
@@ -147,79 +183,82 @@ impl SidewalkPathfinder {
             }
         }
 
-        let mut first_stop = None;
-        let mut last_stop = None;
-        let mut possible_routes: Vec<&BusRoute> = Vec::new();
-        for n in &nodes {
+        let mut rides = Vec::new();
+        let mut current: Option<TransitRide> = None;
+        for n in nodes {
             match n {
-                WalkingNode::RideBus(stop2) => {
-                    if first_stop.is_none() {
-                        first_stop = Some(*stop2);
-                        possible_routes = map.get_routes_serving_stop(*stop2);
-                        assert!(!possible_routes.is_empty());
+                WalkingNode::OnRoute(route, idx) => {
+                    let stop = map.get_br(route).stops[idx];
+                    if let Some(ref mut ride) = current {
+                        assert_eq!(ride.route, route);
+                        ride.alight = Some(stop);
                     } else {
-                        // Keep riding the same route?
-                        // We need to do this check, because some transfers might be instantaneous
-                        // at the same stop and involve no walking.
-                        // Also need to make sure the stops are in the proper order. We might have
-                        // a transfer, then try to hop on the first route again, but starting from
-                        // a different point.
-                        let stop1 = first_stop.unwrap();
-                        let mut filtered = possible_routes.clone();
-                        filtered.retain(|r| {
-                            let idx1 = r.stops.iter().position(|s| *s == stop1).unwrap();
-                            let idx2 = r.stops.iter().position(|s| s == stop2);
-                            idx2.map(|idx2| idx1 < idx2).unwrap_or(false)
+                        current = Some(TransitRide {
+                            route,
+                            board: stop,
+                            alight: None,
                         });
-                        if filtered.is_empty() {
-                            // Aha, a transfer!
-                            return Some((
-                                first_stop.unwrap(),
-                                // TODO I thought this should be impossible, but huge_seattle hits
-                                // it. Workaround for now by just walking.
-                                Some(last_stop?),
-                                possible_routes[0].id,
-                            ));
-                        }
-                        last_stop = Some(*stop2);
-                        possible_routes = filtered;
                     }
                 }
-                WalkingNode::LeaveMap(i) => {
-                    // Make sure the route actually leaves via the correct border!
-                    if let Some(r) = possible_routes.iter().find(|r| {
-                        r.end_border
-                            .map(|l| map.get_l(l).dst_i == *i)
-                            .unwrap_or(false)
-                    }) {
-                        return Some((first_stop.unwrap(), None, r.id));
+                WalkingNode::RideBus(stop) => {
+                    if let Some(ride) = current.take() {
+                        // Boarding and immediately getting off again would be silly
+                        if ride.alight.is_some() {
+                            assert_eq!(ride.alight, Some(stop));
+                            rides.push(ride);
+                        }
                     }
-                    // We can get close to the border, but should hop off at some stop.
-                    return Some((
-                        first_stop.unwrap(),
-                        Some(last_stop.expect("impossible transit transfer")),
-                        possible_routes[0].id,
-                    ));
                 }
-                WalkingNode::SidewalkEndpoint(_, _) => {
-                    if let Some(stop1) = first_stop {
-                        return Some((
-                            stop1,
-                            Some(last_stop.expect("impossible transit transfer")),
-                            possible_routes[0].id,
-                        ));
-                    }
+                WalkingNode::LeaveMap(_) => {
+                    let mut ride = current.take().expect("walked off the map using transit");
+                    // Ride off the border, not to the last stop
+                    ride.alight = None;
+                    rides.push(ride);
                 }
+                WalkingNode::SidewalkEndpoint(_, _) => {}
             }
         }
-        None
+        if rides.is_empty() {
+            None
+        } else {
+            Some((rides, cost))
+        }
+    }
+}
+
+fn make_nodes(map: &Map, use_transit: bool) -> NodeMap<WalkingNode> {
+    let mut nodes = NodeMap::new();
+    // We're assuming that to start with, no sidewalks are closed for construction!
+    for l in map.all_lanes() {
+        if l.is_walkable() {
+            nodes.get_or_insert(WalkingNode::SidewalkEndpoint(l.id, true));
+            nodes.get_or_insert(WalkingNode::SidewalkEndpoint(l.id, false));
+        }
     }
+    if use_transit {
+        // Add a node for each bus stop.
+        for bs in map.all_bus_stops().keys() {
+            nodes.get_or_insert(WalkingNode::RideBus(*bs));
+        }
+        for route in map.all_bus_routes() {
+            for idx in 0..route.stops.len() {
+                nodes.get_or_insert(WalkingNode::OnRoute(route.id, idx));
+            }
+        }
+        // Borders have to be last; see the end of transit_input_graph.
+        for i in map.all_outgoing_borders() {
+            // We could filter for those with sidewalks, but eh
+            nodes.get_or_insert(WalkingNode::LeaveMap(i.id));
+        }
+    }
+    nodes
 }
 
 fn make_input_graph(
     map: &Map,
     nodes: &NodeMap<WalkingNode>,
     use_transit: bool,
+    profile: RoutingProfile,
     bus_graph: &VehiclePathfinder,
     train_graph: &VehiclePathfinder,
 ) -> InputGraph {
@@ -233,7 +272,7 @@ fn make_input_graph(
                 .allow_through_traffic
                 .contains(PathConstraints::Pedestrian)
         {
-            let mut cost = walking_cost(l.length());
+            let mut cost = walking_lane_cost(l, profile, map);
             // TODO Tune this penalty, along with many others.
             if l.is_shoulder() {
                 cost *= 2;
@@ -274,8 +313,16 @@ fn transit_input_graph(
     bus_graph: &VehiclePathfinder,
     train_graph: &VehiclePathfinder,
 ) {
-    // Connect bus stops with both sidewalk endpoints, using the appropriate distance.
+    // Connect bus stops with both sidewalk endpoints, using the appropriate distance. Edits can
+    // reroute everything away from a stop, so skip those; nothing can be ridden from there.
+    let mut served_stops = HashSet::new();
+    for route in map.all_bus_routes() {
+        served_stops.extend(route.stops.iter().cloned());
+    }
     for stop in map.all_bus_stops().values() {
+        if !served_stops.contains(&stop.id) {
+            continue;
+        }
         let ride_bus = nodes.get(WalkingNode::RideBus(stop.id));
         let lane = map.get_l(stop.sidewalk_pos.lane());
         for endpt in &[true, false] {
@@ -295,28 +342,39 @@ fn transit_input_graph(
 
     let mut used_border_nodes = HashSet::new();
 
-    // Connect each adjacent stop along a route, with the cost based on how long it'll take a
-    // bus to drive between the stops. Optimistically assume no waiting time at a stop.
+    // Each route has its own nodes, so the path says exactly what to ride, and transferring
+    // between routes costs another wait. Boarding costs the average wait for the route, since the
+    // graph can't change over the day, and riding between adjacent stops costs however long it'll
+    // take to drive between them.
     for route in map.all_bus_routes() {
+        let graph = match route.route_type {
+            PathConstraints::Bus => bus_graph,
+            PathConstraints::Train => train_graph,
+            _ => unreachable!(),
+        };
+        let wait = wait_cost(route.average_wait());
+        for (idx, bs) in route.stops.iter().enumerate() {
+            let stop = nodes.get(WalkingNode::RideBus(*bs));
+            let on_route = nodes.get(WalkingNode::OnRoute(route.id, idx));
+            input_graph.add_edge(stop, on_route, wait);
+            input_graph.add_edge(on_route, stop, 1);
+        }
+
         // TODO Also plug in border starts
-        for pair in route.stops.windows(2) {
+        for (idx, pair) in route.stops.windows(2).enumerate() {
             let (stop1, stop2) = (map.get_bs(pair[0]), map.get_bs(pair[1]));
-            let graph = match route.route_type {
-                PathConstraints::Bus => bus_graph,
-                PathConstraints::Train => train_graph,
-                _ => unreachable!(),
-            };
             if let Some((_, driving_cost)) = graph.pathfind(
                 &PathRequest {
                     start: stop1.driving_pos,
                     end: stop2.driving_pos,
                     constraints: route.route_type,
+                    profile: RoutingProfile::Fastest,
                 },
                 map,
             ) {
                 input_graph.add_edge(
-                    nodes.get(WalkingNode::RideBus(stop1.id)),
-                    nodes.get(WalkingNode::RideBus(stop2.id)),
+                    nodes.get(WalkingNode::OnRoute(route.id, idx)),
+                    nodes.get(WalkingNode::OnRoute(route.id, idx + 1)),
                     driving_cost,
                 );
             } else {
@@ -328,23 +386,20 @@ fn transit_input_graph(
         }
 
         if let Some(l) = route.end_border {
-            let graph = match route.route_type {
-                PathConstraints::Bus => bus_graph,
-                PathConstraints::Train => train_graph,
-                _ => unreachable!(),
-            };
-            let stop1 = map.get_bs(*route.stops.last().unwrap());
+            let idx = route.stops.len() - 1;
+            let stop1 = map.get_bs(route.stops[idx]);
             if let Some((_, driving_cost)) = graph.pathfind(
                 &PathRequest {
                     start: stop1.driving_pos,
                     end: Position::end(l, map),
                     constraints: route.route_type,
+                    profile: RoutingProfile::Fastest,
                 },
                 map,
             ) {
                 let border = map.get_i(map.get_l(l).dst_i);
                 input_graph.add_edge(
-                    nodes.get(WalkingNode::RideBus(stop1.id)),
+                    nodes.get(WalkingNode::OnRoute(route.id, idx)),
                     nodes.get(WalkingNode::LeaveMap(border.id)),
                     driving_cost,
                 );
@@ -384,18 +439,31 @@ pub fn walking_cost(dist: Distance) -> usize {
     (time.inner_seconds().round() as usize).max(1)
 }
 
+pub fn wait_cost(wait: Duration) -> usize {
+    (wait.inner_seconds().round() as usize).max(1)
+}
+
+// The cost of walking the full length of a sidewalk or shoulder
+pub fn walking_lane_cost(lane: &Lane, profile: RoutingProfile, map: &Map) -> usize {
+    if profile == RoutingProfile::AvoidSteep {
+        walking_cost(lane.length() + steep_penalty(lane, map))
+    } else {
+        walking_cost(lane.length())
+    }
+}
+
 pub fn walking_path_to_steps(path: Vec<WalkingNode>, map: &Map) -> Vec<PathStep> {
     let mut steps: Vec<PathStep> = Vec::new();
 
     for pair in path.windows(2) {
         let (l1, l1_endpt) = match pair[0] {
             WalkingNode::SidewalkEndpoint(l, endpt) => (l, endpt),
-            WalkingNode::RideBus(_) => unreachable!(),
+            WalkingNode::RideBus(_) | WalkingNode::OnRoute(_, _) => unreachable!(),
             WalkingNode::LeaveMap(_) => unreachable!(),
         };
         let l2 = match pair[1] {
             WalkingNode::SidewalkEndpoint(l, _) => l,
-            WalkingNode::RideBus(_) => unreachable!(),
+            WalkingNode::RideBus(_) | WalkingNode::OnRoute(_, _) => unreachable!(),
             WalkingNode::LeaveMap(_) => unreachable!(),
         };
 
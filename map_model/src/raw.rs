@@ -3,7 +3,7 @@ use crate::{
     osm, AreaType, Direction, DrivingSide, IntersectionType, LaneType, MapConfig, NamePerLanguage,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Tags, Timer};
-use geom::{Circle, Distance, GPSBounds, PolyLine, Polygon, Pt2D};
+use geom::{Angle, Circle, Distance, Duration, GPSBounds, PolyLine, Polygon, Pt2D, Time};
 use petgraph::graphmap::DiGraphMap;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
@@ -29,9 +29,12 @@ pub struct RawMap {
     )]
     pub buildings: BTreeMap<osm::OsmID, RawBuilding>,
     pub bus_routes: Vec<RawBusRoute>,
+    // From a GTFS feed, if one was provided. These replace bus_routes from OSM.
+    pub gtfs_routes: Vec<RawGtfsRoute>,
     pub areas: Vec<RawArea>,
     pub parking_lots: Vec<RawParkingLot>,
     pub parking_aisles: Vec<(osm::WayID, Vec<Pt2D>)>,
+    pub docking_stations: Vec<RawDockingStation>,
 
     pub boundary_polygon: Polygon,
     pub gps_bounds: GPSBounds,
@@ -85,9 +88,11 @@ impl RawMap {
             intersections: BTreeMap::new(),
             buildings: BTreeMap::new(),
             bus_routes: Vec::new(),
+            gtfs_routes: Vec::new(),
             areas: Vec::new(),
             parking_lots: Vec::new(),
             parking_aisles: Vec::new(),
+            docking_stations: Vec::new(),
             // Some nonsense thing
             boundary_polygon: Polygon::rectangle(1.0, 1.0),
             gps_bounds: GPSBounds::new(),
@@ -250,6 +255,8 @@ pub struct RawRoad {
     pub turn_restrictions: Vec<(RestrictionType, OriginalRoad)>,
     // (via, to). For turn restrictions where 'via' is an entire road. Only BanTurns.
     pub complicated_turn_restrictions: Vec<(OriginalRoad, OriginalRoad)>,
+    // (distance along center_points, elevation), if elevation data was imported. Empty otherwise.
+    pub elevation_profile: Vec<(Distance, Distance)>,
 }
 
 impl RawRoad {
@@ -333,6 +340,14 @@ pub struct RawParkingLot {
     pub osm_tags: Tags,
 }
 
+// For bikeshare or scooter-share
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RawDockingStation {
+    pub osm_id: osm::NodeID,
+    pub point: Pt2D,
+    pub osm_tags: Tags,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum RestrictionType {
     BanTurns,
@@ -385,3 +400,39 @@ pub struct RawBusStop {
     // If it's not explicitly mapped, we'll do equiv_pos.
     pub ped_pos: Option<Pt2D>,
 }
+
+// One variant of a GTFS route. Trips of the same route that serve a different sequence of stops
+// inside the map become separate variants.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawGtfsRoute {
+    // The GTFS route_id and the stop_ids of the first and last stop. If several variants share
+    // those, all but the one with the most trips also get a hash of their stops. Stable across feed
+    // updates, as long as the agency doesn't renumber things.
+    pub gtfs_id: String,
+    pub full_name: String,
+    pub short_name: String,
+    // If not, light rail
+    pub is_bus: bool,
+    // Only the contiguous stops inside the map, in order. At least 2.
+    pub stops: Vec<RawGtfsStop>,
+    // If the route starts or ends outside the map, the position of the stop just before entering
+    // or after leaving. The route will start or end at the border closest to the way there.
+    pub enters_from: Option<Pt2D>,
+    pub exits_to: Option<Pt2D>,
+    // When each trip departs the first stop in the map, or crosses into the map if it enters from
+    // outside, sorted and deduplicated
+    pub spawn_times: Vec<Time>,
+    // For each of spawn_times, when that trip is scheduled to arrive at and depart from every stop,
+    // relative to its spawn time. Empty for trips whose times can't be used.
+    pub timetables: Vec<Vec<(Duration, Duration)>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawGtfsStop {
+    pub stop_id: String,
+    pub name: String,
+    pub pos: Pt2D,
+    // The direction of travel at the stop, from the shape if the feed has one. Picks the side of
+    // the road.
+    pub heading: Angle,
+}
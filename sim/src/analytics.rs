@@ -1,13 +1,22 @@
-use crate::{AgentType, AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType};
-use abstutil::Counter;
+use crate::{
+    AgentID, AgentType, AlertLocation, CarID, Event, ParkingSpot, PersonID, TripID, TripMode,
+    TripPhaseType, VehicleType,
+};
+use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use geom::{Distance, Duration, Time};
 use map_model::{
-    BusRouteID, BusStopID, CompressedMovementID, IntersectionID, LaneID, Map, MovementID,
-    ParkingLotID, Path, PathRequest, RoadID, Traversable,
+    BuildingID, BusRouteID, BusStopID, CompressedMovementID, DirectedRoadID, DockingStationID,
+    IntersectionID, LaneID, Map, MovementID, ParkingArea, ParkingLotID, Path, PathRequest, RoadID,
+    Traversable,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, VecDeque};
 
+// How much a new travel time observation counts against the previous estimate
+const TRAVEL_TIME_SMOOTHING: f64 = 0.3;
+// Older travel time observations don't say much about current traffic
+const TRAVEL_TIME_MEMORY: Duration = Duration::const_seconds(15.0 * 60.0);
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Analytics {
     pub road_thruput: TimeSeriesCount<RoadID>,
@@ -19,12 +28,25 @@ pub struct Analytics {
 
     // Unlike everything else in Analytics, this is just for a moment in time.
     pub demand: BTreeMap<MovementID, usize>,
+    // Also just for the present. Congestion-aware rerouting depends on this, so it keeps updating
+    // even after restoring from a savestate.
+    pub road_travel_times: RoadTravelTimes,
+    // Per directed road and hour, the total time cars took to cross it, and how many did. Measured
+    // like road_travel_times.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub hourly_road_travel_times: BTreeMap<(DirectedRoadID, usize), (Duration, usize)>,
 
     // TODO Reconsider this one
-    pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID)>,
+    // The last Time is when the vehicle was scheduled to arrive.
+    pub bus_arrivals: Vec<(Time, CarID, BusRouteID, BusStopID, Time)>,
     // For each passenger boarding, how long did they wait at the stop?
     pub passengers_boarding: BTreeMap<BusStopID, Vec<(Time, BusRouteID, Duration)>>,
     pub passengers_alighting: BTreeMap<BusStopID, Vec<(Time, BusRouteID)>>,
+    // How many people couldn't board a full vehicle?
+    pub passengers_left_behind: BTreeMap<BusStopID, Vec<(Time, BusRouteID, usize)>>,
 
     pub started_trips: BTreeMap<TripID, Time>,
     // TODO Hack: No TripMode means aborted
@@ -40,6 +62,28 @@ pub struct Analytics {
     // Per parking lane or lot, when does a spot become filled (true) or free (false)
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
+    // Per priced or time-limited area, when a car left, what it paid in cents, and whether it
+    // stayed past the limit
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub parking_payments: BTreeMap<ParkingArea, Vec<(Time, usize, bool)>>,
+
+    // How many vehicles are docked at each station after every pickup or return
+    pub docking_station_inventory: BTreeMap<DockingStationID, Vec<(Time, usize)>>,
+    // When somebody reached a station that was empty (true) or full (false)
+    pub docking_station_unavailable: BTreeMap<DockingStationID, Vec<(Time, bool)>>,
+
+    // How far each ride-hail vehicle had to drive empty to reach a pickup
+    pub ride_hail_deadheading: Vec<(Time, CarID, Distance)>,
+    // How long each person waited at the curb to be picked up
+    pub ride_hail_wait_times: Vec<(Time, PersonID, Duration)>,
+    // How many ride-hail vehicles are occupied after every pickup and dropoff
+    pub ride_hail_occupancy: Vec<(Time, usize)>,
+    // Every stop a delivery truck made, and whether it double-parked (true) or found a loading
+    // zone (false)
+    pub delivery_stops: Vec<(Time, CarID, BuildingID, bool)>,
 
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
 
@@ -56,25 +100,45 @@ impl Analytics {
             intersection_thruput: TimeSeriesCount::new(),
             traffic_signal_thruput: TimeSeriesCount::new(),
             demand: BTreeMap::new(),
+            road_travel_times: RoadTravelTimes::new(),
+            hourly_road_travel_times: BTreeMap::new(),
             bus_arrivals: Vec::new(),
             passengers_boarding: BTreeMap::new(),
             passengers_alighting: BTreeMap::new(),
+            passengers_left_behind: BTreeMap::new(),
             started_trips: BTreeMap::new(),
             finished_trips: Vec::new(),
             trip_log: Vec::new(),
             intersection_delays: BTreeMap::new(),
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
+            parking_payments: BTreeMap::new(),
+            docking_station_inventory: BTreeMap::new(),
+            docking_station_unavailable: BTreeMap::new(),
+            ride_hail_deadheading: Vec::new(),
+            ride_hail_wait_times: Vec::new(),
+            ride_hail_occupancy: Vec::new(),
+            delivery_stops: Vec::new(),
             alerts: Vec::new(),
             record_anything: true,
         }
     }
 
     pub fn event(&mut self, ev: Event, time: Time, map: &Map) {
+        let observation = self.road_travel_times.event(&ev, time, map);
         if !self.record_anything {
             return;
         }
 
+        if let Some((dr, dt)) = observation {
+            let entry = self
+                .hourly_road_travel_times
+                .entry((dr, time.get_hours()))
+                .or_insert((Duration::ZERO, 0));
+            entry.0 += dt;
+            entry.1 += 1;
+        }
+
         // Throughput
         if let Event::AgentEntersTraversable(a, to, passengers) = ev {
             match to {
@@ -129,8 +193,8 @@ impl Analytics {
         }
 
         // Bus arrivals
-        if let Event::BusArrivedAtStop(bus, route, stop) = ev {
-            self.bus_arrivals.push((time, bus, route, stop));
+        if let Event::BusArrivedAtStop(bus, route, stop, scheduled) = ev {
+            self.bus_arrivals.push((time, bus, route, stop, scheduled));
         }
 
         // Passengers boarding/alighting
@@ -146,6 +210,12 @@ impl Analytics {
                 .or_insert_with(Vec::new)
                 .push((time, route));
         }
+        if let Event::PassengersLeftBehind(_, route, stop, cnt) = ev {
+            self.passengers_left_behind
+                .entry(stop)
+                .or_insert_with(Vec::new)
+                .push((time, route, cnt));
+        }
 
         // Started trips
         if let Event::TripPhaseStarting(id, _, _, _) = ev {
@@ -189,7 +259,7 @@ impl Analytics {
                     .push((time, true));
             }
         }
-        if let Event::CarLeftParkingSpot(_, spot) = ev {
+        if let Event::CarLeftParkingSpot(_, spot, parked_since) = ev {
             if let ParkingSpot::Onstreet(l, _) = spot {
                 self.parking_lane_changes
                     .entry(l)
@@ -201,6 +271,57 @@ impl Analytics {
                     .or_insert_with(Vec::new)
                     .push((time, false));
             }
+
+            // Drivers pay when they leave, at whatever the price is then
+            let policy = spot.policy(map);
+            if !policy.is_free() {
+                let area = match spot {
+                    ParkingSpot::Onstreet(l, _) => ParkingArea::Lane(l),
+                    ParkingSpot::Offstreet(b, _) => ParkingArea::Garage(b),
+                    ParkingSpot::Lot(pl, _) => ParkingArea::Lot(pl),
+                };
+                let stay = time - parked_since;
+                self.parking_payments
+                    .entry(area)
+                    .or_insert_with(Vec::new)
+                    .push((time, policy.cost(stay), policy.overstayed(stay)));
+            }
+        }
+
+        // Bikeshare
+        match ev {
+            Event::SharedVehiclePickedUp(_, ds, cnt) | Event::SharedVehicleReturned(_, ds, cnt) => {
+                self.docking_station_inventory
+                    .entry(ds)
+                    .or_insert_with(Vec::new)
+                    .push((time, cnt));
+            }
+            Event::DockingStationEmpty(_, ds) => {
+                self.docking_station_unavailable
+                    .entry(ds)
+                    .or_insert_with(Vec::new)
+                    .push((time, true));
+            }
+            Event::DockingStationFull(_, ds) => {
+                self.docking_station_unavailable
+                    .entry(ds)
+                    .or_insert_with(Vec::new)
+                    .push((time, false));
+            }
+            Event::RideHailDispatched(_, car, dist) => {
+                self.ride_hail_deadheading.push((time, car, dist));
+            }
+            Event::RideHailPickup(person, _, wait, occupied) => {
+                self.ride_hail_wait_times.push((time, person, wait));
+                self.ride_hail_occupancy.push((time, occupied));
+            }
+            Event::RideHailDropoff(_, _, occupied) => {
+                self.ride_hail_occupancy.push((time, occupied));
+            }
+            Event::DeliveryStop(car, b, spot, _) => {
+                self.delivery_stops.push((time, car, b, spot.is_none()));
+            }
+            _ => {}
         }
 
         // TODO Kinda hacky, but these all consume the event, so kinda bundle em.
@@ -312,6 +433,56 @@ impl Analytics {
         results
     }
 
+    // For every arrival of the route at a stop, how late was the vehicle? Negative means early.
+    pub fn schedule_deviation(
+        &self,
+        now: Time,
+        route: BusRouteID,
+    ) -> Vec<(Time, CarID, BusStopID, Duration)> {
+        let mut results = Vec::new();
+        for (t, car, r, stop, scheduled) in &self.bus_arrivals {
+            if *t > now {
+                break;
+            }
+            if *r == route {
+                results.push((*t, *car, *stop, *t - *scheduled));
+            }
+        }
+        results
+    }
+
+    // Find vehicles of the route arriving at a stop much sooner after the previous one than
+    // scheduled. With a threshold of 0.5, the actual headway has to be less than half of the
+    // scheduled headway. Returns (arrival, stop, the later vehicle, actual headway, scheduled
+    // headway).
+    pub fn bunching(
+        &self,
+        now: Time,
+        route: BusRouteID,
+        threshold: f64,
+    ) -> Vec<(Time, BusStopID, CarID, Duration, Duration)> {
+        let mut results = Vec::new();
+        // Per stop, the previous (actual, scheduled) arrival
+        let mut last_arrival: BTreeMap<BusStopID, (Time, Time)> = BTreeMap::new();
+        for (t, car, r, stop, scheduled) in &self.bus_arrivals {
+            if *t > now {
+                break;
+            }
+            if *r != route {
+                continue;
+            }
+            if let Some((prev_t, prev_scheduled)) = last_arrival.insert(*stop, (*t, *scheduled)) {
+                let actual = *t - prev_t;
+                let planned = *scheduled - prev_scheduled;
+                // If vehicles passed each other, the scheduled headway is negative; skip it.
+                if planned > Duration::ZERO && actual < threshold * planned {
+                    results.push((*t, *stop, *car, actual, planned));
+                }
+            }
+        }
+        results
+    }
+
     pub fn get_trip_phases(&self, trip: TripID, map: &Map) -> Vec<TripPhase> {
         let mut phases: Vec<TripPhase> = Vec::new();
         for (t, id, maybe_req, phase_type) in &self.trip_log {
@@ -435,6 +606,114 @@ impl Analytics {
         }
     }
 
+    // The average fraction of spots filled during each hour, labeled by the start of the hour
+    pub fn parking_lane_occupancy_per_hour(
+        &self,
+        now: Time,
+        l: LaneID,
+        capacity: usize,
+    ) -> Vec<(Time, f64)> {
+        Analytics::occupancy_per_hour(
+            now,
+            self.parking_lane_availability(now, l, capacity),
+            capacity,
+        )
+    }
+    pub fn parking_lot_occupancy_per_hour(
+        &self,
+        now: Time,
+        pl: ParkingLotID,
+        capacity: usize,
+    ) -> Vec<(Time, f64)> {
+        Analytics::occupancy_per_hour(
+            now,
+            self.parking_lot_availability(now, pl, capacity),
+            capacity,
+        )
+    }
+
+    // Revenue in cents collected during each hour, labeled by the start of the hour. If no area is
+    // specified, sums everywhere.
+    pub fn parking_revenue_per_hour(
+        &self,
+        now: Time,
+        area: Option<ParkingArea>,
+    ) -> Vec<(Time, usize)> {
+        let mut per_hour = vec![0; now.get_parts().0 + 1];
+        for (a, payments) in &self.parking_payments {
+            if area.map(|area| area != *a).unwrap_or(false) {
+                continue;
+            }
+            for (t, cents, _) in payments {
+                if *t > now {
+                    break;
+                }
+                per_hour[t.get_parts().0] += cents;
+            }
+        }
+        per_hour
+            .into_iter()
+            .enumerate()
+            .map(|(hour, cents)| (Time::START_OF_DAY + Duration::hours(hour), cents))
+            .collect()
+    }
+
+    // Per area, (total revenue in cents, number of cars that stayed past the time limit)
+    pub fn parking_revenue(&self, now: Time) -> BTreeMap<ParkingArea, (usize, usize)> {
+        let mut results = BTreeMap::new();
+        for (area, payments) in &self.parking_payments {
+            let mut revenue = 0;
+            let mut overstays = 0;
+            for (t, cents, overstayed) in payments {
+                if *t > now {
+                    break;
+                }
+                revenue += cents;
+                if *overstayed {
+                    overstays += 1;
+                }
+            }
+            results.insert(*area, (revenue, overstays));
+        }
+        results
+    }
+
+    fn occupancy_per_hour(
+        now: Time,
+        availability: Vec<(Time, usize)>,
+        capacity: usize,
+    ) -> Vec<(Time, f64)> {
+        if capacity == 0 {
+            return Vec::new();
+        }
+        let mut filled_seconds = vec![0.0; now.get_parts().0 + 1];
+        // Availability is a step function ending at now; split each step at the hour boundaries.
+        for pair in availability.windows(2) {
+            let filled = (capacity - pair[0].1) as f64;
+            let mut t1 = pair[0].0;
+            let t2 = pair[1].0;
+            while t1 < t2 {
+                let hour = t1.get_parts().0;
+                let until = (Time::START_OF_DAY + Duration::hours(hour + 1)).min(t2);
+                filled_seconds[hour] += filled * (until - t1).inner_seconds();
+                t1 = until;
+            }
+        }
+        let mut results = Vec::new();
+        for (hour, secs) in filled_seconds.into_iter().enumerate() {
+            let start = Time::START_OF_DAY + Duration::hours(hour);
+            // The last hour is only partly over
+            let mut elapsed = now - start;
+            if elapsed > Duration::hours(1) {
+                elapsed = Duration::hours(1);
+            }
+            if elapsed > Duration::ZERO {
+                results.push((start, secs / elapsed.inner_seconds() / (capacity as f64)));
+            }
+        }
+        results
+    }
+
     fn parking_spot_availability(
         now: Time,
         changes: &Vec<(Time, bool)>,
@@ -602,6 +881,86 @@ impl<X: Ord + Clone> TimeSeriesCount<X> {
     }
 }
 
+// How long drivers recently took to cross each directed road, from turning onto it until starting
+// the next turn, so waiting in a queue at the end counts.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoadTravelTimes {
+    // Where and when each car last turned onto a lane
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    entered: BTreeMap<CarID, (LaneID, Time)>,
+    // The smoothed travel time, and when it was last observed
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    roads: BTreeMap<DirectedRoadID, (Duration, Time)>,
+}
+
+impl RoadTravelTimes {
+    fn new() -> RoadTravelTimes {
+        RoadTravelTimes {
+            entered: BTreeMap::new(),
+            roads: BTreeMap::new(),
+        }
+    }
+
+    // Returns a new observation of how long somebody took to cross a road
+    fn event(&mut self, ev: &Event, time: Time, map: &Map) -> Option<(DirectedRoadID, Duration)> {
+        match ev {
+            // Only personal cars. Buses, bikes, and trucks stop or move at their own pace.
+            Event::AgentEntersTraversable(AgentID::Car(car), on, _)
+                if car.1 == VehicleType::Car =>
+            {
+                match on {
+                    Traversable::Lane(l) => {
+                        self.entered.insert(*car, (*l, time));
+                    }
+                    Traversable::Turn(t) => {
+                        if let Some((l, entered)) = self.entered.remove(car) {
+                            if l == t.src {
+                                let dr = map.get_l(l).get_directed_parent(map);
+                                self.observe(dr, time - entered, time);
+                                return Some((dr, time - entered));
+                            }
+                        }
+                    }
+                }
+            }
+            // Whatever time was spent stopped here doesn't count as travel time
+            Event::CarReachedParkingSpot(car, _)
+            | Event::RideHailPickup(_, car, _, _)
+            | Event::RideHailDropoff(_, car, _) => {
+                self.entered.remove(car);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn observe(&mut self, dr: DirectedRoadID, dt: Duration, time: Time) {
+        let estimate = match self.roads.get(&dr) {
+            Some((prev, last)) if time - *last <= TRAVEL_TIME_MEMORY => {
+                dt * TRAVEL_TIME_SMOOTHING + *prev * (1.0 - TRAVEL_TIME_SMOOTHING)
+            }
+            _ => dt,
+        };
+        self.roads.insert(dr, (estimate, time));
+    }
+
+    // None if nobody's crossed the road recently
+    pub fn get(&self, dr: DirectedRoadID, now: Time) -> Option<Duration> {
+        let (dt, last) = self.roads.get(&dr)?;
+        if now - *last <= TRAVEL_TIME_MEMORY {
+            Some(*dt)
+        } else {
+            None
+        }
+    }
+}
+
 pub struct Window {
     times: VecDeque<Time>,
     window_size: Duration,
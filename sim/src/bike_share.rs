@@ -0,0 +1,81 @@
+use geom::{Distance, Pt2D};
+use map_model::{DockingStationID, Map};
+use serde::{Deserialize, Serialize};
+
+// How far somebody is willing to walk to or from a docking station
+const MAX_WALK_TO_STATION: Distance = Distance::const_meters(800.0);
+
+// Tracks how many shared bikes or scooters are docked at each station.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BikeShareSimState {
+    // Indexed by DockingStationID
+    inventory: Vec<usize>,
+}
+
+impl BikeShareSimState {
+    pub fn new(map: &Map) -> BikeShareSimState {
+        // Every station starts half full
+        BikeShareSimState {
+            inventory: map
+                .all_docking_stations()
+                .iter()
+                .map(|ds| (ds.capacity + 1) / 2)
+                .collect(),
+        }
+    }
+
+    pub fn get_inventory(&self, ds: DockingStationID) -> usize {
+        self.inventory[ds.0]
+    }
+
+    // The closest station within walking distance that has a vehicle available right now. There's
+    // no reservation; it might be empty by the time somebody gets there.
+    pub fn find_pickup(
+        &self,
+        near: Pt2D,
+        except: Option<DockingStationID>,
+        map: &Map,
+    ) -> Option<DockingStationID> {
+        self.closest_station(near, map, |ds| {
+            Some(ds) != except && self.inventory[ds.0] > 0
+        })
+    }
+
+    // The closest station within walking distance that has a free dock right now
+    pub fn find_dropoff(&self, near: Pt2D, map: &Map) -> Option<DockingStationID> {
+        self.closest_station(near, map, |ds| {
+            self.inventory[ds.0] < map.get_ds(ds).capacity
+        })
+    }
+
+    fn closest_station<F: Fn(DockingStationID) -> bool>(
+        &self,
+        near: Pt2D,
+        map: &Map,
+        filter: F,
+    ) -> Option<DockingStationID> {
+        map.all_docking_stations()
+            .iter()
+            .filter(|ds| filter(ds.id))
+            .map(|ds| (ds.point.dist_to(near), ds.id))
+            .filter(|(dist, _)| *dist <= MAX_WALK_TO_STATION)
+            .min_by_key(|(dist, _)| *dist)
+            .map(|(_, id)| id)
+    }
+
+    // Returns how many vehicles are left, or None if the station is empty.
+    pub fn take_vehicle(&mut self, ds: DockingStationID) -> Option<usize> {
+        if self.inventory[ds.0] == 0 {
+            return None;
+        }
+        self.inventory[ds.0] -= 1;
+        Some(self.inventory[ds.0])
+    }
+
+    // Returns how many vehicles are now docked. If the station was already full, the vehicle is
+    // left next to it anyway, so the inventory may exceed capacity.
+    pub fn return_vehicle(&mut self, ds: DockingStationID) -> usize {
+        self.inventory[ds.0] += 1;
+        self.inventory[ds.0]
+    }
+}
@@ -1,10 +1,10 @@
 use crate::{
     AgentID, CarID, OffMapLocation, ParkingSpot, PedestrianID, PersonID, TripID, TripMode,
 };
-use geom::Duration;
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, CompressedMovementID, IntersectionID, LaneID, Map, Path,
-    PathRequest, Traversable,
+    BuildingID, BusRouteID, BusStopID, CompressedMovementID, DockingStationID, IntersectionID,
+    LaneID, Map, Path, PathRequest, Traversable,
 };
 use serde::{Deserialize, Serialize};
 
@@ -14,10 +14,14 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Event {
     CarReachedParkingSpot(CarID, ParkingSpot),
-    CarLeftParkingSpot(CarID, ParkingSpot),
+    // The Time is when the car parked there
+    CarLeftParkingSpot(CarID, ParkingSpot, Time),
 
-    BusArrivedAtStop(CarID, BusRouteID, BusStopID),
+    // Also when the vehicle was scheduled to arrive
+    BusArrivedAtStop(CarID, BusRouteID, BusStopID, Time),
     BusDepartedFromStop(CarID, BusRouteID, BusStopID),
+    // How many people waiting for this route couldn't fit on the full vehicle?
+    PassengersLeftBehind(CarID, BusRouteID, BusStopID, usize),
     // How long waiting at the stop?
     PassengerBoardsTransit(PersonID, CarID, BusRouteID, BusStopID, Duration),
     PassengerAlightsTransit(PersonID, CarID, BusRouteID, BusStopID),
@@ -39,6 +43,24 @@ pub enum Event {
 
     BikeStoppedAtSidewalk(CarID, LaneID),
 
+    // Also how many vehicles are docked at the station afterwards
+    SharedVehiclePickedUp(PersonID, DockingStationID, usize),
+    SharedVehicleReturned(PersonID, DockingStationID, usize),
+    // Somebody reached a station with no vehicles left
+    DockingStationEmpty(PersonID, DockingStationID),
+    // Somebody reached a station with no free docks
+    DockingStationFull(PersonID, DockingStationID),
+
+    // How far the vehicle has to drive empty to reach the pickup
+    RideHailDispatched(PersonID, CarID, Distance),
+    // How long the person waited, and how many fleet vehicles are occupied afterwards
+    RideHailPickup(PersonID, CarID, Duration, usize),
+    RideHailDropoff(PersonID, CarID, usize),
+
+    // A delivery truck stopped for some time, in the on-street spot used as a loading zone, or
+    // double-parked in the lane if it's None.
+    DeliveryStop(CarID, BuildingID, Option<ParkingSpot>, Duration),
+
     // If the agent is a transit vehicle, then include a count of how many passengers are on
     // board.
     AgentEntersTraversable(AgentID, Traversable, Option<usize>),
@@ -77,6 +99,8 @@ pub enum TripPhaseType {
     WaitingForBus(BusRouteID, BusStopID),
     // What stop did they board at?
     RidingBus(BusRouteID, BusStopID, CarID),
+    WaitingForRideHail,
+    RidingRideHail(CarID),
     Aborted,
     Finished,
     DelayedStart,
@@ -94,6 +118,8 @@ impl TripPhaseType {
                 format!("waiting for bus {}", map.get_br(r).full_name)
             }
             TripPhaseType::RidingBus(r, _, _) => format!("riding bus {}", map.get_br(r).full_name),
+            TripPhaseType::WaitingForRideHail => "waiting for a ride-hail car".to_string(),
+            TripPhaseType::RidingRideHail(_) => "riding in a ride-hail car".to_string(),
             TripPhaseType::Aborted => "trip aborted due to some bug".to_string(),
             TripPhaseType::Finished => "trip finished".to_string(),
             TripPhaseType::DelayedStart => "delayed by previous trip taking too long".to_string(),
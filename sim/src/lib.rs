@@ -1,10 +1,13 @@
 mod analytics;
+mod bike_share;
 mod cap;
 mod events;
 mod make;
 mod mechanics;
 mod pandemic;
 mod render;
+mod replay;
+mod ride_hail;
 mod router;
 mod scheduler;
 mod sim;
@@ -14,10 +17,10 @@ mod trips;
 #[macro_use]
 extern crate log;
 
-pub use self::analytics::{Analytics, TripPhase};
+pub use self::analytics::{Analytics, RoadTravelTimes, TripPhase};
+pub(crate) use self::bike_share::BikeShareSimState;
 pub(crate) use self::cap::CapSimState;
-pub(crate) use self::events::Event;
-pub use self::events::{AlertLocation, TripPhaseType};
+pub use self::events::{AlertLocation, Event, TripPhaseType};
 pub use self::make::{
     BorderSpawnOverTime, ExternalPerson, ExternalTrip, IndividTrip, OffMapLocation,
     OriginDestination, PersonSpec, Scenario, ScenarioGenerator, ScenarioModifier, SimFlags,
@@ -27,7 +30,10 @@ pub(crate) use self::mechanics::{
     DrivingSimState, IntersectionSimState, ParkingSim, ParkingSimState, WalkingSimState,
 };
 pub(crate) use self::pandemic::PandemicModel;
-pub(crate) use self::router::{ActionAtEnd, Router};
+pub use self::replay::{Divergence, ReplayTrace};
+pub use self::ride_hail::RideHailDispatcher;
+pub(crate) use self::ride_hail::RideHailSimState;
+pub(crate) use self::router::{ActionAtEnd, Router, REROUTE_COOLDOWN};
 pub(crate) use self::scheduler::{Command, Scheduler};
 pub use self::sim::{AgentProperties, AlertHandler, Sim, SimCallback, SimOptions};
 pub(crate) use self::transit::TransitSimState;
@@ -39,10 +45,11 @@ pub use crate::render::{
     PedCrowdLocation, UnzoomedAgent,
 };
 use abstutil::{deserialize_usize, serialize_usize};
-use geom::{Distance, Pt2D, Speed, Time};
+use geom::{Distance, Duration, Pt2D, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, DirectedRoadID, IntersectionID, LaneID, Map, ParkingLotID,
-    Path, PathConstraints, PathRequest, Position,
+    BuildingID, BusRouteID, BusStopID, DirectedRoadID, DockingStationID, IntersectionID, LaneID,
+    Map, OffstreetParking, ParkingArea, ParkingLotID, ParkingPolicy, Path, PathConstraints,
+    PathRequest, Position,
 };
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -52,9 +59,16 @@ pub const BIKE_LENGTH: Distance = Distance::const_meters(1.8);
 // These two must be < PARKING_SPOT_LENGTH
 pub const MIN_CAR_LENGTH: Distance = Distance::const_meters(4.5);
 pub const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
+// Box trucks making deliveries. Like cars, these must be < PARKING_SPOT_LENGTH, so they fit in one
+// on-street spot, which can serve as a loading zone.
+pub const MIN_TRUCK_LENGTH: Distance = Distance::const_meters(7.0);
+pub const MAX_TRUCK_LENGTH: Distance = Distance::const_meters(7.5);
 // Note this is more than MAX_CAR_LENGTH
 pub const BUS_LENGTH: Distance = Distance::const_meters(12.5);
 pub const LIGHT_RAIL_LENGTH: Distance = Distance::const_meters(60.0);
+// Seated and standing passengers
+pub const BUS_CAPACITY: usize = 70;
+pub const LIGHT_RAIL_CAPACITY: usize = 200;
 
 // At all speeds (including at rest), cars must be at least this far apart, measured from front of
 // one car to the back of the other.
@@ -84,6 +98,7 @@ impl fmt::Display for CarID {
             VehicleType::Bus => write!(f, "Bus #{}", self.0),
             VehicleType::Train => write!(f, "Train #{}", self.0),
             VehicleType::Bike => write!(f, "Bike #{}", self.0),
+            VehicleType::Truck => write!(f, "Truck #{}", self.0),
         }
     }
 }
@@ -122,7 +137,7 @@ impl AgentID {
     pub fn to_type(self) -> AgentType {
         match self {
             AgentID::Car(c) => match c.1 {
-                VehicleType::Car => AgentType::Car,
+                VehicleType::Car | VehicleType::Truck => AgentType::Car,
                 VehicleType::Bike => AgentType::Bike,
                 VehicleType::Bus => AgentType::Bus,
                 VehicleType::Train => AgentType::Train,
@@ -248,6 +263,7 @@ pub enum VehicleType {
     Bus,
     Train,
     Bike,
+    Truck,
 }
 
 impl fmt::Display for VehicleType {
@@ -257,6 +273,7 @@ impl fmt::Display for VehicleType {
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Train => write!(f, "train"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::Truck => write!(f, "truck"),
         }
     }
 }
@@ -264,19 +281,31 @@ impl fmt::Display for VehicleType {
 impl VehicleType {
     pub fn to_constraints(self) -> PathConstraints {
         match self {
-            VehicleType::Car => PathConstraints::Car,
+            VehicleType::Car | VehicleType::Truck => PathConstraints::Car,
             VehicleType::Bus => PathConstraints::Bus,
             VehicleType::Train => PathConstraints::Train,
             VehicleType::Bike => PathConstraints::Bike,
         }
     }
 
+    // Typical acceleration and comfortable braking, in meters per second squared
+    pub fn default_accel_decel(self) -> (f64, f64) {
+        match self {
+            VehicleType::Car => (2.5, 3.0),
+            VehicleType::Bus => (1.2, 2.0),
+            VehicleType::Train => (1.0, 1.3),
+            VehicleType::Bike => (1.0, 2.0),
+            VehicleType::Truck => (1.5, 2.5),
+        }
+    }
+
     pub(crate) fn is_transit(self) -> bool {
         match self {
             VehicleType::Car => false,
             VehicleType::Bus => true,
             VehicleType::Train => true,
             VehicleType::Bike => false,
+            VehicleType::Truck => false,
         }
     }
 }
@@ -288,6 +317,9 @@ pub struct Vehicle {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    // In meters per second squared, always positive. Only used when cars accelerate.
+    pub max_accel: f64,
+    pub max_decel: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -295,17 +327,32 @@ pub struct VehicleSpec {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    // In meters per second squared. Anything that isn't positive means to use the defaults for the
+    // vehicle type.
+    pub max_accel: f64,
+    pub max_decel: f64,
 }
 
 impl VehicleSpec {
     pub fn make(self, id: CarID, owner: Option<PersonID>) -> Vehicle {
         assert_eq!(id.1, self.vehicle_type);
+        let (default_accel, default_decel) = self.vehicle_type.default_accel_decel();
         Vehicle {
             id,
             owner,
             vehicle_type: self.vehicle_type,
             length: self.length,
             max_speed: self.max_speed,
+            max_accel: if self.max_accel > 0.0 && self.max_accel.is_finite() {
+                self.max_accel
+            } else {
+                default_accel
+            },
+            max_decel: if self.max_decel > 0.0 && self.max_decel.is_finite() {
+                self.max_decel
+            } else {
+                default_decel
+            },
         }
     }
 }
@@ -319,6 +366,22 @@ pub enum ParkingSpot {
     Lot(ParkingLotID, usize),
 }
 
+impl ParkingSpot {
+    // Private driveways and garages are always free for their owners.
+    pub fn policy(self, map: &Map) -> ParkingPolicy {
+        match self {
+            ParkingSpot::Onstreet(l, _) => map.get_parking_policy(ParkingArea::Lane(l)),
+            ParkingSpot::Offstreet(b, _) => match map.get_b(b).parking {
+                OffstreetParking::PublicGarage(_, _) => {
+                    map.get_parking_policy(ParkingArea::Garage(b))
+                }
+                OffstreetParking::Private(_, _) => ParkingPolicy::free(),
+            },
+            ParkingSpot::Lot(pl, _) => map.get_parking_policy(ParkingArea::Lot(pl)),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ParkedCar {
     pub vehicle: Vehicle,
@@ -365,13 +428,20 @@ impl DrivingGoal {
         }
     }
 
-    pub(crate) fn make_router(&self, owner: CarID, path: Path, map: &Map) -> Router {
+    // The stay is how long the driver will leave a car parked at the end, if that's known.
+    pub(crate) fn make_router(
+        &self,
+        owner: CarID,
+        path: Path,
+        stay: Option<Duration>,
+        map: &Map,
+    ) -> Router {
         match self {
             DrivingGoal::ParkNear(b) => {
                 if owner.1 == VehicleType::Bike {
                     Router::bike_then_stop(owner, path, SidewalkSpot::bike_rack(*b, map).unwrap())
                 } else {
-                    Router::park_near(owner, path, *b)
+                    Router::park_near(owner, path, *b, stay)
                 }
             }
             DrivingGoal::Border(i, last_lane, _) => {
@@ -406,6 +476,10 @@ pub enum SidewalkPOI {
     Border(IntersectionID, Option<OffMapLocation>),
     // The bikeable position
     BikeRack(Position),
+    // Pick up or return a shared vehicle
+    DockingStation(DockingStationID),
+    // Get picked up or dropped off by a ride-hail vehicle at this driving position
+    RideHail(Position),
     SuddenlyAppear,
 }
 
@@ -447,6 +521,29 @@ impl SidewalkSpot {
         })
     }
 
+    pub fn docking_station(ds: DockingStationID, map: &Map) -> SidewalkSpot {
+        SidewalkSpot {
+            connection: SidewalkPOI::DockingStation(ds),
+            sidewalk_pos: map.get_ds(ds).sidewalk_pos,
+        }
+    }
+
+    // The curb on the closest driving lane. Fails if the road can't be driven on.
+    pub fn ride_hail(sidewalk_pos: Position, map: &Map) -> Option<SidewalkSpot> {
+        let lane = map.get_parent(sidewalk_pos.lane()).find_closest_lane(
+            sidewalk_pos.lane(),
+            |l| PathConstraints::Car.can_use(l, map),
+            map,
+        )?;
+        let driving_pos = sidewalk_pos
+            .equiv_pos(lane, map)
+            .buffer_dist(Distance::meters(7.0), map)?;
+        Some(SidewalkSpot {
+            connection: SidewalkPOI::RideHail(driving_pos),
+            sidewalk_pos,
+        })
+    }
+
     pub fn bus_stop(stop: BusStopID, map: &Map) -> SidewalkSpot {
         SidewalkSpot {
             sidewalk_pos: map.get_bs(stop).sidewalk_pos,
@@ -602,7 +699,8 @@ pub struct CreateCar {
     pub maybe_parked_car: Option<ParkedCar>,
     // None for buses
     pub trip_and_person: Option<(TripID, PersonID)>,
-    pub maybe_route: Option<BusRouteID>,
+    // For buses, the route and when the vehicle was scheduled to start it
+    pub maybe_route: Option<(BusRouteID, Time)>,
 }
 
 impl CreateCar {
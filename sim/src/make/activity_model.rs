@@ -4,7 +4,7 @@ use crate::{
 };
 use abstutil::{prettyprint_usize, Parallelism, Timer};
 use geom::{Distance, Duration, Time};
-use map_model::{BuildingID, BuildingType, Map, PathConstraints, PathRequest};
+use map_model::{BuildingID, BuildingType, Map, PathConstraints, PathRequest, RoutingProfile};
 use rand::seq::SliceRandom;
 use rand::Rng;
 use rand_xorshift::XorShiftRng;
@@ -217,6 +217,7 @@ fn create_prole(
                     start: map.get_b(*home_bldg).sidewalk_pos,
                     end: map.get_b(*work_bldg).sidewalk_pos,
                     constraints: PathConstraints::Pedestrian,
+                    profile: RoutingProfile::Fastest,
                 })
                 .map(|p| p.total_length())
             {
@@ -262,9 +263,9 @@ fn create_prole(
         );
     }
 
-    let goto_work = SpawnTrip::new(home.clone(), work.clone(), mode, map)
+    let goto_work = SpawnTrip::new(home.clone(), work.clone(), mode, depart_am, map)
         .ok_or("unable to spawn 'goto work' trip")?;
-    let return_home = SpawnTrip::new(work.clone(), home.clone(), mode, map)
+    let return_home = SpawnTrip::new(work.clone(), home.clone(), mode, depart_pm, map)
         .ok_or("unable to spawn 'return home' trip")?;
 
     Ok(PersonSpec {
@@ -1,6 +1,6 @@
 use crate::{IndividTrip, PersonID, PersonSpec, SpawnTrip, TripEndpoint, TripMode, TripPurpose};
 use geom::{Distance, FindClosest, LonLat, Pt2D, Time};
-use map_model::Map;
+use map_model::{Map, RoutingProfile};
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -14,6 +14,8 @@ pub struct ExternalTrip {
     pub departure: Time,
     pub position: LonLat,
     pub mode: TripMode,
+    // Fastest by default
+    pub routing_profile: Option<RoutingProfile>,
 }
 
 impl ExternalPerson {
@@ -46,10 +48,15 @@ impl ExternalPerson {
             let mut from = lookup_pt(person.origin)?;
             for trip in person.trips {
                 let to = lookup_pt(trip.position)?;
-                if let Some(t) = SpawnTrip::new(from.clone(), to.clone(), trip.mode, &map) {
+                if let Some(t) =
+                    SpawnTrip::new(from.clone(), to.clone(), trip.mode, trip.departure, &map)
+                {
                     // TODO Add space in the API to specify purpose, but probably make it optional.
-                    spec.trips
-                        .push(IndividTrip::new(trip.departure, TripPurpose::Shopping, t));
+                    let mut individ = IndividTrip::new(trip.departure, TripPurpose::Shopping, t);
+                    if let Some(profile) = trip.routing_profile {
+                        individ.routing_profile = profile;
+                    }
+                    spec.trips.push(individ);
                     from = to;
                 } else {
                     return Err(format!(
@@ -3,7 +3,7 @@ use crate::{
 };
 use abstutil::Timer;
 use geom::{Duration, Time};
-use map_model::{BuildingID, DirectedRoadID, Map, PathConstraints};
+use map_model::{BuildingID, BuildingType, DirectedRoadID, Map, PathConstraints};
 use rand::seq::SliceRandom;
 use rand::Rng;
 use rand_xorshift::XorShiftRng;
@@ -40,6 +40,8 @@ pub struct BorderSpawnOverTime {
     pub num_peds: usize,
     pub num_cars: usize,
     pub num_bikes: usize,
+    // Delivery trucks making a tour of commercial buildings, then leaving the way they came
+    pub num_trucks: usize,
     pub percent_use_transit: f64,
     // TODO use https://docs.rs/rand/0.5.5/rand/distributions/struct.Normal.html
     pub start_time: Time,
@@ -84,6 +86,7 @@ impl ScenarioGenerator {
                 map,
                 timer,
             );
+            s.spawn_deliveries(rng, &mut scenario, map, timer);
         }
 
         timer.stop(format!("Generating scenario {}", self.scenario_name));
@@ -112,6 +115,7 @@ impl ScenarioGenerator {
                     num_peds: 10,
                     num_cars: 10,
                     num_bikes: 10,
+                    num_trucks: 2,
                     start_time: Time::START_OF_DAY,
                     stop_time: Time::START_OF_DAY + Duration::seconds(5.0),
                     start_from_border: i.some_outgoing_road(map).unwrap(),
@@ -222,8 +226,8 @@ impl SpawnOverTime {
             if rng.gen_bool(self.percent_use_transit) {
                 // TODO This throws away some work. It also sequentially does expensive
                 // work right here.
-                if let Some((stop1, maybe_stop2, route)) =
-                    map.should_use_transit(start_spot.sidewalk_pos, goal.sidewalk_pos)
+                if let Some(rides) =
+                    map.should_use_transit(start_spot.sidewalk_pos, goal.sidewalk_pos, depart)
                 {
                     scenario.people.push(PersonSpec {
                         id,
@@ -231,7 +235,7 @@ impl SpawnOverTime {
                         trips: vec![IndividTrip::new(
                             depart,
                             TripPurpose::Shopping,
-                            SpawnTrip::UsingTransit(start_spot, goal, route, stop1, maybe_stop2),
+                            SpawnTrip::UsingTransit(start_spot, goal, rides),
                         )],
                     });
                     return;
@@ -285,8 +289,8 @@ impl BorderSpawnOverTime {
                 if rng.gen_bool(self.percent_use_transit) {
                     // TODO This throws away some work. It also sequentially does expensive
                     // work right here.
-                    if let Some((stop1, maybe_stop2, route)) =
-                        map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
+                    if let Some(rides) =
+                        map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos, depart)
                     {
                         scenario.people.push(PersonSpec {
                             id,
@@ -294,13 +298,7 @@ impl BorderSpawnOverTime {
                             trips: vec![IndividTrip::new(
                                 depart,
                                 TripPurpose::Shopping,
-                                SpawnTrip::UsingTransit(
-                                    start.clone(),
-                                    goal,
-                                    route,
-                                    stop1,
-                                    maybe_stop2,
-                                ),
+                                SpawnTrip::UsingTransit(start.clone(), goal, rides),
                             )],
                         });
                         continue;
@@ -350,6 +348,73 @@ impl BorderSpawnOverTime {
             }
         }
     }
+
+    fn spawn_deliveries(
+        &self,
+        rng: &mut XorShiftRng,
+        scenario: &mut Scenario,
+        map: &Map,
+        timer: &mut Timer,
+    ) {
+        if self.num_trucks == 0 {
+            return;
+        }
+
+        let commercial: Vec<BuildingID> = map
+            .all_buildings()
+            .iter()
+            .filter(|b| match b.bldg_type {
+                BuildingType::Commercial(_) | BuildingType::ResidentialCommercial(_, _) => true,
+                BuildingType::Residential(_) | BuildingType::Empty => false,
+            })
+            .map(|b| b.id)
+            .collect();
+        if commercial.is_empty() {
+            timer.warn("No commercial buildings to make deliveries to".to_string());
+            return;
+        }
+        let back = DirectedRoadID {
+            id: self.start_from_border.id,
+            dir: self.start_from_border.dir.opposite(),
+        };
+        let goal =
+            if let Some(goal) = DrivingGoal::end_at_border(back, PathConstraints::Car, None, map) {
+                goal
+            } else if let Some(goal) =
+                self.goal
+                    .pick_driving_goal(PathConstraints::Car, map, rng, timer)
+            {
+                goal
+            } else {
+                return;
+            };
+
+        for _ in 0..self.num_trucks {
+            let depart = rand_time(rng, self.start_time, self.stop_time);
+            let num_stops = rng.gen_range(3, 9);
+            let stops = commercial
+                .choose_multiple(rng, num_stops)
+                .cloned()
+                .collect();
+            let dwell = Duration::minutes(rng.gen_range(2, 11));
+            let id = PersonID(scenario.people.len());
+            scenario.people.push(PersonSpec {
+                id,
+                orig_id: None,
+                trips: vec![IndividTrip::new(
+                    depart,
+                    TripPurpose::Work,
+                    SpawnTrip::DeliveryTour {
+                        dr: self.start_from_border,
+                        stops,
+                        dwell,
+                        goal: goal.clone(),
+                        origin: None,
+                    },
+                )],
+            });
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -1,4 +1,4 @@
-use crate::{AlertHandler, Scenario, ScenarioModifier, Sim, SimOptions};
+use crate::{AlertHandler, RideHailDispatcher, Scenario, ScenarioModifier, Sim, SimOptions};
 use abstutil::CmdArgs;
 use map_model::{Map, MapEdits};
 use rand::SeedableRng;
@@ -55,6 +55,22 @@ impl SimFlags {
                 pathfinding_upfront: args.enabled("--pathfinding_upfront"),
                 live_map_edits: args.enabled("--live_map_edits"),
                 infinite_parking: args.enabled("--infinite_parking"),
+                car_acceleration: args.enabled("--car_acceleration"),
+                mid_block_lanechanging: args.enabled("--mid_block_lanechanging"),
+                congestion_rerouting: args.enabled("--congestion_rerouting"),
+                pedestrian_congestion: args.enabled("--pedestrian_congestion"),
+                ride_hail_fleet_size: args.optional_parse("--ride_hail_fleet", |s| s.parse()),
+                ride_hail_dispatcher: args
+                    .optional("--ride_hail_dispatcher")
+                    .map(|x| match x.as_ref() {
+                        "nearest" => RideHailDispatcher::NearestIdle,
+                        "longest_idle" => RideHailDispatcher::LongestIdle,
+                        _ => panic!(
+                            "Bad --ride_hail_dispatcher={}. Must be nearest|longest_idle",
+                            x
+                        ),
+                    })
+                    .unwrap_or(RideHailDispatcher::NearestIdle),
             },
         }
     }
@@ -79,6 +95,16 @@ impl SimFlags {
 
     // Convenience method to setup everything.
     pub fn load(&self, timer: &mut abstutil::Timer) -> (Map, Sim, XorShiftRng) {
+        self.load_with_map(|path, timer| Map::new(path, timer), timer)
+    }
+
+    // Like load, but the caller decides how to produce the map from its path. This lets callers
+    // that load many simulations on the same map reuse it.
+    pub fn load_with_map<F: FnMut(String, &mut abstutil::Timer) -> Map>(
+        &self,
+        mut get_map: F,
+        timer: &mut abstutil::Timer,
+    ) -> (Map, Sim, XorShiftRng) {
         let mut rng = self.make_rng();
 
         let mut opts = self.opts.clone();
@@ -88,7 +114,7 @@ impl SimFlags {
 
             let mut sim: Sim = abstutil::read_binary(self.load.clone(), timer);
 
-            let mut map = Map::new(abstutil::path_map(&sim.map_name), timer);
+            let mut map = get_map(abstutil::path_map(&sim.map_name), timer);
             match MapEdits::load(
                 &map,
                 abstutil::path_edits(map.get_name(), &sim.edits_name),
@@ -122,7 +148,7 @@ impl SimFlags {
 
             let mut scenario: Scenario = abstutil::read_binary(self.load.clone(), timer);
 
-            let map = Map::new(abstutil::path_map(&scenario.map_name), timer);
+            let map = get_map(abstutil::path_map(&scenario.map_name), timer);
 
             let mut modifier_rng = self.make_rng();
             for m in &self.modifiers {
@@ -142,7 +168,7 @@ impl SimFlags {
         {
             timer.note(format!("Loading map {}", self.load));
 
-            let map = Map::new(self.load.clone(), timer);
+            let map = get_map(self.load.clone(), timer);
 
             timer.start("create sim");
             let sim = Sim::new(&map, opts, timer);
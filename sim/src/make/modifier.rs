@@ -46,9 +46,13 @@ impl ScenarioModifier {
                         if !from_modes.contains(&trip.trip.mode()) {
                             continue;
                         }
-                        if let Some(new) =
-                            SpawnTrip::new(trip.trip.start(map), trip.trip.end(map), *to_mode, map)
-                        {
+                        if let Some(new) = SpawnTrip::new(
+                            trip.trip.start(map),
+                            trip.trip.end(map),
+                            *to_mode,
+                            trip.depart,
+                            map,
+                        ) {
                             trip.modified = true;
                             trip.trip = new;
                         }
@@ -114,6 +118,7 @@ fn repeat_days(mut s: Scenario, days: usize) -> Scenario {
             for trip in &person.trips {
                 let mut new =
                     IndividTrip::new(trip.depart + offset, trip.purpose, trip.trip.clone());
+                new.routing_profile = trip.routing_profile;
                 new.modified = true;
                 trips.push(new);
             }
@@ -1,13 +1,13 @@
 use crate::{
     CarID, DrivingGoal, OrigPersonID, ParkingSpot, PersonID, SidewalkPOI, SidewalkSpot, Sim,
     TripEndpoint, TripMode, TripSpec, Vehicle, VehicleSpec, VehicleType, BIKE_LENGTH,
-    MAX_CAR_LENGTH, MIN_CAR_LENGTH, SPAWN_DIST,
+    MAX_CAR_LENGTH, MAX_TRUCK_LENGTH, MIN_CAR_LENGTH, MIN_TRUCK_LENGTH, SPAWN_DIST,
 };
 use abstutil::{prettyprint_usize, Counter, Timer};
 use geom::{Distance, Duration, LonLat, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, DirectedRoadID, Map, OffstreetParking, PathConstraints,
-    Position, RoadID,
+    BuildingID, DirectedRoadID, LaneID, Map, OffstreetParking, PathConstraints, Position, RoadID,
+    RoutingProfile, TransitRide,
 };
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
@@ -25,6 +25,9 @@ pub struct Scenario {
     pub people: Vec<PersonSpec>,
     // None means seed all buses. Otherwise the route name must be present here.
     pub only_seed_buses: Option<BTreeSet<String>>,
+    // What percent of people know about current traffic while driving, and might reroute around
+    // it. Only matters with SimOptions::congestion_rerouting.
+    pub pct_informed_drivers: usize,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -40,6 +43,10 @@ pub struct IndividTrip {
     pub depart: Time,
     pub trip: SpawnTrip,
     pub purpose: TripPurpose,
+    pub routing_profile: RoutingProfile,
+    // Every lane of the driving route picked by traffic assignment. People follow this instead of
+    // pathfinding, as long as the route still fits where the car actually starts and ends.
+    pub route: Option<Vec<LaneID>>,
     pub cancelled: bool,
     // Did a ScenarioModifier affect this?
     pub modified: bool,
@@ -51,6 +58,8 @@ impl IndividTrip {
             depart,
             trip,
             purpose,
+            routing_profile: RoutingProfile::Fastest,
+            route: None,
             cancelled: false,
             modified: false,
         }
@@ -75,13 +84,20 @@ pub enum SpawnTrip {
     UsingParkedCar(BuildingID, DrivingGoal),
     UsingBike(BuildingID, DrivingGoal),
     JustWalking(SidewalkSpot, SidewalkSpot),
-    UsingTransit(
-        SidewalkSpot,
-        SidewalkSpot,
-        BusRouteID,
-        BusStopID,
-        Option<BusStopID>,
-    ),
+    // Every ride in order, possibly with transfers
+    UsingTransit(SidewalkSpot, SidewalkSpot, Vec<TransitRide>),
+    UsingBikeShare(SidewalkSpot, SidewalkSpot),
+    // The start and goal must be next to a road that cars can use
+    UsingRideHail(SidewalkSpot, SidewalkSpot),
+    // A delivery truck enters from a border, stops at each building for the dwell time, then
+    // heads to the goal
+    DeliveryTour {
+        dr: DirectedRoadID,
+        stops: Vec<BuildingID>,
+        dwell: Duration,
+        goal: DrivingGoal,
+        origin: Option<OffMapLocation>,
+    },
     // Completely off-map trip. Don't really simulate much of it.
     Remote {
         from: OffMapLocation,
@@ -158,6 +174,8 @@ impl Scenario {
 
         timer.start_iter("trips for People", self.people.len());
         let mut spawner = sim.make_spawner();
+        // Forked once, so changing the share doesn't affect anything else drawn from the main RNG
+        let mut informed_rng = abstutil::fork_rng(rng);
         let mut parked_cars: Vec<(Vehicle, BuildingID)> = Vec::new();
         for p in &self.people {
             timer.next();
@@ -173,6 +191,7 @@ impl Scenario {
                 p.orig_id,
                 Scenario::rand_ped_speed(rng),
                 vehicle_specs,
+                informed_rng.gen_range(0, 100) < self.pct_informed_drivers,
             );
             let person = sim.get_person(p.id);
             for (idx, b) in cars_initially_parked_at {
@@ -193,6 +212,8 @@ impl Scenario {
                     spec,
                     t.trip.start(map),
                     t.purpose,
+                    t.routing_profile,
+                    t.route.clone(),
                     t.cancelled,
                     t.modified,
                     map,
@@ -204,6 +225,17 @@ impl Scenario {
         parked_cars.shuffle(rng);
         seed_parked_cars(parked_cars, sim, map, rng, timer);
 
+        // Size the ride-hail fleet for the demand, after everybody else has their vehicles
+        let ride_hail_trips = self
+            .people
+            .iter()
+            .flat_map(|p| p.trips.iter())
+            .filter(|t| !t.cancelled && t.trip.mode() == TripMode::RideHail)
+            .count();
+        if ride_hail_trips > 0 {
+            sim.seed_ride_hail_fleet(ride_hail_trips, map);
+        }
+
         sim.flush_spawner(spawner, map, timer);
         timer.stop(format!("Instantiating {}", self.scenario_name));
     }
@@ -221,15 +253,19 @@ impl Scenario {
             map_name: map.get_name().to_string(),
             people: Vec::new(),
             only_seed_buses: Some(BTreeSet::new()),
+            pct_informed_drivers: 100,
         }
     }
 
     pub fn rand_car(rng: &mut XorShiftRng) -> VehicleSpec {
         let length = Scenario::rand_dist(rng, MIN_CAR_LENGTH, MAX_CAR_LENGTH);
+        let (max_accel, max_decel) = VehicleType::Car.default_accel_decel();
         VehicleSpec {
             vehicle_type: VehicleType::Car,
             length,
             max_speed: None,
+            max_accel,
+            max_decel,
         }
     }
 
@@ -239,12 +275,28 @@ impl Scenario {
             Speed::miles_per_hour(8.0),
             Scenario::max_bike_speed(),
         ));
+        let (max_accel, max_decel) = VehicleType::Bike.default_accel_decel();
         VehicleSpec {
             vehicle_type: VehicleType::Bike,
             length: BIKE_LENGTH,
             max_speed,
+            max_accel,
+            max_decel,
         }
     }
+
+    pub fn rand_truck(rng: &mut XorShiftRng) -> VehicleSpec {
+        let length = Scenario::rand_dist(rng, MIN_TRUCK_LENGTH, MAX_TRUCK_LENGTH);
+        let (max_accel, max_decel) = VehicleType::Truck.default_accel_decel();
+        VehicleSpec {
+            vehicle_type: VehicleType::Truck,
+            length,
+            max_speed: Some(Speed::miles_per_hour(30.0)),
+            max_accel,
+            max_decel,
+        }
+    }
+
     pub fn max_bike_speed() -> Speed {
         Speed::miles_per_hour(10.0)
     }
@@ -479,13 +531,39 @@ impl SpawnTrip {
                 goal,
             },
             SpawnTrip::JustWalking(start, goal) => TripSpec::JustWalking { start, goal },
-            SpawnTrip::UsingTransit(start, goal, route, stop1, maybe_stop2) => {
-                TripSpec::UsingTransit {
-                    start,
-                    goal,
-                    route,
-                    stop1,
-                    maybe_stop2,
+            SpawnTrip::UsingTransit(start, goal, rides) => {
+                TripSpec::UsingTransit { start, goal, rides }
+            }
+            SpawnTrip::UsingBikeShare(start, goal) => TripSpec::UsingBikeShare {
+                bike: use_vehicle.unwrap(),
+                start,
+                goal,
+            },
+            SpawnTrip::UsingRideHail(start, goal) => TripSpec::UsingRideHail { start, goal },
+            SpawnTrip::DeliveryTour {
+                dr,
+                stops,
+                dwell,
+                goal,
+                origin,
+            } => {
+                if let Some(l) = dr.lanes(PathConstraints::Car, map).choose(rng) {
+                    TripSpec::DeliveryTour {
+                        start_pos: Position::new(*l, SPAWN_DIST),
+                        truck: use_vehicle.unwrap(),
+                        stops,
+                        dwell,
+                        goal,
+                        origin,
+                    }
+                } else {
+                    TripSpec::NoRoomToSpawn {
+                        i: dr.src_i(map),
+                        goal,
+                        use_vehicle: use_vehicle.unwrap(),
+                        origin,
+                        error: format!("{} has no lanes to spawn a truck", dr.id),
+                    }
                 }
             }
             SpawnTrip::Remote {
@@ -522,7 +600,10 @@ impl SpawnTrip {
             SpawnTrip::UsingParkedCar(_, _) => TripMode::Drive,
             SpawnTrip::UsingBike(_, _) => TripMode::Bike,
             SpawnTrip::JustWalking(_, _) => TripMode::Walk,
-            SpawnTrip::UsingTransit(_, _, _, _, _) => TripMode::Transit,
+            SpawnTrip::UsingTransit(_, _, _) => TripMode::Transit,
+            SpawnTrip::UsingBikeShare(_, _) => TripMode::BikeShare,
+            SpawnTrip::UsingRideHail(_, _) => TripMode::RideHail,
+            SpawnTrip::DeliveryTour { .. } => TripMode::Drive,
             // TODO Uh...
             SpawnTrip::Remote { .. } => TripMode::Drive,
         }
@@ -533,21 +614,23 @@ impl SpawnTrip {
             SpawnTrip::VehicleAppearing { ref start, .. } => {
                 TripEndpoint::Border(map.get_l(start.lane()).src_i, None)
             }
-            SpawnTrip::FromBorder { dr, ref origin, .. } => {
+            SpawnTrip::FromBorder { dr, ref origin, .. }
+            | SpawnTrip::DeliveryTour { dr, ref origin, .. } => {
                 TripEndpoint::Border(dr.src_i(map), origin.clone())
             }
             SpawnTrip::UsingParkedCar(b, _) => TripEndpoint::Bldg(*b),
             SpawnTrip::UsingBike(b, _) => TripEndpoint::Bldg(*b),
-            SpawnTrip::JustWalking(ref spot, _) | SpawnTrip::UsingTransit(ref spot, _, _, _, _) => {
-                match spot.connection {
-                    SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
-                    SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
-                    SidewalkPOI::SuddenlyAppear => {
-                        TripEndpoint::Border(map.get_l(spot.sidewalk_pos.lane()).src_i, None)
-                    }
-                    _ => unreachable!(),
+            SpawnTrip::JustWalking(ref spot, _)
+            | SpawnTrip::UsingTransit(ref spot, _, _)
+            | SpawnTrip::UsingBikeShare(ref spot, _)
+            | SpawnTrip::UsingRideHail(ref spot, _) => match spot.connection {
+                SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
+                SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
+                SidewalkPOI::SuddenlyAppear => {
+                    TripEndpoint::Border(map.get_l(spot.sidewalk_pos.lane()).src_i, None)
                 }
-            }
+                _ => unreachable!(),
+            },
             // Pick an arbitrary border
             SpawnTrip::Remote { ref from, .. } => {
                 TripEndpoint::Border(map.all_outgoing_borders()[0].id, Some(from.clone()))
@@ -559,18 +642,20 @@ impl SpawnTrip {
         match self {
             SpawnTrip::VehicleAppearing { ref goal, .. }
             | SpawnTrip::FromBorder { ref goal, .. }
+            | SpawnTrip::DeliveryTour { ref goal, .. }
             | SpawnTrip::UsingParkedCar(_, ref goal)
             | SpawnTrip::UsingBike(_, ref goal) => match goal {
                 DrivingGoal::ParkNear(b) => TripEndpoint::Bldg(*b),
                 DrivingGoal::Border(i, _, ref loc) => TripEndpoint::Border(*i, loc.clone()),
             },
-            SpawnTrip::JustWalking(_, ref spot) | SpawnTrip::UsingTransit(_, ref spot, _, _, _) => {
-                match spot.connection {
-                    SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
-                    SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
-                    _ => unreachable!(),
-                }
-            }
+            SpawnTrip::JustWalking(_, ref spot)
+            | SpawnTrip::UsingTransit(_, ref spot, _)
+            | SpawnTrip::UsingBikeShare(_, ref spot)
+            | SpawnTrip::UsingRideHail(_, ref spot) => match spot.connection {
+                SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
+                SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
+                _ => unreachable!(),
+            },
             // Pick an arbitrary border
             SpawnTrip::Remote { ref to, .. } => {
                 TripEndpoint::Border(map.all_incoming_borders()[0].id, Some(to.clone()))
@@ -578,10 +663,13 @@ impl SpawnTrip {
         }
     }
 
+    // The departure time only matters for transit, since how long people wait depends on when
+    // they start.
     pub fn new(
         from: TripEndpoint,
         to: TripEndpoint,
         mode: TripMode,
+        depart: Time,
         map: &Map,
     ) -> Option<SpawnTrip> {
         Some(match mode {
@@ -613,16 +701,31 @@ impl SpawnTrip {
             TripMode::Transit => {
                 let start = from.start_sidewalk_spot(map)?;
                 let goal = to.end_sidewalk_spot(map)?;
-                if let Some((stop1, maybe_stop2, route)) =
-                    map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
+                if let Some(rides) =
+                    map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos, depart)
                 {
-                    SpawnTrip::UsingTransit(start, goal, route, stop1, maybe_stop2)
+                    SpawnTrip::UsingTransit(start, goal, rides)
                 } else {
                     //timer.warn(format!("{:?} not actually using transit, because pathfinding
                     // didn't find any useful route", trip));
                     SpawnTrip::JustWalking(start, goal)
                 }
             }
+            TripMode::BikeShare => SpawnTrip::UsingBikeShare(
+                from.start_sidewalk_spot(map)?,
+                to.end_sidewalk_spot(map)?,
+            ),
+            TripMode::RideHail => {
+                let start = from.start_sidewalk_spot(map)?;
+                let goal = to.end_sidewalk_spot(map)?;
+                if SidewalkSpot::ride_hail(start.sidewalk_pos, map).is_some()
+                    && SidewalkSpot::ride_hail(goal.sidewalk_pos, map).is_some()
+                {
+                    SpawnTrip::UsingRideHail(start, goal)
+                } else {
+                    SpawnTrip::JustWalking(start, goal)
+                }
+            }
         })
     }
 }
@@ -683,6 +786,7 @@ impl PersonSpec {
         let mut vehicle_foreach_trip = Vec::new();
 
         let mut bike_idx = None;
+        let mut truck_idx = None;
         // For each indexed car, is it parked somewhere, or off-map?
         let mut car_locations: Vec<(usize, Option<BuildingID>)> = Vec::new();
 
@@ -759,14 +863,26 @@ impl PersonSpec {
 
                     Some(idx)
                 }
-                SpawnTrip::UsingBike(_, _) => {
+                // The shared vehicle is simulated as the person's own bike; they can't use both
+                // at once anyway.
+                SpawnTrip::UsingBike(_, _) | SpawnTrip::UsingBikeShare(_, _) => {
                     if bike_idx.is_none() {
                         bike_idx = Some(vehicle_specs.len());
                         vehicle_specs.push(Scenario::rand_bike(rng));
                     }
                     bike_idx
                 }
-                SpawnTrip::JustWalking(_, _) | SpawnTrip::UsingTransit(_, _, _, _, _) => None,
+                // Delivery drivers just use one truck
+                SpawnTrip::DeliveryTour { .. } => {
+                    if truck_idx.is_none() {
+                        truck_idx = Some(vehicle_specs.len());
+                        vehicle_specs.push(Scenario::rand_truck(rng));
+                    }
+                    truck_idx
+                }
+                SpawnTrip::JustWalking(_, _)
+                | SpawnTrip::UsingTransit(_, _, _)
+                | SpawnTrip::UsingRideHail(_, _) => None,
                 SpawnTrip::Remote { .. } => None,
             };
             vehicle_foreach_trip.push(use_for_trip);
@@ -5,7 +5,8 @@ use crate::{
 use abstutil::{Parallelism, Timer};
 use geom::{Duration, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
+    BuildingID, IntersectionID, LaneID, Map, PathConstraints, PathRequest, Position,
+    RoutingProfile, TransitRide,
 };
 use serde::{Deserialize, Serialize};
 
@@ -48,9 +49,29 @@ pub enum TripSpec {
     UsingTransit {
         start: SidewalkSpot,
         goal: SidewalkSpot,
-        route: BusRouteID,
-        stop1: BusStopID,
-        maybe_stop2: Option<BusStopID>,
+        rides: Vec<TransitRide>,
+    },
+    // Walk to a docking station, ride a shared vehicle, and return it near the goal. The stations
+    // are picked when the trip starts, based on what's available then.
+    UsingBikeShare {
+        bike: CarID,
+        start: SidewalkSpot,
+        goal: SidewalkSpot,
+    },
+    // Walk to the curb, get driven by a ride-hail vehicle, and walk from the curb near the goal.
+    UsingRideHail {
+        start: SidewalkSpot,
+        goal: SidewalkSpot,
+    },
+    // A delivery truck appears at a border, stops at each building in order, then heads to the
+    // goal. Each leg is routed when the previous stop is done.
+    DeliveryTour {
+        start_pos: Position,
+        truck: CarID,
+        stops: Vec<BuildingID>,
+        dwell: Duration,
+        goal: DrivingGoal,
+        origin: Option<OffMapLocation>,
     },
     // Completely off-map trip. Don't really simulate much of it.
     Remote {
@@ -69,6 +90,8 @@ pub struct TripSpawner {
         TripSpec,
         TripEndpoint,
         TripPurpose,
+        RoutingProfile,
+        Option<Vec<LaneID>>,
         bool,
         bool,
     )>,
@@ -86,6 +109,8 @@ impl TripSpawner {
         mut spec: TripSpec,
         trip_start: TripEndpoint,
         purpose: TripPurpose,
+        routing_profile: RoutingProfile,
+        route: Option<Vec<LaneID>>,
         cancelled: bool,
         modified: bool,
         map: &Map,
@@ -185,11 +210,22 @@ impl TripSpawner {
                 }
             }
             TripSpec::UsingTransit { .. } => {}
+            TripSpec::UsingBikeShare { .. } => {}
+            TripSpec::UsingRideHail { .. } => {}
+            TripSpec::DeliveryTour { .. } => {}
             TripSpec::Remote { .. } => {}
         };
 
         self.trips.push((
-            person.id, start_time, spec, trip_start, purpose, cancelled, modified,
+            person.id,
+            start_time,
+            spec,
+            trip_start,
+            purpose,
+            routing_profile,
+            route,
+            cancelled,
+            modified,
         ));
     }
 
@@ -210,7 +246,7 @@ impl TripSpawner {
             Parallelism::Fastest,
             std::mem::replace(&mut self.trips, Vec::new()),
             |tuple| {
-                let req = tuple.2.get_pathfinding_request(map);
+                let req = tuple.2.get_pathfinding_request(tuple.5, map);
                 (
                     tuple,
                     req.clone(),
@@ -228,7 +264,7 @@ impl TripSpawner {
 
         timer.start_iter("spawn trips", paths.len());
         for (
-            (p, start_time, spec, trip_start, purpose, cancelled, modified),
+            (p, start_time, spec, trip_start, purpose, routing_profile, route, cancelled, modified),
             maybe_req,
             maybe_path,
         ) in paths
@@ -258,6 +294,7 @@ impl TripSpawner {
                             TripMode::Drive
                         },
                         purpose,
+                        routing_profile,
                         modified,
                         legs,
                         map,
@@ -280,6 +317,7 @@ impl TripSpawner {
                             TripMode::Drive
                         },
                         purpose,
+                        routing_profile,
                         modified,
                         legs,
                         map,
@@ -302,6 +340,7 @@ impl TripSpawner {
                         trip_start,
                         TripMode::Drive,
                         purpose,
+                        routing_profile,
                         modified,
                         legs,
                         map,
@@ -313,6 +352,7 @@ impl TripSpawner {
                     trip_start,
                     TripMode::Walk,
                     purpose,
+                    routing_profile,
                     modified,
                     vec![TripLeg::Walk(goal.clone())],
                     map,
@@ -335,37 +375,83 @@ impl TripSpawner {
                         trip_start,
                         TripMode::Bike,
                         purpose,
+                        routing_profile,
                         modified,
                         legs,
                         map,
                     )
                 }
-                TripSpec::UsingTransit {
-                    route,
-                    stop1,
-                    maybe_stop2,
+                TripSpec::UsingTransit { rides, goal, .. } => {
+                    let mut legs = Vec::new();
+                    for ride in &rides {
+                        // Transferring at the same stop is just a walk of no distance
+                        legs.push(TripLeg::Walk(SidewalkSpot::bus_stop(ride.board, map)));
+                        legs.push(TripLeg::RideBus(ride.route, ride.alight));
+                    }
+                    if rides.last().unwrap().alight.is_some() {
+                        legs.push(TripLeg::Walk(goal));
+                    }
+                    trips.new_trip(
+                        person.id,
+                        start_time,
+                        trip_start,
+                        TripMode::Transit,
+                        purpose,
+                        routing_profile,
+                        modified,
+                        legs,
+                        map,
+                    )
+                }
+                // Legs to and from the stations get filled in when the trip starts
+                TripSpec::UsingBikeShare { goal, .. } => trips.new_trip(
+                    person.id,
+                    start_time,
+                    trip_start,
+                    TripMode::BikeShare,
+                    purpose,
+                    routing_profile,
+                    modified,
+                    vec![TripLeg::Walk(goal)],
+                    map,
+                ),
+                TripSpec::UsingRideHail { start, goal } => trips.new_trip(
+                    person.id,
+                    start_time,
+                    trip_start,
+                    TripMode::RideHail,
+                    purpose,
+                    routing_profile,
+                    modified,
+                    vec![
+                        TripLeg::Walk(SidewalkSpot::ride_hail(start.sidewalk_pos, map).unwrap()),
+                        TripLeg::RideHail(SidewalkSpot::ride_hail(goal.sidewalk_pos, map).unwrap()),
+                        TripLeg::Walk(goal),
+                    ],
+                    map,
+                ),
+                TripSpec::DeliveryTour {
+                    truck,
+                    stops,
+                    dwell,
                     goal,
                     ..
                 } => {
-                    let walk_to = SidewalkSpot::bus_stop(stop1, map);
-                    let legs = if let Some(stop2) = maybe_stop2 {
-                        vec![
-                            TripLeg::Walk(walk_to.clone()),
-                            TripLeg::RideBus(route, Some(stop2)),
-                            TripLeg::Walk(goal),
-                        ]
-                    } else {
-                        vec![
-                            TripLeg::Walk(walk_to.clone()),
-                            TripLeg::RideBus(route, None),
-                        ]
-                    };
+                    let mut legs: Vec<TripLeg> = stops
+                        .into_iter()
+                        .map(|b| TripLeg::Deliver(truck, b, dwell))
+                        .collect();
+                    legs.push(TripLeg::Drive(truck, goal.clone()));
+                    if let DrivingGoal::ParkNear(b) = goal {
+                        legs.push(TripLeg::Walk(SidewalkSpot::building(b, map)));
+                    }
                     trips.new_trip(
                         person.id,
                         start_time,
                         trip_start,
-                        TripMode::Transit,
+                        TripMode::Drive,
                         purpose,
+                        routing_profile,
                         modified,
                         legs,
                         map,
@@ -377,12 +463,16 @@ impl TripSpawner {
                     trip_start,
                     mode,
                     purpose,
+                    routing_profile,
                     modified,
                     vec![TripLeg::Remote(to)],
                     map,
                 ),
             };
 
+            if let Some(route) = route {
+                trips.assign_route(trip, route);
+            }
             if cancelled {
                 trips.cancel_trip(trip);
             } else {
@@ -396,7 +486,11 @@ impl TripSpawner {
 }
 
 impl TripSpec {
-    pub(crate) fn get_pathfinding_request(&self, map: &Map) -> Option<PathRequest> {
+    pub(crate) fn get_pathfinding_request(
+        &self,
+        profile: RoutingProfile,
+        map: &Map,
+    ) -> Option<PathRequest> {
         match self {
             TripSpec::VehicleAppearing {
                 start_pos,
@@ -413,6 +507,7 @@ impl TripSpec {
                     start: *start_pos,
                     end: goal.goal_pos(constraints, map).unwrap(),
                     constraints,
+                    profile,
                 })
             }
             TripSpec::NoRoomToSpawn { .. } => None,
@@ -422,17 +517,32 @@ impl TripSpec {
                 start: start.sidewalk_pos,
                 end: goal.sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
+                profile,
             }),
             TripSpec::UsingBike { start, .. } => Some(PathRequest {
                 start: map.get_b(*start).sidewalk_pos,
                 end: SidewalkSpot::bike_rack(*start, map).unwrap().sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
+                profile,
+            }),
+            TripSpec::UsingTransit { start, rides, .. } => Some(PathRequest {
+                start: start.sidewalk_pos,
+                end: SidewalkSpot::bus_stop(rides[0].board, map).sidewalk_pos,
+                constraints: PathConstraints::Pedestrian,
+                profile,
             }),
-            TripSpec::UsingTransit { start, stop1, .. } => Some(PathRequest {
+            // We don't know which station to walk to yet
+            TripSpec::UsingBikeShare { .. } => None,
+            TripSpec::UsingRideHail { start, .. } => Some(PathRequest {
                 start: start.sidewalk_pos,
-                end: SidewalkSpot::bus_stop(*stop1, map).sidewalk_pos,
+                end: SidewalkSpot::ride_hail(start.sidewalk_pos, map)
+                    .unwrap()
+                    .sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
+                profile,
             }),
+            // Stops that can't be reached are skipped, so route as the trip goes
+            TripSpec::DeliveryTour { .. } => None,
             TripSpec::Remote { .. } => None,
         }
     }
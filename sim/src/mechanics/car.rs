@@ -1,12 +1,15 @@
+use crate::mechanics::kinematics::SpeedProfile;
 use crate::{
     CarStatus, DistanceInterval, DrawCarInput, ParkingSpot, PersonID, Router, TimeInterval,
     TransitSimState, TripID, Vehicle, VehicleType,
 };
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{Direction, Map, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{Direction, Lane, LaneID, Map, Traversable};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
+const BIKE_UPHILL_SLOWDOWN: f64 = 20.0;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Car {
     pub vehicle: Vehicle,
@@ -17,6 +20,13 @@ pub struct Car {
     pub trip_and_person: Option<(TripID, PersonID)>,
     pub started_at: Time,
     pub total_blocked_time: Duration,
+    // When cars accelerate, the speed at the end of the most recent Crossing. None by default,
+    // where cars instantly move at their cruising speed.
+    pub last_speed: Option<Speed>,
+    // Set after merging mid-block from this adjacent lane, so the body can slide over gradually.
+    pub lane_change: Option<(LaneID, TimeInterval)>,
+    // When this driver last searched for a faster route around traffic
+    pub last_reroute: Option<Time>,
 
     // In reverse order -- most recently left is first. The sum length of these must be >=
     // vehicle.length.
@@ -26,15 +36,44 @@ pub struct Car {
 impl Car {
     // Assumes the current head of the path is the thing to cross.
     pub fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> CarState {
+        let last_step = self.router.last_step();
         let dist_int = DistanceInterval::new_driving(
             start_dist,
-            if self.router.last_step() {
+            if last_step {
                 self.router.get_end_dist()
             } else {
                 self.router.head().length(map)
             },
         );
-        self.crossing_state_with_end_dist(dist_int, start_time, map)
+        self.crossing_state_inner(dist_int, start_time, last_step, None, map)
+    }
+
+    // Like crossing_state, but only when cars accelerate. Starts from an explicit speed, and never
+    // cruises faster than the cap.
+    pub fn following_crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        start_speed: Speed,
+        cap: Speed,
+        map: &Map,
+    ) -> CarState {
+        let last_step = self.router.last_step();
+        let dist_int = DistanceInterval::new_driving(
+            start_dist,
+            if last_step {
+                self.router.get_end_dist()
+            } else {
+                self.router.head().length(map)
+            },
+        );
+        self.crossing_state_inner(
+            dist_int,
+            start_time,
+            last_step,
+            Some((start_speed, cap)),
+            map,
+        )
     }
 
     pub fn crossing_state_with_end_dist(
@@ -42,14 +81,101 @@ impl Car {
         dist_int: DistanceInterval,
         start_time: Time,
         map: &Map,
+    ) -> CarState {
+        self.crossing_state_inner(dist_int, start_time, false, None, map)
+    }
+
+    fn crossing_state_inner(
+        &self,
+        dist_int: DistanceInterval,
+        start_time: Time,
+        stop_at_end: bool,
+        // The starting speed and speed cap, when following somebody
+        following: Option<(Speed, Speed)>,
+        map: &Map,
     ) -> CarState {
         let on = self.router.head();
         let mut speed = on.speed_limit(map);
         if let Some(s) = self.vehicle.max_speed {
             speed = speed.min(s);
         }
+        if self.vehicle.vehicle_type == VehicleType::Bike {
+            if let Traversable::Lane(l) = on {
+                speed = bike_speed_on_lane(map.get_l(l), speed, map);
+            }
+        }
+        let start_speed = match following {
+            Some((start_speed, _)) => Some(start_speed),
+            None => self.current_speed(start_time),
+        };
+        if let Some(start_speed) = start_speed {
+            let profile = SpeedProfile::new(
+                dist_int.end - dist_int.start,
+                start_speed,
+                match following {
+                    Some((_, cap)) => speed.min(cap),
+                    None => speed,
+                },
+                self.vehicle.max_accel,
+                self.vehicle.max_decel,
+                stop_at_end,
+            );
+            return CarState::Crossing(
+                TimeInterval::new(start_time, start_time + profile.duration()),
+                dist_int,
+                Some(profile),
+            );
+        }
         let dt = (dist_int.end - dist_int.start) / speed;
-        CarState::Crossing(TimeInterval::new(start_time, start_time + dt), dist_int)
+        CarState::Crossing(
+            TimeInterval::new(start_time, start_time + dt),
+            dist_int,
+            None,
+        )
+    }
+
+    // Only when cars accelerate. Cars that've been stuck somewhere have to start from rest.
+    pub fn current_speed(&self, now: Time) -> Option<Speed> {
+        let last_speed = self.last_speed?;
+        Some(match self.state {
+            CarState::Crossing(ref time_int, _, Some(ref profile)) => {
+                profile.speed_at(now - time_int.start)
+            }
+            // Just finished crossing something, and didn't have to wait
+            CarState::Queued { blocked_since } | CarState::WaitingToAdvance { blocked_since }
+                if blocked_since == now =>
+            {
+                last_speed
+            }
+            _ => Speed::ZERO,
+        })
+    }
+
+    // While merging from an adjacent lane, slide the body over from that lane.
+    fn shift_for_lane_change(&self, body: PolyLine, now: Time, map: &Map) -> PolyLine {
+        let (from, percent) = match self.lane_change {
+            Some((from, ref time_int)) if now < time_int.end => (from, time_int.percent(now)),
+            _ => {
+                return body;
+            }
+        };
+        let to = self.router.head().as_lane();
+        let r = map.get_parent(to);
+        let mut diff = (r.offset(from) as isize) - (r.offset(to) as isize);
+        if r.dir(to) == Direction::Back {
+            diff *= -1;
+        }
+        let width = (map.get_l(from).width + map.get_l(to).width) / 2.0;
+        match body.shift_right(width * (diff as f64) * (1.0 - percent)) {
+            Ok(pl) => pl,
+            Err(err) => {
+                println!(
+                    "Body for {} changing lanes at {} broken: {}",
+                    self.vehicle.id, now, err
+                );
+                body
+            }
+        }
     }
 
     pub fn get_draw_car(
@@ -192,7 +318,7 @@ impl Car {
                     }
                 }
             }
-            _ => raw_body,
+            _ => self.shift_for_lane_change(raw_body, now, map),
         };
 
         DrawCarInput {
@@ -210,7 +336,7 @@ impl Car {
             status: match self.state {
                 CarState::Queued { .. } => CarStatus::Moving,
                 CarState::WaitingToAdvance { .. } => CarStatus::Moving,
-                CarState::Crossing(_, _) => CarStatus::Moving,
+                CarState::Crossing(_, _, _) => CarStatus::Moving,
                 // Eh they're technically moving, but this is a bit easier to spot
                 CarState::Unparking(_, _, _) => CarStatus::Parked,
                 CarState::Parking(_, _, _) => CarStatus::Parked,
@@ -244,7 +370,8 @@ impl Car {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum CarState {
-    Crossing(TimeInterval, DistanceInterval),
+    // The profile is only used when cars accelerate.
+    Crossing(TimeInterval, DistanceInterval, Option<SpeedProfile>),
     Queued { blocked_since: Time },
     WaitingToAdvance { blocked_since: Time },
     // Where's the front of the car while this is happening?
@@ -254,9 +381,29 @@ pub enum CarState {
 }
 
 impl CarState {
+    // Only valid for Crossing
+    pub fn crossing_dist(&self, now: Time) -> Distance {
+        match self {
+            CarState::Crossing(ref time_int, ref dist_int, ref profile) => {
+                // TODO Why percent_clamp_end? We process car updates in any order, so we might
+                // calculate this before moving this car from Crossing to another state.
+                if let Some(ref profile) = profile {
+                    if now >= time_int.end {
+                        dist_int.end
+                    } else {
+                        (dist_int.start + profile.dist_at(now - time_int.start)).min(dist_int.end)
+                    }
+                } else {
+                    dist_int.lerp(time_int.percent_clamp_end(now))
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
     pub fn get_end_time(&self) -> Time {
         match self {
-            CarState::Crossing(ref time_int, _) => time_int.end,
+            CarState::Crossing(ref time_int, _, _) => time_int.end,
             CarState::Queued { .. } => unreachable!(),
             CarState::WaitingToAdvance { .. } => unreachable!(),
             CarState::Unparking(_, _, ref time_int) => time_int.end,
@@ -265,3 +412,20 @@ impl CarState {
         }
     }
 }
+
+// Cyclists slow down going uphill. Returns the average speed over the whole lane, given the speed on
+// flat ground. Going downhill doesn't help, since bikes are already capped by their max speed.
+fn bike_speed_on_lane(lane: &Lane, flat_speed: Speed, map: &Map) -> Speed {
+    let mut total_dist = Distance::ZERO;
+    let mut total_time = Duration::ZERO;
+    for (dist, grade) in lane.grade_profile(map) {
+        // Roughly halves speed at a 5% grade, and thirds it at 10%
+        let speed = flat_speed * (1.0 / (1.0 + BIKE_UPHILL_SLOWDOWN * grade.max(0.0)));
+        total_dist += dist;
+        total_time += dist / speed;
+    }
+    if total_time == Duration::ZERO {
+        return flat_speed;
+    }
+    Speed::meters_per_second(total_dist.inner_meters() / total_time.inner_seconds())
+}
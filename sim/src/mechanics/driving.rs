@@ -1,15 +1,16 @@
 use crate::mechanics::car::{Car, CarState};
+use crate::mechanics::kinematics::safe_following_speed;
 use crate::mechanics::Queue;
 use crate::sim::Ctx;
 use crate::{
     ActionAtEnd, AgentID, AgentProperties, CarID, Command, CreateCar, DistanceInterval,
     DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSim, ParkingSimState, ParkingSpot,
-    PersonID, Scheduler, TimeInterval, TransitSimState, TripID, TripManager, UnzoomedAgent,
-    Vehicle, WalkingSimState, FOLLOWING_DISTANCE,
+    PersonID, RoadTravelTimes, Scheduler, TimeInterval, TransitSimState, TripID, TripManager,
+    UnzoomedAgent, Vehicle, VehicleType, WalkingSimState, FOLLOWING_DISTANCE, REROUTE_COOLDOWN,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{LaneID, Map, Path, PathStep, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{LaneID, Map, Path, PathStep, Position, Traversable, TurnID};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
@@ -17,7 +18,12 @@ const TIME_TO_UNPARK_ONSTRET: Duration = Duration::const_seconds(10.0);
 const TIME_TO_PARK_ONSTREET: Duration = Duration::const_seconds(15.0);
 const TIME_TO_UNPARK_OFFSTREET: Duration = Duration::const_seconds(5.0);
 const TIME_TO_PARK_OFFSTREET: Duration = Duration::const_seconds(5.0);
-const TIME_TO_WAIT_AT_STOP: Duration = Duration::const_seconds(10.0);
+const TIME_TO_CHANGE_LANES: Duration = Duration::const_seconds(3.0);
+// When cars accelerate, how often somebody following another car on the same queue reconsiders
+// their speed
+const REACTION_TIME: Duration = Duration::const_seconds(1.0);
+// When cars accelerate, this close behind a stopped car, just brake and queue up behind them
+const STOPPING_GAP: Distance = Distance::const_meters(1.0);
 
 // TODO Do something else.
 pub(crate) const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
@@ -39,16 +45,29 @@ pub struct DrivingSimState {
 
     recalc_lanechanging: bool,
     handle_uber_turns: bool,
+    car_acceleration: bool,
+    mid_block_lanechanging: bool,
+    congestion_rerouting: bool,
 }
 
 impl DrivingSimState {
-    pub fn new(map: &Map, recalc_lanechanging: bool, handle_uber_turns: bool) -> DrivingSimState {
+    pub fn new(
+        map: &Map,
+        recalc_lanechanging: bool,
+        handle_uber_turns: bool,
+        car_acceleration: bool,
+        mid_block_lanechanging: bool,
+        congestion_rerouting: bool,
+    ) -> DrivingSimState {
         let mut sim = DrivingSimState {
             cars: BTreeMap::new(),
             queues: BTreeMap::new(),
             events: Vec::new(),
             recalc_lanechanging,
             handle_uber_turns,
+            car_acceleration,
+            mid_block_lanechanging,
+            congestion_rerouting,
         };
 
         for l in map.all_lanes() {
@@ -97,7 +116,14 @@ impl DrivingSimState {
                 last_steps: VecDeque::new(),
                 started_at: now,
                 total_blocked_time: Duration::ZERO,
+                last_speed: if self.car_acceleration {
+                    Some(Speed::ZERO)
+                } else {
+                    None
+                },
                 trip_and_person: params.trip_and_person,
+                lane_change: None,
+                last_reroute: None,
             };
             if let Some(p) = params.maybe_parked_car {
                 let delay = match p.spot {
@@ -147,6 +173,7 @@ impl DrivingSimState {
                 }
 
                 car.state = car.crossing_state(params.start_dist, now, map);
+                start_following(&car, now, scheduler);
             }
             scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             {
@@ -170,6 +197,7 @@ impl DrivingSimState {
         trips: &mut TripManager,
         transit: &mut TransitSimState,
         walking: &mut WalkingSimState,
+        travel_times: &RoadTravelTimes,
     ) {
         // State transitions for this car:
         //
@@ -205,7 +233,8 @@ impl DrivingSimState {
             // checker, temporarily move one of them out of the BTreeMap.
             let mut car = self.cars.remove(&id).unwrap();
             // Responsibility of update_car to manage scheduling stuff!
-            need_distances = self.update_car_without_distances(&mut car, now, ctx, transit);
+            need_distances =
+                self.update_car_without_distances(&mut car, now, ctx, trips, transit, travel_times);
             self.cars.insert(id, car);
         }
 
@@ -229,6 +258,8 @@ impl DrivingSimState {
             } else {
                 self.delete_car_internal(&mut car, dists, idx, now, ctx);
             }
+        } else if self.mid_block_lanechanging {
+            self.try_mid_block_lanechange(id, now, ctx);
         }
     }
 
@@ -240,10 +271,15 @@ impl DrivingSimState {
         car: &mut Car,
         now: Time,
         ctx: &mut Ctx,
+        trips: &TripManager,
         transit: &mut TransitSimState,
+        travel_times: &RoadTravelTimes,
     ) -> bool {
         match car.state {
-            CarState::Crossing(_, _) => {
+            CarState::Crossing(_, _, _) => {
+                if car.last_speed.is_some() {
+                    car.last_speed = car.current_speed(now);
+                }
                 car.state = CarState::Queued { blocked_since: now };
                 if car.router.last_step() {
                     // Immediately run update_car_with_distances.
@@ -281,14 +317,32 @@ impl DrivingSimState {
                 car.state = car.crossing_state(front, now, ctx.map);
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                start_following(car, now, ctx.scheduler);
             }
             CarState::IdlingAtStop(dist, _) => {
-                car.router = transit.bus_departed_from_stop(car.vehicle.id, ctx.map);
+                // Delivery trucks already know where they're headed next
+                if let Some(router) = ctx.ride_hail.vehicle_departed(car.vehicle.id) {
+                    car.router = router;
+                } else if car.vehicle.vehicle_type.is_transit() {
+                    car.router = transit.bus_departed_from_stop(car.vehicle.id, ctx.map);
+                }
+                if car.router.last_step() {
+                    // Like Unparking, just trigger the side effect of choosing an end_dist
+                    car.router.maybe_handle_end(
+                        dist,
+                        &car.vehicle,
+                        ctx.parking,
+                        ctx.map,
+                        car.trip_and_person,
+                        &mut self.events,
+                    );
+                }
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
                 car.state = car.crossing_state(dist, now, ctx.map);
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                start_following(car, now, ctx.scheduler);
 
                 // Update our follower, so they know we stopped idling.
                 let queue = &self.queues[&car.router.head()];
@@ -315,13 +369,14 @@ impl DrivingSimState {
                                     follower.state.get_end_time(),
                                     Command::UpdateCar(follower.vehicle.id),
                                 );
+                                start_following(follower, now, ctx.scheduler);
                             }
                         }
                         CarState::WaitingToAdvance { .. } => unreachable!(),
                         // They weren't blocked. Note that there's no way the Crossing state could
                         // jump forwards here; the leader is still in front
                         // of them.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::IdlingAtStop(_, _) => {}
@@ -371,9 +426,33 @@ impl DrivingSimState {
                     &mut self.events,
                 );
                 car.total_blocked_time += now - blocked_since;
+                car.lane_change = None;
+                // Every new road is a chance to avoid traffic ahead, unless the driver just
+                // looked
+                if self.congestion_rerouting
+                    && goto.maybe_lane().is_some()
+                    && car
+                        .last_reroute
+                        .map(|t| now - t >= REROUTE_COOLDOWN)
+                        .unwrap_or(true)
+                {
+                    if let Some((trip, _)) = car.trip_and_person {
+                        if trips.reroutes_around_congestion(trip)
+                            && car.router.maybe_reroute(
+                                travel_times,
+                                now,
+                                ctx.map,
+                                &mut self.events,
+                            )
+                        {
+                            car.last_reroute = Some(now);
+                        }
+                    }
+                }
                 car.state = car.crossing_state(Distance::ZERO, now, ctx.map);
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                start_following(car, now, ctx.scheduler);
                 self.events.push(Event::AgentEntersTraversable(
                     AgentID::Car(car.vehicle.id),
                     goto,
@@ -430,7 +509,7 @@ impl DrivingSimState {
         let our_dist = dists[idx].1;
 
         match car.state {
-            CarState::Crossing(_, _)
+            CarState::Crossing(_, _, _)
             | CarState::Unparking(_, _, _)
             | CarState::IdlingAtStop(_, _)
             | CarState::WaitingToAdvance { .. } => unreachable!(),
@@ -492,6 +571,7 @@ impl DrivingSimState {
                         car.state = car.crossing_state(our_dist, now, ctx.map);
                         ctx.scheduler
                             .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                        start_following(car, now, ctx.scheduler);
                         true
                     }
                     Some(ActionAtEnd::StopBiking(bike_rack)) => {
@@ -501,18 +581,17 @@ impl DrivingSimState {
                             car.vehicle.id,
                             bike_rack,
                             car.total_blocked_time,
-                            ctx.map,
-                            ctx.scheduler,
+                            ctx,
                         );
                         false
                     }
                     Some(ActionAtEnd::BusAtStop) => {
                         car.total_blocked_time += now - blocked_since;
-                        if transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx) {
-                            car.state = CarState::IdlingAtStop(
-                                our_dist,
-                                TimeInterval::new(now, now + TIME_TO_WAIT_AT_STOP),
-                            );
+                        if let Some(depart) =
+                            transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx)
+                        {
+                            car.state =
+                                CarState::IdlingAtStop(our_dist, TimeInterval::new(now, depart));
                             ctx.scheduler
                                 .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                             true
@@ -521,6 +600,45 @@ impl DrivingSimState {
                             false
                         }
                     }
+                    Some(ActionAtEnd::RideHailAtStop) => {
+                        car.total_blocked_time += now - blocked_since;
+                        if let Some(depart) = trips.ride_hail_vehicle_arrived(
+                            now,
+                            car.vehicle.id,
+                            Position::new(car.router.head().as_lane(), our_dist),
+                            walking,
+                            ctx,
+                        ) {
+                            car.state =
+                                CarState::IdlingAtStop(our_dist, TimeInterval::new(now, depart));
+                            ctx.scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            // Done with this trip, so vanish until the next dispatch
+                            false
+                        }
+                    }
+                    Some(ActionAtEnd::DoublePark(b)) => {
+                        car.total_blocked_time += now - blocked_since;
+                        // Everybody behind is stuck until the delivery is done
+                        if let Some((depart, router)) = trips.truck_double_parked(
+                            now,
+                            car.vehicle.id,
+                            b,
+                            Position::new(car.router.head().as_lane(), our_dist),
+                            ctx,
+                        ) {
+                            car.router = router;
+                            car.state =
+                                CarState::IdlingAtStop(our_dist, TimeInterval::new(now, depart));
+                            ctx.scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            false
+                        }
+                    }
                     None => {
                         ctx.scheduler.push(
                             now + BLIND_RETRY_TO_REACH_END_DIST,
@@ -532,7 +650,7 @@ impl DrivingSimState {
                         /*
                         // If this car wasn't blocked at all, when would it reach its goal?
                         let ideal_end_time = match car.crossing_state(our_dist, now, map) {
-                            CarState::Crossing(time_int, _) => time_int.end,
+                            CarState::Crossing(time_int, _, _) => time_int.end,
                             _ => unreachable!(),
                         };
                         if ideal_end_time == now {
@@ -621,40 +739,222 @@ impl DrivingSimState {
         // We might've scheduled one of those using BLIND_RETRY_TO_CREEP_FORWARDS.
         ctx.scheduler
             .cancel(Command::UpdateLaggyHead(car.vehicle.id));
+        ctx.scheduler.cancel(Command::FollowLeader(car.vehicle.id));
 
         // Update the follower so that they don't suddenly jump forwards.
         if idx != dists.len() - 1 {
             let (follower_id, follower_dist) = dists[idx + 1];
-            let mut follower = self.cars.get_mut(&follower_id).unwrap();
             // TODO If the leader vanished at a border node, this still jumps a bit -- the
             // lead car's back is still sticking out. Need to still be bound by them, even
             // though they don't exist! If the leader just parked, then we're fine.
-            match follower.state {
-                CarState::Queued { blocked_since } => {
-                    // Prevent them from jumping forwards.
-                    follower.total_blocked_time += now - blocked_since;
-                    follower.state = follower.crossing_state(follower_dist, now, ctx.map);
-                    ctx.scheduler.update(
-                        follower.state.get_end_time(),
-                        Command::UpdateCar(follower_id),
-                    );
+            self.pin_follower(follower_id, follower_dist, now, ctx);
+        }
+    }
+
+    // The car in front of this follower just changed. Make sure the follower stays where they
+    // currently are, instead of jumping to the new leader.
+    fn pin_follower(
+        &mut self,
+        follower_id: CarID,
+        follower_dist: Distance,
+        now: Time,
+        ctx: &mut Ctx,
+    ) {
+        let mut follower = self.cars.get_mut(&follower_id).unwrap();
+        match follower.state {
+            CarState::Queued { blocked_since } => {
+                // Prevent them from jumping forwards.
+                follower.total_blocked_time += now - blocked_since;
+                follower.state = follower.crossing_state(follower_dist, now, ctx.map);
+                ctx.scheduler.update(
+                    follower.state.get_end_time(),
+                    Command::UpdateCar(follower_id),
+                );
+                start_following(follower, now, ctx.scheduler);
+            }
+            CarState::Crossing(_, _, _) => {
+                // If the follower was still Crossing, they might not've been blocked
+                // by leader yet. In that case, recalculating their Crossing state is a
+                // no-op.
+                follower.state = follower.crossing_state(follower_dist, now, ctx.map);
+                ctx.scheduler.update(
+                    follower.state.get_end_time(),
+                    Command::UpdateCar(follower_id),
+                );
+                start_following(follower, now, ctx.scheduler);
+            }
+            // They weren't blocked
+            CarState::Unparking(_, _, _)
+            | CarState::Parking(_, _, _)
+            | CarState::IdlingAtStop(_, _) => {}
+            CarState::WaitingToAdvance { .. } => unreachable!(),
+        }
+    }
+
+    // A car that just got stuck behind somebody mid-block might merge into an adjacent lane, if
+    // there's a gap there and fewer cars ahead. The car switches queues immediately;
+    // TIME_TO_CHANGE_LANES only controls how long it takes to visually slide over. Cars only
+    // consider this at the moment they become Queued, so there's no extra scheduling.
+    fn try_mid_block_lanechange(&mut self, id: CarID, now: Time, ctx: &mut Ctx) {
+        let car = &self.cars[&id];
+        let blocked_since = match car.state {
+            CarState::Queued { blocked_since } => blocked_since,
+            _ => {
+                return;
+            }
+        };
+        if !car.last_steps.is_empty()
+            || car.vehicle.vehicle_type == VehicleType::Bike
+            || car.vehicle.vehicle_type.is_transit()
+        {
+            return;
+        }
+        let from = match car.router.head() {
+            Traversable::Lane(l) => l,
+            Traversable::Turn(_) => {
+                return;
+            }
+        };
+        let options = car
+            .router
+            .mid_block_lanechange_options(ctx.map, self.handle_uber_turns);
+        if options.is_empty() {
+            return;
+        }
+
+        let dists =
+            self.queues[&Traversable::Lane(from)].get_car_positions(now, &self.cars, &self.queues);
+        let idx = dists.iter().position(|(c, _)| *c == id).unwrap();
+        // Nobody in front of us to get around
+        if idx == 0 {
+            return;
+        }
+        let our_dist = dists[idx].1;
+        let from_len = self.queues[&Traversable::Lane(from)].geom_len;
+
+        // (cars ahead in the new lane, lane, turn, dist along the new lane)
+        let mut best: Option<(usize, LaneID, TurnID, Distance)> = None;
+        for (to, turn) in options {
+            let queue = &self.queues[&Traversable::Lane(to)];
+            // Lanes on the same road can have slightly different lengths
+            let dist = our_dist * (queue.geom_len / from_len);
+            // Don't cut off anybody entering the lane from the intersection, and don't merge right
+            // at the end.
+            if dist < car.vehicle.length + FOLLOWING_DISTANCE
+                || dist + car.vehicle.length > queue.geom_len
+                || !queue.room_for_car(car)
+            {
+                continue;
+            }
+            if let Some(insert_idx) =
+                queue.get_idx_to_insert_car(dist, car.vehicle.length, now, &self.cars, &self.queues)
+            {
+                if insert_idx < idx && best.map(|(n, _, _, _)| insert_idx < n).unwrap_or(true) {
+                    best = Some((insert_idx, to, turn, dist));
                 }
-                CarState::Crossing(_, _) => {
-                    // If the follower was still Crossing, they might not've been blocked
-                    // by leader yet. In that case, recalculating their Crossing state is a
-                    // no-op.
-                    follower.state = follower.crossing_state(follower_dist, now, ctx.map);
-                    ctx.scheduler.update(
-                        follower.state.get_end_time(),
-                        Command::UpdateCar(follower_id),
-                    );
+            }
+        }
+        let (insert_idx, to, turn, dist) = match best {
+            Some(x) => x,
+            None => {
+                return;
+            }
+        };
+        let to_dists =
+            self.queues[&Traversable::Lane(to)].get_car_positions(now, &self.cars, &self.queues);
+
+        let mut car = self.cars.remove(&id).unwrap();
+        {
+            let queue = self.queues.get_mut(&Traversable::Lane(from)).unwrap();
+            assert_eq!(queue.cars.remove(idx).unwrap(), id);
+            queue.free_reserved_space(&car);
+            ctx.intersections
+                .space_freed(now, ctx.map.get_l(from).src_i, ctx.scheduler, ctx.map);
+        }
+        {
+            let queue = self.queues.get_mut(&Traversable::Lane(to)).unwrap();
+            queue.cars.insert(insert_idx, id);
+            queue.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
+        }
+
+        car.router.change_lanes(to, turn, ctx.map);
+        car.total_blocked_time += now - blocked_since;
+        car.state = car.crossing_state(dist, now, ctx.map);
+        car.lane_change = Some((from, TimeInterval::new(now, now + TIME_TO_CHANGE_LANES)));
+        ctx.scheduler
+            .update(car.state.get_end_time(), Command::UpdateCar(id));
+        start_following(&car, now, ctx.scheduler);
+        self.cars.insert(id, car);
+
+        // Neither our old follower nor our new one should jump forwards.
+        if idx != dists.len() - 1 {
+            let (follower_id, follower_dist) = dists[idx + 1];
+            self.pin_follower(follower_id, follower_dist, now, ctx);
+        }
+        if insert_idx != to_dists.len() {
+            let (follower_id, follower_dist) = to_dists[insert_idx];
+            self.pin_follower(follower_id, follower_dist, now, ctx);
+        }
+    }
+
+    // Gipps-style car-following, only when cars accelerate. While somebody's ahead on the same
+    // queue, a Crossing car re-plans every REACTION_TIME, never going faster than it could and
+    // still stop behind the leader if they braked as hard as possible. Once it's close behind a
+    // stopped leader, it brakes and queues up like usual.
+    pub fn follow_leader(&mut self, id: CarID, now: Time, ctx: &mut Ctx) {
+        let dists = match self.cars.get(&id) {
+            Some(car) => match car.state {
+                CarState::Crossing(_, _, Some(_)) => {
+                    self.queues[&car.router.head()].get_car_positions(now, &self.cars, &self.queues)
+                }
+                _ => {
+                    return;
                 }
-                // They weren't blocked
-                CarState::Unparking(_, _, _)
-                | CarState::Parking(_, _, _)
-                | CarState::IdlingAtStop(_, _) => {}
-                CarState::WaitingToAdvance { .. } => unreachable!(),
+            },
+            None => {
+                return;
             }
+        };
+        let idx = dists.iter().position(|(c, _)| *c == id).unwrap();
+        // The front of the queue has to wait for intersections and laggy heads like usual
+        if idx == 0 {
+            return;
+        }
+        let (leader_id, leader_dist) = dists[idx - 1];
+        let our_dist = dists[idx].1;
+
+        let car = &self.cars[&id];
+        let leader = &self.cars[&leader_id];
+        let gap = leader_dist - leader.vehicle.length - FOLLOWING_DISTANCE - our_dist;
+        let leader_speed = match leader.state {
+            CarState::Crossing(_, _, _) => leader.current_speed(now).unwrap_or(Speed::ZERO),
+            _ => Speed::ZERO,
+        };
+        let mut speed = car.current_speed(now).unwrap_or(Speed::ZERO);
+        if gap <= Distance::ZERO {
+            // Already held back by the leader
+            speed = speed.min(leader_speed);
+        }
+        let cap = if leader_speed == Speed::ZERO && gap < STOPPING_GAP {
+            Speed::ZERO
+        } else {
+            safe_following_speed(
+                gap,
+                speed,
+                leader_speed,
+                REACTION_TIME,
+                car.vehicle.max_decel,
+                leader.vehicle.max_decel,
+            )
+        };
+        let state = car.following_crossing_state(our_dist, now, speed, cap, ctx.map);
+        let end_time = state.get_end_time();
+        self.cars.get_mut(&id).unwrap().state = state;
+        ctx.scheduler.update(end_time, Command::UpdateCar(id));
+        // Once the car is stopping, it'll queue up behind the leader and get woken up normally.
+        if cap > Speed::ZERO && end_time > now + REACTION_TIME {
+            ctx.scheduler
+                .update(now + REACTION_TIME, Command::FollowLeader(id));
         }
     }
 
@@ -782,7 +1082,7 @@ impl DrivingSimState {
                         // They weren't blocked. Note that there's no way the Crossing state
                         // could jump forwards here; the leader
                         // vanished from the end of the traversable.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::IdlingAtStop(_, _) => {}
@@ -1021,6 +1321,21 @@ impl DrivingSimState {
         self.queues[&Traversable::Lane(l)].target_lane_penalty()
     }
 
+    // For traffic signal detectors. How many vehicles have their front within some distance of
+    // the end of the lane?
+    pub fn num_vehicles_approaching(&self, l: LaneID, within: Distance, now: Time) -> usize {
+        let queue = &self.queues[&Traversable::Lane(l)];
+        queue
+            .get_car_positions(now, &self.cars, &self.queues)
+            .into_iter()
+            .filter(|(_, dist)| queue.geom_len - *dist <= within)
+            .count()
+    }
+
+    pub fn num_vehicles_on(&self, l: LaneID) -> usize {
+        self.queues[&Traversable::Lane(l)].cars.len()
+    }
+
     pub fn find_trips_to_edited_parking(
         &self,
         spots: BTreeSet<ParkingSpot>,
@@ -1037,3 +1352,10 @@ impl DrivingSimState {
         affected
     }
 }
+
+// When cars accelerate, a car that just started Crossing checks for somebody ahead right away.
+fn start_following(car: &Car, now: Time, scheduler: &mut Scheduler) {
+    if let CarState::Crossing(_, _, Some(_)) = car.state {
+        scheduler.update(now, Command::FollowLeader(car.vehicle.id));
+    }
+}
@@ -1,10 +1,10 @@
 use crate::mechanics::car::Car;
 use crate::mechanics::Queue;
-use crate::{AgentID, AlertLocation, CarID, Command, Event, Scheduler, Speed};
+use crate::{AgentID, AlertLocation, CarID, Command, DrivingSimState, Event, Scheduler, Speed};
 use abstutil::{deserialize_btreemap, retain_btreeset, serialize_btreemap};
-use geom::{Duration, Time};
+use geom::{Distance, Duration, Time};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, Map, PhaseType, RoadID,
+    ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, Map, PhaseType, RoadID, Stage,
     Traversable, TurnID, TurnPriority, TurnType,
 };
 use serde::{Deserialize, Serialize};
@@ -12,6 +12,8 @@ use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
 const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
+// Actuated signals detect vehicles this close to the end of an incoming lane.
+const DETECTOR_DISTANCE: Distance = Distance::const_meters(30.0);
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct IntersectionSimState {
@@ -47,7 +49,10 @@ struct State {
 #[derive(Clone, Serialize, Deserialize)]
 struct SignalState {
     current_stage: usize,
+    // For actuated and max-pressure stages, this is just when the next decision happens.
     stage_ends_at: Time,
+    // When the current stage first turned green, even if it's been extended or repeated since.
+    stage_started_at: Time,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Debug)]
@@ -222,19 +227,22 @@ impl IntersectionSimState {
         id: IntersectionID,
         map: &Map,
         scheduler: &mut Scheduler,
+        driving: &DrivingSimState,
     ) {
-        let state = self.state.get_mut(&id).unwrap();
-        let signal_state = state.signal.as_mut().unwrap();
         let signal = map.get_traffic_signal(id);
+        let state = &self.state[&id];
+        let signal_state = state.signal.as_ref().unwrap();
 
-        // Switch to a new stage?
+        // Switch to a new stage? If not, how much longer should the current one last?
         assert_eq!(now, signal_state.stage_ends_at);
-        let old_stage = &signal.stages[signal_state.current_stage];
+        let current_stage = signal_state.current_stage;
+        let green_for = now - signal_state.stage_started_at;
+        let old_stage = &signal.stages[current_stage];
+        let mut next_stage = (current_stage + 1) % signal.stages.len();
+        let mut hold_for = None;
         match old_stage.phase_type {
-            PhaseType::Fixed(_) => {
-                signal_state.current_stage += 1;
-            }
-            PhaseType::Adaptive(_) => {
+            PhaseType::Fixed(_) => {}
+            PhaseType::Adaptive(d) => {
                 // TODO Make a better policy here. For now, if there's _anyone_ waiting to start a
                 // protected turn, repeat this stage for the full duration. Note that "waiting" is
                 // only defined as "at the end of the lane, ready to start the turn." If a
@@ -244,26 +252,114 @@ impl IntersectionSimState {
                 if state.waiting.keys().all(|req| {
                     old_stage.get_priority_of_turn(req.turn, signal) != TurnPriority::Protected
                 }) {
-                    signal_state.current_stage += 1;
                     self.events.push(Event::Alert(
                         AlertLocation::Intersection(id),
                         "Repeating an adaptive stage".to_string(),
                     ));
+                } else {
+                    hold_for = Some(d);
+                }
+            }
+            PhaseType::Actuated {
+                min_green,
+                max_green,
+                extension,
+            } => {
+                if green_for < max_green {
+                    let detected = incoming_lanes(old_stage, signal)
+                        .into_iter()
+                        .map(|l| driving.num_vehicles_approaching(l, DETECTOR_DISTANCE, now))
+                        .sum::<usize>();
+                    // Otherwise gap out
+                    if detected > 0 {
+                        // Check again after a while, even with a long queue. The stage won't end
+                        // sooner, but then it's easy to draw how much time is left.
+                        hold_for = Some(
+                            (extension * (detected as f64))
+                                .min(max_green - green_for)
+                                .min(min_green),
+                        );
+                    }
+                }
+            }
+            PhaseType::MaxPressure {
+                min_green,
+                max_green,
+            } => {
+                let mut best: Option<(isize, usize)> = None;
+                // Start with the next stage, so ties keep the usual order.
+                for offset in 1..=signal.stages.len() {
+                    let idx = (current_stage + offset) % signal.stages.len();
+                    if idx == current_stage && green_for >= max_green {
+                        continue;
+                    }
+                    let pressure = self.pressure(id, &signal.stages[idx], signal, map, driving);
+                    // Staying wins ties, since it's considered last.
+                    if best.map(|(p, _)| pressure >= p).unwrap_or(true) {
+                        best = Some((pressure, idx));
+                    }
+                }
+                if let Some((_, idx)) = best {
+                    if idx == current_stage {
+                        hold_for = Some(min_green.min(max_green - green_for));
+                    } else {
+                        next_stage = idx;
+                    }
                 }
             }
-        }
-        if signal_state.current_stage == signal.stages.len() {
-            signal_state.current_stage = 0;
         }
 
-        signal_state.stage_ends_at = now
-            + signal.stages[signal_state.current_stage]
-                .phase_type
-                .simple_duration();
+        let signal_state = self.state.get_mut(&id).unwrap().signal.as_mut().unwrap();
+        if let Some(dt) = hold_for {
+            signal_state.stage_ends_at = now + dt;
+        } else {
+            signal_state.current_stage = next_stage;
+            signal_state.stage_started_at = now;
+            signal_state.stage_ends_at = now
+                + signal.stages[signal_state.current_stage]
+                    .phase_type
+                    .simple_duration();
+        }
         scheduler.push(signal_state.stage_ends_at, Command::UpdateIntersection(id));
         self.wakeup_waiting(now, id, scheduler, map);
     }
 
+    // For max-pressure signals: how many vehicles are queued for the stage's protected movements,
+    // minus how many are on the lanes they lead to? Pedestrians waiting to cross count too.
+    fn pressure(
+        &self,
+        id: IntersectionID,
+        stage: &Stage,
+        signal: &ControlTrafficSignal,
+        map: &Map,
+        driving: &DrivingSimState,
+    ) -> isize {
+        let mut pressure = 0;
+        for m in &stage.protected_movements {
+            if m.crosswalk {
+                continue;
+            }
+            let members = &signal.movements[m].members;
+            let src: BTreeSet<LaneID> = members.iter().map(|t| t.src).collect();
+            let dst: BTreeSet<LaneID> = members.iter().map(|t| t.dst).collect();
+            for l in src {
+                pressure += driving.num_vehicles_on(l) as isize;
+            }
+            for l in dst {
+                pressure -= driving.num_vehicles_on(l) as isize;
+            }
+        }
+        pressure += self.state[&id]
+            .waiting
+            .keys()
+            .filter(|req| {
+                matches!(req.agent, AgentID::Pedestrian(_))
+                    && stage.get_priority_of_turn(req.turn, signal) == TurnPriority::Protected
+            })
+            .count() as isize;
+        pressure
+    }
+
     // For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
     // this returns true, then the head car MUST actually start this turn.
     // For peds: Likewise -- only called when the ped is at the start of the turn. They must
@@ -621,7 +717,13 @@ impl IntersectionSimState {
         let state = &self.state[&req.turn.parent];
         let signal_state = state.signal.as_ref().unwrap();
         let stage = &signal.stages[signal_state.current_stage];
-        let full_stage_duration = stage.phase_type.simple_duration();
+        // The longest this stage could possibly last
+        let full_stage_duration = match stage.phase_type {
+            PhaseType::Fixed(d) | PhaseType::Adaptive(d) => d,
+            PhaseType::Actuated { max_green, .. } | PhaseType::MaxPressure { max_green, .. } => {
+                max_green
+            }
+        };
         let remaining_stage_time = signal_state.stage_ends_at - now;
         let our_time = state.waiting[req];
 
@@ -780,6 +882,7 @@ impl SignalState {
         let mut state = SignalState {
             current_stage: 0,
             stage_ends_at: now,
+            stage_started_at: now,
         };
 
         let signal = map.get_traffic_signal(id);
@@ -797,6 +900,7 @@ impl SignalState {
                 }
             } else {
                 state.stage_ends_at = now + dt - offset;
+                state.stage_started_at = now - offset;
                 break;
             }
         }
@@ -805,6 +909,16 @@ impl SignalState {
     }
 }
 
+// Where vehicles wait for the stage's protected movements
+fn incoming_lanes(stage: &Stage, signal: &ControlTrafficSignal) -> BTreeSet<LaneID> {
+    stage
+        .protected_movements
+        .iter()
+        .filter(|m| !m.crosswalk)
+        .flat_map(|m| signal.movements[m].members.iter().map(|t| t.src))
+        .collect()
+}
+
 // TODO Sometimes a traffic signal is surrounded by tiny lanes with almost no capacity. Workaround
 // for now.
 fn allow_block_the_box(osm_node_id: i64) -> bool {
@@ -0,0 +1,219 @@
+use geom::{Distance, Duration, Speed};
+use serde::{Deserialize, Serialize};
+
+// Used when cars accelerate (SimOptions::car_acceleration). By default, a Crossing car instantly
+// moves at its cruising speed, so a queue at a signal discharges unrealistically quickly. Instead,
+// plan the crossing: accelerate (or brake) towards the cruising speed, hold it, and if the car has
+// to stop at the end, brake to a stop right there.
+//
+// The plan alone ignores the car ahead. DrivingSimState::follow_leader re-plans cars behind
+// somebody, capping the cruising speed with safe_following_speed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SpeedProfile {
+    phases: Vec<Phase>,
+}
+
+// Constant acceleration. Everything is in meters and seconds, to avoid rounding at every step.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct Phase {
+    duration: f64,
+    start_speed: f64,
+    accel: f64,
+}
+
+impl Phase {
+    fn dist_at(&self, t: f64) -> f64 {
+        self.start_speed * t + 0.5 * self.accel * t * t
+    }
+
+    fn end_speed(&self) -> f64 {
+        (self.start_speed + self.accel * self.duration).max(0.0)
+    }
+
+    fn length(&self) -> f64 {
+        self.dist_at(self.duration)
+    }
+}
+
+impl SpeedProfile {
+    // max_accel and max_decel are in meters per second squared, and must be positive.
+    pub fn new(
+        dist: Distance,
+        start_speed: Speed,
+        cruise_speed: Speed,
+        max_accel: f64,
+        max_decel: f64,
+        stop_at_end: bool,
+    ) -> SpeedProfile {
+        assert!(
+            max_accel > 0.0 && max_decel > 0.0,
+            "max_accel {} and max_decel {} must be positive",
+            max_accel,
+            max_decel
+        );
+        let d = dist.inner_meters();
+        let v0 = start_speed.inner_meters_per_second().max(0.0);
+        let cruise = cruise_speed.inner_meters_per_second();
+        let mut phases = Vec::new();
+        if d <= 0.0 {
+            // Nothing to cross, but remember the speed
+            phases.push(Phase {
+                duration: 0.0,
+                start_speed: if stop_at_end { 0.0 } else { v0 },
+                accel: 0.0,
+            });
+            return SpeedProfile { phases };
+        }
+
+        if stop_at_end && v0 * v0 / (2.0 * max_decel) >= d {
+            // Too fast to stop comfortably; brake harder.
+            phases.push(Phase {
+                duration: 2.0 * d / v0,
+                start_speed: v0,
+                accel: -v0 * v0 / (2.0 * d),
+            });
+            return SpeedProfile { phases };
+        }
+
+        // The fastest the car can go and still do everything else in the distance available
+        let mut peak = cruise;
+        if v0 < cruise {
+            let mut reachable = v0 * v0 + 2.0 * max_accel * d;
+            if stop_at_end {
+                // Accelerate then brake, meeting at the peak
+                reachable = (2.0 * d + v0 * v0 / max_accel) / (1.0 / max_accel + 1.0 / max_decel);
+            }
+            peak = peak.min(reachable.sqrt());
+        } else if !stop_at_end {
+            let reachable = v0 * v0 - 2.0 * max_decel * d;
+            if reachable > cruise * cruise {
+                // Can't slow down to the cruising speed in time
+                peak = reachable.sqrt();
+            }
+        }
+
+        let mut remaining = d;
+        if peak != v0 {
+            let rate = if peak > v0 { max_accel } else { -max_decel };
+            let phase = Phase {
+                duration: (peak - v0) / rate,
+                start_speed: v0,
+                accel: rate,
+            };
+            remaining -= phase.length();
+            phases.push(phase);
+        }
+        let braking = if stop_at_end {
+            Some(Phase {
+                duration: peak / max_decel,
+                start_speed: peak,
+                accel: -max_decel,
+            })
+        } else {
+            None
+        };
+        if let Some(ref phase) = braking {
+            remaining -= phase.length();
+        }
+        // Floating point rounding might leave a tiny gap here, or overshoot slightly
+        if remaining > 0.0 && peak > 0.0 {
+            phases.push(Phase {
+                duration: remaining / peak,
+                start_speed: peak,
+                accel: 0.0,
+            });
+        }
+        phases.extend(braking);
+        SpeedProfile { phases }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::seconds(self.phases.iter().map(|p| p.duration).sum())
+    }
+
+    // How far along the profile after some time. Callers should clamp the result, in case of
+    // rounding.
+    pub fn dist_at(&self, elapsed: Duration) -> Distance {
+        let mut t = elapsed.inner_seconds().max(0.0);
+        let mut dist = 0.0;
+        for phase in &self.phases {
+            if t <= phase.duration {
+                return Distance::meters(dist + phase.dist_at(t));
+            }
+            dist += phase.length();
+            t -= phase.duration;
+        }
+        Distance::meters(dist)
+    }
+
+    pub fn speed_at(&self, elapsed: Duration) -> Speed {
+        let mut t = elapsed.inner_seconds().max(0.0);
+        for phase in &self.phases {
+            if t <= phase.duration {
+                return Speed::meters_per_second((phase.start_speed + phase.accel * t).max(0.0));
+            }
+            t -= phase.duration;
+        }
+        self.end_speed()
+    }
+
+    pub fn end_speed(&self) -> Speed {
+        Speed::meters_per_second(self.phases.last().map(|p| p.end_speed()).unwrap_or(0.0))
+    }
+}
+
+// Gipps' safe speed: the fastest a follower can go and still stop behind the leader, if the
+// leader starts braking as hard as they can right now and the follower only reacts after
+// reaction_time. The gap is from the follower's front to the leader's back, minus any buffer.
+// Decelerations are in meters per second squared, and must be positive.
+pub fn safe_following_speed(
+    gap: Distance,
+    speed: Speed,
+    leader_speed: Speed,
+    reaction_time: Duration,
+    max_decel: f64,
+    leader_max_decel: f64,
+) -> Speed {
+    let g = gap.inner_meters();
+    let v = speed.inner_meters_per_second().max(0.0);
+    let v_leader = leader_speed.inner_meters_per_second().max(0.0);
+    let t = reaction_time.inner_seconds();
+    let b = max_decel;
+    let radicand = b * b * t * t + b * (2.0 * g - v * t + v_leader * v_leader / leader_max_decel);
+    if radicand <= 0.0 {
+        return Speed::ZERO;
+    }
+    Speed::meters_per_second((radicand.sqrt() - b * t).max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_following_speed() {
+        let t = Duration::seconds(1.0);
+        let fast = Speed::meters_per_second(15.0);
+
+        // Right behind a stopped car
+        assert_eq!(
+            safe_following_speed(Distance::ZERO, fast, Speed::ZERO, t, 3.0, 3.0),
+            Speed::ZERO
+        );
+
+        // Closing in on a stopped car, the follower has to slow down more the closer they get
+        let far = safe_following_speed(Distance::meters(50.0), fast, Speed::ZERO, t, 3.0, 3.0);
+        let near = safe_following_speed(Distance::meters(10.0), fast, Speed::ZERO, t, 3.0, 3.0);
+        assert!(near < far);
+        assert!(far < fast);
+
+        // Matching the leader's speed with a reasonable gap is fine
+        let gap = Distance::meters(30.0);
+        assert!(safe_following_speed(gap, fast, fast, t, 3.0, 3.0) >= fast);
+        // A leader who brakes harder than the follower can needs more room
+        assert!(
+            safe_following_speed(gap, fast, fast, t, 3.0, 6.0)
+                < safe_following_speed(gap, fast, fast, t, 3.0, 3.0)
+        );
+    }
+}
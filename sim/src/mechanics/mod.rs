@@ -1,12 +1,13 @@
 mod car;
 mod driving;
 mod intersection;
+mod kinematics;
 mod parking;
 mod queue;
 mod walking;
 
 pub use self::driving::DrivingSimState;
 pub use self::intersection::IntersectionSimState;
-pub use self::parking::{ParkingSim, ParkingSimState};
+pub use self::parking::{allows_stay, spot_cost, ParkingSim, ParkingSimState};
 pub use self::queue::Queue;
 pub use self::walking::WalkingSimState;
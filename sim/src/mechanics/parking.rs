@@ -1,17 +1,44 @@
-use crate::{CarID, CarStatus, DrawCarInput, Event, ParkedCar, ParkingSpot, PersonID, Vehicle};
+use crate::{
+    CarID, CarStatus, DrawCarInput, Event, ParkedCar, ParkingSpot, PersonID, Vehicle, VehicleType,
+};
 use abstutil::{
     deserialize_btreemap, deserialize_multimap, serialize_btreemap, serialize_multimap, MultiMap,
     Timer,
 };
 use enum_dispatch::enum_dispatch;
-use geom::{Distance, PolyLine, Pt2D};
+use geom::{Distance, Duration, PolyLine, Pt2D};
 use map_model::{
-    BuildingID, Lane, LaneID, LaneType, Map, OffstreetParking, ParkingLotID, PathConstraints,
-    PathStep, Position, Traversable, TurnID,
+    BuildingID, Lane, LaneID, LaneType, Map, OffstreetParking, ParkingArea, ParkingLotID,
+    PathConstraints, PathStep, Position, Traversable, TurnID,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
 
+// After finding paid parking, how much farther drivers will search for something cheaper
+const MAX_EXTRA_SEARCH: Distance = Distance::const_meters(400.0);
+// What drivers would pay (per hour of parking) to avoid walking one more meter
+const CENTS_PER_METER_WALKED: f64 = 0.5;
+
+// How drivers compare spots, in cents: the hourly price, plus the walk to where they're going.
+pub fn spot_cost<P: ParkingSim + ?Sized>(
+    parking: &P,
+    spot: ParkingSpot,
+    target: BuildingID,
+    map: &Map,
+) -> f64 {
+    let walk = parking
+        .spot_to_sidewalk_pos(spot, map)
+        .pt(map)
+        .dist_to(map.get_b(target).sidewalk_pos.pt(map));
+    (spot.policy(map).cents_per_hour as f64) + CENTS_PER_METER_WALKED * walk.inner_meters()
+}
+
+// Drivers who know how long they'll stay don't park somewhere they'd overstay the time limit.
+pub fn allows_stay(spot: ParkingSpot, stay: Option<Duration>, map: &Map) -> bool {
+    stay.map(|d| !spot.policy(map).overstayed(d))
+        .unwrap_or(true)
+}
+
 #[enum_dispatch(ParkingSimState)]
 pub trait ParkingSim {
     // Returns any cars that got very abruptly evicted from existence
@@ -51,11 +78,13 @@ pub trait ParkingSim {
     // they're far away. Since they don't reserve the spot in advance, somebody else can still beat
     // them there, producing some nice, realistic churn if there's too much contention.
     // The first PathStep is the turn after start, NOT PathStep::Lane(start).
+    // Spots with a time limit shorter than the stay (if it's known) are skipped.
     fn path_to_free_parking_spot(
         &self,
         start: LaneID,
         vehicle: &Vehicle,
         target: BuildingID,
+        stay: Option<Duration>,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)>;
     fn collect_events(&mut self) -> Vec<Event>;
@@ -271,8 +300,11 @@ impl ParkingSim for NormalParkingSimState {
         self.occupants
             .remove(&p.spot)
             .expect("remove_parked_car missing from occupants");
-        self.events
-            .push(Event::CarLeftParkingSpot(p.vehicle.id, p.spot));
+        self.events.push(Event::CarLeftParkingSpot(
+            p.vehicle.id,
+            p.spot,
+            p.parked_since,
+        ));
     }
 
     fn add_parked_car(&mut self, p: ParkedCar) {
@@ -400,6 +432,12 @@ impl ParkingSim for NormalParkingSimState {
         let mut candidates = Vec::new();
 
         for l in self.driving_to_parking_lanes.get(driving_pos.lane()) {
+            // Loading zones are only for delivery trucks
+            if map.get_parking_policy(ParkingArea::Lane(*l)).loading_zone
+                && vehicle.vehicle_type != VehicleType::Truck
+            {
+                continue;
+            }
             for spot in self.onstreet_lanes[l].spots() {
                 if self.is_free(spot)
                     && driving_pos.dist_along()
@@ -515,6 +553,7 @@ impl ParkingSim for NormalParkingSimState {
         start: LaneID,
         vehicle: &Vehicle,
         target: BuildingID,
+        stay: Option<Duration>,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
         let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
@@ -524,31 +563,40 @@ impl ParkingSim for NormalParkingSimState {
         let mut queue: BinaryHeap<(Distance, LaneID)> = BinaryHeap::new();
         queue.push((Distance::ZERO, start));
 
-        while !queue.is_empty() {
-            let (dist_so_far, current) = queue.pop().unwrap();
+        // Drivers take the first free spot they find. If the first one costs money, they keep
+        // driving a little farther, then settle for whatever's cheapest, counting the walk to the
+        // target as a cost too. (Cost, spot, position, lane)
+        let mut best: Option<(f64, ParkingSpot, Position, LaneID)> = None;
+        let mut search_until: Option<Distance> = None;
+
+        while let Some((dist_so_far, current)) = queue.pop() {
+            if search_until
+                .map(|limit| -dist_so_far > limit)
+                .unwrap_or(false)
+            {
+                break;
+            }
             // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
             // opens up on the 'start' lane, but behind the car.
             if current != start {
-                // Pick the closest to the start of the lane, since that's closest to where we came
-                // from
-                if let Some((spot, pos)) = self
+                // Pick the cheapest, then the closest to the start of the lane, since that's
+                // closest to where we came from
+                if let Some((spot, pos, price)) = self
                     .get_all_free_spots(Position::start(current), vehicle, target, map)
                     .into_iter()
-                    .min_by_key(|(_, pos)| pos.dist_along())
+                    .filter(|(spot, _)| allows_stay(*spot, stay, map))
+                    .map(|(spot, pos)| (spot, pos, spot.policy(map).cents_per_hour))
+                    .min_by_key(|(_, pos, price)| (*price, pos.dist_along()))
                 {
-                    let mut steps = vec![PathStep::Lane(current)];
-                    let mut current = current;
-                    loop {
-                        if current == start {
-                            // Don't include PathStep::Lane(start)
-                            steps.pop();
-                            steps.reverse();
-                            return Some((steps, spot, pos));
-                        }
-                        let turn = backrefs[&current];
-                        steps.push(PathStep::Turn(turn));
-                        steps.push(PathStep::Lane(turn.src));
-                        current = turn.src;
+                    if price == 0 && best.is_none() {
+                        return Some((backtrack(start, current, &backrefs), spot, pos));
+                    }
+                    let cost = spot_cost(self, spot, target, map);
+                    if best.as_ref().map(|(c, _, _, _)| cost < *c).unwrap_or(true) {
+                        best = Some((cost, spot, pos, current));
+                    }
+                    if search_until.is_none() {
+                        search_until = Some(-dist_so_far + MAX_EXTRA_SEARCH);
                     }
                 }
             }
@@ -562,7 +610,7 @@ impl ParkingSim for NormalParkingSimState {
             }
         }
 
-        None
+        best.map(|(_, spot, pos, lane)| (backtrack(start, lane, &backrefs), spot, pos))
     }
 
     fn collect_events(&mut self) -> Vec<Event> {
@@ -593,6 +641,21 @@ impl ParkingSim for NormalParkingSimState {
     }
 }
 
+// The PathSteps from start to end, not including PathStep::Lane(start)
+fn backtrack(start: LaneID, end: LaneID, backrefs: &HashMap<LaneID, TurnID>) -> Vec<PathStep> {
+    let mut steps = vec![PathStep::Lane(end)];
+    let mut current = end;
+    while current != start {
+        let turn = backrefs[&current];
+        steps.push(PathStep::Turn(turn));
+        steps.push(PathStep::Lane(turn.src));
+        current = turn.src;
+    }
+    steps.pop();
+    steps.reverse();
+    steps
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct ParkingLane {
     parking_lane: LaneID,
@@ -758,8 +821,11 @@ impl ParkingSim for InfiniteParkingSimState {
         self.occupants
             .remove(&p.spot)
             .expect("remove_parked_car missing from occupants");
-        self.events
-            .push(Event::CarLeftParkingSpot(p.vehicle.id, p.spot));
+        self.events.push(Event::CarLeftParkingSpot(
+            p.vehicle.id,
+            p.spot,
+            p.parked_since,
+        ));
     }
 
     fn add_parked_car(&mut self, p: ParkedCar) {
@@ -868,6 +934,7 @@ impl ParkingSim for InfiniteParkingSimState {
         _: LaneID,
         _: &Vehicle,
         _: BuildingID,
+        _: Option<Duration>,
         _: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
         // The original building we're aiming for will always have room, unless it's located on a
@@ -143,11 +143,7 @@ impl Queue {
                     assert_eq!(bound, self.geom_len);
                     self.geom_len
                 }
-                CarState::Crossing(ref time_int, ref dist_int) => {
-                    // TODO Why percent_clamp_end? We process car updates in any order, so we might
-                    // calculate this before moving this car from Crossing to another state.
-                    dist_int.lerp(time_int.percent_clamp_end(now)).min(bound)
-                }
+                CarState::Crossing(_, _, _) => car.state.crossing_dist(now).min(bound),
                 CarState::Unparking(front, _, _) => front,
                 CarState::Parking(front, _, _) => front,
                 CarState::IdlingAtStop(front, _) => front,
@@ -276,7 +272,7 @@ fn dump_cars(
         let car = &cars[id];
         println!("- {} @ {} (length {})", id, dist, car.vehicle.length);
         match car.state {
-            CarState::Crossing(ref time_int, ref dist_int) => {
+            CarState::Crossing(ref time_int, ref dist_int, _) => {
                 println!(
                     "  Going {} .. {} during {} .. {}",
                     dist_int.start, dist_int.end, time_int.start, time_int.end
@@ -5,10 +5,10 @@ use crate::{
     PedestrianID, PersonID, Scheduler, SidewalkPOI, SidewalkSpot, TimeInterval, TransitSimState,
     TripID, TripManager, UnzoomedAgent,
 };
-use abstutil::{deserialize_multimap, serialize_multimap, MultiMap};
+use abstutil::{deserialize_multimap, serialize_multimap, Counter, MultiMap};
 use geom::{Distance, Duration, Line, PolyLine, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, DrivingSide, Map, ParkingLotID, Path, PathStep, Traversable,
+    BuildingID, BusRouteID, DrivingSide, Map, ParkingLotID, Path, PathStep, Traversable, TurnType,
     SIDEWALK_THICKNESS,
 };
 use serde::{Deserialize, Serialize};
@@ -17,6 +17,15 @@ use std::collections::{BTreeMap, BTreeSet};
 const TIME_TO_START_BIKING: Duration = Duration::const_seconds(30.0);
 const TIME_TO_FINISH_BIKING: Duration = Duration::const_seconds(45.0);
 
+// With pedestrian congestion, walking speed depends on density (people per square meter), following
+// Weidmann's fundamental diagram.
+const JAM_DENSITY: f64 = 5.4;
+const WEIDMANN_GAMMA: f64 = 1.913;
+// Even in a dense crowd, people shuffle forwards.
+const MIN_CROWDED_SPEED_FRACTION: f64 = 0.1;
+// Past this density, people wait at the entrance of a crosswalk instead of stepping onto it.
+const MAX_CROSSWALK_DENSITY: f64 = 2.0;
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct WalkingSimState {
     // BTreeMap not for deterministic simulation, but to make serialized things easier to compare.
@@ -26,15 +35,25 @@ pub struct WalkingSimState {
         deserialize_with = "deserialize_multimap"
     )]
     peds_per_traversable: MultiMap<Traversable, PedestrianID>,
+    // Only with pedestrian congestion. Keyed by the full crosswalk.
+    #[serde(
+        serialize_with = "serialize_multimap",
+        deserialize_with = "deserialize_multimap"
+    )]
+    waiting_for_room: MultiMap<Traversable, PedestrianID>,
     events: Vec<Event>,
+
+    pedestrian_congestion: bool,
 }
 
 impl WalkingSimState {
-    pub fn new() -> WalkingSimState {
+    pub fn new(pedestrian_congestion: bool) -> WalkingSimState {
         WalkingSimState {
             peds: BTreeMap::new(),
             peds_per_traversable: MultiMap::new(),
+            waiting_for_room: MultiMap::new(),
             events: Vec::new(),
+            pedestrian_congestion,
         }
     }
 
@@ -86,7 +105,20 @@ impl WalkingSimState {
                 Line::must_new(driving_pos.pt(map), params.start.sidewalk_pos.pt(map)),
                 TimeInterval::new(now, now + TIME_TO_FINISH_BIKING),
             ),
-            _ => ped.crossing_state(params.start.sidewalk_pos.dist_along(), now, map),
+            SidewalkPOI::DockingStation(ds) => PedState::FinishingBiking(
+                params.start.clone(),
+                Line::must_new(
+                    map.get_ds(ds).driving_pos.pt(map),
+                    params.start.sidewalk_pos.pt(map),
+                ),
+                TimeInterval::new(now, now + TIME_TO_FINISH_BIKING),
+            ),
+            _ => ped.crossing_state(
+                params.start.sidewalk_pos.dist_along(),
+                now,
+                map,
+                crowds(self.pedestrian_congestion, &self.peds_per_traversable),
+            ),
         };
 
         scheduler.push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
@@ -95,6 +127,7 @@ impl WalkingSimState {
             Traversable::Lane(params.start.sidewalk_pos.lane()),
             params.id,
         );
+        self.update_crowd_speed(Traversable::Lane(start_lane), now, map, scheduler);
     }
 
     pub fn get_draw_ped(
@@ -122,6 +155,7 @@ impl WalkingSimState {
         transit: &mut TransitSimState,
     ) {
         let mut ped = self.peds.get_mut(&id).unwrap();
+        let before = ped.path.current_step().as_traversable();
         match ped.state {
             PedState::Crossing(ref dist_int, _) => {
                 if ped.path.is_last_step() {
@@ -194,6 +228,43 @@ impl WalkingSimState {
                             ctx.scheduler
                                 .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
                         }
+                        SidewalkPOI::DockingStation(ds) => {
+                            if trips.ped_reached_docking_station(
+                                now,
+                                ped.id,
+                                ds,
+                                ped.total_blocked_time,
+                                ctx,
+                            ) {
+                                let pt1 = ped.goal.sidewalk_pos.pt(ctx.map);
+                                let pt2 = ctx.map.get_ds(ds).driving_pos.pt(ctx.map);
+                                ped.state = PedState::StartingToBike(
+                                    ped.goal.clone(),
+                                    Line::must_new(pt1, pt2),
+                                    TimeInterval::new(now, now + TIME_TO_START_BIKING),
+                                );
+                                ctx.scheduler
+                                    .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
+                            } else {
+                                self.peds_per_traversable
+                                    .remove(ped.path.current_step().as_traversable(), ped.id);
+                                self.peds.remove(&id);
+                            }
+                        }
+                        SidewalkPOI::RideHail(_) => {
+                            if trips.ped_reached_ride_hail_pickup(
+                                now,
+                                ped.id,
+                                ped.total_blocked_time,
+                                ctx,
+                            ) {
+                                ped.state = PedState::WaitingForRideHail(now);
+                            } else {
+                                self.peds_per_traversable
+                                    .remove(ped.path.current_step().as_traversable(), ped.id);
+                                self.peds.remove(&id);
+                            }
+                        }
                         SidewalkPOI::SuddenlyAppear => unreachable!(),
                         SidewalkPOI::DeferredParkingSpot => unreachable!(),
                     }
@@ -209,13 +280,21 @@ impl WalkingSimState {
                     }
 
                     let dist = dist_int.end;
-                    if ped.maybe_transition(
+                    if self.pedestrian_congestion
+                        && !room_on_next_step(ped, &self.peds_per_traversable, ctx.map)
+                    {
+                        // Somebody leaving the crosswalk will wake us up.
+                        self.waiting_for_room
+                            .insert(ped.path.next_step().as_traversable(), ped.id);
+                        ped.state = PedState::WaitingToTurn(dist, now);
+                    } else if ped.maybe_transition(
                         now,
                         ctx.map,
                         ctx.intersections,
                         &mut self.peds_per_traversable,
                         &mut self.events,
                         ctx.scheduler,
+                        self.pedestrian_congestion,
                     ) {
                         ctx.scheduler
                             .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
@@ -226,13 +305,19 @@ impl WalkingSimState {
                 }
             }
             PedState::WaitingToTurn(_, blocked_since) => {
-                if ped.maybe_transition(
+                if self.pedestrian_congestion
+                    && !room_on_next_step(ped, &self.peds_per_traversable, ctx.map)
+                {
+                    self.waiting_for_room
+                        .insert(ped.path.next_step().as_traversable(), ped.id);
+                } else if ped.maybe_transition(
                     now,
                     ctx.map,
                     ctx.intersections,
                     &mut self.peds_per_traversable,
                     &mut self.events,
                     ctx.scheduler,
+                    self.pedestrian_congestion,
                 ) {
                     ctx.scheduler
                         .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
@@ -240,8 +325,12 @@ impl WalkingSimState {
                 }
             }
             PedState::LeavingBuilding(b, _) => {
-                ped.state =
-                    ped.crossing_state(ctx.map.get_b(b).sidewalk_pos.dist_along(), now, ctx.map);
+                ped.state = ped.crossing_state(
+                    ctx.map.get_b(b).sidewalk_pos.dist_along(),
+                    now,
+                    ctx.map,
+                    crowds(self.pedestrian_congestion, &self.peds_per_traversable),
+                );
                 ctx.scheduler
                     .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
@@ -252,8 +341,12 @@ impl WalkingSimState {
                 self.peds.remove(&id);
             }
             PedState::LeavingParkingLot(pl, _) => {
-                ped.state =
-                    ped.crossing_state(ctx.map.get_pl(pl).sidewalk_pos.dist_along(), now, ctx.map);
+                ped.state = ped.crossing_state(
+                    ctx.map.get_pl(pl).sidewalk_pos.dist_along(),
+                    now,
+                    ctx.map,
+                    crowds(self.pedestrian_congestion, &self.peds_per_traversable),
+                );
                 ctx.scheduler
                     .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
@@ -279,31 +372,112 @@ impl WalkingSimState {
                 self.peds.remove(&id);
             }
             PedState::FinishingBiking(ref spot, _, _) => {
-                ped.state = ped.crossing_state(spot.sidewalk_pos.dist_along(), now, ctx.map);
+                ped.state = ped.crossing_state(
+                    spot.sidewalk_pos.dist_along(),
+                    now,
+                    ctx.map,
+                    crowds(self.pedestrian_congestion, &self.peds_per_traversable),
+                );
                 ctx.scheduler
                     .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
-            PedState::WaitingForBus(_, _) => unreachable!(),
+            PedState::WaitingForBus(_, _) | PedState::WaitingForRideHail(_) => unreachable!(),
+        }
+
+        // Whether they moved on to the next step or finished walking, the crowd changed.
+        let after = self
+            .peds
+            .get(&id)
+            .map(|p| p.path.current_step().as_traversable());
+        if after != Some(before) {
+            self.left_step(before, now, ctx.map, ctx.scheduler);
+            if let Some(on) = after {
+                self.update_crowd_speed(on, now, ctx.map, ctx.scheduler);
+            }
         }
     }
 
-    pub fn ped_boarded_bus(&mut self, now: Time, id: PedestrianID) {
+    pub fn ped_boarded_bus(
+        &mut self,
+        now: Time,
+        id: PedestrianID,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
         let mut ped = self.peds.remove(&id).unwrap();
+        let on = ped.path.current_step().as_traversable();
         match ped.state {
-            PedState::WaitingForBus(_, blocked_since) => {
-                self.peds_per_traversable
-                    .remove(ped.path.current_step().as_traversable(), id);
+            PedState::WaitingForBus(_, blocked_since)
+            | PedState::WaitingForRideHail(blocked_since) => {
+                self.peds_per_traversable.remove(on, id);
                 ped.total_blocked_time += now - blocked_since;
             }
             _ => unreachable!(),
         };
+        self.left_step(on, now, map, scheduler);
     }
 
-    pub fn delete_ped(&mut self, id: PedestrianID, scheduler: &mut Scheduler) {
+    pub fn delete_ped(
+        &mut self,
+        now: Time,
+        id: PedestrianID,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
         let ped = self.peds.remove(&id).unwrap();
-        self.peds_per_traversable
-            .remove(ped.path.current_step().as_traversable(), id);
+        let on = ped.path.current_step().as_traversable();
+        self.peds_per_traversable.remove(on, id);
+        if let PedState::WaitingToTurn(_, _) = ped.state {
+            self.waiting_for_room
+                .remove(ped.path.next_step().as_traversable(), id);
+        }
         scheduler.cancel(Command::UpdatePed(id));
+        self.left_step(on, now, map, scheduler);
+    }
+
+    // Somebody stepped off of this, so everybody waiting to step onto it can try again, and
+    // everybody still on it might speed up.
+    fn left_step(&mut self, on: Traversable, now: Time, map: &Map, scheduler: &mut Scheduler) {
+        wake_waiting_for_room(&mut self.waiting_for_room, on, now, scheduler);
+        self.update_crowd_speed(on, now, map, scheduler);
+    }
+
+    // With pedestrian congestion, the speed of everybody walking along a step depends on how
+    // crowded it is. When somebody steps on or off, figure out the rest of everybody's crossing
+    // again.
+    fn update_crowd_speed(
+        &mut self,
+        on: Traversable,
+        now: Time,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        if !self.pedestrian_congestion {
+            return;
+        }
+        for id in self.peds_per_traversable.get(on).clone() {
+            let ped = self.peds.get_mut(&id).unwrap();
+            let dist = match ped.state {
+                PedState::Crossing(ref dist_int, ref time_int) => {
+                    dist_int.lerp(time_int.percent_clamp_end(now))
+                }
+                _ => continue,
+            };
+            ped.state = ped.crossing_state(dist, now, map, Some(&self.peds_per_traversable));
+            scheduler.update(ped.state.get_end_time(), Command::UpdatePed(id));
+        }
+    }
+
+    // People per square meter on every sidewalk and crosswalk with somebody on it
+    pub fn get_pedestrian_density(&self, map: &Map) -> Vec<(Traversable, f64)> {
+        let mut cnt = Counter::new();
+        for ped in self.peds.values() {
+            cnt.inc(ped.path.current_step().as_traversable());
+        }
+        cnt.consume()
+            .into_iter()
+            .map(|(on, n)| (on, (n as f64) / walkable_area(on, map)))
+            .collect()
     }
 
     pub fn debug_ped(&self, id: PedestrianID) {
@@ -318,7 +492,8 @@ impl WalkingSimState {
         let p = &self.peds[&id];
         let time_spent_waiting = match p.state {
             PedState::WaitingToTurn(_, blocked_since)
-            | PedState::WaitingForBus(_, blocked_since) => now - blocked_since,
+            | PedState::WaitingForBus(_, blocked_since)
+            | PedState::WaitingForRideHail(blocked_since) => now - blocked_since,
             _ => Duration::ZERO,
         };
 
@@ -425,7 +600,8 @@ impl WalkingSimState {
                 }
                 PedState::StartingToBike(_, _, _)
                 | PedState::FinishingBiking(_, _, _)
-                | PedState::WaitingForBus(_, _) => {
+                | PedState::WaitingForBus(_, _)
+                | PedState::WaitingForRideHail(_) => {
                     // The backwards half of the sidewalk is closer to the road.
                     backwards.push((*id, dist));
                 }
@@ -521,7 +697,16 @@ struct Pedestrian {
 }
 
 impl Pedestrian {
-    fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> PedState {
+    // With pedestrian congestion, pass in everybody's current position; the speed depends on how
+    // crowded the current step is right now. WalkingSimState recalculates this whenever the crowd
+    // changes.
+    fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        map: &Map,
+        crowds: Option<&MultiMap<Traversable, PedestrianID>>,
+    ) -> PedState {
         let end_dist = if self.path.is_last_step() {
             self.goal.sidewalk_pos.dist_along()
         } else {
@@ -532,8 +717,18 @@ impl Pedestrian {
                 PathStep::Turn(t) => map.get_t(t).geom.length(),
             }
         };
+        let speed = if let Some(peds_per_traversable) = crowds {
+            let on = self.path.current_step().as_traversable();
+            let mut num_peds = peds_per_traversable.get(on).len();
+            if !peds_per_traversable.get(on).contains(&self.id) {
+                num_peds += 1;
+            }
+            crowded_speed(self.speed, (num_peds as f64) / walkable_area(on, map))
+        } else {
+            self.speed
+        };
         let dist_int = DistanceInterval::new_walking(start_dist, end_dist);
-        let time_int = TimeInterval::new(start_time, start_time + dist_int.length() / self.speed);
+        let time_int = TimeInterval::new(start_time, start_time + dist_int.length() / speed);
         PedState::Crossing(dist_int, time_int)
     }
 
@@ -549,7 +744,9 @@ impl Pedestrian {
             }
             PedState::StartingToBike(ref spot, _, _) => spot.sidewalk_pos.dist_along(),
             PedState::FinishingBiking(ref spot, _, _) => spot.sidewalk_pos.dist_along(),
-            PedState::WaitingForBus(_, _) => self.goal.sidewalk_pos.dist_along(),
+            PedState::WaitingForBus(_, _) | PedState::WaitingForRideHail(_) => {
+                self.goal.sidewalk_pos.dist_along()
+            }
         }
     }
 
@@ -637,9 +834,9 @@ impl Pedestrian {
                     .unwrap_or(line.pt1()),
                 line.angle(),
             ),
-            PedState::WaitingForBus(_, _) => {
+            PedState::WaitingForBus(_, _) | PedState::WaitingForRideHail(_) => {
                 let (pt, angle) = self.goal.sidewalk_pos.pt_and_angle(map);
-                // Stand on the far side of the sidewalk (by the bus stop), facing the road
+                // Stand on the far side of the sidewalk (by the bus stop or curb), facing the road
                 (
                     pt.project_away(SIDEWALK_THICKNESS / 4.0, angle.rotate_degs(angle_offset)),
                     angle.rotate_degs(-angle_offset),
@@ -670,6 +867,7 @@ impl Pedestrian {
         peds_per_traversable: &mut MultiMap<Traversable, PedestrianID>,
         events: &mut Vec<Event>,
         scheduler: &mut Scheduler,
+        pedestrian_congestion: bool,
     ) -> bool {
         if let PathStep::Turn(t) = self.path.next_step() {
             if !intersections.maybe_start_turn(
@@ -692,7 +890,12 @@ impl Pedestrian {
             PathStep::ContraflowLane(l) => map.get_l(l).length(),
             PathStep::Turn(_) => Distance::ZERO,
         };
-        self.state = self.crossing_state(start_dist, now, map);
+        self.state = self.crossing_state(
+            start_dist,
+            now,
+            map,
+            crowds(pedestrian_congestion, peds_per_traversable),
+        );
         peds_per_traversable.insert(self.path.current_step().as_traversable(), self.id);
         events.push(Event::AgentEntersTraversable(
             AgentID::Pedestrian(self.id),
@@ -715,6 +918,8 @@ enum PedState {
     StartingToBike(SidewalkSpot, Line, TimeInterval),
     FinishingBiking(SidewalkSpot, Line, TimeInterval),
     WaitingForBus(BusRouteID, Time),
+    // The Time is blocked_since
+    WaitingForRideHail(Time),
 }
 
 impl PedState {
@@ -729,10 +934,77 @@ impl PedState {
             PedState::StartingToBike(_, _, ref time_int) => time_int.end,
             PedState::FinishingBiking(_, _, ref time_int) => time_int.end,
             PedState::WaitingForBus(_, _) => unreachable!(),
+            PedState::WaitingForRideHail(_) => unreachable!(),
         }
     }
 }
 
+fn crowds(
+    pedestrian_congestion: bool,
+    peds_per_traversable: &MultiMap<Traversable, PedestrianID>,
+) -> Option<&MultiMap<Traversable, PedestrianID>> {
+    if pedestrian_congestion {
+        Some(peds_per_traversable)
+    } else {
+        None
+    }
+}
+
+// In square meters. Crosswalks are as wide as a sidewalk.
+fn walkable_area(on: Traversable, map: &Map) -> f64 {
+    let area = match on {
+        Traversable::Lane(l) => {
+            let lane = map.get_l(l);
+            lane.length().inner_meters() * lane.width.inner_meters()
+        }
+        Traversable::Turn(t) => {
+            map.get_t(t).geom.length().inner_meters() * SIDEWALK_THICKNESS.inner_meters()
+        }
+    };
+    // Tiny corners shouldn't look impossibly dense
+    area.max(1.0)
+}
+
+fn crowded_speed(free_speed: Speed, density: f64) -> Speed {
+    if density >= JAM_DENSITY {
+        return free_speed * MIN_CROWDED_SPEED_FRACTION;
+    }
+    let pct = 1.0 - (-WEIDMANN_GAMMA * (1.0 / density - 1.0 / JAM_DENSITY)).exp();
+    free_speed * pct.max(MIN_CROWDED_SPEED_FRACTION).min(1.0)
+}
+
+// Only crosswalks have a capacity. Everywhere else, crowds just slow people down; limiting how many
+// people fit on a sidewalk is out of scope, since queueing along it could gridlock.
+fn room_on_next_step(
+    ped: &Pedestrian,
+    peds_per_traversable: &MultiMap<Traversable, PedestrianID>,
+    map: &Map,
+) -> bool {
+    let next = ped.path.next_step().as_traversable();
+    match next {
+        Traversable::Turn(t) if map.get_t(t).turn_type == TurnType::Crosswalk => {
+            let num_peds = peds_per_traversable.get(next).len();
+            num_peds == 0
+                || ((num_peds + 1) as f64) / walkable_area(next, map) <= MAX_CROSSWALK_DENSITY
+        }
+        _ => true,
+    }
+}
+
+// Somebody just left this step, so everybody waiting to step onto it can try again.
+fn wake_waiting_for_room(
+    waiting_for_room: &mut MultiMap<Traversable, PedestrianID>,
+    on: Traversable,
+    now: Time,
+    scheduler: &mut Scheduler,
+) {
+    for id in waiting_for_room.get(on).clone() {
+        waiting_for_room.remove(on, id);
+        // Use update; the intersection might also be waking up somebody waiting to turn.
+        scheduler.update(now, Command::UpdatePed(id));
+    }
+}
+
 // The crowds returned here may have low/high values extending up to radius past the real geometry.
 fn find_crowds(
     input: Vec<(PedestrianID, Distance)>,
@@ -777,3 +1049,41 @@ fn find_crowds(
 
     (loners, crowds)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crowded_speed() {
+        let free = Speed::meters_per_second(1.34);
+        // Nearly nobody around
+        assert!(crowded_speed(free, 0.01) > free * 0.99);
+        // Past the jam density, people still shuffle forwards
+        assert_eq!(
+            crowded_speed(free, JAM_DENSITY),
+            free * MIN_CROWDED_SPEED_FRACTION
+        );
+        assert_eq!(
+            crowded_speed(free, 2.0 * JAM_DENSITY),
+            free * MIN_CROWDED_SPEED_FRACTION
+        );
+
+        // Denser crowds are never faster
+        let mut ok = true;
+        let mut last = free;
+        for i in 1..=60 {
+            let density = (i as f64) / 10.0;
+            let speed = crowded_speed(free, density);
+            if speed > last || speed < free * MIN_CROWDED_SPEED_FRACTION {
+                println!(
+                    "At density {}, speed is {} (previously {})",
+                    density, speed, last
+                );
+                ok = false;
+            }
+            last = speed;
+        }
+        assert!(ok);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::scheduler::{Command, CommandType};
+use crate::Event;
+use geom::Time;
+use serde::{Deserialize, Serialize};
+
+// Once a trace has this many steps, only keep every other one
+const MAX_STEPS: usize = 1_000_000;
+
+// The simulation is supposed to be deterministic, given the same map, scenario, and RNG seed. To
+// check that, record a trace of the commands the scheduler runs and a rolling hash of every
+// command and every event it produces. Two runs (or one run and a trace saved earlier) can then be
+// compared to find where they disagree.
+//
+// The hash covers the binary serialization, using FNV, so it doesn't depend on the build or Rust
+// version. Long runs don't keep every step; once there are too many, only every 2nd, 4th, and so
+// on are kept, so a divergence is narrowed down to a range of steps.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplayTrace {
+    // Only the steps that're a multiple of sample_every
+    steps: Vec<Step>,
+    sample_every: usize,
+    num_steps: usize,
+    // Covers everything so far
+    hash: u64,
+    // Covers events that happen before the first command
+    initial_hash: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct Step {
+    // Which command, counting from 0
+    idx: usize,
+    time: Time,
+    cmd: CommandType,
+    // Covers this command, everything before it, and the events it produced
+    hash: u64,
+}
+
+pub struct Divergence {
+    // The runs agree through this step. None if they disagree from the start.
+    pub last_match: Option<usize>,
+    // And disagree by this one
+    pub step: usize,
+    // What each run was doing at that step. None if the run had already ended.
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl ReplayTrace {
+    pub(crate) fn new() -> ReplayTrace {
+        ReplayTrace {
+            steps: Vec::new(),
+            sample_every: 1,
+            num_steps: 0,
+            hash: FNV_OFFSET,
+            initial_hash: FNV_OFFSET,
+        }
+    }
+
+    pub(crate) fn command(&mut self, time: Time, cmd: &Command) {
+        let idx = self.num_steps;
+        self.num_steps += 1;
+        self.hash = mix(self.hash, time, cmd);
+        if idx % self.sample_every == 0 {
+            self.steps.push(Step {
+                idx,
+                time,
+                cmd: cmd.to_type(),
+                hash: self.hash,
+            });
+            if self.steps.len() > MAX_STEPS {
+                self.sample_every *= 2;
+                let every = self.sample_every;
+                self.steps.retain(|s| s.idx % every == 0);
+            }
+        }
+    }
+
+    pub(crate) fn event(&mut self, time: Time, ev: &Event) {
+        self.hash = mix(self.hash, time, ev);
+        if self.num_steps == 0 {
+            self.initial_hash = self.hash;
+        } else if let Some(step) = self.steps.last_mut() {
+            if step.idx == self.num_steps - 1 {
+                step.hash = self.hash;
+            }
+        }
+    }
+
+    pub fn num_steps(&self) -> usize {
+        self.num_steps
+    }
+
+    // Treats self as the expected run. Returns None if both traces are identical.
+    pub fn first_divergence(&self, actual: &ReplayTrace) -> Option<Divergence> {
+        if self.initial_hash != actual.initial_hash {
+            return Some(Divergence {
+                last_match: None,
+                step: 0,
+                expected: Some("events before the first command".to_string()),
+                actual: Some("different events before the first command".to_string()),
+            });
+        }
+
+        // The traces might keep different steps, so only compare the ones both have.
+        let mut last_match = None;
+        for expected in &self.steps {
+            if expected.idx >= actual.num_steps {
+                return Some(Divergence {
+                    last_match,
+                    step: expected.idx,
+                    expected: Some(expected.describe()),
+                    actual: None,
+                });
+            }
+            if let Ok(i) = actual.steps.binary_search_by_key(&expected.idx, |s| s.idx) {
+                if &actual.steps[i] != expected {
+                    return Some(Divergence {
+                        last_match,
+                        step: expected.idx,
+                        expected: Some(expected.describe()),
+                        actual: Some(actual.steps[i].describe()),
+                    });
+                }
+                last_match = Some(expected.idx);
+            }
+        }
+        if self.num_steps != actual.num_steps || self.hash != actual.hash {
+            let step = self.num_steps.min(actual.num_steps);
+            return Some(Divergence {
+                last_match,
+                step,
+                expected: if step < self.num_steps {
+                    Some("more steps".to_string())
+                } else {
+                    Some(format!("the end (hash {:x})", self.hash))
+                },
+                actual: if step < actual.num_steps {
+                    Some("more steps".to_string())
+                } else {
+                    Some(format!("the end (hash {:x})", actual.hash))
+                },
+            });
+        }
+        None
+    }
+}
+
+impl Step {
+    fn describe(&self) -> String {
+        // CommandType identifies the agent or intersection.
+        format!("{}: {:?} (hash {:x})", self.time, self.cmd, self.hash)
+    }
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.last_match {
+            Some(idx) if idx + 1 < self.step => writeln!(
+                f,
+                "Runs diverge somewhere after step {}, by step {}",
+                idx, self.step
+            )?,
+            _ => writeln!(f, "Runs diverge at step {}", self.step)?,
+        }
+        writeln!(
+            f,
+            "  expected: {}",
+            self.expected.as_deref().unwrap_or("the run already ended")
+        )?;
+        write!(
+            f,
+            "  actual: {}",
+            self.actual.as_deref().unwrap_or("the run already ended")
+        )
+    }
+}
+
+// 64-bit FNV-1a (http://www.isthe.com/chongo/tech/comp/fnv/)
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn mix<T: Serialize>(prev: u64, time: Time, x: &T) -> u64 {
+    let mut hasher = FnvHasher(prev);
+    abstutil::serialize_binary_into(&mut hasher, &time);
+    abstutil::serialize_binary_into(&mut hasher, x);
+    hasher.0
+}
+
+// Serialized bytes go straight into the hash, without building up a buffer.
+struct FnvHasher(u64);
+
+impl std::io::Write for FnvHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for byte in buf {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{
+    CarID, Command, CreateCar, PersonID, Router, Scheduler, TripID, Vehicle, VehicleSpec,
+    VehicleType, MIN_CAR_LENGTH,
+};
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Time};
+use map_model::{Map, Path, PathConstraints, PathRequest, Position, RoutingProfile};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+// How long a vehicle waits at the curb while somebody gets in
+pub const TIME_TO_BOARD: Duration = Duration::const_seconds(30.0);
+// Unless SimOptions say otherwise, have one vehicle for this many ride-hailing trips
+const TRIPS_PER_VEHICLE: usize = 10;
+// While requests are waiting for a vehicle, look again this often, in case a vehicle that was
+// blocked from reaching them has moved.
+const RETRY_DISPATCH: Duration = Duration::const_seconds(60.0);
+// Give up on a request if nobody's been dispatched after this long
+pub const MAX_WAIT: Duration = Duration::const_seconds(30.0 * 60.0);
+
+// How to pick which idle vehicle answers a request
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum RideHailDispatcher {
+    // The idle vehicle closest to the pickup, as the crow flies
+    NearestIdle,
+    // The vehicle that's been idle the longest, spreading work over the fleet
+    LongestIdle,
+}
+
+impl RideHailDispatcher {
+    // Every idle vehicle, best match first
+    fn rank(
+        self,
+        pickup: Position,
+        mut idle: Vec<(CarID, Position, Time)>,
+        map: &Map,
+    ) -> Vec<CarID> {
+        match self {
+            RideHailDispatcher::NearestIdle => {
+                let pt = pickup.pt(map);
+                idle.sort_by_key(|(_, pos, _)| pos.pt(map).dist_to(pt));
+            }
+            RideHailDispatcher::LongestIdle => {
+                idle.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+            }
+        }
+        idle.into_iter().map(|(id, _, _)| id).collect()
+    }
+}
+
+// Positions are on driving lanes.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RideRequest {
+    pub trip: TripID,
+    pub person: PersonID,
+    pub pickup: Position,
+    pub dropoff: Position,
+    pub requested_at: Time,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct FleetVehicle {
+    vehicle: Vehicle,
+    state: FleetState,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+enum FleetState {
+    // Waiting off to the side somewhere since some time. Not simulated until it's dispatched.
+    Idle(Position, Time),
+    // Driving empty to a pickup
+    Deadheading(RideRequest),
+    // Stopped at the pickup while the passenger gets in. The router then takes them to the
+    // dropoff.
+    Boarding(RideRequest, Router),
+    Occupied(RideRequest),
+}
+
+pub enum RideHailStop {
+    Pickup(RideRequest),
+    Dropoff(RideRequest),
+}
+
+// A fleet of vehicles that nobody owns, driving people from a pickup to a dropoff on request. Like
+// TransitSimState, this tracks what each vehicle is doing; TripManager handles the passengers.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RideHailSimState {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, FleetVehicle>,
+    // Nobody's been dispatched for these yet, oldest first
+    pending: VecDeque<RideRequest>,
+    dispatcher: RideHailDispatcher,
+    // If None, size the fleet from demand
+    fleet_size: Option<usize>,
+}
+
+impl RideHailSimState {
+    pub fn new(fleet_size: Option<usize>, dispatcher: RideHailDispatcher) -> RideHailSimState {
+        RideHailSimState {
+            vehicles: BTreeMap::new(),
+            pending: VecDeque::new(),
+            dispatcher,
+            fleet_size,
+        }
+    }
+
+    pub fn fleet_size(&self, num_trips: usize) -> usize {
+        self.fleet_size
+            .unwrap_or((num_trips + TRIPS_PER_VEHICLE - 1) / TRIPS_PER_VEHICLE)
+    }
+
+    // Spread the idle fleet evenly over the map.
+    pub fn seed_fleet(&mut self, ids: Vec<CarID>, map: &Map) {
+        let lanes: Vec<_> = map
+            .all_lanes()
+            .iter()
+            .filter(|l| {
+                PathConstraints::Car.can_use(l, map)
+                    && !l.driving_blackhole
+                    && l.length() > 2.0 * MIN_CAR_LENGTH
+            })
+            .collect();
+        if lanes.is_empty() {
+            return;
+        }
+        let n = ids.len();
+        for (idx, id) in ids.into_iter().enumerate() {
+            let lane = lanes[idx * lanes.len() / n];
+            let (max_accel, max_decel) = VehicleType::Car.default_accel_decel();
+            self.vehicles.insert(
+                id,
+                FleetVehicle {
+                    vehicle: VehicleSpec {
+                        vehicle_type: VehicleType::Car,
+                        length: MIN_CAR_LENGTH,
+                        max_speed: None,
+                        max_accel,
+                        max_decel,
+                    }
+                    .make(id, None),
+                    state: FleetState::Idle(
+                        Position::new(lane.id, lane.length() / 2.0),
+                        Time::START_OF_DAY,
+                    ),
+                },
+            );
+        }
+    }
+
+    pub fn has_fleet(&self) -> bool {
+        !self.vehicles.is_empty()
+    }
+
+    pub fn is_ride_hail(&self, id: CarID) -> bool {
+        self.vehicles.contains_key(&id)
+    }
+
+    // Vehicles boarding or carrying a passenger
+    pub fn num_occupied(&self) -> usize {
+        self.vehicles
+            .values()
+            .filter(|v| match v.state {
+                FleetState::Boarding(_, _) | FleetState::Occupied(_) => true,
+                FleetState::Idle(_, _) | FleetState::Deadheading(_) => false,
+            })
+            .count()
+    }
+
+    pub fn request_ride(&mut self, req: RideRequest) {
+        self.pending.push_back(req);
+    }
+
+    // Match waiting requests to idle vehicles, oldest request first. Returns (who, which vehicle,
+    // how far it has to drive empty) for every dispatch.
+    pub fn dispatch(
+        &mut self,
+        now: Time,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) -> Vec<(PersonID, CarID, Distance)> {
+        let mut dispatched = Vec::new();
+        let mut still_pending = VecDeque::new();
+        while let Some(req) = self.pending.pop_front() {
+            let idle: Vec<(CarID, Position, Time)> = self
+                .vehicles
+                .iter()
+                .filter_map(|(id, v)| match v.state {
+                    FleetState::Idle(pos, since) => Some((*id, pos, since)),
+                    _ => None,
+                })
+                .collect();
+            if idle.is_empty() {
+                still_pending.push_back(req);
+                still_pending.extend(self.pending.drain(..));
+                break;
+            }
+
+            let mut found = None;
+            for id in self.dispatcher.rank(req.pickup, idle, map) {
+                let start = match self.vehicles[&id].state {
+                    FleetState::Idle(pos, _) => pos,
+                    _ => unreachable!(),
+                };
+                if let Some((path_req, path)) = pathfind(start, req.pickup, map) {
+                    found = Some((id, start, path_req, path));
+                    break;
+                }
+            }
+            if let Some((id, start, path_req, path)) = found {
+                let deadhead = path.total_length();
+                let v = self.vehicles.get_mut(&id).unwrap();
+                scheduler.push(
+                    now,
+                    Command::SpawnCar(
+                        CreateCar {
+                            vehicle: v.vehicle.clone(),
+                            router: Router::ride_hail(id, path, req.pickup.dist_along()),
+                            req: path_req,
+                            start_dist: start.dist_along(),
+                            maybe_parked_car: None,
+                            trip_and_person: None,
+                            maybe_route: None,
+                        },
+                        true,
+                    ),
+                );
+                dispatched.push((req.person, id, deadhead));
+                v.state = FleetState::Deadheading(req);
+            } else {
+                // No idle vehicle can reach this pickup right now. Maybe one that frees up later
+                // can.
+                still_pending.push_back(req);
+            }
+        }
+        self.pending = still_pending;
+
+        // Nothing else might happen to free up a vehicle, so check again later. Don't wait past
+        // the point where the oldest request gives up.
+        if let Some(oldest) = self.pending.front() {
+            let retry_at = (now + RETRY_DISPATCH).min((oldest.requested_at + MAX_WAIT).max(now));
+            scheduler.update(retry_at, Command::RetryRideHail);
+        }
+        dispatched
+    }
+
+    // Removes and returns every request that's been waiting for a vehicle for too long.
+    pub fn expire_requests(&mut self, now: Time) -> Vec<RideRequest> {
+        let (expired, still_pending): (Vec<RideRequest>, VecDeque<RideRequest>) = self
+            .pending
+            .drain(..)
+            .partition(|req| now - req.requested_at >= MAX_WAIT);
+        self.pending = still_pending;
+        expired
+    }
+
+    // The vehicle reached the end of its current path.
+    pub fn vehicle_arrived(&self, id: CarID) -> RideHailStop {
+        match self.vehicles[&id].state {
+            FleetState::Deadheading(ref req) => RideHailStop::Pickup(req.clone()),
+            FleetState::Occupied(ref req) => RideHailStop::Dropoff(req.clone()),
+            FleetState::Idle(_, _) | FleetState::Boarding(_, _) => unreachable!(),
+        }
+    }
+
+    pub fn start_boarding(&mut self, id: CarID, router: Router) {
+        let v = self.vehicles.get_mut(&id).unwrap();
+        let req = match v.state {
+            FleetState::Deadheading(ref req) => req.clone(),
+            _ => unreachable!(),
+        };
+        v.state = FleetState::Boarding(req, router);
+    }
+
+    // Returns the route to the dropoff, or None if this isn't a ride-hail vehicle.
+    pub fn vehicle_departed(&mut self, id: CarID) -> Option<Router> {
+        let v = self.vehicles.get_mut(&id)?;
+        let (req, router) = match v.state {
+            FleetState::Boarding(ref req, ref router) => (req.clone(), router.clone()),
+            _ => unreachable!(),
+        };
+        v.state = FleetState::Occupied(req);
+        Some(router)
+    }
+
+    // The vehicle leaves the map here, until it's dispatched again. This also covers giving up on
+    // a pickup.
+    pub fn vehicle_idle(&mut self, now: Time, id: CarID, pos: Position, map: &Map) {
+        let v = self.vehicles.get_mut(&id).unwrap();
+        // Make sure it can spawn here again later
+        let pos = Position::new(
+            pos.lane(),
+            pos.dist_along()
+                .max(v.vehicle.length)
+                .min(map.get_l(pos.lane()).length()),
+        );
+        v.state = FleetState::Idle(pos, now);
+    }
+}
+
+// Vehicles don't loop around the block to reach something behind them on the same lane.
+pub fn pathfind(start: Position, end: Position, map: &Map) -> Option<(PathRequest, Path)> {
+    if start.lane() == end.lane() && start.dist_along() >= end.dist_along() {
+        return None;
+    }
+    let req = PathRequest {
+        start,
+        end,
+        constraints: PathConstraints::Car,
+        profile: RoutingProfile::Fastest,
+    };
+    let path = map.pathfind(req.clone())?;
+    Some((req, path))
+}
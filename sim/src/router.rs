@@ -1,16 +1,24 @@
-use crate::mechanics::Queue;
+use crate::mechanics::{allows_stay, spot_cost, Queue};
 use crate::{
-    AlertLocation, CarID, Event, ParkingSim, ParkingSimState, ParkingSpot, PersonID, SidewalkSpot,
-    TripID, TripPhaseType, Vehicle, VehicleType,
+    AlertLocation, CarID, Event, ParkingSim, ParkingSimState, ParkingSpot, PersonID,
+    RoadTravelTimes, SidewalkSpot, TripID, TripPhaseType, Vehicle, VehicleType,
 };
-use geom::Distance;
+use geom::{Distance, Duration, Time};
 use map_model::{
-    BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
-    Position, Traversable, TurnID,
+    BuildingID, IntersectionID, Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
+    Position, RoutingProfile, Traversable, TurnID,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+// Delivery trucks only pull into a free spot in a loading zone this close to the building they're
+// serving. Otherwise they double-park.
+const LOADING_ZONE_RADIUS: Distance = Distance::const_meters(30.0);
+// Drivers only bother switching routes if it saves at least this much time
+const REROUTE_THRESHOLD: Duration = Duration::const_seconds(60.0);
+// After searching for a new route, drivers stick with their choice for at least this long
+pub const REROUTE_COOLDOWN: Duration = Duration::const_seconds(180.0);
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Router {
     // Front is always the current step
@@ -26,7 +34,9 @@ pub enum ActionAtEnd {
     GotoLaneEnd,
     StopBiking(SidewalkSpot),
     BusAtStop,
+    RideHailAtStop,
     GiveUpOnParking,
+    DoublePark(BuildingID),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -38,6 +48,9 @@ enum Goal {
         // No parking available at all!
         stuck_end_dist: Option<Distance>,
         started_looking: bool,
+        // How long the driver will leave the car here, if they drive again later. Counted from
+        // when they set off, so it's a slight overestimate.
+        stay: Option<Duration>,
     },
     EndAtBorder {
         end_dist: Distance,
@@ -49,6 +62,18 @@ enum Goal {
     FollowBusRoute {
         end_dist: Distance,
     },
+    // Stop at the curb to pick up or drop off a ride-hail passenger
+    RideHail {
+        end_dist: Distance,
+    },
+    // Stop at a building to make a delivery, ideally using a nearby on-street spot as a loading
+    // zone
+    Deliver {
+        target: BuildingID,
+        end_dist: Distance,
+        loading_zone: Option<ParkingSpot>,
+        started_looking: bool,
+    },
 }
 
 impl Router {
@@ -76,7 +101,7 @@ impl Router {
         }
     }
 
-    pub fn park_near(owner: CarID, path: Path, bldg: BuildingID) -> Router {
+    pub fn park_near(owner: CarID, path: Path, bldg: BuildingID, stay: Option<Duration>) -> Router {
         Router {
             path,
             goal: Goal::ParkNearBuilding {
@@ -84,6 +109,7 @@ impl Router {
                 spot: None,
                 stuck_end_dist: None,
                 started_looking: false,
+                stay,
             },
             owner,
         }
@@ -105,6 +131,27 @@ impl Router {
         }
     }
 
+    pub fn ride_hail(owner: CarID, path: Path, end_dist: Distance) -> Router {
+        Router {
+            path,
+            goal: Goal::RideHail { end_dist },
+            owner,
+        }
+    }
+
+    pub fn deliver(owner: CarID, path: Path, target: BuildingID, end_dist: Distance) -> Router {
+        Router {
+            path,
+            goal: Goal::Deliver {
+                target,
+                end_dist,
+                loading_zone: None,
+                started_looking: false,
+            },
+            owner,
+        }
+    }
+
     pub fn head(&self) -> Traversable {
         self.path.current_step().as_traversable()
     }
@@ -137,6 +184,8 @@ impl Router {
             } => stuck_end_dist.unwrap_or_else(|| spot.unwrap().1),
             Goal::BikeThenStop { ref goal } => goal.sidewalk_pos.dist_along(),
             Goal::FollowBusRoute { end_dist } => end_dist,
+            Goal::RideHail { end_dist } => end_dist,
+            Goal::Deliver { end_dist, .. } => end_dist,
         }
     }
 
@@ -205,6 +254,7 @@ impl Router {
                 ref mut stuck_end_dist,
                 target,
                 ref mut started_looking,
+                stay,
             } => {
                 if let Some(d) = stuck_end_dist {
                     if *d == front {
@@ -221,32 +271,32 @@ impl Router {
                 if need_new_spot {
                     *started_looking = true;
                     let current_lane = self.path.current_step().as_lane();
-                    let candidates = parking.get_all_free_spots(
-                        Position::new(current_lane, front),
-                        vehicle,
-                        target,
-                        map,
-                    );
-                    let best =
-                        if let Some((driving_pos, _)) = map.get_b(target).driving_connection(map) {
-                            if driving_pos.lane() == current_lane {
-                                let target_dist = driving_pos.dist_along();
-                                // Closest to the building
-                                candidates
-                                    .into_iter()
-                                    .min_by_key(|(_, pos)| (pos.dist_along() - target_dist).abs())
-                            } else {
-                                // Closest to the road endpoint, I guess
-                                candidates
-                                    .into_iter()
-                                    .min_by_key(|(_, pos)| pos.dist_along())
-                            }
-                        } else {
-                            // Closest to the road endpoint, I guess
-                            candidates
-                                .into_iter()
-                                .min_by_key(|(_, pos)| pos.dist_along())
-                        };
+                    let candidates = parking
+                        .get_all_free_spots(
+                            Position::new(current_lane, front),
+                            vehicle,
+                            target,
+                            map,
+                        )
+                        .into_iter()
+                        .filter(|(spot, _)| allows_stay(*spot, stay, map));
+                    let best = if map
+                        .get_b(target)
+                        .driving_connection(map)
+                        .map(|(driving_pos, _)| driving_pos.lane() == current_lane)
+                        .unwrap_or(false)
+                    {
+                        // Trade off the price against the walk to the building
+                        candidates
+                            .map(|(spot, pos)| (spot, pos, spot_cost(parking, spot, target, map)))
+                            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap())
+                            .map(|(spot, pos, _)| (spot, pos))
+                    } else {
+                        // The cheapest, then the closest to the road endpoint, I guess
+                        candidates.min_by_key(|(spot, pos)| {
+                            (spot.policy(map).cents_per_hour, pos.dist_along())
+                        })
+                    };
                     if let Some((new_spot, new_pos)) = best {
                         if let Some((t, p)) = trip_and_person {
                             events.push(Event::TripPhaseStarting(
@@ -256,6 +306,7 @@ impl Router {
                                     start: Position::new(current_lane, front),
                                     end: new_pos,
                                     constraints: PathConstraints::Car,
+                                    profile: RoutingProfile::Fastest,
                                 }),
                                 TripPhaseType::Parking,
                             ));
@@ -264,8 +315,8 @@ impl Router {
                         assert!(new_pos.dist_along() >= front);
                         *spot = Some((new_spot, new_pos.dist_along()));
                     } else {
-                        if let Some((new_path_steps, new_spot, new_pos)) =
-                            parking.path_to_free_parking_spot(current_lane, vehicle, target, map)
+                        if let Some((new_path_steps, new_spot, new_pos)) = parking
+                            .path_to_free_parking_spot(current_lane, vehicle, target, stay, map)
                         {
                             assert!(!new_path_steps.is_empty());
                             for step in new_path_steps {
@@ -282,6 +333,7 @@ impl Router {
                                         start: Position::new(current_lane, front),
                                         end: new_pos,
                                         constraints: PathConstraints::Car,
+                                        profile: RoutingProfile::Fastest,
                                     }),
                                     TripPhaseType::Parking,
                                 ));
@@ -323,6 +375,53 @@ impl Router {
                     None
                 }
             }
+            Goal::RideHail { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::RideHailAtStop)
+                } else {
+                    None
+                }
+            }
+            Goal::Deliver {
+                target,
+                ref mut end_dist,
+                ref mut loading_zone,
+                ref mut started_looking,
+            } => {
+                if !*started_looking {
+                    *started_looking = true;
+                    let current_lane = self.path.current_step().as_lane();
+                    let target_dist = *end_dist;
+                    if let Some((spot, pos)) = parking
+                        .get_all_free_spots(
+                            Position::new(current_lane, front),
+                            vehicle,
+                            target,
+                            map,
+                        )
+                        .into_iter()
+                        .filter(|(spot, pos)| {
+                            matches!(spot, ParkingSpot::Onstreet(_, _))
+                                && spot.policy(map).loading_zone
+                                && (pos.dist_along() - target_dist).abs() <= LOADING_ZONE_RADIUS
+                        })
+                        .min_by_key(|(_, pos)| (pos.dist_along() - target_dist).abs())
+                    {
+                        assert_eq!(pos.lane(), current_lane);
+                        *loading_zone = Some(spot);
+                        *end_dist = pos.dist_along();
+                    }
+                }
+
+                if *end_dist != front {
+                    return None;
+                }
+                match loading_zone {
+                    Some(spot) if parking.is_free(*spot) => Some(ActionAtEnd::StartParking(*spot)),
+                    // Nothing free, or somebody else took the spot in the meantime
+                    _ => Some(ActionAtEnd::DoublePark(target)),
+                }
+            }
         }
     }
 
@@ -422,6 +521,149 @@ impl Router {
         self.path.modify_step(3, PathStep::Turn(turn2.id), map);
     }
 
+    // Adjacent lanes going the same way that the vehicle could merge into mid-block, along with
+    // the turn from there to the same next lane as the current path.
+    pub fn mid_block_lanechange_options(
+        &self,
+        map: &Map,
+        handle_uber_turns: bool,
+    ) -> Vec<(LaneID, TurnID)> {
+        if handle_uber_turns
+            && (self.path.about_to_start_ut().is_some()
+                || self.path.currently_inside_ut().is_some())
+        {
+            return Vec::new();
+        }
+        let (current_lane, next_lane) = {
+            let steps = self.path.get_steps();
+            if steps.len() < 3 {
+                return Vec::new();
+            }
+            match (steps[0], steps[2]) {
+                (PathStep::Lane(l1), PathStep::Lane(l2)) => (l1, l2),
+                _ => {
+                    return Vec::new();
+                }
+            }
+        };
+
+        let parent = map.get_parent(current_lane);
+        let lanes = parent.lanes_ltr();
+        let offset = parent.offset(current_lane);
+        let dir = parent.dir(current_lane);
+        let constraints = self.owner.1.to_constraints();
+        let mut options = Vec::new();
+        for idx in vec![offset.checked_sub(1), Some(offset + 1)]
+            .into_iter()
+            .flatten()
+        {
+            if let Some((l, d, _)) = lanes.get(idx) {
+                if *d != dir || !constraints.can_use(map.get_l(*l), map) {
+                    continue;
+                }
+                if let Some(turn) = map.maybe_get_t(TurnID {
+                    parent: map.get_l(*l).dst_i,
+                    src: *l,
+                    dst: next_lane,
+                }) {
+                    options.push((*l, turn.id));
+                }
+            }
+        }
+        options
+    }
+
+    // The caller is responsible for moving the vehicle between queues.
+    pub fn change_lanes(&mut self, lane: LaneID, turn: TurnID, map: &Map) {
+        self.path.change_current_lane(lane, turn, map);
+    }
+
+    // Called when a driver who knows about current traffic turns onto a new lane. If a route from
+    // here that's faster by REROUTE_THRESHOLD exists given recent travel times, switch to it.
+    // Returns true if this searched for a new route, whether or not it switched.
+    pub fn maybe_reroute(
+        &mut self,
+        travel_times: &RoadTravelTimes,
+        now: Time,
+        map: &Map,
+        events: &mut Vec<Event>,
+    ) -> bool {
+        // Only people driving themselves somewhere. Once they're hunting for parking, they're
+        // committed.
+        match self.goal {
+            Goal::ParkNearBuilding {
+                started_looking: false,
+                ..
+            }
+            | Goal::EndAtBorder { .. } => {}
+            _ => {
+                return false;
+            }
+        }
+        if self.owner.1 != VehicleType::Car
+            || self.last_step()
+            || self.path.currently_inside_ut().is_some()
+            || self.path.approaching_uber_turn()
+        {
+            return false;
+        }
+        let start = self.head().as_lane();
+        let end = self.path.last_step().as_lane();
+        // The search avoids zones entirely
+        if start == end
+            || map.get_parent(start).get_zone(map).is_some()
+            || map.get_parent(end).get_zone(map).is_some()
+        {
+            return false;
+        }
+
+        // Drivers don't expect to go faster than the speed limit, even if somebody did recently
+        let lane_time = |lane: &Lane| {
+            let speed_limit_time = lane.length() / map.get_r(lane.parent).speed_limit;
+            travel_times
+                .get(lane.get_directed_parent(map), now)
+                .map(|dt| dt.max(speed_limit_time))
+                .unwrap_or(speed_limit_time)
+        };
+        let free_flow = |on: Traversable| on.length(map) / on.speed_limit(map);
+        let estimate = |path: &Path| {
+            let mut total = Duration::ZERO;
+            for step in path.get_steps() {
+                total += match step.as_traversable() {
+                    Traversable::Lane(l) => lane_time(map.get_l(l)),
+                    on => free_flow(on),
+                };
+            }
+            total
+        };
+
+        // Searching is expensive, so only bother when there's enough delay ahead to possibly
+        // avoid. The current route was the fastest one without traffic.
+        let current = estimate(&self.path);
+        let mut delay = current;
+        for step in self.path.get_steps() {
+            delay -= free_flow(step.as_traversable());
+        }
+        if delay < REROUTE_THRESHOLD {
+            return false;
+        }
+
+        let req = PathRequest {
+            start: Position::start(start),
+            end: Position::end(end, map),
+            constraints: PathConstraints::Car,
+            profile: RoutingProfile::Fastest,
+        };
+        // Recent observations are the best guess for the rest of the trip
+        if let Some(path) = map.pathfind_with_travel_times(req, now, |lane, _| lane_time(lane)) {
+            if current - estimate(&path) >= REROUTE_THRESHOLD {
+                self.path.replace_remaining(path, map);
+                events.push(Event::PathAmended(self.path.clone()));
+            }
+        }
+        true
+    }
+
     pub fn replace_path_for_serialization(&mut self, path: Path) -> Path {
         std::mem::replace(&mut self.path, path)
     }
@@ -17,13 +17,19 @@ pub enum Command {
     UpdateCar(CarID),
     // Distinguish this from UpdateCar to avoid confusing things
     UpdateLaggyHead(CarID),
+    // Only when cars accelerate. Re-plan a car's speed based on the car ahead.
+    FollowLeader(CarID),
     UpdatePed(PedestrianID),
     UpdateIntersection(IntersectionID),
     Callback(Duration),
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
+    // A delivery truck is done in a loading zone
+    ResumeDelivery(TripID),
     // The Time is redundant, just used to dedupe commands
     StartBus(BusRouteID, Time),
+    // Try again to find ride-hail vehicles for waiting requests, and give up on old ones
+    RetryRideHail,
 }
 
 impl Command {
@@ -42,12 +48,15 @@ impl Command {
             Command::StartTrip(id, _, _, _) => CommandType::StartTrip(*id),
             Command::UpdateCar(id) => CommandType::Car(*id),
             Command::UpdateLaggyHead(id) => CommandType::CarLaggyHead(*id),
+            Command::FollowLeader(id) => CommandType::CarFollowLeader(*id),
             Command::UpdatePed(id) => CommandType::Ped(*id),
             Command::UpdateIntersection(id) => CommandType::Intersection(*id),
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::FinishRemoteTrip(t) => CommandType::FinishRemoteTrip(*t),
+            Command::ResumeDelivery(t) => CommandType::ResumeDelivery(*t),
             Command::StartBus(r, t) => CommandType::StartBus(*r, *t),
+            Command::RetryRideHail => CommandType::RetryRideHail,
         }
     }
 }
@@ -59,12 +68,15 @@ pub enum CommandType {
     StartTrip(TripID),
     Car(CarID),
     CarLaggyHead(CarID),
+    CarFollowLeader(CarID),
     Ped(PedestrianID),
     Intersection(IntersectionID),
     Callback,
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
+    ResumeDelivery(TripID),
     StartBus(BusRouteID, Time),
+    RetryRideHail,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
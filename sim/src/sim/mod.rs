@@ -2,20 +2,20 @@ mod queries;
 
 pub use self::queries::AgentProperties;
 use crate::{
-    AgentID, AlertLocation, Analytics, CapSimState, CarID, Command, CreateCar, DrawCarInput,
-    DrawPedCrowdInput, DrawPedestrianInput, DrivingSimState, Event, GetDrawAgents,
+    AgentID, AlertLocation, Analytics, BikeShareSimState, CapSimState, CarID, Command, CreateCar,
+    DrawCarInput, DrawPedCrowdInput, DrawPedestrianInput, DrivingSimState, Event, GetDrawAgents,
     IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSim, ParkingSimState,
-    ParkingSpot, PedestrianID, Person, PersonID, Router, Scheduler, SidewalkPOI, SidewalkSpot,
-    TransitSimState, TripID, TripManager, TripPhaseType, TripSpawner, UnzoomedAgent, Vehicle,
-    VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
-    SPAWN_DIST,
+    ParkingSpot, PedestrianID, Person, PersonID, ReplayTrace, RideHailDispatcher, RideHailSimState,
+    Router, Scheduler, SidewalkPOI, SidewalkSpot, TransitSimState, TripID, TripManager,
+    TripPhaseType, TripSpawner, UnzoomedAgent, Vehicle, VehicleSpec, VehicleType, WalkingSimState,
+    BUS_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH, SPAWN_DIST,
 };
 use abstutil::{prettyprint_usize, serialized_size_bytes, Parallelism, Timer};
 use geom::{Distance, Duration, Speed, Time};
 use instant::Instant;
 use map_model::{
     BuildingID, BusRoute, LaneID, Map, ParkingLotID, Path, PathConstraints, PathRequest, Position,
-    Traversable,
+    RoutingProfile, Traversable,
 };
 use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
@@ -33,6 +33,8 @@ pub struct Sim {
     intersections: IntersectionSimState,
     transit: TransitSimState,
     cap: CapSimState,
+    bike_share: BikeShareSimState,
+    ride_hail: RideHailSimState,
     trips: TripManager,
     #[serde(skip_serializing, skip_deserializing)]
     pandemic: Option<PandemicModel>,
@@ -54,12 +56,21 @@ pub struct Sim {
 
     #[serde(skip_serializing, skip_deserializing)]
     alerts: AlertHandler,
+
+    // If enabled, buffer every event, so callers outside the simulation can observe them.
+    #[serde(skip_serializing, skip_deserializing)]
+    recorded_events: Option<Vec<(Time, Event)>>,
+    // If enabled, used to check that the simulation is deterministic.
+    #[serde(skip_serializing, skip_deserializing)]
+    trace: Option<ReplayTrace>,
 }
 
 pub struct Ctx<'a> {
     pub parking: &'a mut ParkingSimState,
     pub intersections: &'a mut IntersectionSimState,
     pub cap: &'a mut CapSimState,
+    pub bike_share: &'a mut BikeShareSimState,
+    pub ride_hail: &'a mut RideHailSimState,
     pub scheduler: &'a mut Scheduler,
     pub map: &'a Map,
 }
@@ -77,6 +88,20 @@ pub struct SimOptions {
     pub pathfinding_upfront: bool,
     pub live_map_edits: bool,
     pub infinite_parking: bool,
+    // Cars accelerate and brake according to their VehicleSpec, instead of instantly moving at
+    // their cruising speed. Produces more believable queue discharge at signals.
+    pub car_acceleration: bool,
+    // Cars stuck behind others can merge into an adjacent lane mid-block when there's a gap,
+    // instead of only picking lanes at intersections.
+    pub mid_block_lanechanging: bool,
+    // Informed drivers (see Scenario::pct_informed_drivers) watch recent travel times and switch
+    // routes mid-trip to avoid congestion.
+    pub congestion_rerouting: bool,
+    // Crowds slow down pedestrians, and people wait to step onto full crosswalks.
+    pub pedestrian_congestion: bool,
+    // How many ride-hail vehicles to have. If None, one per 10 ride-hailing trips.
+    pub ride_hail_fleet_size: Option<usize>,
+    pub ride_hail_dispatcher: RideHailDispatcher,
 }
 
 impl std::default::Default for SimOptions {
@@ -115,6 +140,12 @@ impl SimOptions {
             pathfinding_upfront: false,
             live_map_edits: false,
             infinite_parking: false,
+            car_acceleration: false,
+            mid_block_lanechanging: false,
+            congestion_rerouting: false,
+            pedestrian_congestion: false,
+            ride_hail_fleet_size: None,
+            ride_hail_dispatcher: RideHailDispatcher::NearestIdle,
         }
     }
 }
@@ -124,9 +155,16 @@ impl Sim {
     pub fn new(map: &Map, opts: SimOptions, timer: &mut Timer) -> Sim {
         let mut scheduler = Scheduler::new();
         Sim {
-            driving: DrivingSimState::new(map, opts.recalc_lanechanging, opts.handle_uber_turns),
+            driving: DrivingSimState::new(
+                map,
+                opts.recalc_lanechanging,
+                opts.handle_uber_turns,
+                opts.car_acceleration,
+                opts.mid_block_lanechanging,
+                opts.congestion_rerouting,
+            ),
             parking: ParkingSimState::new(map, opts.infinite_parking, timer),
-            walking: WalkingSimState::new(),
+            walking: WalkingSimState::new(opts.pedestrian_congestion),
             intersections: IntersectionSimState::new(
                 map,
                 &mut scheduler,
@@ -137,6 +175,8 @@ impl Sim {
             ),
             transit: TransitSimState::new(map),
             cap: CapSimState::new(map),
+            bike_share: BikeShareSimState::new(map),
+            ride_hail: RideHailSimState::new(opts.ride_hail_fleet_size, opts.ride_hail_dispatcher),
             trips: TripManager::new(opts.pathfinding_upfront),
             pandemic: if let Some(rng) = opts.enable_pandemic_model {
                 Some(PandemicModel::new(rng))
@@ -153,6 +193,8 @@ impl Sim {
             alerts: opts.alerts,
 
             analytics: Analytics::new(),
+            recorded_events: None,
+            trace: None,
         }
     }
 
@@ -196,12 +238,15 @@ impl Sim {
         map: &Map,
         b: BuildingID,
     ) -> Option<(Path, Distance)> {
+        let (max_accel, max_decel) = VehicleType::Car.default_accel_decel();
         let vehicle = Vehicle {
             id: CarID(0, VehicleType::Car),
             owner: None,
             vehicle_type: VehicleType::Car,
             length: MIN_CAR_LENGTH,
             max_speed: None,
+            max_accel,
+            max_decel,
         };
         let driving_lane = map.find_driving_lane_near_building(b);
 
@@ -217,7 +262,7 @@ impl Sim {
         } else {
             let (_, spot, _) =
                 self.parking
-                    .path_to_free_parking_spot(driving_lane, &vehicle, b, map)?;
+                    .path_to_free_parking_spot(driving_lane, &vehicle, b, None, map)?;
             spot
         };
 
@@ -227,6 +272,7 @@ impl Sim {
             start,
             end,
             constraints: PathConstraints::Pedestrian,
+            profile: RoutingProfile::Fastest,
         })?;
         Some((path, start.dist_along()))
     }
@@ -238,8 +284,10 @@ impl Sim {
         orig_id: Option<OrigPersonID>,
         ped_speed: Speed,
         vehicle_specs: Vec<VehicleSpec>,
+        informed_driver: bool,
     ) {
-        self.trips.new_person(p, orig_id, ped_speed, vehicle_specs);
+        self.trips
+            .new_person(p, orig_id, ped_speed, vehicle_specs, informed_driver);
     }
     pub fn random_person(&mut self, ped_speed: Speed, vehicle_specs: Vec<VehicleSpec>) -> &Person {
         self.trips.random_person(ped_speed, vehicle_specs)
@@ -259,7 +307,15 @@ impl Sim {
         }
     }
 
-    fn start_bus(&mut self, route: &BusRoute, map: &Map) {
+    // Size the fleet for this many ride-hailing trips
+    pub(crate) fn seed_ride_hail_fleet(&mut self, num_trips: usize, map: &Map) {
+        let ids = (0..self.ride_hail.fleet_size(num_trips))
+            .map(|_| CarID(self.trips.new_car_id(), VehicleType::Car))
+            .collect();
+        self.ride_hail.seed_fleet(ids, map);
+    }
+
+    fn start_bus(&mut self, route: &BusRoute, scheduled_start: Time, map: &Map) {
         // Spawn one bus for the first leg.
         let (req, path) = self.transit.create_empty_route(route, map);
 
@@ -270,10 +326,13 @@ impl Sim {
             PathConstraints::Train => (VehicleType::Train, LIGHT_RAIL_LENGTH),
             _ => unreachable!(),
         };
+        let (max_accel, max_decel) = vehicle_type.default_accel_decel();
         let vehicle = VehicleSpec {
             vehicle_type,
             length,
             max_speed: None,
+            max_accel,
+            max_decel,
         }
         .make(CarID(self.trips.new_car_id(), vehicle_type), None);
         let start_lane = map.get_l(path.current_step().as_lane());
@@ -298,7 +357,7 @@ impl Sim {
                     req,
                     maybe_parked_car: None,
                     trip_and_person: None,
-                    maybe_route: Some(route.id),
+                    maybe_route: Some((route.id, scheduled_start)),
                 },
                 true,
             ),
@@ -425,11 +484,16 @@ impl Sim {
         self.time = time;
         let mut events = Vec::new();
         let mut halt = false;
+        if let Some(ref mut trace) = self.trace {
+            trace.command(time, &cmd);
+        }
 
         let mut ctx = Ctx {
             parking: &mut self.parking,
             intersections: &mut self.intersections,
             cap: &mut self.cap,
+            bike_share: &mut self.bike_share,
+            ride_hail: &mut self.ride_hail,
             scheduler: &mut self.scheduler,
             map,
         };
@@ -455,10 +519,10 @@ impl Sim {
                             trip,
                             person,
                             Some(create_car.req.clone()),
-                            if create_car.vehicle.id.1 == VehicleType::Car {
-                                TripPhaseType::Driving
-                            } else {
+                            if create_car.vehicle.id.1 == VehicleType::Bike {
                                 TripPhaseType::Biking
+                            } else {
+                                TripPhaseType::Driving
                             },
                         ));
                     }
@@ -472,8 +536,12 @@ impl Sim {
                         }
                         self.parking.remove_parked_car(parked_car);
                     }
-                    if let Some(route) = create_car.maybe_route {
-                        self.transit.bus_created(create_car.vehicle.id, route);
+                    if let Some((route, scheduled_start)) = create_car.maybe_route {
+                        self.transit.bus_created(
+                            create_car.vehicle.id,
+                            map.get_br(route),
+                            scheduled_start,
+                        );
                     }
                     self.analytics
                         .record_demand(create_car.router.get_path(), map);
@@ -495,6 +563,8 @@ impl Sim {
                         parking: &mut self.parking,
                         intersections: &mut self.intersections,
                         cap: &mut self.cap,
+                        bike_share: &mut self.bike_share,
+                        ride_hail: &mut self.ride_hail,
                         scheduler: &mut self.scheduler,
                         map,
                     };
@@ -547,11 +617,15 @@ impl Sim {
                     &mut self.trips,
                     &mut self.transit,
                     &mut self.walking,
+                    &self.analytics.road_travel_times,
                 );
             }
             Command::UpdateLaggyHead(car) => {
                 self.driving.update_laggy_head(car, self.time, &mut ctx);
             }
+            Command::FollowLeader(car) => {
+                self.driving.follow_leader(car, self.time, &mut ctx);
+            }
             Command::UpdatePed(ped) => {
                 self.walking.update_ped(
                     ped,
@@ -562,8 +636,13 @@ impl Sim {
                 );
             }
             Command::UpdateIntersection(i) => {
-                self.intersections
-                    .update_intersection(self.time, i, map, &mut self.scheduler);
+                self.intersections.update_intersection(
+                    self.time,
+                    i,
+                    map,
+                    &mut self.scheduler,
+                    &self.driving,
+                );
             }
             Command::Callback(frequency) => {
                 self.scheduler
@@ -581,8 +660,15 @@ impl Sim {
             Command::FinishRemoteTrip(trip) => {
                 self.trips.remote_trip_finished(self.time, trip, &mut ctx);
             }
-            Command::StartBus(r, _) => {
-                self.start_bus(map.get_br(r), map);
+            Command::ResumeDelivery(trip) => {
+                self.trips.resume_delivery(self.time, trip, &mut ctx);
+            }
+            Command::StartBus(r, t) => {
+                self.start_bus(map.get_br(r), t, map);
+            }
+            Command::RetryRideHail => {
+                self.trips
+                    .retry_ride_hail(self.time, &mut self.walking, &mut ctx);
             }
         }
 
@@ -603,6 +689,12 @@ impl Sim {
             if let Some(ref mut m) = self.pandemic {
                 m.handle_event(self.time, &ev, &mut self.scheduler);
             }
+            if let Some(ref mut list) = self.recorded_events {
+                list.push((self.time, ev.clone()));
+            }
+            if let Some(ref mut trace) = self.trace {
+                trace.event(self.time, &ev);
+            }
 
             self.analytics.event(ev, self.time, map);
         }
@@ -789,6 +881,13 @@ impl Sim {
     }
 
     pub fn save(&mut self) -> String {
+        let path = self.save_path(self.time);
+        self.save_as(path.clone());
+        path
+    }
+
+    // Like save, but the caller decides where the file goes. Load it with load_savestate.
+    pub fn save_as(&mut self, path: String) {
         let restore = self.scheduler.before_savestate();
 
         if true {
@@ -817,6 +916,14 @@ impl Sim {
                 "- cap: {} bytes",
                 prettyprint_usize(serialized_size_bytes(&self.cap))
             );
+            println!(
+                "- bike_share: {} bytes",
+                prettyprint_usize(serialized_size_bytes(&self.bike_share))
+            );
+            println!(
+                "- ride_hail: {} bytes",
+                prettyprint_usize(serialized_size_bytes(&self.ride_hail))
+            );
             println!(
                 "- trips: {} bytes",
                 prettyprint_usize(serialized_size_bytes(&self.trips))
@@ -827,12 +934,9 @@ impl Sim {
             );
         }
 
-        let path = self.save_path(self.time);
-        abstutil::write_binary(path.clone(), self);
+        abstutil::write_binary(path, self);
 
         self.scheduler.after_savestate(restore);
-
-        path
     }
 
     pub fn find_previous_savestate(&self, base_time: Time) -> Option<String> {
@@ -881,6 +985,8 @@ impl Sim {
             parking: &mut self.parking,
             intersections: &mut self.intersections,
             cap: &mut self.cap,
+            bike_share: &mut self.bike_share,
+            ride_hail: &mut self.ride_hail,
             scheduler: &mut self.scheduler,
             map,
         };
@@ -892,7 +998,8 @@ impl Sim {
                         .abort_trip(self.time, trip, Some(vehicle), &mut ctx);
                 }
                 AgentID::Pedestrian(ped) => {
-                    self.walking.delete_ped(ped, ctx.scheduler);
+                    self.walking
+                        .delete_ped(self.time, ped, ctx.map, ctx.scheduler);
                     self.trips.abort_trip(self.time, trip, None, &mut ctx);
                 }
                 AgentID::BusPassenger(_, _) => unreachable!(),
@@ -955,6 +1062,8 @@ impl Sim {
                 parking: &mut self.parking,
                 intersections: &mut self.intersections,
                 cap: &mut self.cap,
+                bike_share: &mut self.bike_share,
+                ride_hail: &mut self.ride_hail,
                 scheduler: &mut self.scheduler,
                 map,
             };
@@ -972,6 +1081,38 @@ impl Sim {
     }
 }
 
+// Observing events
+impl Sim {
+    // Start or stop buffering every event that happens. Stopping discards anything not drained
+    // yet.
+    pub fn record_events(&mut self, enabled: bool) {
+        if !enabled {
+            self.recorded_events = None;
+        } else if self.recorded_events.is_none() {
+            self.recorded_events = Some(Vec::new());
+        }
+    }
+
+    // Returns every event since the last call, in the order they happened.
+    pub fn drain_recorded_events(&mut self) -> Vec<(Time, Event)> {
+        if let Some(ref mut list) = self.recorded_events {
+            std::mem::replace(list, Vec::new())
+        } else {
+            Vec::new()
+        }
+    }
+
+    // Start recording every command and event from now on, to compare against another run later.
+    pub fn record_trace(&mut self) {
+        self.trace = Some(ReplayTrace::new());
+    }
+
+    // Stops recording and returns everything so far.
+    pub fn take_trace(&mut self) -> Option<ReplayTrace> {
+        self.trace.take()
+    }
+}
+
 // Callbacks
 pub trait SimCallback: downcast_rs::Downcast {
     // Run at some scheduled time. If this returns true, halt simulation.
@@ -7,7 +7,8 @@ use crate::{
 use abstutil::Counter;
 use geom::{Distance, Duration, PolyLine, Pt2D, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Lane, LaneID, Map, Path, Position, RoadID,
+    BuildingID, BusRouteID, BusStopID, DockingStationID, IntersectionID, Lane, LaneID, Map, Path,
+    Position, RoadID, Traversable,
 };
 use std::collections::{BTreeMap, HashSet};
 
@@ -141,6 +142,11 @@ impl Sim {
             .collect()
     }
 
+    // How many shared vehicles are docked at a station right now
+    pub fn docking_station_inventory(&self, ds: DockingStationID) -> usize {
+        self.bike_share.get_inventory(ds)
+    }
+
     pub fn lookup_person(&self, id: PersonID) -> Option<&Person> {
         self.trips.get_person(id)
     }
@@ -165,6 +171,7 @@ impl Sim {
             VehicleType::Bike,
             VehicleType::Bus,
             VehicleType::Train,
+            VehicleType::Truck,
         ] {
             let id = CarID(idx, *vt);
             if self.driving.does_car_exist(id) {
@@ -191,6 +198,10 @@ impl Sim {
     pub fn get_all_driving_paths(&self) -> Vec<&Path> {
         self.driving.get_all_driving_paths()
     }
+    // People per square meter on sidewalks and crosswalks
+    pub fn get_pedestrian_density(&self, map: &Map) -> Vec<(Traversable, f64)> {
+        self.walking.get_pedestrian_density(map)
+    }
 
     pub fn trace_route(
         &self,
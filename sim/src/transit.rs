@@ -1,22 +1,37 @@
 use crate::sim::Ctx;
 use crate::{
     CarID, Event, PedestrianID, PersonID, Router, TripID, TripManager, TripPhaseType, VehicleType,
-    WalkingSimState,
+    WalkingSimState, BUS_CAPACITY, LIGHT_RAIL_CAPACITY,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
-use map_model::{BusRoute, BusRouteID, BusStopID, Map, Path, PathRequest, Position};
+use geom::{Duration, Time};
+use map_model::{
+    BusRoute, BusRouteID, BusStopID, Map, Path, PathRequest, Position, RoutingProfile,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
 // These index stops along a route, not stops along a single sidewalk.
 type StopIdx = usize;
 
+// Routes without a timetable from GTFS get one made up from the free-flow travel time between
+// stops. Real schedules leave some slack for traffic and signals.
+const SCHEDULE_PADDING: f64 = 1.3;
+const SCHEDULED_DWELL_TIME: Duration = Duration::const_seconds(20.0);
+
+// The actual dwell time is a fixed cost to pull in and work the doors, plus some time per
+// passenger.
+const DWELL_OVERHEAD: Duration = Duration::const_seconds(5.0);
+const TIME_PER_BOARDING: Duration = Duration::const_seconds(3.0);
+const TIME_PER_ALIGHTING: Duration = Duration::const_seconds(2.0);
+
 #[derive(Serialize, Deserialize, Clone)]
 struct Stop {
     id: BusStopID,
     driving_pos: Position,
     next_stop: Option<(PathRequest, Path)>,
+    // Relative to when a vehicle starts the route, if the route has no real timetable
+    scheduled_arrival: Duration,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -31,6 +46,9 @@ struct Route {
 struct Bus {
     car: CarID,
     route: BusRouteID,
+    scheduled_start: Time,
+    // When to arrive at and depart from each stop, relative to scheduled_start
+    schedule: Vec<(Duration, Duration)>,
     // Where does each passenger want to deboard?
     passengers: Vec<(PersonID, Option<BusStopID>)>,
     state: BusState,
@@ -87,6 +105,18 @@ impl TransitSimState {
     pub fn create_empty_route(&mut self, bus_route: &BusRoute, map: &Map) -> (PathRequest, Path) {
         if !self.routes.contains_key(&bus_route.id) {
             assert!(bus_route.stops.len() > 1);
+            let start_req = PathRequest {
+                start: Position::start(bus_route.start),
+                end: map.get_bs(bus_route.stops[0]).driving_pos,
+                constraints: bus_route.route_type,
+                profile: RoutingProfile::Fastest,
+            };
+            let start = (
+                start_req.clone(),
+                map.pathfind(start_req).expect("no route to first stop"),
+            );
+
+            let mut scheduled_arrival = SCHEDULE_PADDING * free_flow_time(&start.1, map);
             let mut stops = Vec::new();
             for (idx, stop1_id) in bus_route.stops.iter().enumerate() {
                 let stop1 = map.get_bs(*stop1_id);
@@ -95,6 +125,7 @@ impl TransitSimState {
                         id: stop1.id,
                         driving_pos: stop1.driving_pos,
                         next_stop: None,
+                        scheduled_arrival,
                     });
                     continue;
                 }
@@ -102,34 +133,30 @@ impl TransitSimState {
                     start: stop1.driving_pos,
                     end: map.get_bs(bus_route.stops[idx + 1]).driving_pos,
                     constraints: bus_route.route_type,
+                    profile: RoutingProfile::Fastest,
                 };
                 if let Some(path) = map.pathfind(req.clone()) {
                     if path.is_empty() {
                         panic!("Empty path between stops?! {}", req);
                     }
+                    let leg = SCHEDULE_PADDING * free_flow_time(&path, map);
                     stops.push(Stop {
                         id: stop1.id,
                         driving_pos: stop1.driving_pos,
                         next_stop: Some((req, path)),
+                        scheduled_arrival,
                     });
+                    scheduled_arrival += SCHEDULED_DWELL_TIME + leg;
                 } else {
                     panic!("No route between stops: {}", req);
                 }
             }
-            let start_req = PathRequest {
-                start: Position::start(bus_route.start),
-                end: map.get_bs(bus_route.stops[0]).driving_pos,
-                constraints: bus_route.route_type,
-            };
-            let start = (
-                start_req.clone(),
-                map.pathfind(start_req).expect("no route to first stop"),
-            );
             let end_at_border = if let Some(l) = bus_route.end_border {
                 let req = PathRequest {
                     start: map.get_bs(*bus_route.stops.last().unwrap()).driving_pos,
                     end: Position::end(l, map),
                     constraints: bus_route.route_type,
+                    profile: RoutingProfile::Fastest,
                 };
                 let path = map
                     .pathfind(req.clone())
@@ -152,22 +179,37 @@ impl TransitSimState {
         self.routes[&bus_route.id].start.clone()
     }
 
-    pub fn bus_created(&mut self, bus: CarID, r: BusRouteID) {
-        let route = self.routes.get_mut(&r).unwrap();
+    pub fn bus_created(&mut self, bus: CarID, r: &BusRoute, scheduled_start: Time) {
+        let route = self.routes.get_mut(&r.id).unwrap();
         route.active_vehicles.insert(bus);
+        let schedule = match r.scheduled_stops(scheduled_start) {
+            Some(times) if times.len() == route.stops.len() => times.clone(),
+            _ => route
+                .stops
+                .iter()
+                .map(|s| {
+                    (
+                        s.scheduled_arrival,
+                        s.scheduled_arrival + SCHEDULED_DWELL_TIME,
+                    )
+                })
+                .collect(),
+        };
         self.buses.insert(
             bus,
             Bus {
                 car: bus,
-                route: r,
+                route: r.id,
+                scheduled_start,
+                schedule,
                 passengers: Vec::new(),
                 state: BusState::DrivingToStop(0),
             },
         );
     }
 
-    // If true, the bus is idling. If false, the bus actually arrived at a border and should now
-    // vanish.
+    // If Some, the bus is idling until the returned time. If None, the bus actually arrived at a
+    // border and should now vanish.
     pub fn bus_arrived_at_stop(
         &mut self,
         now: Time,
@@ -175,19 +217,23 @@ impl TransitSimState {
         trips: &mut TripManager,
         walking: &mut WalkingSimState,
         ctx: &mut Ctx,
-    ) -> bool {
+    ) -> Option<Time> {
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
                 bus.state = BusState::AtStop(stop_idx);
                 let stop1 = self.routes[&bus.route].stops[stop_idx].id;
+                let (arrival, departure) = bus.schedule[stop_idx];
+                let scheduled = bus.scheduled_start + arrival;
                 self.events
-                    .push(Event::BusArrivedAtStop(id, bus.route, stop1));
+                    .push(Event::BusArrivedAtStop(id, bus.route, stop1, scheduled));
 
                 // Deboard existing passengers.
+                let mut alighting = 0;
                 let mut still_riding = Vec::new();
                 for (person, maybe_stop2) in bus.passengers.drain(..) {
                     if Some(stop1) == maybe_stop2 {
+                        alighting += 1;
                         trips.person_left_bus(now, person, bus.car, ctx);
                         self.events.push(Event::PassengerAlightsTransit(
                             person, bus.car, bus.route, stop1,
@@ -198,18 +244,25 @@ impl TransitSimState {
                 }
                 bus.passengers = still_riding;
 
-                // Board new passengers.
+                // Board new passengers, until the vehicle is full.
+                let mut boarding = 0;
+                let mut left_behind = 0;
                 let mut still_waiting = Vec::new();
                 for (ped, route, maybe_stop2, started_waiting) in
                     self.peds_waiting.remove(&stop1).unwrap()
                 {
-                    if bus.route == route {
+                    if bus.route == route && bus.passengers.len() >= capacity(bus.car) {
+                        left_behind += 1;
+                        still_waiting.push((ped, route, maybe_stop2, started_waiting));
+                    } else if bus.route == route {
+                        boarding += 1;
                         let (trip, person) = trips.ped_boarded_bus(
                             now,
                             ped,
                             bus.car,
                             now - started_waiting,
                             walking,
+                            ctx,
                         );
                         self.events.push(Event::PassengerBoardsTransit(
                             person,
@@ -229,6 +282,7 @@ impl TransitSimState {
                                     self.routes[&route].end_at_border.as_ref().unwrap().0.end
                                 },
                                 constraints: bus.car.1.to_constraints(),
+                                profile: RoutingProfile::Fastest,
                             }),
                             TripPhaseType::RidingBus(route, stop1, bus.car),
                         ));
@@ -238,7 +292,26 @@ impl TransitSimState {
                     }
                 }
                 self.peds_waiting.insert(stop1, still_waiting);
-                true
+                if left_behind > 0 {
+                    self.events.push(Event::PassengersLeftBehind(
+                        id,
+                        bus.route,
+                        stop1,
+                        left_behind,
+                    ));
+                }
+
+                // Run late if needed, but don't leave before the schedule says to.
+                let done_boarding = now
+                    + DWELL_OVERHEAD
+                    + (alighting as f64) * TIME_PER_ALIGHTING
+                    + (boarding as f64) * TIME_PER_BOARDING;
+                let scheduled_departure = bus.scheduled_start + departure;
+                if done_boarding < scheduled_departure {
+                    Some(scheduled_departure)
+                } else {
+                    Some(done_boarding)
+                }
             }
             BusState::DrivingOffMap => {
                 self.routes
@@ -257,7 +330,7 @@ impl TransitSimState {
                     }
                     trips.transit_rider_reached_border(now, person, id, ctx);
                 }
-                false
+                None
             }
             BusState::AtStop(_) | BusState::Done => unreachable!(),
         }
@@ -313,7 +386,9 @@ impl TransitSimState {
         if let Some(route) = self.routes.get(&route_id) {
             for bus in &route.active_vehicles {
                 if let BusState::AtStop(idx) = self.buses[bus].state {
-                    if route.stops[idx].id == stop1 {
+                    if route.stops[idx].id == stop1
+                        && self.buses[bus].passengers.len() < capacity(*bus)
+                    {
                         self.buses
                             .get_mut(bus)
                             .unwrap()
@@ -330,6 +405,7 @@ impl TransitSimState {
                                     route.end_at_border.as_ref().unwrap().0.end
                                 },
                                 constraints: bus.1.to_constraints(),
+                                profile: RoutingProfile::Fastest,
                             }),
                             TripPhaseType::RidingBus(route_id, stop1, *bus),
                         ));
@@ -413,3 +489,22 @@ impl TransitSimState {
         &self.peds_waiting[&at]
     }
 }
+
+fn capacity(bus: CarID) -> usize {
+    match bus.1 {
+        VehicleType::Bus => BUS_CAPACITY,
+        VehicleType::Train => LIGHT_RAIL_CAPACITY,
+        VehicleType::Car | VehicleType::Bike | VehicleType::Truck => unreachable!(),
+    }
+}
+
+// How long to follow the path at the speed limit? Overestimates a bit, since the first and last
+// steps are only partly crossed.
+fn free_flow_time(path: &Path, map: &Map) -> Duration {
+    let mut total = Duration::ZERO;
+    for step in path.get_steps() {
+        let t = step.as_traversable();
+        total += t.length(map) / t.speed_limit(map);
+    }
+    total
+}
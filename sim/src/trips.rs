@@ -1,16 +1,17 @@
+use crate::ride_hail::{self, RideHailStop, RideRequest, TIME_TO_BOARD};
 use crate::sim::Ctx;
 use crate::{
     AgentID, AgentType, AlertLocation, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal,
     Event, IndividTrip, OffMapLocation, OrigPersonID, ParkedCar, ParkingSim, ParkingSpot,
-    PedestrianID, PersonID, PersonSpec, Scenario, Scheduler, SidewalkPOI, SidewalkSpot, SpawnTrip,
-    TransitSimState, TripID, TripPhaseType, TripPurpose, TripSpec, Vehicle, VehicleSpec,
+    PedestrianID, PersonID, PersonSpec, Router, Scenario, Scheduler, SidewalkPOI, SidewalkSpot,
+    SpawnTrip, TransitSimState, TripID, TripPhaseType, TripPurpose, TripSpec, Vehicle, VehicleSpec,
     VehicleType, WalkingSimState,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use geom::{Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, Path, PathConstraints, PathRequest,
-    Position,
+    BuildingID, BusRouteID, BusStopID, DockingStationID, IntersectionID, LaneID, Map, Path,
+    PathConstraints, PathRequest, Position, RoutingProfile, TransitRide,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, VecDeque};
@@ -27,6 +28,12 @@ pub struct TripManager {
     active_trip_mode: BTreeMap<AgentID, TripID>,
     unfinished_trips: usize,
     pub pathfinding_upfront: bool,
+    // Fixed driving routes from traffic assignment, as every lane along the way
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    assigned_routes: BTreeMap<TripID, Vec<LaneID>>,
 
     car_id_counter: usize,
 
@@ -40,6 +47,7 @@ impl TripManager {
             people: Vec::new(),
             active_trip_mode: BTreeMap::new(),
             unfinished_trips: 0,
+            assigned_routes: BTreeMap::new(),
             car_id_counter: 0,
             events: Vec::new(),
             pathfinding_upfront,
@@ -53,6 +61,7 @@ impl TripManager {
         orig_id: Option<OrigPersonID>,
         ped_speed: Speed,
         vehicle_specs: Vec<VehicleSpec>,
+        informed_driver: bool,
     ) {
         assert_eq!(id.0, self.people.len());
         let vehicles = vehicle_specs
@@ -71,13 +80,14 @@ impl TripManager {
             ped: PedestrianID(id.0),
             ped_speed,
             vehicles,
+            informed_driver,
             delayed_trips: Vec::new(),
             on_bus: None,
         });
     }
     pub fn random_person(&mut self, ped_speed: Speed, vehicle_specs: Vec<VehicleSpec>) -> &Person {
         let id = PersonID(self.people.len());
-        self.new_person(id, None, ped_speed, vehicle_specs);
+        self.new_person(id, None, ped_speed, vehicle_specs, true);
         self.get_person(id).unwrap()
     }
 
@@ -94,6 +104,7 @@ impl TripManager {
         start: TripEndpoint,
         mode: TripMode,
         purpose: TripPurpose,
+        routing_profile: RoutingProfile,
         modified: bool,
         legs: Vec<TripLeg>,
         map: &Map,
@@ -122,6 +133,19 @@ impl TripManager {
             }
             _ => unreachable!(),
         };
+        let mut itinerary = Vec::new();
+        for pair in legs.windows(2) {
+            if let (TripLeg::Walk(ref spot), TripLeg::RideBus(route, alight)) = (&pair[0], &pair[1])
+            {
+                if let SidewalkPOI::BusStop(board) = spot.connection {
+                    itinerary.push(TransitRide {
+                        route: *route,
+                        board,
+                        alight: *alight,
+                    });
+                }
+            }
+        }
         let trip = Trip {
             id,
             info: TripInfo {
@@ -130,8 +154,10 @@ impl TripManager {
                 start,
                 end,
                 purpose,
+                routing_profile,
                 modified,
                 capped: false,
+                itinerary,
             },
             person,
             started: false,
@@ -195,6 +221,15 @@ impl TripManager {
             Some(TripLeg::Drive(c, DrivingGoal::ParkNear(_))) => {
                 assert_eq!(car, c);
             }
+            Some(TripLeg::Deliver(c, b, dwell)) => {
+                assert_eq!(car, c);
+                // The truck stays in the loading zone until the delivery is done
+                self.events
+                    .push(Event::DeliveryStop(car, b, Some(spot), dwell));
+                ctx.scheduler
+                    .push(now + dwell, Command::ResumeDelivery(trip.id));
+                return;
+            }
             _ => unreachable!(),
         };
 
@@ -244,11 +279,12 @@ impl TripManager {
         ctx: &mut Ctx,
     ) {
         self.events.push(Event::PedReachedParkingSpot(ped, spot));
-        let trip = &mut self.trips[self
+        let id = self
             .active_trip_mode
             .remove(&AgentID::Pedestrian(ped))
-            .unwrap()
-            .0];
+            .unwrap();
+        let stay = self.planned_stay(now, id);
+        let trip = &mut self.trips[id.0];
         trip.total_blocked_time += blocked_time;
 
         trip.assert_walking_leg(SidewalkSpot::deferred_parking_spot());
@@ -281,9 +317,16 @@ impl TripManager {
             start,
             end,
             constraints: PathConstraints::Car,
+            profile: trip.info.routing_profile,
         };
 
-        match ctx.map.pathfind(req.clone()).and_then(|path| {
+        // Stick to the assigned route if it still fits
+        let maybe_path = self
+            .assigned_routes
+            .get(&trip.id)
+            .and_then(|route| Path::from_lanes(&req, route, ctx.map))
+            .or_else(|| ctx.map.pathfind(req.clone()));
+        match maybe_path.and_then(|path| {
             ctx.cap.validate_path(
                 &req,
                 path,
@@ -294,7 +337,7 @@ impl TripManager {
             )
         }) {
             Some(path) => {
-                let router = drive_to.make_router(parked_car.vehicle.id, path, ctx.map);
+                let router = drive_to.make_router(parked_car.vehicle.id, path, stay, ctx.map);
                 ctx.scheduler.push(
                     now,
                     Command::SpawnCar(
@@ -327,6 +370,151 @@ impl TripManager {
         }
     }
 
+    // A delivery truck stopped in the lane, since there was no loading zone free. Returns when it
+    // leaves and its route to the next stop, or None if the trip was aborted.
+    pub fn truck_double_parked(
+        &mut self,
+        now: Time,
+        car: CarID,
+        b: BuildingID,
+        pos: Position,
+        ctx: &mut Ctx,
+    ) -> Option<(Time, Router)> {
+        let trip = &mut self.trips[self.active_trip_mode[&AgentID::Car(car)].0];
+        let dwell = match trip.legs.pop_front() {
+            Some(TripLeg::Deliver(c, b2, dwell)) => {
+                assert_eq!(car, c);
+                assert_eq!(b, b2);
+                dwell
+            }
+            _ => unreachable!(),
+        };
+        self.events.push(Event::DeliveryStop(car, b, None, dwell));
+
+        if let Some((_, router)) = route_delivery_truck(
+            car,
+            pos,
+            &mut trip.legs,
+            trip.person,
+            ctx.map,
+            &mut self.events,
+        ) {
+            return Some((now + dwell, router));
+        }
+        self.events.push(Event::Alert(
+            AlertLocation::Person(trip.person),
+            format!("Aborting {} because {} can't leave {}", trip.id, car, pos),
+        ));
+        let id = trip.id;
+        self.abort_trip(now, id, None, ctx);
+        None
+    }
+
+    // A delivery truck finished dwelling in a loading zone.
+    pub fn resume_delivery(&mut self, now: Time, id: TripID, ctx: &mut Ctx) {
+        let trip = &mut self.trips[id.0];
+        let truck = match trip.legs[0] {
+            TripLeg::Deliver(c, _, _) | TripLeg::Drive(c, _) => c,
+            _ => unreachable!(),
+        };
+        let parked_car = ctx.parking.lookup_parked_car(truck).unwrap().clone();
+        let start = ctx
+            .parking
+            .spot_to_driving_pos(parked_car.spot, &parked_car.vehicle, ctx.map);
+
+        if let Some((req, router)) = route_delivery_truck(
+            truck,
+            start,
+            &mut trip.legs,
+            trip.person,
+            ctx.map,
+            &mut self.events,
+        ) {
+            ctx.scheduler.push(
+                now,
+                Command::SpawnCar(
+                    CreateCar::for_parked_car(
+                        parked_car,
+                        router,
+                        req,
+                        start.dist_along(),
+                        trip.id,
+                        trip.person,
+                    ),
+                    true,
+                ),
+            );
+        } else {
+            self.events.push(Event::Alert(
+                AlertLocation::Person(trip.person),
+                format!(
+                    "Aborting {} because {} can't leave {}",
+                    trip.id, truck, start
+                ),
+            ));
+            ctx.parking.remove_parked_car(parked_car);
+            self.abort_trip(now, id, None, ctx);
+        }
+    }
+
+    // Returns true if the pedestrian picked up a shared vehicle and should start riding it.
+    // Otherwise, they've already been sent off to another station or the rest of the way on foot.
+    pub fn ped_reached_docking_station(
+        &mut self,
+        now: Time,
+        ped: PedestrianID,
+        ds: DockingStationID,
+        blocked_time: Duration,
+        ctx: &mut Ctx,
+    ) -> bool {
+        let person = self.trips[self.active_trip_mode[&AgentID::Pedestrian(ped)].0].person;
+        if let Some(cnt) = ctx.bike_share.take_vehicle(ds) {
+            self.events
+                .push(Event::SharedVehiclePickedUp(person, ds, cnt));
+            return true;
+        }
+        self.events.push(Event::DockingStationEmpty(person, ds));
+
+        let trip = &mut self.trips[self
+            .active_trip_mode
+            .remove(&AgentID::Pedestrian(ped))
+            .unwrap()
+            .0];
+        trip.total_blocked_time += blocked_time;
+        trip.assert_walking_leg(SidewalkSpot::docking_station(ds, ctx.map));
+        let dropoff = match trip.legs[0] {
+            TripLeg::RideShared(_, dropoff) => dropoff,
+            _ => unreachable!(),
+        };
+
+        // Try the next closest station with a vehicle, or give up and walk the rest of the way
+        let station = ctx.map.get_ds(ds);
+        match ctx.bike_share.find_pickup(station.point, Some(ds), ctx.map) {
+            Some(next) if next != dropoff => {
+                trip.legs
+                    .push_front(TripLeg::Walk(SidewalkSpot::docking_station(next, ctx.map)));
+            }
+            _ => {
+                trip.legs.pop_front();
+            }
+        }
+
+        if !trip.spawn_ped(
+            now,
+            SidewalkSpot {
+                connection: SidewalkPOI::SuddenlyAppear,
+                sidewalk_pos: station.sidewalk_pos,
+            },
+            &self.people[trip.person.0],
+            ctx.map,
+            ctx.scheduler,
+            &mut self.events,
+        ) {
+            self.unfinished_trips -= 1;
+        }
+        false
+    }
+
     pub fn ped_ready_to_bike(
         &mut self,
         now: Time,
@@ -343,23 +531,25 @@ impl TripManager {
         trip.total_blocked_time += blocked_time;
 
         trip.assert_walking_leg(spot.clone());
-        let (bike, drive_to) = match trip.legs[0] {
-            TripLeg::Drive(bike, ref to) => (bike, to.clone()),
+        let (bike, end) = match trip.legs[0] {
+            TripLeg::Drive(bike, ref to) => (bike, to.goal_pos(PathConstraints::Bike, ctx.map)),
+            TripLeg::RideShared(bike, dropoff) => (bike, Some(ctx.map.get_ds(dropoff).driving_pos)),
             _ => unreachable!(),
         };
         let driving_pos = match spot.connection {
             SidewalkPOI::BikeRack(p) => p,
+            SidewalkPOI::DockingStation(ds) => ctx.map.get_ds(ds).driving_pos,
             _ => unreachable!(),
         };
 
-        let end = if let Some(end) = drive_to.goal_pos(PathConstraints::Bike, ctx.map) {
+        let end = if let Some(end) = end {
             end
         } else {
             self.events.push(Event::Alert(
                 AlertLocation::Person(trip.person),
                 format!(
-                    "Aborting {} because no bike connection at {:?}",
-                    trip.id, drive_to
+                    "Aborting {} because no bike connection for {:?}",
+                    trip.id, trip.legs[0]
                 ),
             ));
             let trip = trip.id;
@@ -370,11 +560,20 @@ impl TripManager {
             start: driving_pos,
             end,
             constraints: PathConstraints::Bike,
+            profile: trip.info.routing_profile,
         };
         if let Some(router) = ctx
             .map
             .pathfind(req.clone())
-            .map(|path| drive_to.make_router(bike, path, ctx.map))
+            .map(|path| match trip.legs[0] {
+                TripLeg::Drive(_, ref to) => to.make_router(bike, path, None, ctx.map),
+                TripLeg::RideShared(_, dropoff) => Router::bike_then_stop(
+                    bike,
+                    path,
+                    SidewalkSpot::docking_station(dropoff, ctx.map),
+                ),
+                _ => unreachable!(),
+            })
         {
             ctx.scheduler.push(
                 now,
@@ -399,6 +598,12 @@ impl TripManager {
                     trip.id, driving_pos, end
                 ),
             ));
+            // Don't lose the shared vehicle
+            if let SidewalkPOI::DockingStation(ds) = spot.connection {
+                let cnt = ctx.bike_share.return_vehicle(ds);
+                self.events
+                    .push(Event::SharedVehicleReturned(trip.person, ds, cnt));
+            }
             let trip = trip.id;
             self.abort_trip(now, trip, None, ctx);
         }
@@ -410,8 +615,7 @@ impl TripManager {
         bike: CarID,
         bike_rack: SidewalkSpot,
         blocked_time: Duration,
-        map: &Map,
-        scheduler: &mut Scheduler,
+        ctx: &mut Ctx,
     ) {
         self.events.push(Event::BikeStoppedAtSidewalk(
             bike,
@@ -424,6 +628,16 @@ impl TripManager {
             Some(TripLeg::Drive(c, DrivingGoal::ParkNear(_))) => {
                 assert_eq!(c, bike);
             }
+            Some(TripLeg::RideShared(c, ds)) => {
+                assert_eq!(c, bike);
+                // If there's no free dock, leave the vehicle next to the station anyway
+                let cnt = ctx.bike_share.return_vehicle(ds);
+                if cnt > ctx.map.get_ds(ds).capacity {
+                    self.events.push(Event::DockingStationFull(trip.person, ds));
+                }
+                self.events
+                    .push(Event::SharedVehicleReturned(trip.person, ds, cnt));
+            }
             _ => unreachable!(),
         };
 
@@ -431,8 +645,8 @@ impl TripManager {
             now,
             bike_rack,
             &self.people[trip.person.0],
-            map,
-            scheduler,
+            ctx.map,
+            ctx.scheduler,
             &mut self.events,
         ) {
             self.unfinished_trips -= 1;
@@ -531,6 +745,7 @@ impl TripManager {
         bus: CarID,
         blocked_time: Duration,
         walking: &mut WalkingSimState,
+        ctx: &mut Ctx,
     ) -> (TripID, PersonID) {
         let trip = &mut self.trips[self
             .active_trip_mode
@@ -540,7 +755,7 @@ impl TripManager {
         trip.total_blocked_time += blocked_time;
 
         trip.legs.pop_front();
-        walking.ped_boarded_bus(now, ped);
+        walking.ped_boarded_bus(now, ped, ctx.map, ctx.scheduler);
         self.active_trip_mode
             .insert(AgentID::BusPassenger(trip.person, bus), trip.id);
         self.people[trip.person.0].on_bus = Some(bus);
@@ -575,6 +790,215 @@ impl TripManager {
         }
     }
 
+    // Returns true if the person waits at the curb to be picked up. Otherwise, they're replaced by
+    // a new pedestrian walking the rest of the way, or the trip was aborted.
+    pub fn ped_reached_ride_hail_pickup(
+        &mut self,
+        now: Time,
+        ped: PedestrianID,
+        blocked_time: Duration,
+        ctx: &mut Ctx,
+    ) -> bool {
+        let trip = &mut self.trips[self.active_trip_mode[&AgentID::Pedestrian(ped)].0];
+        trip.total_blocked_time += blocked_time;
+
+        let pickup = match trip.legs[0] {
+            TripLeg::Walk(ref spot) => spot.clone(),
+            _ => unreachable!(),
+        };
+        let dropoff = match trip.legs[1] {
+            TripLeg::RideHail(ref spot) => spot.clone(),
+            _ => unreachable!(),
+        };
+
+        if !ctx.ride_hail.has_fleet() {
+            // Nobody's coming, so walk the rest of the way
+            self.active_trip_mode
+                .remove(&AgentID::Pedestrian(ped))
+                .unwrap();
+            trip.legs.pop_front();
+            trip.legs.pop_front();
+            if !trip.spawn_ped(
+                now,
+                SidewalkSpot {
+                    connection: SidewalkPOI::SuddenlyAppear,
+                    sidewalk_pos: pickup.sidewalk_pos,
+                },
+                &self.people[trip.person.0],
+                ctx.map,
+                ctx.scheduler,
+                &mut self.events,
+            ) {
+                self.unfinished_trips -= 1;
+            }
+            return false;
+        }
+
+        self.events.push(Event::TripPhaseStarting(
+            trip.id,
+            trip.person,
+            None,
+            TripPhaseType::WaitingForRideHail,
+        ));
+        ctx.ride_hail.request_ride(RideRequest {
+            trip: trip.id,
+            person: trip.person,
+            pickup: match pickup.connection {
+                SidewalkPOI::RideHail(pos) => pos,
+                _ => unreachable!(),
+            },
+            dropoff: match dropoff.connection {
+                SidewalkPOI::RideHail(pos) => pos,
+                _ => unreachable!(),
+            },
+            requested_at: now,
+        });
+        self.dispatch_ride_hail(now, ctx);
+        true
+    }
+
+    // A ride-hail vehicle reached a pickup or dropoff. Returns when it should depart, or None if
+    // it vanishes until it's dispatched again.
+    pub fn ride_hail_vehicle_arrived(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pos: Position,
+        walking: &mut WalkingSimState,
+        ctx: &mut Ctx,
+    ) -> Option<Time> {
+        match ctx.ride_hail.vehicle_arrived(car) {
+            RideHailStop::Pickup(req) => {
+                // Maybe the trip was aborted while the vehicle was on the way
+                if self.trips[req.trip.0].aborted {
+                    ctx.ride_hail.vehicle_idle(now, car, pos, ctx.map);
+                    self.dispatch_ride_hail(now, ctx);
+                    return None;
+                }
+
+                let ped = self.people[req.person.0].ped;
+                let (path_req, path) =
+                    if let Some(pair) = ride_hail::pathfind(pos, req.dropoff, ctx.map) {
+                        pair
+                    } else {
+                        self.events.push(Event::Alert(
+                            AlertLocation::Person(req.person),
+                            format!(
+                                "Aborting {} because {} can't reach the dropoff {}",
+                                req.trip, car, req.dropoff
+                            ),
+                        ));
+                        self.active_trip_mode
+                            .remove(&AgentID::Pedestrian(ped))
+                            .unwrap();
+                        walking.delete_ped(now, ped, ctx.map, ctx.scheduler);
+                        self.abort_trip(now, req.trip, None, ctx);
+                        ctx.ride_hail.vehicle_idle(now, car, pos, ctx.map);
+                        self.dispatch_ride_hail(now, ctx);
+                        return None;
+                    };
+
+                let trip = &mut self.trips[self
+                    .active_trip_mode
+                    .remove(&AgentID::Pedestrian(ped))
+                    .unwrap()
+                    .0];
+                let wait = now - req.requested_at;
+                trip.total_blocked_time += wait;
+                trip.legs.pop_front();
+                walking.ped_boarded_bus(now, ped, ctx.map, ctx.scheduler);
+                self.active_trip_mode
+                    .insert(AgentID::BusPassenger(trip.person, car), trip.id);
+                self.people[trip.person.0].on_bus = Some(car);
+
+                ctx.ride_hail
+                    .start_boarding(car, Router::ride_hail(car, path, req.dropoff.dist_along()));
+                self.events.push(Event::RideHailPickup(
+                    trip.person,
+                    car,
+                    wait,
+                    ctx.ride_hail.num_occupied(),
+                ));
+                self.events.push(Event::TripPhaseStarting(
+                    trip.id,
+                    trip.person,
+                    Some(path_req),
+                    TripPhaseType::RidingRideHail(car),
+                ));
+                Some(now + TIME_TO_BOARD)
+            }
+            RideHailStop::Dropoff(req) => {
+                let trip = &mut self.trips[self
+                    .active_trip_mode
+                    .remove(&AgentID::BusPassenger(req.person, car))
+                    .unwrap()
+                    .0];
+                let start = match trip.legs.pop_front().unwrap() {
+                    TripLeg::RideHail(spot) => spot,
+                    _ => unreachable!(),
+                };
+                self.people[req.person.0].on_bus.take().unwrap();
+                if !trip.spawn_ped(
+                    now,
+                    start,
+                    &self.people[req.person.0],
+                    ctx.map,
+                    ctx.scheduler,
+                    &mut self.events,
+                ) {
+                    self.unfinished_trips -= 1;
+                }
+
+                ctx.ride_hail.vehicle_idle(now, car, pos, ctx.map);
+                self.events.push(Event::RideHailDropoff(
+                    req.person,
+                    car,
+                    ctx.ride_hail.num_occupied(),
+                ));
+                self.dispatch_ride_hail(now, ctx);
+                None
+            }
+        }
+    }
+
+    // Called periodically while anybody's waiting for a ride-hail vehicle. People who've waited
+    // too long give up.
+    pub fn retry_ride_hail(&mut self, now: Time, walking: &mut WalkingSimState, ctx: &mut Ctx) {
+        for req in ctx.ride_hail.expire_requests(now) {
+            self.events.push(Event::Alert(
+                AlertLocation::Person(req.person),
+                format!(
+                    "Aborting {} because no ride-hail vehicle came within {}",
+                    req.trip,
+                    ride_hail::MAX_WAIT
+                ),
+            ));
+            let ped = self.people[req.person.0].ped;
+            self.active_trip_mode
+                .remove(&AgentID::Pedestrian(ped))
+                .unwrap();
+            walking.delete_ped(now, ped, ctx.map, ctx.scheduler);
+            self.abort_trip(now, req.trip, None, ctx);
+        }
+        self.dispatch_ride_hail(now, ctx);
+    }
+
+    // How long somebody will leave their car parked after this trip, counting from now, or None if
+    // they don't have another trip.
+    fn planned_stay(&self, now: Time, trip: TripID) -> Option<Duration> {
+        let person = &self.people[self.trips[trip.0].person.0];
+        let idx = person.trips.iter().position(|t| *t == trip)?;
+        let next = person.trips.get(idx + 1)?;
+        Some((self.trips[next.0].info.departure - now).max(Duration::ZERO))
+    }
+
+    fn dispatch_ride_hail(&mut self, now: Time, ctx: &mut Ctx) {
+        for (person, car, deadhead) in ctx.ride_hail.dispatch(now, ctx.scheduler, ctx.map) {
+            self.events
+                .push(Event::RideHailDispatched(person, car, deadhead));
+        }
+    }
+
     pub fn ped_reached_border(
         &mut self,
         now: Time,
@@ -720,6 +1144,10 @@ impl TripManager {
         self.person_finished_trip(now, person, ctx);
     }
 
+    pub fn assign_route(&mut self, id: TripID, route: Vec<LaneID>) {
+        self.assigned_routes.insert(id, route);
+    }
+
     // Different than aborting a trip. Don't warp any vehicles or change where the person is.
     pub fn cancel_trip(&mut self, id: TripID) {
         let trip = &mut self.trips[id.0];
@@ -774,7 +1202,7 @@ impl TripManager {
                         .map(|(spot, _)| spot.clone())
                         .or_else(|| {
                             ctx.parking
-                                .path_to_free_parking_spot(driving_lane, &vehicle, b, ctx.map)
+                                .path_to_free_parking_spot(driving_lane, &vehicle, b, None, ctx.map)
                                 .map(|(_, spot, _)| spot)
                         })
                     {
@@ -805,10 +1233,13 @@ impl TripManager {
         } else {
             // If the trip was aborted because we'e totally out of parking, don't forget to clean
             // this up.
-            if let TripLeg::Drive(c, _) = &trip.legs[0] {
-                if let Some(t) = self.active_trip_mode.remove(&AgentID::Car(*c)) {
-                    assert_eq!(t, trip.id);
+            match &trip.legs[0] {
+                TripLeg::Drive(c, _) | TripLeg::RideShared(c, _) | TripLeg::Deliver(c, _, _) => {
+                    if let Some(t) = self.active_trip_mode.remove(&AgentID::Car(*c)) {
+                        assert_eq!(t, trip.id);
+                    }
                 }
+                _ => {}
             }
         }
 
@@ -850,8 +1281,12 @@ impl TripManager {
         let person = &self.people[trip.person.0];
         let a = match &trip.legs[0] {
             TripLeg::Walk(_) => AgentID::Pedestrian(person.ped),
-            TripLeg::Drive(c, _) => AgentID::Car(*c),
-            TripLeg::RideBus(_, _) => AgentID::BusPassenger(person.id, person.on_bus.unwrap()),
+            TripLeg::Drive(c, _) | TripLeg::RideShared(c, _) | TripLeg::Deliver(c, _, _) => {
+                AgentID::Car(*c)
+            }
+            TripLeg::RideBus(_, _) | TripLeg::RideHail(_) => {
+                AgentID::BusPassenger(person.id, person.on_bus.unwrap())
+            }
             TripLeg::Remote(_) => {
                 return TripResult::RemoteTrip;
             }
@@ -951,6 +1386,13 @@ impl TripManager {
         self.trips[id.0].person
     }
 
+    // Informed drivers just looking for the fastest route will react to traffic
+    pub(crate) fn reroutes_around_congestion(&self, id: TripID) -> bool {
+        let trip = &self.trips[id.0];
+        trip.info.routing_profile == RoutingProfile::Fastest
+            && self.people[trip.person.0].informed_driver
+    }
+
     fn person_finished_trip(&mut self, now: Time, person: PersonID, ctx: &mut Ctx) {
         let person = &mut self.people[person.0];
         if person.delayed_trips.is_empty() {
@@ -980,10 +1422,18 @@ impl TripManager {
     ) {
         assert!(!self.trips[trip.0].cancelled);
         assert!(!self.trips[trip.0].aborted);
-        if !self.pathfinding_upfront && maybe_path.is_none() && maybe_req.is_some() {
+        if let Some(path) = self.assigned_routes.get(&trip).and_then(|route| {
+            maybe_req
+                .as_ref()
+                .and_then(|req| Path::from_lanes(req, route, ctx.map))
+        }) {
+            maybe_path = Some(path);
+        } else if !self.pathfinding_upfront && maybe_path.is_none() && maybe_req.is_some() {
             maybe_path = ctx.map.pathfind(maybe_req.clone().unwrap());
         }
 
+        let profile = self.trips[trip.0].info.routing_profile;
+        let stay = self.planned_stay(now, trip);
         let person = &mut self.people[self.trips[trip.0].person.0];
         if let PersonState::Trip(_) = person.state {
             // Previous trip isn't done. Defer this one!
@@ -1041,7 +1491,7 @@ impl TripManager {
                     )
                 }) {
                     Some(path) => {
-                        let router = goal.make_router(vehicle.id, path, ctx.map);
+                        let router = goal.make_router(vehicle.id, path, stay, ctx.map);
                         ctx.scheduler.push(
                             now,
                             Command::SpawnCar(
@@ -1095,6 +1545,7 @@ impl TripManager {
                         start: start.sidewalk_pos,
                         end: walking_goal.sidewalk_pos,
                         constraints: PathConstraints::Pedestrian,
+                        profile,
                     };
                     if let Some(path) = ctx.map.pathfind(req.clone()) {
                         ctx.scheduler.push(
@@ -1224,7 +1675,7 @@ impl TripManager {
                     self.abort_trip(now, trip, None, ctx);
                 }
             }
-            TripSpec::UsingTransit { start, stop1, .. } => {
+            TripSpec::UsingTransit { start, rides, .. } => {
                 assert_eq!(
                     person.state,
                     match start.connection {
@@ -1254,7 +1705,7 @@ impl TripManager {
                 );
                 person.state = PersonState::Trip(trip);
 
-                let walk_to = SidewalkSpot::bus_stop(stop1, ctx.map);
+                let walk_to = SidewalkSpot::bus_stop(rides[0].board, ctx.map);
                 let req = maybe_req.unwrap();
                 if let Some(path) = maybe_path {
                     ctx.scheduler.push(
@@ -1278,6 +1729,179 @@ impl TripManager {
                     self.abort_trip(now, trip, None, ctx);
                 }
             }
+            TripSpec::UsingBikeShare { bike, start, goal } => {
+                assert_eq!(
+                    person.state,
+                    match start.connection {
+                        SidewalkPOI::Building(b) => PersonState::Inside(b),
+                        SidewalkPOI::Border(i, ref loc) => {
+                            self.events.push(Event::PersonEntersMap(
+                                person.id,
+                                AgentID::Pedestrian(person.ped),
+                                i,
+                                loc.clone(),
+                            ));
+                            PersonState::OffMap
+                        }
+                        SidewalkPOI::SuddenlyAppear => {
+                            self.events.push(Event::PersonEntersMap(
+                                person.id,
+                                AgentID::Pedestrian(person.ped),
+                                ctx.map.get_l(start.sidewalk_pos.lane()).src_i,
+                                None,
+                            ));
+                            PersonState::OffMap
+                        }
+                        _ => unreachable!(),
+                    }
+                );
+                person.state = PersonState::Trip(trip);
+
+                // Pick stations based on what's available right now. If there's nothing nearby,
+                // just walk.
+                let walk_to = match (
+                    ctx.bike_share
+                        .find_pickup(start.sidewalk_pos.pt(ctx.map), None, ctx.map),
+                    ctx.bike_share
+                        .find_dropoff(goal.sidewalk_pos.pt(ctx.map), ctx.map),
+                ) {
+                    (Some(pickup), Some(dropoff)) if pickup != dropoff => {
+                        let walk_to = SidewalkSpot::docking_station(pickup, ctx.map);
+                        let legs = &mut self.trips[trip.0].legs;
+                        legs.push_front(TripLeg::RideShared(bike, dropoff));
+                        legs.push_front(TripLeg::Walk(walk_to.clone()));
+                        walk_to
+                    }
+                    _ => goal,
+                };
+
+                let req = PathRequest {
+                    start: start.sidewalk_pos,
+                    end: walk_to.sidewalk_pos,
+                    constraints: PathConstraints::Pedestrian,
+                    profile,
+                };
+                if let Some(path) = ctx.map.pathfind(req.clone()) {
+                    ctx.scheduler.push(
+                        now,
+                        Command::SpawnPed(CreatePedestrian {
+                            id: person.ped,
+                            speed: person.ped_speed,
+                            start,
+                            goal: walk_to,
+                            path,
+                            req,
+                            trip,
+                            person: person.id,
+                        }),
+                    );
+                } else {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Person(person.id),
+                        format!("UsingBikeShare trip couldn't find the first path {}", req),
+                    ));
+                    self.abort_trip(now, trip, None, ctx);
+                }
+            }
+            TripSpec::UsingRideHail { start, .. } => {
+                assert_eq!(
+                    person.state,
+                    match start.connection {
+                        SidewalkPOI::Building(b) => PersonState::Inside(b),
+                        SidewalkPOI::Border(i, ref loc) => {
+                            self.events.push(Event::PersonEntersMap(
+                                person.id,
+                                AgentID::Pedestrian(person.ped),
+                                i,
+                                loc.clone(),
+                            ));
+                            PersonState::OffMap
+                        }
+                        SidewalkPOI::SuddenlyAppear => {
+                            self.events.push(Event::PersonEntersMap(
+                                person.id,
+                                AgentID::Pedestrian(person.ped),
+                                ctx.map.get_l(start.sidewalk_pos.lane()).src_i,
+                                None,
+                            ));
+                            PersonState::OffMap
+                        }
+                        _ => unreachable!(),
+                    }
+                );
+                person.state = PersonState::Trip(trip);
+
+                // Walk to the curb first
+                let walk_to = match self.trips[trip.0].legs[0] {
+                    TripLeg::Walk(ref spot) => spot.clone(),
+                    _ => unreachable!(),
+                };
+                let req = maybe_req.unwrap();
+                if let Some(path) = maybe_path {
+                    ctx.scheduler.push(
+                        now,
+                        Command::SpawnPed(CreatePedestrian {
+                            id: person.ped,
+                            speed: person.ped_speed,
+                            start,
+                            goal: walk_to,
+                            path,
+                            req,
+                            trip,
+                            person: person.id,
+                        }),
+                    );
+                } else {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Person(person.id),
+                        format!("UsingRideHail trip couldn't find the first path {}", req),
+                    ));
+                    self.abort_trip(now, trip, None, ctx);
+                }
+            }
+            TripSpec::DeliveryTour {
+                start_pos,
+                truck,
+                origin,
+                ..
+            } => {
+                assert_eq!(person.state, PersonState::OffMap);
+                self.events.push(Event::PersonEntersMap(
+                    person.id,
+                    AgentID::Car(truck),
+                    ctx.map.get_l(start_pos.lane()).src_i,
+                    origin,
+                ));
+                person.state = PersonState::Trip(trip);
+
+                let vehicle = person.get_vehicle(truck);
+                let person = person.id;
+                if let Some((req, router)) = route_delivery_truck(
+                    truck,
+                    start_pos,
+                    &mut self.trips[trip.0].legs,
+                    person,
+                    ctx.map,
+                    &mut self.events,
+                ) {
+                    ctx.scheduler.push(
+                        now,
+                        Command::SpawnCar(
+                            CreateCar::for_appearing(vehicle, start_pos, router, req, trip, person),
+                            true,
+                        ),
+                    );
+                } else {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Person(person),
+                        format!(
+                            "DeliveryTour trip couldn't find the first path from {}",
+                            start_pos
+                        ),
+                    ));
+                    self.abort_trip(now, trip, Some(vehicle), ctx);
+                }
+            }
             TripSpec::Remote {
                 trip_time, from, ..
             } => {
@@ -1313,6 +1937,8 @@ impl TripManager {
                         // TODO Not true for long. People will be able to spawn at borders already
                         // on a bus.
                         TripMode::Transit => AgentType::Pedestrian,
+                        TripMode::BikeShare => AgentType::Pedestrian,
+                        TripMode::RideHail => AgentType::Pedestrian,
                     };
                     times.push((t.info.departure, agent_type));
                 }
@@ -1341,10 +1967,15 @@ impl TripManager {
                             trip.info.start.clone(),
                             trip.info.end.clone(),
                             trip.info.mode,
+                            trip.info.departure,
                             map,
                         )
                         .map(|spawn| {
-                            IndividTrip::new(trip.info.departure, trip.info.purpose, spawn)
+                            let mut individ =
+                                IndividTrip::new(trip.info.departure, trip.info.purpose, spawn);
+                            individ.routing_profile = trip.info.routing_profile;
+                            individ.route = self.assigned_routes.get(t).cloned();
+                            individ
                         })
                     })
                     .collect(),
@@ -1375,10 +2006,14 @@ pub struct TripInfo {
     pub start: TripEndpoint,
     pub end: TripEndpoint,
     pub purpose: TripPurpose,
+    // Used for every leg of the trip
+    pub routing_profile: RoutingProfile,
     // Did a ScenarioModifier apply to this?
     pub modified: bool,
     // Was this trip affected by a congestion cap?
     pub capped: bool,
+    // For transit trips, every ride in order
+    pub itinerary: Vec<TransitRide>,
 }
 
 impl Trip {
@@ -1401,6 +2036,7 @@ impl Trip {
             start: start.sidewalk_pos,
             end: walk_to.sidewalk_pos,
             constraints: PathConstraints::Pedestrian,
+            profile: self.info.routing_profile,
         };
         let path = if let Some(p) = map.pathfind(req.clone()) {
             p
@@ -1450,6 +2086,12 @@ pub enum TripLeg {
     Drive(CarID, DrivingGoal),
     // Maybe get off at a stop, maybe ride off-map
     RideBus(BusRouteID, Option<BusStopID>),
+    // Ride a shared bike or scooter, returning it to this station
+    RideShared(CarID, DockingStationID),
+    // Ride in whichever ride-hail vehicle is dispatched, getting dropped off here
+    RideHail(SidewalkSpot),
+    // Drive a delivery truck to this building and stop there for some time
+    Deliver(CarID, BuildingID, Duration),
     Remote(OffMapLocation),
 }
 
@@ -1459,6 +2101,10 @@ pub enum TripMode {
     Bike,
     Transit,
     Drive,
+    // Bikeshare or scooter-share, picking up and returning at docking stations
+    BikeShare,
+    // Get picked up and dropped off by a ride-hail vehicle
+    RideHail,
 }
 
 impl TripMode {
@@ -1468,6 +2114,8 @@ impl TripMode {
             TripMode::Bike,
             TripMode::Transit,
             TripMode::Drive,
+            TripMode::BikeShare,
+            TripMode::RideHail,
         ]
     }
 
@@ -1477,6 +2125,8 @@ impl TripMode {
             TripMode::Bike => "bike",
             TripMode::Transit => "use transit",
             TripMode::Drive => "drive",
+            TripMode::BikeShare => "use bikeshare",
+            TripMode::RideHail => "use ride-hailing",
         }
     }
 
@@ -1487,6 +2137,8 @@ impl TripMode {
             TripMode::Bike => "biking",
             TripMode::Transit => "using transit",
             TripMode::Drive => "driving",
+            TripMode::BikeShare => "using bikeshare",
+            TripMode::RideHail => "using ride-hailing",
         }
     }
 
@@ -1496,16 +2148,18 @@ impl TripMode {
             TripMode::Bike => "Bike",
             TripMode::Transit => "Bus",
             TripMode::Drive => "Car",
+            TripMode::BikeShare => "Shared bike",
+            TripMode::RideHail => "Ride-hail car",
         }
     }
 
     pub fn to_constraints(self) -> PathConstraints {
         match self {
             TripMode::Walk => PathConstraints::Pedestrian,
-            TripMode::Bike => PathConstraints::Bike,
+            TripMode::Bike | TripMode::BikeShare => PathConstraints::Bike,
             // TODO WRONG
             TripMode::Transit => PathConstraints::Bus,
-            TripMode::Drive => PathConstraints::Car,
+            TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
         }
     }
 
@@ -1531,39 +2185,86 @@ impl TripEndpoint {
         from: TripEndpoint,
         to: TripEndpoint,
         mode: TripMode,
+        profile: RoutingProfile,
         map: &Map,
     ) -> Option<PathRequest> {
         Some(PathRequest {
             start: pos(from, mode, true, map)?,
             end: pos(to, mode, false, map)?,
             constraints: match mode {
-                TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-                TripMode::Drive => PathConstraints::Car,
+                TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
+                    PathConstraints::Pedestrian
+                }
+                TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
                 TripMode::Bike => PathConstraints::Bike,
             },
+            profile,
         })
     }
 }
 
+// Route a delivery truck to the next stop, skipping any it can't reach. Only the last leg, leaving
+// the stops behind, has to succeed.
+fn route_delivery_truck(
+    truck: CarID,
+    start: Position,
+    legs: &mut VecDeque<TripLeg>,
+    person: PersonID,
+    map: &Map,
+    events: &mut Vec<Event>,
+) -> Option<(PathRequest, Router)> {
+    loop {
+        match legs[0] {
+            TripLeg::Deliver(c, b, _) => {
+                assert_eq!(truck, c);
+                if let Some((req, path)) = map
+                    .get_b(b)
+                    .driving_connection(map)
+                    .and_then(|(end, _)| ride_hail::pathfind(start, end, map))
+                {
+                    let end_dist = req.end.dist_along();
+                    return Some((req, Router::deliver(truck, path, b, end_dist)));
+                }
+                events.push(Event::Alert(
+                    AlertLocation::Person(person),
+                    format!("{} can't reach {} from {}; skipping it", truck, b, start),
+                ));
+                legs.pop_front();
+            }
+            TripLeg::Drive(c, ref goal) => {
+                assert_eq!(truck, c);
+                let (req, path) = goal
+                    .goal_pos(PathConstraints::Car, map)
+                    .and_then(|end| ride_hail::pathfind(start, end, map))?;
+                let router = goal.make_router(truck, path, None, map);
+                return Some((req, router));
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
 fn pos(endpt: TripEndpoint, mode: TripMode, from: bool, map: &Map) -> Option<Position> {
     match endpt {
         TripEndpoint::Bldg(b) => match mode {
-            TripMode::Walk | TripMode::Transit => Some(map.get_b(b).sidewalk_pos),
+            TripMode::Walk | TripMode::Transit | TripMode::BikeShare => {
+                Some(map.get_b(b).sidewalk_pos)
+            }
             TripMode::Bike => Some(DrivingGoal::ParkNear(b).goal_pos(PathConstraints::Bike, map)?),
-            TripMode::Drive => Some(
+            TripMode::Drive | TripMode::RideHail => Some(
                 DrivingGoal::ParkNear(b)
                     .goal_pos(PathConstraints::Car, map)
                     .unwrap(),
             ),
         },
         TripEndpoint::Border(i, _) => match mode {
-            TripMode::Walk | TripMode::Transit => if from {
+            TripMode::Walk | TripMode::Transit | TripMode::BikeShare => if from {
                 SidewalkSpot::start_at_border(i, None, map)
             } else {
                 SidewalkSpot::end_at_border(i, None, map)
             }
             .map(|spot| spot.sidewalk_pos),
-            TripMode::Bike | TripMode::Drive => (if from {
+            TripMode::Bike | TripMode::Drive | TripMode::RideHail => (if from {
                 map.get_i(i).some_outgoing_road(map)
             } else {
                 map.get_i(i).some_incoming_road(map)
@@ -1629,6 +2330,8 @@ pub struct Person {
     pub ped_speed: Speed,
     // Both cars and bikes
     pub vehicles: Vec<Vehicle>,
+    // Knows about current traffic, and might reroute around it
+    pub informed_driver: bool,
 
     delayed_trips: Vec<(TripID, TripSpec, Option<PathRequest>, Option<Path>)>,
     on_bus: Option<CarID>,